@@ -0,0 +1,28 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fatbinary::FatBinaryEntry;
+
+/// Compressed ELF entry of `size` bytes, repeating a pattern of `period` bytes
+fn make_entry(size: usize, period: usize) -> FatBinaryEntry<'static> {
+    let mut payload = b"\x7fELF".to_vec();
+    payload.extend((0..size - 4).map(|i| ((i % period) * 31 % 251) as u8));
+    let mut entry = FatBinaryEntry::new_auto(80, payload);
+    assert!(entry.compress());
+    entry
+}
+
+fn decompress(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decompress");
+    for (size, period) in [(16 * 1024 * 1024, 1), (16 * 1024 * 1024, 4096)] {
+        let entry = make_entry(size, period);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}/period{}", size, period)),
+            &entry,
+            |b, entry| b.iter(|| entry.get_decompressed_payload()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decompress);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fatbinary::{FatBinary, FatBinaryEntry};
+
+/// Fatbinary with `count` ELF entries of `size` bytes each
+fn make_fatbin(count: usize, size: usize) -> Vec<u8> {
+    let mut fatbin = FatBinary::new();
+    for i in 0..count {
+        let mut payload = b"\x7fELF".to_vec();
+        payload.resize(size, i as u8);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70 + i as u32, payload));
+    }
+    let mut res = vec![];
+    fatbin.write(&mut res).unwrap();
+    res
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    for (count, size) in [(1024, 4 * 1024), (16, 4 * 1024 * 1024)] {
+        let data = make_fatbin(count, size);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}x{}", count, size)),
+            &data,
+            |b, data| b.iter(|| FatBinary::read(std::io::Cursor::new(&data[..])).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read);
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+//! Minimal fatbin inspector for the browser
+//!
+//! Build with `cargo build --example wasm_inspect --target wasm32-unknown-unknown --no-default-features`,
+//! then from JavaScript:
+//!
+//! ```js
+//! const { instance } = await WebAssembly.instantiate(wasmBytes);
+//! const { memory, alloc, inspect, summary_len } = instance.exports;
+//! const ptr = alloc(fatbin.length);
+//! new Uint8Array(memory.buffer, ptr, fatbin.length).set(fatbin);
+//! const summary = inspect(ptr, fatbin.length);
+//! console.log(new TextDecoder().decode(new Uint8Array(memory.buffer, summary, summary_len())));
+//! ```
+
+use fatbinary::FatBinary;
+use std::cell::RefCell;
+use std::fmt::Write;
+
+thread_local! {
+    static SUMMARY: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Allocate a buffer of `len` bytes for the fatbin, it is leaked on purpose
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8
+}
+
+/// Parse the fatbin and return pointer to a text summary
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn inspect(data: *const u8, len: usize) -> *const u8 {
+    let data = std::slice::from_raw_parts(data, len);
+    let mut summary = String::new();
+    match FatBinary::parse(data) {
+        Ok(fatbin) => {
+            for (index, entry) in fatbin.entries().iter().enumerate() {
+                let info = entry.info();
+                writeln!(
+                    summary,
+                    "{}: {:?} {} size={} compressed={}",
+                    index, info.kind, info.arch, info.size, info.is_compressed
+                )
+                .unwrap();
+            }
+        }
+        Err(err) => writeln!(summary, "error: {}", err).unwrap(),
+    }
+
+    SUMMARY.with(|res| {
+        *res.borrow_mut() = summary;
+        res.borrow().as_ptr()
+    })
+}
+
+/// Length of the summary returned by the last `inspect` call
+#[no_mangle]
+pub extern "C" fn summary_len() -> usize {
+    SUMMARY.with(|res| res.borrow().len())
+}
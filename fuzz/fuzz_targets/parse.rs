@@ -0,0 +1,56 @@
+//! Untrusted bytes must never panic the library: parse them at every
+//! validation level, then inspect, decompress and rewrite what was parsed
+//!
+//! Run with `cargo +nightly fuzz run parse` from the repository root.
+
+#![no_main]
+
+use fatbinary::{FatBinary, FatBinaryWrapper, OffloadBundle, ParseOptions, ValidationLevel};
+use libfuzzer_sys::fuzz_target;
+use std::io::{Cursor, Read};
+
+fuzz_target!(|data: &[u8]| {
+    for level in [
+        ValidationLevel::Strict,
+        ValidationLevel::Normal,
+        ValidationLevel::Permissive,
+    ] {
+        let options = ParseOptions {
+            level,
+            ..Default::default()
+        };
+        let _ = FatBinary::read_with(Cursor::new(data), options.clone());
+        let _ = FatBinary::read_stream_with(data, options.clone());
+        let _ = FatBinary::parse_all_with(data, options.clone());
+        let Ok((mut fatbin, _)) = FatBinary::parse_with(data, options) else {
+            continue;
+        };
+
+        let _ = fatbin.verify();
+        let _ = fatbin.audit();
+        let _ = fatbin.report();
+        let _ = fatbin.kernels();
+        let _ = fatbin.write(&mut Vec::new());
+        for entry in fatbin.entries() {
+            let _ = entry.info();
+            let _ = entry.get_decompressed_payload();
+            let _ = entry.try_get_decompressed_payload();
+            let _ = entry.cubin_header();
+            let _ = entry.payload_reader().read_to_end(&mut Vec::new());
+        }
+        fatbin.decompress();
+        fatbin.normalize();
+        fatbin.compress();
+        let _ = fatbin.write(&mut Vec::new());
+    }
+
+    let _ = FatBinary::read_metadata(Cursor::new(data));
+    let _ = FatBinary::read_entry_at(Cursor::new(data), 1);
+    let _ = FatBinary::copy_entry_payload(Cursor::new(data), 0, std::io::sink());
+    let _ = FatBinary::parse_wrapped(data, 0);
+    let _ = FatBinaryWrapper::parse(data);
+    let _ = FatBinary::find_in_core_dump(data);
+    if let Ok(bundle) = OffloadBundle::parse(data) {
+        let _ = bundle.to_fatbinary();
+    }
+});
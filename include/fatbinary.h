@@ -0,0 +1,133 @@
+#ifndef FATBINARY_H
+#define FATBINARY_H
+
+/* Generated by cbindgen, do not edit */
+
+#include <stdint.h>
+#include <stddef.h>
+
+/**
+ * A fatbinary file
+ */
+typedef struct fatbinary_t fatbinary_t;
+
+/**
+ * Get message of the last error in this thread, or NULL if none.
+ * The string is valid until the next failing call in this thread.
+ */
+const char *fatbinary_last_error(void);
+
+/**
+ * Create an empty fatbinary, free with `fatbinary_free`
+ */
+struct fatbinary_t *fatbinary_new(void);
+
+/**
+ * Parse fatbinary from memory, return NULL on error. Free with `fatbinary_free`.
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes.
+ */
+struct fatbinary_t *fatbinary_parse(const uint8_t *data, size_t len);
+
+/**
+ * Free fatbinary returned by `fatbinary_new` or `fatbinary_parse`
+ *
+ * # Safety
+ *
+ * `fatbin` must be NULL or returned by this library and not freed yet.
+ */
+void fatbinary_free(struct fatbinary_t *fatbin);
+
+/**
+ * Get number of entries
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer.
+ */
+size_t fatbinary_entry_count(const struct fatbinary_t *fatbin);
+
+/**
+ * Check if entry contains ELF, return -1 if entry does not exist
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer.
+ */
+int32_t fatbinary_entry_is_elf(const struct fatbinary_t *fatbin, size_t index);
+
+/**
+ * Get CUDA SM architecture of entry, return 0 if entry does not exist
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer.
+ */
+uint32_t fatbinary_entry_sm_arch(const struct fatbinary_t *fatbin, size_t index);
+
+/**
+ * Check if payload of entry is compressed, return -1 if entry does not exist
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer.
+ */
+int32_t fatbinary_entry_is_compressed(const struct fatbinary_t *fatbin, size_t index);
+
+/**
+ * Get (possibly compressed) payload of entry, return NULL if entry does not exist.
+ * The payload is owned by the fatbinary.
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer, `out_len` must be NULL or writable.
+ */
+const uint8_t *fatbinary_entry_payload(const struct fatbinary_t *fatbin,
+                                       size_t index,
+                                       size_t *out_len);
+
+/**
+ * Get decompressed payload of entry, return NULL on error.
+ * Free with `fatbinary_buffer_free`.
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer, `out_len` must be NULL or writable.
+ */
+uint8_t *fatbinary_entry_decompressed_payload(const struct fatbinary_t *fatbin,
+                                              size_t index,
+                                              size_t *out_len);
+
+/**
+ * Append a new entry, kind is detected from payload. Return 0 on success.
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer, `data` must point to `len` readable bytes.
+ */
+int32_t fatbinary_add_entry(struct fatbinary_t *fatbin,
+                            uint32_t sm_arch,
+                            const uint8_t *data,
+                            size_t len);
+
+/**
+ * Serialize fatbinary, return NULL on error. Free with `fatbinary_buffer_free`.
+ *
+ * # Safety
+ *
+ * `fatbin` must be a valid fatbinary pointer, `out_len` must be NULL or writable.
+ */
+uint8_t *fatbinary_build(const struct fatbinary_t *fatbin, size_t *out_len);
+
+/**
+ * Free buffer returned by this library
+ *
+ * # Safety
+ *
+ * `data` must be NULL or returned by this library with length `len` and not freed yet.
+ */
+void fatbinary_buffer_free(uint8_t *data, size_t len);
+
+#endif  /* FATBINARY_H */
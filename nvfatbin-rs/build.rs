@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=CUDA_PATH");
+
+    // by default, libnvfatbin.so is loaded at runtime
+    if std::env::var_os("CARGO_FEATURE_STATIC").is_none() {
+        return;
+    }
+
+    let cuda_path = std::env::var_os("CUDA_PATH")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from("/usr/local/cuda"));
+    let lib_dir = cuda_path.join("lib64");
+    if !lib_dir.join("libnvfatbin_static.a").exists() {
+        panic!(
+            "libnvfatbin_static.a not found in {}, set CUDA_PATH or disable the `static` feature",
+            lib_dir.display()
+        );
+    }
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib=static=nvfatbin_static");
+    println!("cargo:rustc-link-lib=dylib=stdc++");
+}
@@ -0,0 +1,316 @@
+//! nvfatbin-rs: safe wrapper of NVIDIA nvFatbin library
+//!
+//! nvFatbin is NVIDIA's reference implementation to create fatbinary files.
+//! Use [Fatbin] to add PTX, cubin, LTO-IR and index entries and get the
+//! serialized fatbinary. The `fatbinary` crate provides a pure-Rust alternative.
+//!
+//! By default (`dlopen` feature) `libnvfatbin.so` is loaded at runtime from
+//! `NVFATBIN_PATH`, `$CUDA_PATH/lib64` (default `/usr/local/cuda`) or the
+//! library search path, use [is_available] to check for it. With the `static`
+//! feature, `libnvfatbin_static.a` is linked from `CUDA_PATH` at build time.
+
+pub mod sys;
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr::null_mut;
+use thiserror::Error;
+
+/// Errors from nvFatbin
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NvFatbinError {
+    /// nvFatbin library could not be loaded
+    #[error("nvFatbin library not found: {0}")]
+    LibraryNotFound(String),
+    #[error("Internal error")]
+    Internal,
+    #[error("ELF architecture mismatch")]
+    ElfArchMismatch,
+    #[error("ELF size mismatch")]
+    ElfSizeMismatch,
+    #[error("Missing PTX version")]
+    MissingPtxVersion,
+    #[error("Null pointer")]
+    NullPointer,
+    #[error("Compression failed")]
+    CompressionFailed,
+    #[error("Compressed size exceeded")]
+    CompressedSizeExceeded,
+    #[error("Unrecognized option")]
+    UnrecognizedOption,
+    #[error("Invalid architecture")]
+    InvalidArch,
+    #[error("Invalid NVVM")]
+    InvalidNvvm,
+    #[error("Empty input")]
+    EmptyInput,
+    #[error("Missing PTX architecture")]
+    MissingPtxArch,
+    #[error("PTX architecture mismatch")]
+    PtxArchMismatch,
+    #[error("Missing fatbin")]
+    MissingFatbin,
+    #[error("Invalid index")]
+    InvalidIndex,
+    #[error("Identifier reuse")]
+    IdentifierReuse,
+    #[error("Internal PTX option")]
+    InternalPtxOption,
+    /// Got string containing NUL
+    #[error("String contains NUL")]
+    InteriorNul,
+    /// Result code unknown to this crate
+    #[error("Unknown error {0}")]
+    Unknown(i32),
+}
+
+impl From<sys::nvFatbinResult> for NvFatbinError {
+    fn from(result: sys::nvFatbinResult) -> Self {
+        match result {
+            1 => NvFatbinError::Internal,
+            2 => NvFatbinError::ElfArchMismatch,
+            3 => NvFatbinError::ElfSizeMismatch,
+            4 => NvFatbinError::MissingPtxVersion,
+            5 => NvFatbinError::NullPointer,
+            6 => NvFatbinError::CompressionFailed,
+            7 => NvFatbinError::CompressedSizeExceeded,
+            8 => NvFatbinError::UnrecognizedOption,
+            9 => NvFatbinError::InvalidArch,
+            10 => NvFatbinError::InvalidNvvm,
+            11 => NvFatbinError::EmptyInput,
+            12 => NvFatbinError::MissingPtxArch,
+            13 => NvFatbinError::PtxArchMismatch,
+            14 => NvFatbinError::MissingFatbin,
+            15 => NvFatbinError::InvalidIndex,
+            16 => NvFatbinError::IdentifierReuse,
+            17 => NvFatbinError::InternalPtxOption,
+            result => NvFatbinError::Unknown(result),
+        }
+    }
+}
+
+impl From<std::ffi::NulError> for NvFatbinError {
+    fn from(_: std::ffi::NulError) -> Self {
+        NvFatbinError::InteriorNul
+    }
+}
+
+fn api() -> Result<&'static sys::Api, NvFatbinError> {
+    sys::api().map_err(NvFatbinError::LibraryNotFound)
+}
+
+/// Check if nvFatbin library is available
+pub fn is_available() -> bool {
+    sys::api().is_ok()
+}
+
+fn check(result: sys::nvFatbinResult) -> Result<(), NvFatbinError> {
+    if result == sys::NVFATBIN_SUCCESS {
+        Ok(())
+    } else {
+        Err(result.into())
+    }
+}
+
+/// Get message of nvFatbin result code from the library
+pub fn error_string(result: i32) -> Result<String, NvFatbinError> {
+    let message = unsafe { (api()?.nvFatbinGetErrorString)(result) };
+    if message.is_null() {
+        Ok(String::new())
+    } else {
+        Ok(unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+/// Get version of nvFatbin library
+pub fn version() -> Result<(u32, u32), NvFatbinError> {
+    let mut major = 0;
+    let mut minor = 0;
+    check(unsafe { (api()?.nvFatbinVersion)(&mut major, &mut minor) })?;
+    Ok((major, minor))
+}
+
+/// Options passed to nvFatbinCreate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Generate 64 bit entries (`-64`) or 32 bit entries (`-32`)
+    pub is_64bit: bool,
+    /// Mark entries as containing debug info (`-g`)
+    pub debug: bool,
+    /// Compress entries if beneficial (`-compress=true/false`), library default if None
+    pub compress: Option<bool>,
+    /// Compress all entries including small ones (`-compress-all`)
+    pub compress_all: bool,
+    /// Host operating system (`-host=linux/windows`)
+    pub host: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            is_64bit: true,
+            debug: false,
+            compress: None,
+            compress_all: false,
+            host: None,
+        }
+    }
+}
+
+impl Options {
+    fn to_args(&self) -> Result<Vec<CString>, NvFatbinError> {
+        let mut res = vec![CString::new(if self.is_64bit { "-64" } else { "-32" })?];
+        if self.debug {
+            res.push(CString::new("-g")?);
+        }
+        if let Some(compress) = self.compress {
+            res.push(CString::new(format!("-compress={}", compress))?);
+        }
+        if self.compress_all {
+            res.push(CString::new("-compress-all")?);
+        }
+        if let Some(host) = &self.host {
+            res.push(CString::new(format!("-host={}", host))?);
+        }
+        Ok(res)
+    }
+}
+
+/// A fatbinary under construction by nvFatbin
+pub struct Fatbin {
+    api: &'static sys::Api,
+    handle: sys::nvFatbinHandle,
+}
+
+impl Fatbin {
+    /// Create an empty fatbinary
+    pub fn new(options: &Options) -> Result<Self, NvFatbinError> {
+        let api = api()?;
+        let args = options.to_args()?;
+        let arg_ptrs: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+        let mut handle = null_mut();
+        check(unsafe { (api.nvFatbinCreate)(&mut handle, arg_ptrs.as_ptr(), arg_ptrs.len()) })?;
+        Ok(Self { api, handle })
+    }
+
+    /// Add PTX code for compute_{arch}, with options passed to ptxas when JIT-ing
+    pub fn add_ptx(
+        &mut self,
+        code: &str,
+        arch: u32,
+        identifier: &str,
+        ptxas_options: &str,
+    ) -> Result<(), NvFatbinError> {
+        // nvFatbin expects NUL-terminated PTX
+        let code = CString::new(code)?;
+        let arch = CString::new(arch.to_string())?;
+        let identifier = CString::new(identifier)?;
+        let ptxas_options = CString::new(ptxas_options)?;
+        check(unsafe {
+            (self.api.nvFatbinAddPTX)(
+                self.handle,
+                code.as_ptr(),
+                code.as_bytes_with_nul().len(),
+                arch.as_ptr(),
+                identifier.as_ptr(),
+                ptxas_options.as_ptr(),
+            )
+        })
+    }
+
+    /// Add cubin for sm_{arch}
+    pub fn add_cubin(
+        &mut self,
+        code: &[u8],
+        arch: u32,
+        identifier: &str,
+    ) -> Result<(), NvFatbinError> {
+        let arch = CString::new(arch.to_string())?;
+        let identifier = CString::new(identifier)?;
+        check(unsafe {
+            (self.api.nvFatbinAddCubin)(
+                self.handle,
+                code.as_ptr().cast(),
+                code.len(),
+                arch.as_ptr(),
+                identifier.as_ptr(),
+            )
+        })
+    }
+
+    /// Add LTO-IR for lto_{arch}
+    pub fn add_ltoir(
+        &mut self,
+        code: &[u8],
+        arch: u32,
+        identifier: &str,
+        options: &str,
+    ) -> Result<(), NvFatbinError> {
+        let arch = CString::new(arch.to_string())?;
+        let identifier = CString::new(identifier)?;
+        let options = CString::new(options)?;
+        check(unsafe {
+            (self.api.nvFatbinAddLTOIR)(
+                self.handle,
+                code.as_ptr().cast(),
+                code.len(),
+                arch.as_ptr(),
+                identifier.as_ptr(),
+                options.as_ptr(),
+            )
+        })
+    }
+
+    /// Add index file
+    pub fn add_index(&mut self, code: &[u8], identifier: &str) -> Result<(), NvFatbinError> {
+        let identifier = CString::new(identifier)?;
+        check(unsafe {
+            (self.api.nvFatbinAddIndex)(
+                self.handle,
+                code.as_ptr().cast(),
+                code.len(),
+                identifier.as_ptr(),
+            )
+        })
+    }
+
+    /// Get serialized fatbinary
+    pub fn build(&self) -> Result<Vec<u8>, NvFatbinError> {
+        let mut size = 0;
+        check(unsafe { (self.api.nvFatbinSize)(self.handle, &mut size) })?;
+        let mut res = vec![0u8; size];
+        check(unsafe { (self.api.nvFatbinGet)(self.handle, res.as_mut_ptr().cast()) })?;
+        Ok(res)
+    }
+}
+
+impl Drop for Fatbin {
+    fn drop(&mut self) {
+        unsafe { (self.api.nvFatbinDestroy)(&mut self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{is_available, Fatbin, Options};
+
+    #[test]
+    fn create_ptx() {
+        if !is_available() {
+            eprintln!("nvFatbin not available, skipping");
+            return;
+        }
+        let mut fatbin = Fatbin::new(&Options::default()).unwrap();
+        fatbin
+            .add_ptx(
+                ".version 7.0\n.target sm_70\n.address_size 64\n",
+                70,
+                "test.cu",
+                "-O3",
+            )
+            .unwrap();
+        let res = fatbin.build().unwrap();
+        assert!(res.starts_with(&0xBA55ED50u32.to_le_bytes()));
+    }
+}
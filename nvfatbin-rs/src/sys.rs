@@ -0,0 +1,197 @@
+//! Raw bindings to nvFatbin, see `nvFatbin.h` in CUDA toolkit
+//!
+//! The functions are resolved into [Api], either from the statically linked
+//! library (`static` feature) or from `libnvfatbin.so` loaded at runtime
+//! (`dlopen` feature).
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::ffi::{c_char, c_uint, c_void};
+
+#[cfg(not(any(feature = "static", feature = "dlopen")))]
+compile_error!("either `static` or `dlopen` feature must be enabled");
+
+#[repr(C)]
+pub struct nvFatbinHandleImpl {
+    _private: [u8; 0],
+}
+
+pub type nvFatbinHandle = *mut nvFatbinHandleImpl;
+pub type nvFatbinResult = i32;
+
+pub const NVFATBIN_SUCCESS: nvFatbinResult = 0;
+
+/// Function table of nvFatbin
+pub struct Api {
+    pub nvFatbinGetErrorString: unsafe extern "C" fn(result: nvFatbinResult) -> *const c_char,
+    pub nvFatbinCreate: unsafe extern "C" fn(
+        handle_indirect: *mut nvFatbinHandle,
+        options: *const *const c_char,
+        options_count: usize,
+    ) -> nvFatbinResult,
+    pub nvFatbinDestroy:
+        unsafe extern "C" fn(handle_indirect: *mut nvFatbinHandle) -> nvFatbinResult,
+    pub nvFatbinAddPTX: unsafe extern "C" fn(
+        handle: nvFatbinHandle,
+        code: *const c_char,
+        size: usize,
+        arch: *const c_char,
+        identifier: *const c_char,
+        options_cmd_line: *const c_char,
+    ) -> nvFatbinResult,
+    pub nvFatbinAddCubin: unsafe extern "C" fn(
+        handle: nvFatbinHandle,
+        code: *const c_void,
+        size: usize,
+        arch: *const c_char,
+        identifier: *const c_char,
+    ) -> nvFatbinResult,
+    pub nvFatbinAddLTOIR: unsafe extern "C" fn(
+        handle: nvFatbinHandle,
+        code: *const c_void,
+        size: usize,
+        arch: *const c_char,
+        identifier: *const c_char,
+        options_cmd_line: *const c_char,
+    ) -> nvFatbinResult,
+    pub nvFatbinAddIndex: unsafe extern "C" fn(
+        handle: nvFatbinHandle,
+        code: *const c_void,
+        size: usize,
+        identifier: *const c_char,
+    ) -> nvFatbinResult,
+    pub nvFatbinSize:
+        unsafe extern "C" fn(handle: nvFatbinHandle, size: *mut usize) -> nvFatbinResult,
+    pub nvFatbinGet:
+        unsafe extern "C" fn(handle: nvFatbinHandle, buffer: *mut c_void) -> nvFatbinResult,
+    pub nvFatbinVersion:
+        unsafe extern "C" fn(major: *mut c_uint, minor: *mut c_uint) -> nvFatbinResult,
+    // keep the library loaded as long as the function pointers are in use
+    #[cfg(all(feature = "dlopen", not(feature = "static")))]
+    _library: libloading::Library,
+}
+
+#[cfg(feature = "static")]
+mod linked {
+    use super::*;
+
+    extern "C" {
+        pub fn nvFatbinGetErrorString(result: nvFatbinResult) -> *const c_char;
+        pub fn nvFatbinCreate(
+            handle_indirect: *mut nvFatbinHandle,
+            options: *const *const c_char,
+            options_count: usize,
+        ) -> nvFatbinResult;
+        pub fn nvFatbinDestroy(handle_indirect: *mut nvFatbinHandle) -> nvFatbinResult;
+        pub fn nvFatbinAddPTX(
+            handle: nvFatbinHandle,
+            code: *const c_char,
+            size: usize,
+            arch: *const c_char,
+            identifier: *const c_char,
+            options_cmd_line: *const c_char,
+        ) -> nvFatbinResult;
+        pub fn nvFatbinAddCubin(
+            handle: nvFatbinHandle,
+            code: *const c_void,
+            size: usize,
+            arch: *const c_char,
+            identifier: *const c_char,
+        ) -> nvFatbinResult;
+        pub fn nvFatbinAddLTOIR(
+            handle: nvFatbinHandle,
+            code: *const c_void,
+            size: usize,
+            arch: *const c_char,
+            identifier: *const c_char,
+            options_cmd_line: *const c_char,
+        ) -> nvFatbinResult;
+        pub fn nvFatbinAddIndex(
+            handle: nvFatbinHandle,
+            code: *const c_void,
+            size: usize,
+            identifier: *const c_char,
+        ) -> nvFatbinResult;
+        pub fn nvFatbinSize(handle: nvFatbinHandle, size: *mut usize) -> nvFatbinResult;
+        pub fn nvFatbinGet(handle: nvFatbinHandle, buffer: *mut c_void) -> nvFatbinResult;
+        pub fn nvFatbinVersion(major: *mut c_uint, minor: *mut c_uint) -> nvFatbinResult;
+    }
+
+    pub static API: Api = Api {
+        nvFatbinGetErrorString,
+        nvFatbinCreate,
+        nvFatbinDestroy,
+        nvFatbinAddPTX,
+        nvFatbinAddCubin,
+        nvFatbinAddLTOIR,
+        nvFatbinAddIndex,
+        nvFatbinSize,
+        nvFatbinGet,
+        nvFatbinVersion,
+    };
+}
+
+/// Get function table of the statically linked library
+#[cfg(feature = "static")]
+pub fn api() -> Result<&'static Api, String> {
+    Ok(&linked::API)
+}
+
+#[cfg(all(feature = "dlopen", not(feature = "static")))]
+impl Api {
+    /// Load nvFatbin from shared library
+    ///
+    /// # Safety
+    ///
+    /// `path` must be a nvFatbin library, since its initialization routines are run.
+    pub unsafe fn load<P: AsRef<std::ffi::OsStr>>(path: P) -> Result<Self, libloading::Error> {
+        let library = libloading::Library::new(path)?;
+        Ok(Self {
+            nvFatbinGetErrorString: *library.get(b"nvFatbinGetErrorString\0")?,
+            nvFatbinCreate: *library.get(b"nvFatbinCreate\0")?,
+            nvFatbinDestroy: *library.get(b"nvFatbinDestroy\0")?,
+            nvFatbinAddPTX: *library.get(b"nvFatbinAddPTX\0")?,
+            nvFatbinAddCubin: *library.get(b"nvFatbinAddCubin\0")?,
+            nvFatbinAddLTOIR: *library.get(b"nvFatbinAddLTOIR\0")?,
+            nvFatbinAddIndex: *library.get(b"nvFatbinAddIndex\0")?,
+            nvFatbinSize: *library.get(b"nvFatbinSize\0")?,
+            nvFatbinGet: *library.get(b"nvFatbinGet\0")?,
+            nvFatbinVersion: *library.get(b"nvFatbinVersion\0")?,
+            _library: library,
+        })
+    }
+}
+
+/// Candidate paths of nvFatbin shared library, `NVFATBIN_PATH` takes precedence
+#[cfg(all(feature = "dlopen", not(feature = "static")))]
+fn candidates() -> Vec<std::ffi::OsString> {
+    let mut res = vec![];
+    if let Some(path) = std::env::var_os("NVFATBIN_PATH") {
+        res.push(path);
+    }
+    let cuda_path = std::env::var_os("CUDA_PATH")
+        .map(std::path::PathBuf::from)
+        .unwrap_or(std::path::PathBuf::from("/usr/local/cuda"));
+    res.push(cuda_path.join("lib64/libnvfatbin.so").into_os_string());
+    res.push("libnvfatbin.so".into());
+    res.push("libnvfatbin.so.12".into());
+    res
+}
+
+/// Get function table, loading the shared library on first call
+#[cfg(all(feature = "dlopen", not(feature = "static")))]
+pub fn api() -> Result<&'static Api, String> {
+    static API: std::sync::OnceLock<Result<Api, String>> = std::sync::OnceLock::new();
+    API.get_or_init(|| {
+        let mut errors = vec![];
+        for path in candidates() {
+            match unsafe { Api::load(&path) } {
+                Ok(api) => return Ok(api),
+                Err(err) => errors.push(format!("{}: {}", path.to_string_lossy(), err)),
+            }
+        }
+        Err(errors.join("; "))
+    })
+    .as_ref()
+    .map_err(|err| err.clone())
+}
@@ -0,0 +1,238 @@
+//! Differential test between nvFatbin and fatbinary crate
+//!
+//! Random entry sets are written by both nvFatbin and the fatbinary crate,
+//! then both outputs are parsed by the fatbinary crate and compared.
+//! Run with `cargo test -p nvfatbin-rs --features differential-tests`,
+//! set `FATBINARY_DIFF_SEED` to reproduce a failing seed. Skipped if nvFatbin
+//! is not available.
+
+#![cfg(feature = "differential-tests")]
+
+use fatbinary::{EntryKind, FatBinary, FatBinaryEntry};
+use nvfatbin_rs::{is_available, Fatbin, Options};
+
+const ROUNDS: usize = 64;
+
+/// xorshift64, to avoid depending on rand
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Input {
+    is_elf: bool,
+    arch: u32,
+    identifier: String,
+    ptxas_options: String,
+    payload: Vec<u8>,
+}
+
+const ARCHS: &[u32] = &[50, 52, 60, 61, 70, 75, 80, 86, 89, 90];
+
+fn random_ptx(rng: &mut Rng, arch: u32) -> Vec<u8> {
+    let mut res = format!(
+        ".version 7.{}\n.target sm_{}\n.address_size 64\n",
+        rng.below(9),
+        arch
+    );
+    // repeated lines make the payload compressible
+    for i in 0..rng.below(64) {
+        res += &format!(".global .align 4 .u32 var{} = {};\n", i, rng.below(1000));
+    }
+    res.into_bytes()
+}
+
+/// Minimal ELF64 relocatable for EM_CUDA without sections
+fn random_cubin(rng: &mut Rng, arch: u32) -> Vec<u8> {
+    let mut res = vec![0u8; 64];
+    res[0..4].copy_from_slice(b"\x7fELF");
+    res[4] = 2; // ELFCLASS64
+    res[5] = 1; // ELFDATA2LSB
+    res[6] = 1; // EV_CURRENT
+    res[7] = 0x33; // ELFOSABI_CUDA
+    res[8] = 7; // ABI version
+    res[16..18].copy_from_slice(&1u16.to_le_bytes()); // ET_REL
+    res[18..20].copy_from_slice(&190u16.to_le_bytes()); // EM_CUDA
+    res[20..24].copy_from_slice(&1u32.to_le_bytes());
+    res[48..52].copy_from_slice(&(arch | (arch << 16) | 0x500).to_le_bytes());
+    res[52..54].copy_from_slice(&64u16.to_le_bytes()); // e_ehsize
+    for _ in 0..rng.below(256) {
+        res.push(rng.below(4) as u8);
+    }
+    res
+}
+
+fn random_inputs(rng: &mut Rng) -> Vec<Input> {
+    let mut res = vec![];
+    for i in 0..(1 + rng.below(6)) {
+        let is_elf = rng.below(2) == 0;
+        let arch = *rng.pick(ARCHS);
+        res.push(Input {
+            is_elf,
+            arch,
+            // nvFatbin rejects reused identifiers
+            identifier: format!("kernel{}_{}.cu", i, rng.below(100)),
+            ptxas_options: if is_elf {
+                String::new()
+            } else {
+                rng.pick(&["", "-O3", "-O0 -lineinfo"]).to_string()
+            },
+            payload: if is_elf {
+                random_cubin(rng, arch)
+            } else {
+                random_ptx(rng, arch)
+            },
+        });
+    }
+    res
+}
+
+fn write_nvfatbin(inputs: &[Input], options: &Options) -> Vec<u8> {
+    let mut fatbin = Fatbin::new(options).unwrap();
+    for input in inputs {
+        if input.is_elf {
+            fatbin
+                .add_cubin(&input.payload, input.arch, &input.identifier)
+                .unwrap();
+        } else {
+            fatbin
+                .add_ptx(
+                    std::str::from_utf8(&input.payload).unwrap(),
+                    input.arch,
+                    &input.identifier,
+                    &input.ptxas_options,
+                )
+                .unwrap();
+        }
+    }
+    fatbin.build().unwrap()
+}
+
+fn write_fatbinary(inputs: &[Input]) -> Vec<u8> {
+    let mut fatbin = FatBinary::new();
+    for input in inputs {
+        let mut entry = FatBinaryEntry::new_auto(input.arch, input.payload.clone());
+        entry.set_identifier(Some(input.identifier.as_str()));
+        if !input.is_elf {
+            entry.set_ptxas_options(Some(input.ptxas_options.as_str()));
+        }
+        fatbin.entries_mut().push(entry);
+    }
+    let mut res = vec![];
+    fatbin.write(&mut res).unwrap();
+    res
+}
+
+/// Payload without padding NULs
+fn normalized_payload(entry: &FatBinaryEntry) -> Vec<u8> {
+    let mut payload = entry.get_decompressed_payload().into_owned();
+    if entry.kind() == EntryKind::Ptx {
+        while payload.last() == Some(&0) {
+            payload.pop();
+        }
+    }
+    payload
+}
+
+fn check(seed: u64, inputs: &[Input], data: &[u8], writer: &str) {
+    let fatbin = FatBinary::parse(data)
+        .unwrap_or_else(|err| panic!("seed {}: parse {} output: {}", seed, writer, err));
+    assert!(
+        fatbin.verify().is_empty(),
+        "seed {}: verify {} output: {:?}",
+        seed,
+        writer,
+        fatbin.verify()
+    );
+
+    // nvFatbin may reorder entries, so match them by identifier
+    assert_eq!(fatbin.entries().len(), inputs.len(), "seed {}", seed);
+    for input in inputs {
+        let entry = fatbin
+            .entries()
+            .iter()
+            .find(|entry| entry.get_identifier() == Some(input.identifier.as_str()))
+            .unwrap_or_else(|| panic!("seed {}: {} lost {}", seed, writer, input.identifier));
+        assert_eq!(entry.contains_elf(), input.is_elf, "seed {}", seed);
+        assert_eq!(entry.get_sm_arch(), input.arch, "seed {}", seed);
+        if !input.is_elf {
+            assert_eq!(
+                entry
+                    .get_ptxas_options()
+                    .unwrap_or_default()
+                    .trim_end_matches('\0'),
+                input.ptxas_options,
+                "seed {}",
+                seed
+            );
+        }
+        assert_eq!(normalized_payload(entry), input.payload, "seed {}", seed);
+    }
+}
+
+#[test]
+fn differential() {
+    if !is_available() {
+        eprintln!("nvFatbin not available, skipping");
+        return;
+    }
+
+    let seed = std::env::var("FATBINARY_DIFF_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(0x5eed_fa7b_1a4e);
+    let mut rng = Rng(seed);
+    for _ in 0..ROUNDS {
+        let round_seed = rng.next();
+        let mut round_rng = Rng(round_seed);
+        let inputs = random_inputs(&mut round_rng);
+
+        for compress in [false, true] {
+            let options = Options {
+                compress: Some(compress),
+                compress_all: compress,
+                ..Options::default()
+            };
+            let nv = write_nvfatbin(&inputs, &options);
+            check(round_seed, &inputs, &nv, "nvFatbin");
+        }
+
+        let ours = write_fatbinary(&inputs);
+        check(round_seed, &inputs, &ours, "fatbinary");
+
+        // both writers agree on metadata of each entry
+        let nv = write_nvfatbin(
+            &inputs,
+            &Options {
+                compress: Some(false),
+                ..Options::default()
+            },
+        );
+        let nv = FatBinary::parse(&nv).unwrap();
+        let ours = FatBinary::parse(&ours).unwrap();
+        for entry in ours.entries() {
+            let other = nv
+                .entries()
+                .iter()
+                .find(|other| other.get_identifier() == entry.get_identifier())
+                .unwrap();
+            assert_eq!(entry.kind(), other.kind(), "seed {}", round_seed);
+            assert_eq!(entry.is_64bit(), other.is_64bit(), "seed {}", round_seed);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Database of known GPU architectures: family names, the CUDA release
+//! adding each architecture and its arch- and family-specific variants
+//!
+//! Arch-specific targets like `sm_90a` only run on exactly that
+//! architecture, family-specific targets like `sm_100f` run on later
+//! architectures of the same family.
+
+use crate::SmArch;
+
+/// Metadata of a known architecture, see [SmArch::info]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArchInfo {
+    pub arch: SmArch,
+    /// Marketing name of the architecture family, e.g. `Ampere`
+    pub family: &'static str,
+    /// CUDA release adding the architecture as (major, minor)
+    pub cuda_version: (u16, u16),
+    /// Whether an arch-specific variant like `sm_90a` exists
+    pub has_arch_specific: bool,
+    /// Whether a family-specific variant like `sm_100f` exists
+    pub has_family_specific: bool,
+}
+
+const fn info(
+    arch: u32,
+    family: &'static str,
+    cuda_version: (u16, u16),
+    has_arch_specific: bool,
+    has_family_specific: bool,
+) -> ArchInfo {
+    ArchInfo {
+        arch: SmArch(arch),
+        family,
+        cuda_version,
+        has_arch_specific,
+        has_family_specific,
+    }
+}
+
+/// All known architectures in ascending order, new architectures are
+/// appended here and, if nvcc `-arch=all` includes them, to
+/// [KNOWN_SM_ARCHS]
+pub const ARCH_INFOS: &[ArchInfo] = &[
+    info(20, "Fermi", (3, 0), false, false),
+    info(21, "Fermi", (3, 2), false, false),
+    info(30, "Kepler", (4, 2), false, false),
+    info(32, "Kepler", (6, 0), false, false),
+    info(35, "Kepler", (5, 0), false, false),
+    info(37, "Kepler", (6, 5), false, false),
+    info(50, "Maxwell", (6, 0), false, false),
+    info(52, "Maxwell", (6, 5), false, false),
+    info(53, "Maxwell", (7, 0), false, false),
+    info(60, "Pascal", (8, 0), false, false),
+    info(61, "Pascal", (8, 0), false, false),
+    info(62, "Pascal", (8, 0), false, false),
+    info(70, "Volta", (9, 0), false, false),
+    info(72, "Volta", (10, 0), false, false),
+    info(75, "Turing", (10, 0), false, false),
+    info(80, "Ampere", (11, 0), false, false),
+    info(86, "Ampere", (11, 1), false, false),
+    info(87, "Ampere", (11, 4), false, false),
+    info(89, "Ada Lovelace", (11, 8), false, false),
+    info(90, "Hopper", (11, 8), true, false),
+    info(100, "Blackwell", (12, 8), true, true),
+    info(101, "Blackwell", (12, 8), true, true),
+    info(103, "Blackwell", (12, 9), true, true),
+    info(120, "Blackwell", (12, 8), true, true),
+    info(121, "Blackwell", (12, 9), true, true),
+];
+
+/// Real architectures of nvcc `-arch=all` as of CUDA 12.9, in ascending
+/// order
+pub const KNOWN_SM_ARCHS: &[SmArch] = &[
+    SmArch(50),
+    SmArch(52),
+    SmArch(53),
+    SmArch(60),
+    SmArch(61),
+    SmArch(62),
+    SmArch(70),
+    SmArch(72),
+    SmArch(75),
+    SmArch(80),
+    SmArch(86),
+    SmArch(87),
+    SmArch(89),
+    SmArch(90),
+    SmArch(100),
+    SmArch(101),
+    SmArch(103),
+    SmArch(120),
+    SmArch(121),
+];
+
+impl SmArch {
+    /// Metadata of this architecture, `None` if unknown
+    pub fn info(&self) -> Option<&'static ArchInfo> {
+        ARCH_INFOS.iter().find(|info| info.arch == *self)
+    }
+
+    /// Marketing name of the architecture family, e.g. `Hopper` for sm_90,
+    /// `None` if unknown
+    pub fn family_name(&self) -> Option<&'static str> {
+        self.info().map(|info| info.family)
+    }
+
+    /// CUDA release adding this architecture as (major, minor), `None` if
+    /// unknown
+    pub fn cuda_version(&self) -> Option<(u16, u16)> {
+        self.info().map(|info| info.cuda_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SmArch, ARCH_INFOS, KNOWN_SM_ARCHS};
+
+    #[test]
+    fn arch_info() {
+        assert!(ARCH_INFOS
+            .windows(2)
+            .all(|infos| infos[0].arch < infos[1].arch));
+        assert!(KNOWN_SM_ARCHS.iter().all(|arch| arch.info().is_some()));
+
+        assert_eq!(SmArch(70).family_name(), Some("Volta"));
+        assert_eq!(SmArch(86).family_name(), Some("Ampere"));
+        assert_eq!(SmArch(90).family_name(), Some("Hopper"));
+        assert_eq!(SmArch(120).family_name(), Some("Blackwell"));
+        assert_eq!(SmArch(89).cuda_version(), Some((11, 8)));
+        let info = SmArch(100).info().unwrap();
+        assert!(info.has_arch_specific && info.has_family_specific);
+        assert!(!SmArch(80).info().unwrap().has_arch_specific);
+        assert_eq!(SmArch(99).info(), None);
+        assert_eq!(SmArch(99).family_name(), None);
+    }
+}
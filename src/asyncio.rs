@@ -0,0 +1,201 @@
+//! Async read/write, enabled by the `tokio` feature
+
+use crate::{
+    check_in_memory, checked_size, EntryBounds, FatBinary, FatBinaryEntry, FatBinaryEntryHeader,
+    FatBinaryError, FatBinaryHeader, ParseOptions, Payload, STREAM_PREALLOCATION,
+};
+use binread::BinReaderExt;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Read exactly `size` bytes without zero-filling, preallocation is capped
+/// as the size is only checked against the fatbinary header. If input ends
+/// early, the current entry of `bounds` of `needed` bytes is truncated,
+/// `consumed` bytes of which were read before.
+async fn read_payload<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    size: u64,
+    bounds: &EntryBounds,
+    consumed: u64,
+    needed: u64,
+) -> Result<Vec<u8>, FatBinaryError> {
+    let mut payload = Vec::with_capacity(checked_size(size)?.min(STREAM_PREALLOCATION));
+    reader.take(size).read_to_end(&mut payload).await?;
+    if payload.len() as u64 != size {
+        return Err(FatBinaryError::Truncated {
+            entry_index: bounds.index,
+            header_offset: bounds.offset,
+            needed,
+            available: consumed + payload.len() as u64,
+        });
+    }
+    Ok(payload)
+}
+
+impl FatBinary<'_> {
+    /// Read fatbinary from async reader
+    pub async fn read_async<R: AsyncRead + Unpin>(mut reader: R) -> Result<Self, FatBinaryError> {
+        let mut header = [0u8; core::mem::size_of::<FatBinaryHeader>()];
+        reader.read_exact(&mut header).await?;
+        let header: FatBinaryHeader = std::io::Cursor::new(header).read_le()?;
+        header.check(&ParseOptions::default())?;
+
+        // size of input is unknown, bound entries by fatbinary header instead
+        let mut bounds = EntryBounds {
+            index: 0,
+            offset: header.header_size as u64,
+            end: (header.header_size as u64).saturating_add(header.size),
+        };
+
+        let mut entries = vec![];
+        let mut current_size = 0;
+
+        while current_size < header.size {
+            let header_size = core::mem::size_of::<FatBinaryEntryHeader>() as u64;
+            let entry_header =
+                read_payload(&mut reader, header_size, &bounds, 0, header_size).await?;
+            let entry_header: FatBinaryEntryHeader =
+                std::io::Cursor::new(entry_header).read_le()?;
+            let entry_size = bounds.check_entry(&entry_header)?;
+            bounds.context(check_in_memory(&entry_header))?;
+
+            let extra_header_size = (entry_header.header_size as u64).saturating_sub(header_size);
+            let extra_header = read_payload(
+                &mut reader,
+                extra_header_size,
+                &bounds,
+                header_size,
+                entry_size,
+            )
+            .await?;
+            current_size += entry_header.header_size as u64;
+
+            let payload = read_payload(
+                &mut reader,
+                entry_header.size,
+                &bounds,
+                header_size + extra_header_size,
+                entry_size,
+            )
+            .await?;
+            current_size += entry_header.size;
+
+            entries.push(bounds.context(FatBinaryEntry::from_parts(
+                entry_header,
+                &extra_header,
+                Payload::Owned(payload).into_aligned(),
+                &ParseOptions::default(),
+            ))?);
+            bounds.advance(entry_size);
+        }
+
+        Ok(FatBinary { entries })
+    }
+
+    /// Write fatbinary to async writer
+    pub async fn write_async<W: AsyncWrite + Unpin>(
+        &self,
+        mut writer: W,
+    ) -> Result<(), FatBinaryError> {
+        writer
+            .write_all(
+                &Self::header_of(self.entries.iter().map(|entry| &entry.entry_header)).to_bytes(),
+            )
+            .await?;
+
+        for entry in &self.entries {
+            writer.write_all(&entry.entry_header.to_bytes()).await?;
+
+            if entry.entry_header.header_size > core::mem::size_of::<FatBinaryEntryHeader>() as u32
+            {
+                writer.write_all(&entry.serialize_extra_header()).await?;
+            }
+
+            writer.write_all(&entry.payload).await?;
+        }
+
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+
+    #[tokio::test]
+    async fn async_roundtrip() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        fatbin.entries_mut().push(entry);
+
+        let mut buffer = vec![];
+        fatbin.write_async(&mut buffer).await.unwrap();
+        let mut sync_buffer = vec![];
+        fatbin.write(&mut sync_buffer).unwrap();
+        assert_eq!(buffer, sync_buffer);
+
+        let read = FatBinary::read_async(&buffer[..]).await.unwrap();
+        assert_eq!(read, fatbin);
+    }
+
+    #[tokio::test]
+    async fn async_oversized_header() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, b"\x7fELF".to_vec()));
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        buffer[16 + 4..16 + 8].copy_from_slice(&0xfffffff0u32.to_le_bytes());
+
+        let err = FatBinary::read_async(&buffer[..]).await.unwrap_err();
+        assert!(matches!(
+            err,
+            FatBinaryError::Truncated {
+                entry_index: 0,
+                header_offset: 16,
+                needed: 0xfffffff0..,
+                available: 68,
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn async_truncated() {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80] {
+            let ptx = format!(".version 7.0\n.target sm_{}\n", arch);
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, ptx.into_bytes()));
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        // PTX payloads are 27 bytes
+        let second = 16 + 64 + 27;
+
+        // ends in the payload of the second entry
+        assert!(matches!(
+            FatBinary::read_async(&buffer[..buffer.len() - 4]).await,
+            Err(FatBinaryError::Truncated {
+                entry_index: 1,
+                needed: 91,
+                available: 87,
+                header_offset,
+            }) if header_offset == second as u64
+        ));
+
+        // ends in the header of the second entry
+        assert!(matches!(
+            FatBinary::read_async(&buffer[..second + 10]).await,
+            Err(FatBinaryError::Truncated {
+                entry_index: 1,
+                needed: 64,
+                available: 10,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,359 @@
+//! Heuristics flagging fatbinaries crafted or tampered with
+//!
+//! Unlike [FatBinary::verify], which checks that entries are well-formed,
+//! audit looks for content which loads fine but is unusual for fatbinaries
+//! produced by the CUDA toolchain.
+
+use crate::{
+    try_decompress, EntryKind, FatBinary, FatBinaryEntry, FATBINARY_FLAG_HOST_LINUX,
+    FATBINARY_FLAG_HOST_MAC, FATBINARY_FLAG_HOST_WINDOWS, FATBINARY_FLAG_KNOWN,
+    FATBINARY_FLAG_PRODUCER_CUDA, FATBINARY_FLAG_PRODUCER_OPENCL,
+};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::elf;
+
+/// Category of [AuditFinding]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AuditCategory {
+    /// Ranges in entry header or payload overlapping each other
+    Overlap,
+    /// Another entry of the same kind and arch, which shadows or is
+    /// shadowed by this one
+    Duplicate,
+    /// Entry kind other than PTX or ELF
+    UnknownKind,
+    /// Flags unknown or contradicting each other
+    UnknownFlags,
+    /// Sizes in header not matching the payload
+    SizeMismatch,
+    /// ELF payload for machine other than `EM_CUDA`
+    ForeignMachine,
+    /// Identifier looking like a path outside the build tree
+    SuspiciousIdentifier,
+}
+
+impl core::fmt::Display for AuditCategory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            AuditCategory::Overlap => "overlap",
+            AuditCategory::Duplicate => "duplicate",
+            AuditCategory::UnknownKind => "unknown kind",
+            AuditCategory::UnknownFlags => "unknown flags",
+            AuditCategory::SizeMismatch => "size mismatch",
+            AuditCategory::ForeignMachine => "foreign machine",
+            AuditCategory::SuspiciousIdentifier => "suspicious identifier",
+        })
+    }
+}
+
+/// Anomaly found by [FatBinary::audit]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AuditFinding {
+    /// Index of the entry with the anomaly
+    pub entry_index: usize,
+    pub category: AuditCategory,
+    /// Description of the anomaly
+    pub message: String,
+}
+
+impl core::fmt::Display for AuditFinding {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "entry {}: {}: {}",
+            self.entry_index, self.category, self.message
+        )
+    }
+}
+
+/// Whether identifier points into home or temporary directories, or
+/// escapes the build tree with `..`
+fn suspicious_path(identifier: &str) -> bool {
+    const PREFIXES: [&str; 6] = ["/home/", "/root/", "/Users/", "/tmp/", "/var/tmp/", "~"];
+    let lower = identifier.to_ascii_lowercase();
+    PREFIXES.iter().any(|prefix| identifier.starts_with(prefix))
+        || lower.contains(":\\users\\")
+        || lower.contains("\\temp\\")
+        || identifier
+            .split(['/', '\\'])
+            .any(|component| component == "..")
+}
+
+/// `e_machine` of ELF payload, `None` if too short for the ELF header
+fn elf_machine(payload: &[u8]) -> Option<u16> {
+    let bytes = [*payload.get(18)?, *payload.get(19)?];
+    // e_ident[EI_DATA]
+    Some(match payload.get(5) {
+        Some(&elf::ELFDATA2MSB) => u16::from_be_bytes(bytes),
+        _ => u16::from_le_bytes(bytes),
+    })
+}
+
+impl FatBinaryEntry<'_> {
+    fn audit(&self, res: &mut Vec<(AuditCategory, String)>) {
+        let header = &self.entry_header;
+
+        // ranges of options descriptor and strings in the entry header
+        let mut ranges: Vec<(&str, u64, u64)> = Vec::new();
+        if header.options_offset == 0x40 && header.header_size >= 0x48 {
+            ranges.push(("options descriptor", 0x40, 0x48));
+        }
+        if let Some(ptxas_options) = &self.ptxas_options {
+            let offset = self.ptxas_options_offset as u64;
+            ranges.push(("ptxas options", offset, offset + ptxas_options.len() as u64));
+        }
+        if self.identifier.is_some() {
+            let offset = header.obj_name_offset as u64;
+            ranges.push(("identifier", offset, offset + header.obj_name_len as u64));
+        }
+        for (i, (name, begin, end)) in ranges.iter().enumerate() {
+            for (other, other_begin, other_end) in &ranges[i + 1..] {
+                if begin < other_end && other_begin < end {
+                    res.push((
+                        AuditCategory::Overlap,
+                        format!("{} overlaps {} in entry header", name, other),
+                    ));
+                }
+            }
+        }
+
+        let kind = header.kind;
+        if let EntryKind::Unknown(kind) = EntryKind::from_raw(kind) {
+            res.push((AuditCategory::UnknownKind, format!("kind {:#x}", kind)));
+        }
+
+        let flags = header.flags;
+        if flags & !FATBINARY_FLAG_KNOWN != 0 {
+            res.push((
+                AuditCategory::UnknownFlags,
+                format!("unknown flags {:#x}", flags & !FATBINARY_FLAG_KNOWN),
+            ));
+        }
+        let hosts =
+            FATBINARY_FLAG_HOST_LINUX | FATBINARY_FLAG_HOST_MAC | FATBINARY_FLAG_HOST_WINDOWS;
+        if (flags & hosts).count_ones() > 1 {
+            res.push((
+                AuditCategory::UnknownFlags,
+                format!("multiple host flags {:#x}", flags & hosts),
+            ));
+        }
+        let producers = FATBINARY_FLAG_PRODUCER_CUDA | FATBINARY_FLAG_PRODUCER_OPENCL;
+        if flags & producers == producers {
+            res.push((
+                AuditCategory::UnknownFlags,
+                "both CUDA and OpenCL producer flags".to_string(),
+            ));
+        }
+
+        let size = self.payload.len() as u64;
+        let compressed_size = header.compressed_size as u64;
+        let decompressed_size = header.decompressed_size;
+        let payload = if self.is_compressed() {
+            if compressed_size > size {
+                res.push((
+                    AuditCategory::Overlap,
+                    format!(
+                        "compressed size {} exceeds entry size {}, overlapping the next entry",
+                        compressed_size, size
+                    ),
+                ));
+                return;
+            }
+            match try_decompress(
+                &self.payload[..compressed_size as usize],
+                decompressed_size as usize,
+            ) {
+                Some(payload) if payload.len() as u64 == decompressed_size => Some(payload),
+                Some(payload) => {
+                    res.push((
+                        AuditCategory::SizeMismatch,
+                        format!(
+                            "decompressed to {} bytes instead of {}",
+                            payload.len(),
+                            decompressed_size
+                        ),
+                    ));
+                    Some(payload)
+                }
+                None => {
+                    res.push((
+                        AuditCategory::SizeMismatch,
+                        format!(
+                            "compressed payload does not decompress to {} bytes",
+                            decompressed_size
+                        ),
+                    ));
+                    None
+                }
+            }
+        } else {
+            if compressed_size != 0 {
+                res.push((
+                    AuditCategory::SizeMismatch,
+                    format!(
+                        "compressed size {} without compressed flag",
+                        compressed_size
+                    ),
+                ));
+            }
+            None
+        };
+
+        if self.kind() == EntryKind::Elf {
+            let payload = payload.as_deref().unwrap_or(&self.payload);
+            match elf_machine(payload) {
+                Some(elf::EM_CUDA) => {}
+                Some(machine) => res.push((
+                    AuditCategory::ForeignMachine,
+                    format!("ELF machine {} instead of EM_CUDA", machine),
+                )),
+                None => res.push((
+                    AuditCategory::ForeignMachine,
+                    "ELF payload too short for ELF header".to_string(),
+                )),
+            }
+        }
+
+        if let Some(identifier) = self.get_identifier_lossy() {
+            let identifier = identifier.trim_end_matches('\0');
+            if identifier.chars().any(char::is_control) {
+                res.push((
+                    AuditCategory::SuspiciousIdentifier,
+                    format!("identifier {:?} contains control characters", identifier),
+                ));
+            } else if suspicious_path(identifier) {
+                res.push((
+                    AuditCategory::SuspiciousIdentifier,
+                    format!(
+                        "identifier {:?} looks like a path outside the build tree",
+                        identifier
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+impl FatBinary<'_> {
+    /// Flag anomalies of entries which are unusual for fatbinaries produced
+    /// by the CUDA toolchain: overlapping ranges, duplicate archs, unknown
+    /// kinds and flags, mismatched sizes, ELF for machines other than
+    /// `EM_CUDA` and identifiers pointing outside the build tree
+    pub fn audit(&self) -> Vec<AuditFinding> {
+        let mut res = Vec::new();
+        for (entry_index, entry) in self.entries.iter().enumerate() {
+            let mut findings = Vec::new();
+            entry.audit(&mut findings);
+            if let Some(first) = self.entries[..entry_index].iter().position(|other| {
+                other.kind() == entry.kind() && other.get_sm_arch() == entry.get_sm_arch()
+            }) {
+                findings.push((
+                    AuditCategory::Duplicate,
+                    format!(
+                        "same kind and arch sm_{} as entry {}",
+                        entry.get_sm_arch(),
+                        first
+                    ),
+                ));
+            }
+            res.extend(
+                findings
+                    .into_iter()
+                    .map(|(category, message)| AuditFinding {
+                        entry_index,
+                        category,
+                        message,
+                    }),
+            );
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::suspicious_path;
+    use crate::{AuditCategory, FatBinary, FatBinaryEntry};
+
+    /// Minimal 64-bit little endian ELF header for `machine`
+    fn elf(machine: u16) -> Vec<u8> {
+        let mut res = vec![0u8; 64];
+        res[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        res[18..20].copy_from_slice(&machine.to_le_bytes());
+        res
+    }
+
+    #[test]
+    fn paths() {
+        assert!(suspicious_path("/home/user/kernels.cu"));
+        assert!(suspicious_path("../../outside/kernels.cu"));
+        assert!(suspicious_path("C:\\Users\\user\\kernels.cu"));
+        assert!(suspicious_path("/tmp/tmpxft_0000_kernels.cu"));
+        assert!(!suspicious_path("kernels.cu"));
+        assert!(!suspicious_path("src/kernels/axpy.cu"));
+    }
+
+    #[test]
+    fn audit() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(80, elf(190));
+        entry.set_identifier(Some("src/axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            70,
+            ".version 7.0\n.target sm_70\n".as_bytes(),
+        ));
+        assert_eq!(fatbin.audit(), vec![]);
+
+        // x86-64 ELF with unknown flags, shadowing the first entry
+        let mut entry = FatBinaryEntry::new_auto(80, elf(62));
+        entry.set_flags(entry.get_flags() | 0x10000 | 0x10 | 0x20);
+        entry.set_identifier(Some("/home/user/../evil.cu"));
+        fatbin.entries_mut().push(entry);
+        let categories: Vec<_> = fatbin
+            .audit()
+            .iter()
+            .map(|finding| (finding.entry_index, finding.category))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                (2, AuditCategory::UnknownFlags),
+                (2, AuditCategory::UnknownFlags),
+                (2, AuditCategory::ForeignMachine),
+                (2, AuditCategory::SuspiciousIdentifier),
+                (2, AuditCategory::Duplicate),
+            ]
+        );
+        assert_eq!(
+            fatbin.audit()[4].to_string(),
+            "entry 2: duplicate: same kind and arch sm_80 as entry 0"
+        );
+
+        // identifier overlapping ptxas options
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".target sm_70\n".as_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_identifier(Some("a.cu"));
+        entry.entry_header.obj_name_offset = entry.ptxas_options_offset;
+        fatbin.entries_mut().push(entry);
+        let findings = fatbin.audit();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].message,
+            "ptxas options overlaps identifier in entry header"
+        );
+
+        // decompressed size in header not matching the payload
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".target sm_70\n".repeat(16).into_bytes());
+        assert!(entry.compress());
+        entry.entry_header.decompressed_size += 8;
+        fatbin.entries_mut().push(entry);
+        let findings = fatbin.audit();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].category, AuditCategory::SizeMismatch);
+    }
+}
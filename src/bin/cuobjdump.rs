@@ -1,116 +1,604 @@
 use clap::Parser;
-use fatbinary::FatBinary;
+use fatbinary::{
+    CubinHeader, EntryFileName, EntryInfo, EntryKind, FatBinary, FatBinaryEntryHeader,
+    NameTemplate, SmArch, AR_MAGIC, ELFOSABI_CUDA,
+};
 use std::{
-    ffi::OsString,
     fs::File,
-    io::{Seek, Write},
-    path::PathBuf,
+    io::{BufRead, BufWriter, Read, Seek, SeekFrom, StdinLock},
+    path::{Path, PathBuf},
 };
 
 #[derive(Parser)]
 struct Cli {
-    /// Extract ptx code
+    /// Extract ptx code, with stored ptxas options written to .ptxas_options files
     #[arg(long = "extract-ptx")]
     ptx: Option<String>,
 
+    /// List cubins as `ELF file N: NAME` like NVIDIA's `-lelf`, with names
+    /// from --name-template and stems from entry identifiers
+    #[arg(long = "list-elf", conflicts_with_all = ["ptx", "grep", "extract_entry", "hexdump", "sbom", "verbose"])]
+    list_elf: bool,
+
+    /// Search PTX lines, cubin symbols and cubin strings of all entries for
+    /// PATTERN, entries of concatenated fatbins are numbered together
+    #[arg(long, value_name = "PATTERN")]
+    grep: Option<String>,
+
+    /// Extract decompressed payload of entry of any kind, index starts from 0
+    /// and counts entries of concatenated fatbins together
+    #[arg(long = "extract-entry", value_name = "N")]
+    extract_entry: Option<usize>,
+
+    /// Output file of --extract-entry, defaults to a name from --name-template
+    #[arg(short = 'o', long, requires = "extract_entry")]
+    output: Option<PathBuf>,
+
+    /// Output file names of --extract-ptx and --extract-entry, with
+    /// placeholders {stem}, {index}, {arch}, {sm} and {ext}
+    #[arg(long, value_name = "TEMPLATE", default_value = NameTemplate::DEFAULT)]
+    name_template: String,
+
+    /// Hexdump payload of entry, index starts from 0 and counts entries of
+    /// concatenated fatbins together
+    #[arg(long, value_name = "N")]
+    hexdump: Option<usize>,
+
+    /// Hexdump decompressed payload instead of the stored one
+    #[arg(long, requires = "hexdump")]
+    decompressed: bool,
+
+    /// Print SASS address to source line mappings of `.debug_line` of
+    /// cubins with debug info
+    #[arg(long, conflicts_with_all = ["ptx", "grep", "extract_entry", "hexdump", "sbom", "list_elf"])]
+    dump_line_info: bool,
+
+    /// Print reproducible JSON listing with SHA-256 of payloads for SBOMs,
+    /// entries of concatenated fatbins are listed together
+    #[arg(long)]
+    sbom: bool,
+
     /// Enable verbose message
     #[arg(long)]
     verbose: bool,
 
-    /// Fatbin file
+    /// Match listing and extracted files of NVIDIA's cuobjdump from CUDA 12
+    /// exactly: no producer line, no .ptxas_options files, PTX numbered
+    /// across concatenated fatbins and filtered by the --extract-ptx name
+    #[arg(long, conflicts_with_all = ["grep", "extract_entry", "hexdump", "sbom", "verbose", "name_template", "dump_line_info"])]
+    nv_compat: bool,
+
+    /// Fatbin file or static library of host objects, `-` for standard input
     fatbin: PathBuf,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
-    let mut file = File::open(&args.fatbin)?;
+/// Input fatbin file, or standard input which cannot seek
+enum Input {
+    File(File),
+    Stdin(StdinLock<'static>),
+}
 
-    if args.ptx.is_some() {
-        let fatbinary = FatBinary::read(file)?;
-        let mut i = 1;
-        let file_name = args
-            .fatbin
+impl Input {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        if path == Path::new("-") {
+            Ok(Input::Stdin(std::io::stdin().lock()))
+        } else {
+            Ok(Input::File(File::open(path)?))
+        }
+    }
+
+    /// File to seek in, `None` for standard input
+    fn file(&mut self) -> Option<&mut File> {
+        match self {
+            Input::File(file) => Some(file),
+            Input::Stdin(_) => None,
+        }
+    }
+
+    /// Whether another fatbin follows, e.g. in concatenated fatbin files
+    fn has_more(&mut self) -> anyhow::Result<bool> {
+        Ok(match self {
+            Input::File(file) => file.stream_position()? < file.metadata()?.len(),
+            Input::Stdin(stdin) => !stdin.fill_buf()?.is_empty(),
+        })
+    }
+
+    /// Whether the input is an `ar` archive like a static library
+    fn is_archive(&mut self) -> anyhow::Result<bool> {
+        Ok(match self {
+            Input::File(file) => {
+                let mut magic = vec![];
+                file.take(AR_MAGIC.len() as u64).read_to_end(&mut magic)?;
+                file.rewind()?;
+                magic == AR_MAGIC
+            }
+            Input::Stdin(stdin) => stdin.fill_buf()?.starts_with(AR_MAGIC),
+        })
+    }
+
+    /// Read the rest of the input
+    fn read_all(&mut self) -> anyhow::Result<Vec<u8>> {
+        let mut res = vec![];
+        match self {
+            Input::File(file) => file.read_to_end(&mut res)?,
+            Input::Stdin(stdin) => stdin.read_to_end(&mut res)?,
+        };
+        Ok(res)
+    }
+
+    /// Read the next fatbin
+    fn read(&mut self) -> anyhow::Result<FatBinary<'static>> {
+        Ok(match self {
+            Input::File(file) => FatBinary::read(file)?,
+            Input::Stdin(stdin) => FatBinary::read_stream(stdin)?,
+        })
+    }
+
+    /// Read the remaining fatbins, e.g. concatenated in `.nv_fatbin` dumps
+    fn read_remaining(&mut self) -> anyhow::Result<Vec<FatBinary<'static>>> {
+        let mut res = vec![];
+        while self.has_more()? {
+            res.push(self.read()?);
+        }
+        Ok(res)
+    }
+}
+
+/// Print offset, hex and ASCII columns of 16 bytes per line, like `hexdump -C`
+fn hexdump(data: &[u8]) {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (i, byte) in chunk.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex += &format!(" {:02x}", byte);
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!("{:08x} {:<49}  |{}|", line * 16, hex, ascii);
+    }
+    println!("{:08x}", data.len());
+}
+
+/// Print decoded ELF header of cubin, as in verbose listing
+fn print_cubin_header(cubin: &CubinHeader) {
+    println!(
+        "elf class = {}",
+        if cubin.is_64bit { "ELF64" } else { "ELF32" }
+    );
+    println!(
+        "elf machine = {}",
+        if cubin.machine == object::elf::EM_CUDA {
+            "EM_CUDA".to_string()
+        } else {
+            cubin.machine.to_string()
+        }
+    );
+    println!(
+        "elf os abi = {:#x}{}",
+        cubin.os_abi,
+        if cubin.os_abi == ELFOSABI_CUDA {
+            " (CUDA)"
+        } else {
+            ""
+        }
+    );
+    println!("cubin ABI version = {}", cubin.abi_version);
+    print!("elf flags = {:#x} ({}", cubin.flags, cubin.sm_arch());
+    if let Some(virtual_arch) = cubin.virtual_arch() {
+        print!(", virtual compute_{}", virtual_arch.0);
+    }
+    if cubin.is_arch_specific() {
+        print!(", arch-specific");
+    }
+    println!(")");
+}
+
+/// Print listing of entry, with internal headers if given as in verbose
+/// listing
+fn print_entry(
+    info: &EntryInfo,
+    header: Option<(FatBinaryEntryHeader, Option<CubinHeader>)>,
+    nv_compat: bool,
+) {
+    println!();
+    println!(
+        "Fatbin {} code:",
+        if info.kind == EntryKind::Elf {
+            "elf"
+        } else {
+            "ptx"
+        }
+    );
+    println!("================");
+    println!("arch = sm_{}", info.arch.0);
+    println!(
+        "code version = [{},{}]",
+        info.version_major, info.version_minor
+    );
+    // NVIDIA's tool stopped printing the producer in CUDA 11
+    if !nv_compat {
+        println!(
+            "producer = {}",
+            match info.producer {
+                fatbinary::Producer::CUDA => "cuda",
+                fatbinary::Producer::OpenCL => "opencl",
+                fatbinary::Producer::Unknown => "<unknown>",
+            }
+        );
+    }
+    println!(
+        "host = {}",
+        match info.host {
+            fatbinary::Host::Linux => "linux",
+            fatbinary::Host::Mac => "mac",
+            fatbinary::Host::Windows => "windows",
+            fatbinary::Host::Unknown => "unknown",
+        },
+    );
+    println!(
+        "compile_size = {}",
+        if info.is_64bit { "64bit" } else { "32bit" }
+    );
+
+    if info.has_debug_info {
+        println!("has debug info");
+    }
+
+    if info.is_compressed {
+        println!("compressed");
+    }
+
+    if let Some(identifier) = &info.identifier {
+        println!("identifier = {}", identifier);
+    }
+
+    if let Some(ptxas_options) = &info.ptxas_options {
+        println!("ptxasOptions = {}", ptxas_options);
+    }
+
+    if let Some((header, cubin_header)) = header {
+        if let Some(cubin) = cubin_header {
+            print_cubin_header(&cubin);
+        }
+        println!("internal: {:#x?}", header);
+    }
+}
+
+/// Print `ELF file N: NAME` for cubins numbered from `i`, like NVIDIA's
+/// tool. The stem of names is that of the identifier of the entry, or
+/// `stem` without identifier.
+fn list_elf(entries: &[EntryInfo], template: &NameTemplate, stem: &str, i: &mut usize) {
+    for info in entries.iter().filter(|info| info.kind == EntryKind::Elf) {
+        let identifier = info
+            .identifier
+            .as_deref()
+            .map(|identifier| identifier.trim_end_matches('\0'))
+            .and_then(|identifier| Path::new(identifier).file_stem());
+        let stem = identifier.map_or(stem.into(), |identifier| identifier.to_string_lossy());
+        let name = EntryFileName::new(&stem, *i, EntryKind::Elf, info.arch);
+        println!("ELF file {:4}: {}", i, template.render(&name));
+        *i += 1;
+    }
+}
+
+/// Extract PTX entries of `fatbinary` numbered from `i`, whose file names
+/// contain `filter` with --nv-compat
+fn extract_ptx(
+    args: &Cli,
+    fatbinary: &FatBinary,
+    template: &NameTemplate,
+    stem: &str,
+    filter: &str,
+    i: &mut usize,
+) -> anyhow::Result<()> {
+    for entry in fatbinary.entries() {
+        if entry.contains_elf() {
+            continue;
+        }
+
+        // PTX entries are numbered from 1, like NVIDIA's tool
+        let name = EntryFileName::new(stem, *i, EntryKind::Ptx, SmArch(entry.get_sm_arch()));
+        let output_file_name = template.render(&name);
+        // NVIDIA's tool extracts files whose name contains the argument
+        if args.nv_compat && filter != "all" && !output_file_name.contains(filter) {
+            *i += 1;
+            continue;
+        }
+        // stored options follow -arch in the banner, like NVIDIA's tool
+        let ptxas_options = entry
+            .get_ptxas_options_lossy()
+            .map(|options| {
+                options
+                    .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                    .to_string()
+            })
+            .filter(|options| !options.is_empty());
+        println!(
+            "Extracting PTX file and ptxas options {:4}: {} -arch=sm_{}{}",
+            i,
+            output_file_name,
+            entry.get_sm_arch(),
+            ptxas_options
+                .as_deref()
+                .map(|options| format!(" {}", options))
+                .unwrap_or_default()
+        );
+
+        entry.copy_payload_to(BufWriter::new(File::create(&output_file_name)?))?;
+
+        // companion file for scripts re-running ptxas with the original flags
+        if let Some(ptxas_options) = ptxas_options.filter(|_| !args.nv_compat) {
+            let mut options_file_name = output_file_name;
+            options_file_name.push_str(".ptxas_options");
+            std::fs::write(options_file_name, format!("{}\n", ptxas_options))?;
+        }
+
+        *i += 1;
+    }
+    Ok(())
+}
+
+/// List entries or cubins, or extract PTX of fatbins in members of an `ar` archive like a
+/// static library, each member prefixed by `member {archive}:{member}:` like
+/// NVIDIA's tool
+fn dump_archive(args: &Cli, template: &NameTemplate, data: &[u8]) -> anyhow::Result<()> {
+    if args.grep.is_some()
+        || args.extract_entry.is_some()
+        || args.hexdump.is_some()
+        || args.sbom
+        || args.dump_line_info
+    {
+        anyhow::bail!("Only listing, --list-elf and --extract-ptx support archives");
+    }
+    let archive = args
+        .fatbin
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let mut i = 1;
+    for member in FatBinary::scan_archive(data)? {
+        println!("member {}:{}:", archive, member.name);
+        let stem = Path::new(&member.name)
             .file_stem()
-            .map(OsString::from)
-            .unwrap_or(OsString::new());
-        for entry in fatbinary.entries() {
-            if entry.contains_elf() {
+            .unwrap_or_default()
+            .to_string_lossy();
+        for range in member.fatbinaries {
+            let fatbinary = FatBinary::parse(&data[range])?;
+            if let Some(filter) = &args.ptx {
+                extract_ptx(args, &fatbinary, template, &stem, filter, &mut i)?;
                 continue;
             }
+            if args.list_elf {
+                let entries: Vec<_> = fatbinary
+                    .entries()
+                    .iter()
+                    .map(|entry| entry.info())
+                    .collect();
+                list_elf(&entries, template, &stem, &mut i);
+                continue;
+            }
+            for entry in fatbinary.entries() {
+                let header = args
+                    .verbose
+                    .then(|| (*entry.get_header(), entry.cubin_header()));
+                print_entry(&entry.info(), header, args.nv_compat);
+            }
+        }
+    }
+    Ok(())
+}
 
-            let suffix = format!(".{}.sm_{}.ptx", i, entry.get_sm_arch());
-            let mut output_file_name = file_name.clone();
-            output_file_name.push(suffix);
-            println!(
-                "Extracting PTX file and ptxas options {:4}: {} -arch=sm_{}",
-                i,
-                output_file_name.to_string_lossy(),
-                entry.get_sm_arch()
-            );
+fn main() -> anyhow::Result<()> {
+    // accept -lelf of NVIDIA's cuobjdump, which clap cannot declare
+    let args = Cli::parse_from(std::env::args_os().map(|arg| match arg.to_str() {
+        Some("-lelf") => "--list-elf".into(),
+        _ => arg,
+    }));
+    let mut input = Input::open(&args.fatbin)?;
+    let template: NameTemplate = args.name_template.parse()?;
+    let stem = args
+        .fatbin
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
 
-            let mut output_file = File::create(output_file_name)?;
-            output_file.write_all(&entry.get_decompressed_payload())?;
+    if input.is_archive()? {
+        return dump_archive(&args, &template, &input.read_all()?);
+    }
 
-            i += 1;
+    if args.list_elf {
+        // ELF files are numbered across concatenated fatbins
+        let mut i = 1;
+        while input.has_more()? {
+            let entries = match input.file() {
+                Some(file) => FatBinary::read_metadata(file)?,
+                None => input
+                    .read()?
+                    .entries()
+                    .iter()
+                    .map(|entry| entry.info())
+                    .collect(),
+            };
+            list_elf(&entries, &template, &stem, &mut i);
         }
         return Ok(());
     }
 
-    // support concatenated fatbinary file (e.g. objcopy-ed from .nv_fatbin section)
-    let file_size = file.metadata()?.len();
-    while file.stream_position()? < file_size {
-        let fatbinary = FatBinary::read(&mut file)?;
-        for entry in fatbinary.entries() {
-            println!();
-            println!(
-                "Fatbin {} code:",
-                if entry.contains_elf() { "elf" } else { "ptx" }
-            );
-            println!("================");
-            println!("arch = sm_{}", entry.get_sm_arch());
-            println!(
-                "code version = [{},{}]",
-                entry.get_version_major(),
-                entry.get_version_minor()
-            );
-            println!(
-                "producer = {}",
-                match entry.producer() {
-                    fatbinary::Producer::CUDA => "cuda",
-                    fatbinary::Producer::OpenCL => "opencl",
-                    fatbinary::Producer::Unknown => "<unknown>",
+    if args.sbom {
+        let mut fatbinary = FatBinary::new();
+        while input.has_more()? {
+            fatbinary.merge(input.read()?, false);
+        }
+        print!("{}", fatbinary.sbom_listing());
+        return Ok(());
+    }
+
+    if let Some(pattern) = &args.grep {
+        let mut found = false;
+        // entries are numbered across fatbins
+        let mut first = 0;
+        for (index, fatbinary) in input.read_remaining()?.iter().enumerate() {
+            for m in fatbinary.grep(pattern.as_bytes()) {
+                println!(
+                    "entry {} (fatbin {}, {} {}) {} offset {:#x}: {}",
+                    first + m.entry_index,
+                    index,
+                    m.arch,
+                    m.kind,
+                    m.location,
+                    m.offset,
+                    m.text.escape_debug()
+                );
+                found = true;
+            }
+            first += fatbinary.entries().len();
+        }
+        // exit status of grep
+        if !found {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(index) = args.extract_entry {
+        // find the fatbin holding the entry, only reading metadata of files
+        let mut first = 0;
+        let (entry, start, fatbinary) = loop {
+            if !input.has_more()? {
+                anyhow::bail!("Entry {} does not exist", index);
+            }
+            let (entries, start, fatbinary) = match input.file() {
+                Some(file) => {
+                    let start = file.stream_position()?;
+                    (FatBinary::read_metadata(file)?, start, None)
+                }
+                None => {
+                    let fatbinary = input.read()?;
+                    let entries = fatbinary.entries().iter().map(|entry| entry.info());
+                    (entries.collect(), 0, Some(fatbinary))
                 }
-            );
-            println!(
-                "host = {}",
-                match entry.host() {
-                    fatbinary::Host::Linux => "linux",
-                    fatbinary::Host::Mac => "mac",
-                    fatbinary::Host::Windows => "windows",
-                    fatbinary::Host::Unknown => "unknown",
-                },
-            );
-            println!(
-                "compile_size = {}",
-                if entry.is_64bit() { "64bit" } else { "32bit" }
-            );
-
-            if entry.has_debug_info() {
-                println!("has debug info");
+            };
+            if let Some(entry) = entries.get(index - first) {
+                break (entry.clone(), start, fatbinary);
             }
+            first += entries.len();
+        };
+        let output_file_name = args.output.unwrap_or_else(|| {
+            let name = EntryFileName::new(&stem, index, entry.kind, entry.arch);
+            template.render(&name).into()
+        });
+        println!(
+            "Extracting entry {:4}: {}",
+            index,
+            output_file_name.to_string_lossy()
+        );
+        let output_file = BufWriter::new(File::create(output_file_name)?);
+        // stream the payload from files, entries may be too large to load
+        if let Some(fatbinary) = fatbinary {
+            fatbinary.entries()[index - first].copy_payload_to(output_file)?;
+        } else if let Some(file) = input.file() {
+            file.seek(SeekFrom::Start(start))?;
+            FatBinary::copy_entry_payload(file, index - first, output_file)?;
+        }
+        return Ok(());
+    }
 
-            if entry.is_compressed() {
-                println!("compressed");
+    if args.dump_line_info {
+        let fatbinaries = input.read_remaining()?;
+        let entries = fatbinaries.iter().flat_map(|fatbinary| fatbinary.entries());
+        for (index, entry) in entries.enumerate() {
+            if !entry.contains_elf() || !entry.has_debug_info() {
+                continue;
+            }
+            println!("entry {} (sm_{}):", index, entry.get_sm_arch());
+            let rows = entry.line_info()?;
+            if rows.is_empty() {
+                println!("  no line info");
             }
+            for row in rows {
+                println!(
+                    "  {:#010x} {}:{}:{}",
+                    row.address, row.file, row.line, row.column
+                );
+            }
+        }
+        return Ok(());
+    }
 
-            if let Some(ptxas_options) = entry.get_ptxas_options() {
-                println!("ptxasOptions = {}", ptxas_options);
+    if let Some(index) = args.hexdump {
+        let fatbinaries = input.read_remaining()?;
+        let mut entries = fatbinaries.iter().flat_map(|fatbinary| fatbinary.entries());
+        let Some(entry) = entries.nth(index) else {
+            anyhow::bail!("Entry {} does not exist", index);
+        };
+        let payload = if args.decompressed {
+            entry.get_decompressed_payload()
+        } else {
+            entry.get_payload().into()
+        };
+        println!(
+            "entry {}: {} sm_{}, {} bytes{}",
+            index,
+            if entry.contains_elf() { "elf" } else { "ptx" },
+            entry.get_sm_arch(),
+            payload.len(),
+            match (entry.is_compressed(), args.decompressed) {
+                (true, true) => " decompressed",
+                (true, false) => " compressed",
+                (false, _) => "",
             }
+        );
+        hexdump(&payload);
+        return Ok(());
+    }
 
-            if args.verbose {
-                println!("internal: {:#x?}", entry.get_header());
+    if let Some(filter) = &args.ptx {
+        let mut i = 1;
+        loop {
+            let fatbinary = input.read()?;
+            extract_ptx(&args, &fatbinary, &template, &stem, filter, &mut i)?;
+            // NVIDIA's tool numbers PTX across concatenated fatbins
+            if !args.nv_compat || !input.has_more()? {
+                break;
             }
         }
+        return Ok(());
+    }
+
+    // support concatenated fatbinary file (e.g. objcopy-ed from .nv_fatbin section)
+    while input.has_more()? {
+        // only read payloads when headers are printed or input cannot seek
+        let entries = match input.file() {
+            Some(file) if !args.verbose => FatBinary::read_metadata(file)?
+                .into_iter()
+                .map(|info| (info, None))
+                .collect::<Vec<_>>(),
+            _ => input
+                .read()?
+                .entries()
+                .iter()
+                .map(|entry| {
+                    (
+                        entry.info(),
+                        args.verbose
+                            .then(|| (*entry.get_header(), entry.cubin_header())),
+                    )
+                })
+                .collect(),
+        };
+        for (info, header) in entries {
+            print_entry(&info, header, args.nv_compat);
+        }
     }
     Ok(())
 }
@@ -1,52 +1,1205 @@
-use clap::Parser;
-use fatbinary::{FatBinary, FatBinaryEntry};
-use std::{fs::File, io::Read, path::PathBuf};
+use clap::{Parser, Subcommand, ValueEnum};
+use fatbinary::digest::{DigestManifest, Sha256Digest};
+use fatbinary::{
+    CubinHeader, DriverSupport, EntryKind, FatBinary, FatBinaryEntry, Host, ParseOptions, Producer,
+    SmArch,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Create fatbin
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Create fatbin, `-` writes to stdout
     #[arg(long = "create")]
     fatbin: Option<PathBuf>,
 
-    /// Image source
+    /// Image source in the form of profile={sm,compute}_{sm_arch},file={file},
+    /// `file=-` reads from stdin. `profile=all` or `profile=all-major`
+    /// embeds PTX for each known arch like nvcc `-arch=all`. An optional
+    /// `ident={identifier}` sets the identifier of the entries.
     #[arg(long = "image")]
     images: Vec<String>,
+
+    /// Identifier of images and manifest entries without their own, e.g.
+    /// the source file name
+    #[arg(long = "ident")]
+    ident: Option<String>,
+
+    /// Host OS recorded in images, defaults to the current platform. Also
+    /// applies to manifest entries without their own.
+    #[arg(long = "host-os", value_enum)]
+    host_os: Option<HostOs>,
+
+    /// Producer recorded in images, defaults to cuda. Also applies to
+    /// manifest entries without their own.
+    #[arg(long = "producer", value_enum)]
+    producer: Option<ProducerArg>,
+
+    /// Mark images as having debug info like nvcc -g, fails if a cubin
+    /// has no DWARF sections
+    #[arg(short = 'g', long = "debug")]
+    debug: bool,
+
+    /// Create 32-bit images, `-32` is accepted too. Defaults to the ELF
+    /// class of cubins and 64-bit for PTX.
+    #[arg(long = "32", conflicts_with = "is_64bit")]
+    is_32bit: bool,
+
+    /// Create 64-bit images, `-64` is accepted too
+    #[arg(long = "64")]
+    is_64bit: bool,
+
+    /// Read entries from a JSON or YAML manifest like those written by
+    /// `FatBinary::to_manifest`, payload files are relative to it
+    #[arg(long = "from-manifest")]
+    manifest: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Modify entries of an existing fatbin
+    Edit {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Remove entries of sm_{sm_arch} or compute_{sm_arch}
+        #[arg(long = "remove-arch")]
+        remove_archs: Vec<String>,
+
+        /// Add image in the same format as --image
+        #[arg(long = "add")]
+        add_images: Vec<String>,
+
+        /// Set identifier of entry in the form of {index}={identifier}, index starts from 0
+        #[arg(long = "set-ident")]
+        set_idents: Vec<String>,
+
+        /// Remove identifiers and ptxas options of all entries, before --set-ident
+        #[arg(long = "strip-identifiers")]
+        strip_identifiers: bool,
+
+        /// Output fatbin, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Set or clear flags in header of an entry
+    SetFlag {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Index of entry, starts from 0
+        index: usize,
+
+        /// 64bit, debug, cuda, opencl, linux, mac, windows, compressed or raw
+        /// mask like 0x100, payloads are kept as is
+        #[arg(required = true)]
+        flags: Vec<String>,
+
+        /// Clear the flags instead of setting them
+        #[arg(long)]
+        clear: bool,
+
+        /// Output fatbin, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Set arch in header of an entry
+    SetArch {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Index of entry, starts from 0
+        index: usize,
+
+        /// sm_{sm_arch}, compute_{sm_arch} or {sm_arch}
+        arch: String,
+
+        /// Output fatbin, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Set code version in header of an entry
+    SetVersion {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Index of entry, starts from 0
+        index: usize,
+
+        /// {major}.{minor}
+        version: String,
+
+        /// Output fatbin, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Compress every entry if it makes it smaller
+    Compress {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Output fatbin, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Decompress every entry
+    Decompress {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Output fatbin, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Merge entries of fatbins into one fatbin
+    Merge {
+        /// Input fatbins, each may contain concatenated fatbins
+        #[arg(required = true)]
+        fatbins: Vec<PathBuf>,
+
+        /// Skip entries with the same kind, arch and payload as an earlier entry
+        #[arg(long)]
+        dedupe: bool,
+
+        /// Output fatbin
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+    },
+
+    /// Split fatbin into one fatbin per arch or per major arch
+    Split {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Group entries by arch into sm_XX.fatbin, or by major arch into sm_Xx.fatbin
+        #[arg(long, value_enum, default_value_t = SplitBy::Arch)]
+        by: SplitBy,
+
+        /// Output directory, created if missing
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+    },
+
+    /// List entries with sizes, padding and compression ratios
+    Ls {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = LsFormat::Table)]
+        format: LsFormat,
+    },
+
+    /// Replace entries of fatbins in .nv_fatbin section of a host ELF, e.g. a
+    /// shared library, in place without changing its layout
+    PatchLib {
+        /// Host ELF
+        library: PathBuf,
+
+        /// Replace entries of the same arch and kind in the form of
+        /// sm_{sm_arch}={file}, fails if a fatbin does not fit anymore
+        #[arg(long = "replace-entry", required = true)]
+        replacements: Vec<String>,
+
+        /// Output host ELF, overwrite input if omitted
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare two fatbins entry by entry, exit with 1 if they differ
+    Diff {
+        /// Old fatbin
+        old: PathBuf,
+
+        /// New fatbin
+        new: PathBuf,
+    },
+
+    /// Validate structure of fatbin, exit with 1 if any issue is found
+    Verify {
+        /// Fatbin file, may contain concatenated fatbins
+        fatbin: PathBuf,
+    },
+
+    /// Flag anomalies unusual for fatbins produced by the CUDA toolchain, exit
+    /// with 1 if any is found
+    Audit {
+        /// Fatbin file, may contain concatenated fatbins, or a host binary
+        /// or archive of host objects with `.nv_fatbin` sections
+        fatbin: PathBuf,
+    },
+
+    /// Check whether a driver of the given CUDA version loads every cubin,
+    /// exit with 1 if any is too new for it
+    DriverCheck {
+        /// Fatbin file, may contain concatenated fatbins, or a host binary
+        /// or archive of host objects with `.nv_fatbin` sections
+        fatbin: PathBuf,
+
+        /// CUDA version of the driver in the form of MAJOR.MINOR, e.g. 12.4
+        #[arg(long)]
+        driver: String,
+    },
+
+    /// Print human-readable summary of fatbin
+    Report {
+        /// Input fatbin
+        fatbin: PathBuf,
+    },
+
+    /// Print SHA-256 digests of decompressed payloads, or check them against
+    /// a digest manifest and exit with 1 if they differ
+    Digest {
+        /// Input fatbin
+        fatbin: PathBuf,
+
+        /// Digest manifest to check against
+        #[arg(long = "verify")]
+        manifest: Option<PathBuf>,
+    },
+}
+
+/// Grouping of entries in split command
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SplitBy {
+    Arch,
+    Major,
+}
+
+/// Host OS of created entries
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum HostOs {
+    Linux,
+    Mac,
+    Windows,
+}
+
+impl From<HostOs> for Host {
+    fn from(host: HostOs) -> Self {
+        match host {
+            HostOs::Linux => Host::Linux,
+            HostOs::Mac => Host::Mac,
+            HostOs::Windows => Host::Windows,
+        }
+    }
+}
+
+/// Producer of created entries
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ProducerArg {
+    Cuda,
+    Opencl,
+}
+
+impl From<ProducerArg> for Producer {
+    fn from(producer: ProducerArg) -> Self {
+        match producer {
+            ProducerArg::Cuda => Producer::CUDA,
+            ProducerArg::Opencl => Producer::OpenCL,
+        }
+    }
+}
+
+/// Host OS nvcc records when running on the current platform
+const DEFAULT_HOST: Host = if cfg!(target_os = "linux") {
+    Host::Linux
+} else if cfg!(target_os = "macos") {
+    Host::Mac
+} else if cfg!(target_os = "windows") {
+    Host::Windows
+} else {
+    Host::Unknown
+};
+
+/// Provenance recorded in headers of entries created from images
+struct Provenance<'a> {
+    ident: Option<&'a str>,
+    host: Host,
+    producer: Producer,
+    debug: bool,
+    /// Address size, `None` to follow the ELF class of cubins
+    is_64bit: Option<bool>,
+}
+
+impl Default for Provenance<'_> {
+    fn default() -> Self {
+        Provenance {
+            ident: None,
+            host: DEFAULT_HOST,
+            producer: Producer::CUDA,
+            debug: false,
+            is_64bit: None,
+        }
+    }
+}
+
+/// Output format of ls command
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LsFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+/// Row of ls command
+#[derive(Serialize, Debug)]
+struct LsRow {
+    /// Entry index, `None` for the totals row
+    index: Option<usize>,
+    kind: String,
+    arch: String,
+    version: String,
+    flags: String,
+    stored_size: u64,
+    decompressed_size: u64,
+    /// Bytes stored after the compressed payload
+    padding: u64,
+    ratio: f64,
+    identifier: String,
+}
+
+impl LsRow {
+    fn new(index: Option<usize>, stored_size: u64, decompressed_size: u64, padding: u64) -> Self {
+        LsRow {
+            index,
+            kind: String::new(),
+            arch: String::new(),
+            version: String::new(),
+            flags: String::new(),
+            stored_size,
+            decompressed_size,
+            padding,
+            ratio: if stored_size == 0 {
+                0.0
+            } else {
+                decompressed_size as f64 / stored_size as f64
+            },
+            identifier: String::new(),
+        }
+    }
+}
+
+fn read_payload(file_name: &Path, stdin_used: &mut bool) -> anyhow::Result<Vec<u8>> {
+    let mut payload = vec![];
+    if file_name.as_os_str() == "-" {
+        // stdin can only be consumed once
+        if *stdin_used {
+            anyhow::bail!("file=- can only be used by one image");
+        }
+        *stdin_used = true;
+        std::io::stdin().lock().read_to_end(&mut payload)?;
+    } else {
+        File::open(file_name)?.read_to_end(&mut payload)?;
+    }
+    Ok(payload)
+}
+
+/// Address size of entries from `source`: that of --32/--64, which must
+/// match the ELF class of cubins, or else the ELF class, `None` if neither
+/// is known
+fn address_size(
+    provenance: &Provenance,
+    elf_class: Option<bool>,
+    source: &str,
+) -> anyhow::Result<Option<bool>> {
+    match (provenance.is_64bit, elf_class) {
+        (Some(is_64bit), Some(elf_class)) if is_64bit != elf_class => {
+            anyhow::bail!("ELF class of cubin differs from --32/--64: {}", source)
+        }
+        (is_64bit, elf_class) => Ok(is_64bit.or(elf_class)),
+    }
+}
+
+/// Create entries from image spec: profile=sm/compute_{sm_arch},file={file}
+/// with optional ident={identifier}, falling back to that of `provenance`.
+/// Profile `all` or `all-major` duplicates PTX for each known arch.
+fn image_entries(
+    image: &str,
+    provenance: &Provenance,
+    stdin_used: &mut bool,
+) -> anyhow::Result<Vec<FatBinaryEntry<'static>>> {
+    let mut file_name = None;
+    let mut sm_archs = vec![SmArch(50)];
+    let mut ident = provenance.ident;
+    for part in image.split(',') {
+        if let Some((key, value)) = part.split_once('=') {
+            if key == "file" {
+                file_name = Some(value);
+            } else if key == "ident" {
+                ident = Some(value);
+            } else if key == "profile" {
+                if value == "all" || value == "all-major" {
+                    sm_archs = SmArch::expand(value)?;
+                } else if let Some((prefix, arch)) = value.split_once('_') {
+                    if prefix == "compute" || prefix == "sm" {
+                        sm_archs = vec![SmArch(arch.parse()?)];
+                    }
+                }
+            }
+        }
+    }
+
+    let Some(file_name) = file_name else {
+        return Ok(vec![]);
+    };
+    let payload = read_payload(Path::new(file_name), stdin_used)?;
+    // a cubin only runs on the arch it was compiled for
+    if sm_archs.len() > 1 && payload.starts_with(b"\x7fELF") {
+        anyhow::bail!("profile=all and profile=all-major require PTX: {}", image);
+    }
+    let elf_class = CubinHeader::parse(&payload).map(|cubin| cubin.is_64bit);
+    let is_64bit = address_size(provenance, elf_class, image)?.unwrap_or(true);
+    let entries: Vec<_> = sm_archs
+        .into_iter()
+        .map(|arch| {
+            let mut entry = FatBinaryEntry::new_auto(arch.0, payload.clone());
+            entry.set_64bit(is_64bit);
+            entry.set_identifier(ident);
+            entry.set_host(provenance.host);
+            entry.set_producer(provenance.producer);
+            entry.set_debug_info(provenance.debug);
+            entry
+        })
+        .collect();
+    // the debugger expects DWARF in cubins marked as debug
+    if provenance.debug
+        && entries
+            .iter()
+            .any(|entry| entry.kind() == EntryKind::Elf && !entry.has_dwarf())
+    {
+        anyhow::bail!("--debug requires DWARF sections in cubin: {}", image);
+    }
+    Ok(entries)
+}
+
+fn edit(
+    fatbin: PathBuf,
+    remove_archs: Vec<String>,
+    add_images: Vec<String>,
+    set_idents: Vec<String>,
+    strip_identifiers: bool,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut res = FatBinary::read(File::open(&fatbin)?)?;
+    let mut stdin_used = false;
+
+    if strip_identifiers {
+        res.strip_identifiers();
+    }
+
+    // indices refer to the input fatbin, so set identifiers first
+    for set_ident in set_idents {
+        let Some((index, identifier)) = set_ident.split_once('=') else {
+            anyhow::bail!("Invalid --set-ident {}", set_ident);
+        };
+        let index: usize = index.parse()?;
+        let Some(entry) = res.entries_mut().get_mut(index) else {
+            anyhow::bail!("Entry {} does not exist", index);
+        };
+        entry.set_identifier(Some(identifier));
+    }
+
+    for remove_arch in remove_archs {
+        let sm_arch = remove_arch.parse::<SmArch>()?.0;
+        res.entries_mut()
+            .retain(|entry| entry.get_sm_arch() != sm_arch);
+    }
+
+    for image in add_images {
+        res.entries_mut().extend(image_entries(
+            &image,
+            &Provenance::default(),
+            &mut stdin_used,
+        )?);
+    }
+
+    res.write(File::create(output.unwrap_or(fatbin))?)?;
+    Ok(())
+}
+
+/// Modify header of entry at `index` and rewrite the fatbin
+fn edit_header(
+    fatbin: PathBuf,
+    index: usize,
+    output: Option<PathBuf>,
+    modify: impl FnOnce(&mut FatBinaryEntry) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut res = FatBinary::read(File::open(&fatbin)?)?;
+    let Some(entry) = res.entries_mut().get_mut(index) else {
+        anyhow::bail!("Entry {} does not exist", index);
+    };
+    modify(entry)?;
+    res.write(File::create(output.unwrap_or(fatbin))?)?;
+    Ok(())
+}
+
+/// Parse flag name or raw mask of set-flag
+fn flag_mask(flag: &str) -> anyhow::Result<u64> {
+    Ok(match flag {
+        "64bit" => 0x1,
+        "debug" => 0x2,
+        "cuda" => 0x4,
+        "opencl" => 0x8,
+        "linux" => 0x10,
+        "mac" => 0x20,
+        "windows" => 0x40,
+        "compressed" => 0x2000,
+        _ => match flag.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16)?,
+            None => anyhow::bail!("Invalid flag {}", flag),
+        },
+    })
+}
+
+/// Group entries by kind and arch, duplicates are paired in order
+fn diff_keys<'a>(
+    fatbin: &'a FatBinary<'a>,
+) -> BTreeMap<(EntryKind, u32, usize), &'a FatBinaryEntry<'a>> {
+    let mut res = BTreeMap::new();
+    for entry in fatbin.entries() {
+        let mut nth = 0;
+        while res.contains_key(&(entry.kind(), entry.get_sm_arch(), nth)) {
+            nth += 1;
+        }
+        res.insert((entry.kind(), entry.get_sm_arch(), nth), entry);
+    }
+    res
+}
+
+/// Collect differences between two entries
+fn diff_entry(old: &FatBinaryEntry, new: &FatBinaryEntry) -> Vec<String> {
+    let mut changes = vec![];
+    let mut field = |name: &str, old: String, new: String| {
+        if old != new {
+            changes.push(format!("  {}: {} -> {}", name, old, new));
+        }
+    };
+    field(
+        "code version",
+        format!("[{},{}]", old.get_version_major(), old.get_version_minor()),
+        format!("[{},{}]", new.get_version_major(), new.get_version_minor()),
+    );
+    field(
+        "producer",
+        format!("{:?}", old.producer()),
+        format!("{:?}", new.producer()),
+    );
+    field(
+        "host",
+        format!("{:?}", old.host()),
+        format!("{:?}", new.host()),
+    );
+    field(
+        "64bit",
+        old.is_64bit().to_string(),
+        new.is_64bit().to_string(),
+    );
+    field(
+        "debug",
+        old.has_debug_info().to_string(),
+        new.has_debug_info().to_string(),
+    );
+    field(
+        "compressed",
+        old.is_compressed().to_string(),
+        new.is_compressed().to_string(),
+    );
+    field(
+        "identifier",
+        format!("{:?}", old.get_identifier_lossy()),
+        format!("{:?}", new.get_identifier_lossy()),
+    );
+    field(
+        "ptxas options",
+        format!("{:?}", old.get_ptxas_options_lossy()),
+        format!("{:?}", new.get_ptxas_options_lossy()),
+    );
+
+    let old_payload = old.get_decompressed_payload();
+    let new_payload = new.get_decompressed_payload();
+    if old_payload.len() != new_payload.len() {
+        changes.push(format!(
+            "  size: {} -> {} ({:+})",
+            old_payload.len(),
+            new_payload.len(),
+            new_payload.len() as i64 - old_payload.len() as i64
+        ));
+    }
+    if old.get_payload().len() != new.get_payload().len() {
+        changes.push(format!(
+            "  stored size: {} -> {} ({:+})",
+            old.get_payload().len(),
+            new.get_payload().len(),
+            new.get_payload().len() as i64 - old.get_payload().len() as i64
+        ));
+    }
+
+    let old_hash = Sha256::digest(&old_payload);
+    let new_hash = Sha256::digest(&new_payload);
+    if old_hash != new_hash {
+        changes.push(format!("  sha256: {:x} -> {:x}", old_hash, new_hash));
+
+        if !new.contains_elf() {
+            let old_ptx = String::from_utf8_lossy(&old_payload);
+            let new_ptx = String::from_utf8_lossy(&new_payload);
+            let text_diff = similar::TextDiff::from_lines(&old_ptx, &new_ptx);
+            changes.push(
+                text_diff
+                    .unified_diff()
+                    .header("old", "new")
+                    .to_string()
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+    }
+
+    changes
+}
+
+/// Compare two fatbins, return true if they differ
+fn diff(old: PathBuf, new: PathBuf) -> anyhow::Result<bool> {
+    let old = FatBinary::read(File::open(old)?)?;
+    let new = FatBinary::read(File::open(new)?)?;
+    let old_entries = diff_keys(&old);
+    let new_entries = diff_keys(&new);
+
+    let mut differ = false;
+    for (key @ (kind, sm_arch, _), old_entry) in &old_entries {
+        match new_entries.get(key) {
+            Some(new_entry) => {
+                let changes = diff_entry(old_entry, new_entry);
+                if !changes.is_empty() {
+                    println!("changed: {} sm_{}", kind, sm_arch);
+                    for change in changes {
+                        println!("{}", change);
+                    }
+                    differ = true;
+                }
+            }
+            None => {
+                println!("removed: {} sm_{}", kind, sm_arch);
+                differ = true;
+            }
+        }
+    }
+    for key @ (kind, sm_arch, _) in new_entries.keys() {
+        if !old_entries.contains_key(key) {
+            println!("added: {} sm_{}", kind, sm_arch);
+            differ = true;
+        }
+    }
+
+    Ok(differ)
+}
+
+/// Merge all fatbins in the input files
+fn merge(fatbins: Vec<PathBuf>, dedupe: bool, output: PathBuf) -> anyhow::Result<()> {
+    let mut res = FatBinary::new();
+    for fatbin in fatbins {
+        let data = std::fs::read(&fatbin)?;
+        for (_, fatbinary) in FatBinary::parse_all(&data)? {
+            res.merge(fatbinary.into_owned(), dedupe);
+        }
+    }
+    res.write(File::create(output)?)?;
+    Ok(())
+}
+
+/// Write fatbins grouped by arch to `output` directory
+fn split(fatbin: PathBuf, by: SplitBy, output: PathBuf) -> anyhow::Result<()> {
+    let fatbin = FatBinary::read(File::open(fatbin)?)?;
+    std::fs::create_dir_all(&output)?;
+    let groups = fatbin.split_by(|entry| match by {
+        SplitBy::Arch => format!("sm_{}", entry.get_sm_arch()),
+        SplitBy::Major => format!("sm_{}x", entry.get_sm_arch() / 10),
+    });
+    for (name, group) in groups {
+        let path = output.join(format!("{}.fatbin", name));
+        println!("{}: {} entries", path.display(), group.entries().len());
+        group.write(File::create(path)?)?;
+    }
+    Ok(())
+}
+
+/// Quote CSV field if needed
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Print entries of fatbin in the given format
+fn ls(fatbin: PathBuf, format: LsFormat) -> anyhow::Result<()> {
+    let fatbin = FatBinary::read(File::open(fatbin)?)?;
+    let mut rows = vec![];
+    for (index, entry) in fatbin.entries().iter().enumerate() {
+        let info = entry.info();
+        rows.push(LsRow {
+            kind: info.kind.to_string(),
+            arch: info.arch.to_string(),
+            version: format!("{}.{}", info.version_major, info.version_minor),
+            flags: format!("{:#x}", info.flags),
+            identifier: entry.identifier().unwrap_or_default().to_string(),
+            ..LsRow::new(Some(index), info.size, info.payload_size(), info.padding())
+        });
+    }
+    let total = LsRow::new(
+        None,
+        rows.iter().map(|row| row.stored_size).sum(),
+        rows.iter().map(|row| row.decompressed_size).sum(),
+        rows.iter().map(|row| row.padding).sum(),
+    );
+
+    match format {
+        LsFormat::Table => {
+            println!(
+                "{:>5} {:<4} {:<8} {:<7} {:<8} {:>10} {:>12} {:>7} {:>6} identifier",
+                "#",
+                "kind",
+                "arch",
+                "version",
+                "flags",
+                "stored",
+                "decompressed",
+                "padding",
+                "ratio"
+            );
+            for row in rows.iter().chain([&total]) {
+                let line = format!(
+                    "{:>5} {:<4} {:<8} {:<7} {:<8} {:>10} {:>12} {:>7} {:>6.2} {}",
+                    row.index
+                        .map_or("total".to_string(), |index| index.to_string()),
+                    row.kind,
+                    row.arch,
+                    row.version,
+                    row.flags,
+                    row.stored_size,
+                    row.decompressed_size,
+                    row.padding,
+                    row.ratio,
+                    match (&row.index, row.identifier.as_str()) {
+                        (Some(_), "") => "-",
+                        (_, identifier) => identifier,
+                    }
+                );
+                println!("{}", line.trim_end());
+            }
+        }
+        LsFormat::Csv => {
+            println!(
+                "index,kind,arch,version,flags,stored_size,decompressed_size,padding,ratio,identifier"
+            );
+            for row in rows.iter().chain([&total]) {
+                println!(
+                    "{},{},{},{},{},{},{},{},{:.2},{}",
+                    row.index
+                        .map_or("total".to_string(), |index| index.to_string()),
+                    row.kind,
+                    row.arch,
+                    row.version,
+                    row.flags,
+                    row.stored_size,
+                    row.decompressed_size,
+                    row.padding,
+                    row.ratio,
+                    csv_field(&row.identifier)
+                );
+            }
+        }
+        LsFormat::Json => {
+            let json = serde_json::json!({
+                "entries": rows,
+                "total": {
+                    "stored_size": total.stored_size,
+                    "decompressed_size": total.decompressed_size,
+                    "padding": total.padding,
+                    "ratio": total.ratio,
+                },
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+    }
+    Ok(())
+}
+
+/// Replace entries of fatbins in host ELF
+fn patch_lib(
+    library: PathBuf,
+    replacements: Vec<String>,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut payloads = vec![];
+    for replacement in replacements {
+        let Some((arch, file_name)) = replacement.split_once('=') else {
+            anyhow::bail!("Invalid --replace-entry {}", replacement);
+        };
+        payloads.push((arch.parse::<SmArch>()?, std::fs::read(file_name)?));
+    }
+    let payloads: Vec<_> = payloads
+        .iter()
+        .map(|(arch, payload)| (*arch, &payload[..]))
+        .collect();
+
+    let mut image = std::fs::read(&library)?;
+    let replaced = FatBinary::patch_host_elf(&mut image, &payloads)?;
+    if replaced == 0 {
+        anyhow::bail!("No entry to replace in {}", library.display());
+    }
+    std::fs::write(output.unwrap_or(library), image)?;
+    println!("Replaced {} entries", replaced);
+    Ok(())
+}
+
+/// Verify all fatbins in file, return true if any issue is found
+fn verify(fatbin: PathBuf) -> anyhow::Result<bool> {
+    let data = std::fs::read(&fatbin)?;
+    let ranges = match FatBinary::scan_containers(&data) {
+        Ok(ranges) => ranges,
+        Err(err) => {
+            println!("{}: {}", fatbin.display(), err);
+            return Ok(true);
+        }
+    };
+    let mut entries = 0;
+    let mut failed = false;
+    for (index, range) in ranges.iter().enumerate() {
+        let offset = range.start;
+        let fatbinary = match FatBinary::parse_with(&data[range.clone()], ParseOptions::default()) {
+            Ok((fatbinary, warnings)) => {
+                for warning in warnings {
+                    println!(
+                        "fatbin {} at offset {:#x}: warning: {}",
+                        index, offset, warning
+                    );
+                }
+                fatbinary
+            }
+            Err(err) => {
+                println!("fatbin {} at offset {:#x}: {}", index, offset, err);
+                return Ok(true);
+            }
+        };
+        for issue in fatbinary.verify() {
+            println!("fatbin {} at offset {:#x}: {}", index, offset, issue);
+            failed = true;
+        }
+        entries += fatbinary.entries().len();
+    }
+
+    if !failed {
+        println!(
+            "{}: OK ({} fatbins, {} entries)",
+            fatbin.display(),
+            ranges.len(),
+            entries
+        );
+    }
+    Ok(failed)
+}
+
+/// Check cubins of all fatbins in file against driver version, return true
+/// if any cannot be loaded
+fn driver_check(fatbin: PathBuf, driver: (u16, u16)) -> anyhow::Result<bool> {
+    let data = std::fs::read(&fatbin)?;
+    let mut failed = false;
+    let mut cubins = 0;
+    for (index, (offset, fatbinary)) in FatBinary::parse_all(&data)?.iter().enumerate() {
+        for (entry_index, entry) in fatbinary.entries().iter().enumerate() {
+            if entry.kind() != EntryKind::Elf {
+                continue;
+            }
+            cubins += 1;
+            let message = match entry.driver_support(driver) {
+                DriverSupport::Supported => continue,
+                DriverSupport::ArchTooNew { arch, required } => {
+                    failed = true;
+                    format!("{} needs CUDA {}.{}", arch, required.0, required.1)
+                }
+                DriverSupport::AbiTooNew {
+                    abi_version,
+                    required: Some(required),
+                } => {
+                    failed = true;
+                    format!(
+                        "cubin ABI version {} needs CUDA {}.{}",
+                        abi_version, required.0, required.1
+                    )
+                }
+                DriverSupport::AbiTooNew {
+                    abi_version,
+                    required: None,
+                } => {
+                    failed = true;
+                    format!("unknown cubin ABI version {}", abi_version)
+                }
+                DriverSupport::Unknown => {
+                    "warning: unknown architecture or malformed cubin".to_string()
+                }
+            };
+            println!(
+                "fatbin {} at offset {:#x}: entry {}: {}",
+                index, offset, entry_index, message
+            );
+        }
+    }
+    if !failed {
+        println!(
+            "{}: OK ({} cubins loadable by CUDA {}.{})",
+            fatbin.display(),
+            cubins,
+            driver.0,
+            driver.1
+        );
+    }
+    Ok(failed)
+}
+
+/// Audit all fatbins in file, return true if any anomaly is found
+fn audit(fatbin: PathBuf) -> anyhow::Result<bool> {
+    let data = std::fs::read(&fatbin)?;
+    let fatbins = FatBinary::parse_all(&data)?;
+    let mut found = false;
+    for (index, (offset, fatbinary)) in fatbins.iter().enumerate() {
+        for finding in fatbinary.audit() {
+            println!("fatbin {} at offset {:#x}: {}", index, offset, finding);
+            found = true;
+        }
+    }
+    if !found {
+        println!(
+            "{}: no anomalies ({} fatbins)",
+            fatbin.display(),
+            fatbins.len()
+        );
+    }
+    Ok(found)
+}
+
+/// Fill in fields of a manifest entry it leaves unset like images get them,
+/// and follow the ELF class of cubins
+fn fill_manifest_entry(
+    entry: &mut FatBinaryEntry,
+    provenance: &Provenance,
+    manifest: &Path,
+) -> anyhow::Result<()> {
+    if entry.get_identifier_bytes().is_none() {
+        entry.set_identifier(provenance.ident);
+    }
+    if entry.host() == Host::Unknown {
+        entry.set_host(provenance.host);
+    }
+    if entry.producer() == Producer::Unknown {
+        entry.set_producer(provenance.producer);
+    }
+    let elf_class = entry.cubin_header().map(|cubin| cubin.is_64bit);
+    if let Some(is_64bit) = address_size(provenance, elf_class, &manifest.display().to_string())? {
+        entry.set_64bit(is_64bit);
+    }
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
-    if let Some(fatbin) = args.fatbin {
-        let file = File::create(fatbin)?;
-        let mut res = FatBinary::new();
-
-        // profile=sm/compute_{sm_arch},file={file}
-        for image in args.images {
-            let mut file_name = None;
-            let mut sm_arch = 50;
-            for part in image.split(',') {
-                if let Some((key, value)) = part.split_once('=') {
-                    if key == "file" {
-                        file_name = Some(value);
-                    } else if key == "profile" {
-                        if let Some((prefix, arch)) = value.split_once('_') {
-                            if prefix == "compute" || prefix == "sm" {
-                                sm_arch = arch.parse()?;
-                            }
-                        }
+    // accept -32 and -64 of NVIDIA's fatbinary, which clap cannot declare
+    let args = Cli::parse_from(std::env::args_os().map(|arg| match arg.to_str() {
+        Some("-32") => "--32".into(),
+        Some("-64") => "--64".into(),
+        _ => arg,
+    }));
+    match args.command {
+        Some(Command::Edit {
+            fatbin,
+            remove_archs,
+            add_images,
+            set_idents,
+            strip_identifiers,
+            output,
+        }) => {
+            return edit(
+                fatbin,
+                remove_archs,
+                add_images,
+                set_idents,
+                strip_identifiers,
+                output,
+            )
+        }
+        Some(Command::SetFlag {
+            fatbin,
+            index,
+            flags,
+            clear,
+            output,
+        }) => {
+            let mut mask = 0;
+            for flag in flags {
+                mask |= flag_mask(&flag)?;
+            }
+            return edit_header(fatbin, index, output, |entry| {
+                if clear {
+                    entry.set_flags(entry.get_flags() & !mask);
+                } else {
+                    entry.set_flags(entry.get_flags() | mask);
+                }
+                Ok(())
+            });
+        }
+        Some(Command::SetArch {
+            fatbin,
+            index,
+            arch,
+            output,
+        }) => {
+            let sm_arch = arch.parse::<SmArch>()?.0;
+            return edit_header(fatbin, index, output, |entry| {
+                entry.set_sm_arch(sm_arch);
+                Ok(())
+            });
+        }
+        Some(Command::SetVersion {
+            fatbin,
+            index,
+            version,
+            output,
+        }) => {
+            let Some((major, minor)) = version.split_once('.') else {
+                anyhow::bail!("Invalid version {}, expected major.minor", version);
+            };
+            let (major, minor) = (major.parse()?, minor.parse()?);
+            return edit_header(fatbin, index, output, |entry| {
+                entry.set_version(major, minor);
+                Ok(())
+            });
+        }
+        Some(Command::Compress { fatbin, output }) => {
+            let mut res = FatBinary::read(File::open(&fatbin)?)?;
+            res.compress();
+            res.write(File::create(output.unwrap_or(fatbin))?)?;
+            return Ok(());
+        }
+        Some(Command::Decompress { fatbin, output }) => {
+            let mut res = FatBinary::read(File::open(&fatbin)?)?;
+            res.decompress();
+            res.write(File::create(output.unwrap_or(fatbin))?)?;
+            return Ok(());
+        }
+        Some(Command::Merge {
+            fatbins,
+            dedupe,
+            output,
+        }) => return merge(fatbins, dedupe, output),
+        Some(Command::Split { fatbin, by, output }) => return split(fatbin, by, output),
+        Some(Command::Ls { fatbin, format }) => return ls(fatbin, format),
+        Some(Command::PatchLib {
+            library,
+            replacements,
+            output,
+        }) => return patch_lib(library, replacements, output),
+        Some(Command::Diff { old, new }) => {
+            if diff(old, new)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Verify { fatbin }) => {
+            if verify(fatbin)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Audit { fatbin }) => {
+            if audit(fatbin)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::DriverCheck { fatbin, driver }) => {
+            let Some((major, minor)) = driver.split_once('.') else {
+                anyhow::bail!("Invalid version {}, expected major.minor", driver);
+            };
+            if driver_check(fatbin, (major.parse()?, minor.parse()?))? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Report { fatbin }) => {
+            print!("{}", FatBinary::read(File::open(fatbin)?)?.report());
+            return Ok(());
+        }
+        Some(Command::Digest { fatbin, manifest }) => {
+            let fatbin = FatBinary::read(File::open(fatbin)?)?;
+            match manifest {
+                Some(manifest) => {
+                    let manifest = DigestManifest::from_text(&std::fs::read_to_string(manifest)?)?;
+                    let issues = fatbin.verify_digests(&manifest, &Sha256Digest);
+                    for issue in &issues {
+                        println!("{}", issue);
+                    }
+                    if !issues.is_empty() {
+                        std::process::exit(1);
                     }
                 }
+                None => print!("{}", fatbin.digests(&Sha256Digest).to_text()),
             }
+            return Ok(());
+        }
+        None => {}
+    }
 
-            if let Some(file_name) = file_name {
-                let mut payload = vec![];
-                File::open(file_name)?.read_to_end(&mut payload)?;
+    if let Some(fatbin) = args.fatbin {
+        let mut stdin_used = false;
+        let provenance = Provenance {
+            ident: args.ident.as_deref(),
+            host: args.host_os.map_or(DEFAULT_HOST, Host::from),
+            producer: args.producer.map_or(Producer::CUDA, Producer::from),
+            debug: args.debug,
+            is_64bit: match (args.is_32bit, args.is_64bit) {
+                (true, _) => Some(false),
+                (_, true) => Some(true),
+                _ => None,
+            },
+        };
 
-                let entry = FatBinaryEntry::new_auto(sm_arch, payload);
-                res.entries_mut().push(entry);
+        let mut res = match &args.manifest {
+            Some(manifest) => {
+                let mut res = FatBinary::from_manifest(manifest)?;
+                for entry in res.entries_mut() {
+                    fill_manifest_entry(entry, &provenance, manifest)?;
+                }
+                res
             }
+            None => FatBinary::new(),
+        };
+        for image in &args.images {
+            res.entries_mut()
+                .extend(image_entries(image, &provenance, &mut stdin_used)?);
         }
 
-        res.write(file)?;
+        if fatbin.as_os_str() == "-" {
+            let mut stdout = std::io::stdout().lock();
+            res.write(&mut stdout)?;
+            stdout.flush()?;
+        } else {
+            res.write(File::create(fatbin)?)?;
+        }
     }
     Ok(())
 }
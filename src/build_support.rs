@@ -0,0 +1,163 @@
+//! Bundling GPU code from build scripts
+//!
+//! ```no_run
+//! // build.rs
+//! fatbinary::build_support::FatbinBuilder::new("kernels")
+//!     .ptx("src/kernels.ptx", 70)
+//!     .cubin("src/kernels.sm_80.cubin", 80)
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! The fatbinary is written to `$OUT_DIR/kernels.fatbin`, with a module
+//! generated by [FatBinary::generate_rust_module] in `$OUT_DIR/kernels.rs`:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/kernels.rs"));
+//! ```
+
+use crate::{FatBinary, FatBinaryEntry, FatBinaryError, WriteOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Files written by [FatbinBuilder]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildOutput {
+    /// Path of the fatbinary
+    pub fatbin: PathBuf,
+    /// Path of the generated Rust module
+    pub module: PathBuf,
+}
+
+/// Collects PTX and cubin files into a fatbinary in build scripts
+#[derive(Debug, Clone)]
+pub struct FatbinBuilder {
+    name: String,
+    /// Input files and their archs, `true` for cubins
+    inputs: Vec<(PathBuf, u32, bool)>,
+    /// Archs to compile cubins for with ptxas
+    #[cfg(feature = "toolchain")]
+    ptxas_archs: Vec<u32>,
+    compress: bool,
+}
+
+impl FatbinBuilder {
+    /// Create builder for `{name}.fatbin` and `{name}.rs`
+    pub fn new(name: &str) -> Self {
+        FatbinBuilder {
+            name: name.to_string(),
+            inputs: vec![],
+            #[cfg(feature = "toolchain")]
+            ptxas_archs: vec![],
+            compress: false,
+        }
+    }
+
+    /// Add PTX file for `compute_{arch}`
+    pub fn ptx<P: AsRef<Path>>(mut self, path: P, arch: u32) -> Self {
+        self.inputs.push((path.as_ref().to_path_buf(), arch, false));
+        self
+    }
+
+    /// Add cubin file for `sm_{arch}`
+    pub fn cubin<P: AsRef<Path>>(mut self, path: P, arch: u32) -> Self {
+        self.inputs.push((path.as_ref().to_path_buf(), arch, true));
+        self
+    }
+
+    /// Compile cubin for `sm_{arch}` from the PTX of the highest arch not
+    /// above it, see [FatBinary::append_ptxas_cubin]
+    #[cfg(feature = "toolchain")]
+    pub fn ptxas(mut self, arch: u32) -> Self {
+        self.ptxas_archs.push(arch);
+        self
+    }
+
+    /// Compress entries when it saves space
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Write files to `OUT_DIR` and print cargo directives to stdout
+    pub fn build(self) -> Result<BuildOutput, FatBinaryError> {
+        let out_dir = std::env::var_os("OUT_DIR").ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "OUT_DIR is not set")
+        })?;
+        self.build_in(Path::new(&out_dir), std::io::stdout().lock())
+    }
+
+    /// Write files to `out_dir` and cargo directives to `directives`
+    pub fn build_in<W: Write>(
+        self,
+        out_dir: &Path,
+        mut directives: W,
+    ) -> Result<BuildOutput, FatBinaryError> {
+        let mut fatbin = FatBinary::new();
+        for (path, arch, is_elf) in &self.inputs {
+            writeln!(directives, "cargo:rerun-if-changed={}", path.display())?;
+            let payload = std::fs::read(path)?;
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new(*is_elf, *arch, 0, 0, true, payload));
+        }
+        #[cfg(feature = "toolchain")]
+        if !self.ptxas_archs.is_empty() {
+            writeln!(directives, "cargo:rerun-if-env-changed=PTXAS")?;
+            for arch in &self.ptxas_archs {
+                fatbin.append_ptxas_cubin(*arch, &[])?;
+            }
+        }
+
+        let output = BuildOutput {
+            fatbin: out_dir.join(format!("{}.fatbin", self.name)),
+            module: out_dir.join(format!("{}.rs", self.name)),
+        };
+        let options = WriteOptions {
+            compress: self.compress,
+            ..Default::default()
+        };
+        fatbin.write_with_options(std::fs::File::create(&output.fatbin)?, &options)?;
+        let module = fatbin.generate_rust_module(&output.fatbin.to_string_lossy());
+        std::fs::write(&output.module, module)?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_support::FatbinBuilder;
+    use crate::FatBinary;
+
+    #[test]
+    fn build_in() {
+        let dir = std::env::temp_dir().join(format!("fatbinary-build-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ptx = dir.join("kernels.ptx");
+        std::fs::write(&ptx, ".version 7.0\n.target sm_70\n.entry axpy()\n{\n}\n").unwrap();
+        let cubin = dir.join("kernels.sm_80.cubin");
+        std::fs::write(&cubin, b"\x7fELF").unwrap();
+
+        let mut directives = vec![];
+        let output = FatbinBuilder::new("kernels")
+            .ptx(&ptx, 70)
+            .cubin(&cubin, 80)
+            .build_in(&dir, &mut directives)
+            .unwrap();
+        let fatbin = FatBinary::read(std::fs::File::open(&output.fatbin).unwrap()).unwrap();
+        let module = std::fs::read_to_string(&output.module).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(fatbin.entries().len(), 2);
+        assert!(fatbin.entries()[1].contains_elf());
+        assert!(module.contains("pub const KERNELS: &[&str] = &[\"axpy\"];\n"));
+        assert_eq!(
+            String::from_utf8(directives).unwrap(),
+            format!(
+                "cargo:rerun-if-changed={}\ncargo:rerun-if-changed={}\n",
+                ptx.display(),
+                cubin.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Clang offload bundles, used by HIP and OpenMP offload
+//!
+//! Layout (all integers are little endian u64):
+//! magic `__CLANG_OFFLOAD_BUNDLE__`, number of entries, then for each entry
+//! offset, size, triple size and triple, followed by the code objects.
+
+use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+const OFFLOAD_BUNDLE_MAGIC: &[u8] = b"__CLANG_OFFLOAD_BUNDLE__";
+
+// hipcc aligns code objects to page size
+#[cfg(feature = "std")]
+const OFFLOAD_BUNDLE_ALIGNMENT: u64 = 4096;
+
+/// An entry in [OffloadBundle]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OffloadBundleEntry {
+    /// Bundle entry id, e.g. `hipv4-amdgcn-amd-amdhsa--gfx90a` or `host-x86_64-unknown-linux-gnu`
+    pub triple: String,
+    pub payload: Vec<u8>,
+}
+
+impl OffloadBundleEntry {
+    /// Create entry with bundle entry id and payload
+    pub fn new<S: Into<String>, T: Into<Vec<u8>>>(triple: S, payload: T) -> Self {
+        Self {
+            triple: triple.into(),
+            payload: payload.into(),
+        }
+    }
+
+    /// Offload kind, e.g. `hip`, `hipv4`, `openmp`, `cuda` or `host`
+    pub fn offload_kind(&self) -> &str {
+        self.triple.split('-').next().unwrap_or_default()
+    }
+
+    /// Target triple and target id after offload kind, e.g. `amdgcn-amd-amdhsa--gfx90a`
+    pub fn target(&self) -> &str {
+        self.triple
+            .split_once('-')
+            .map(|(_, target)| target)
+            .unwrap_or_default()
+    }
+}
+
+/// A clang offload bundle
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct OffloadBundle {
+    entries: Vec<OffloadBundleEntry>,
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, FatBinaryError> {
+    bundle_slice(data, offset as u64, 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn bundle_slice(data: &[u8], offset: u64, size: u64) -> Result<&[u8], FatBinaryError> {
+    offset
+        .checked_add(size)
+        .filter(|end| *end <= data.len() as u64)
+        .map(|end| &data[offset as usize..end as usize])
+        .ok_or(FatBinaryError::OutOfBundleBounds {
+            offset,
+            size,
+            len: data.len() as u64,
+        })
+}
+
+impl OffloadBundle {
+    /// Create a new empty offload bundle
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    /// Get entries contained in the offload bundle
+    pub fn entries(&self) -> &Vec<OffloadBundleEntry> {
+        &self.entries
+    }
+
+    /// Get mutable entries contained in the offload bundle
+    pub fn entries_mut(&mut self) -> &mut Vec<OffloadBundleEntry> {
+        &mut self.entries
+    }
+
+    /// Check if data starts with offload bundle magic
+    pub fn is_offload_bundle(data: &[u8]) -> bool {
+        data.starts_with(OFFLOAD_BUNDLE_MAGIC)
+    }
+
+    /// Read offload bundle from memory
+    pub fn parse(data: &[u8]) -> Result<Self, FatBinaryError> {
+        if !Self::is_offload_bundle(data) {
+            return Err(FatBinaryError::InvalidBundleMagic);
+        }
+
+        let mut offset = OFFLOAD_BUNDLE_MAGIC.len();
+        let num_entries = read_u64(data, offset)?;
+        offset += 8;
+
+        let mut entries = vec![];
+        for _ in 0..num_entries {
+            let entry_offset = read_u64(data, offset)?;
+            let entry_size = read_u64(data, offset + 8)?;
+            let triple_size = read_u64(data, offset + 16)?;
+            offset += 24;
+            let triple = bundle_slice(data, offset as u64, triple_size)?;
+            offset += triple.len();
+
+            entries.push(OffloadBundleEntry {
+                triple: String::from_utf8(triple.to_owned())?,
+                payload: bundle_slice(data, entry_offset, entry_size)?.to_owned(),
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Write offload bundle to writer
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), FatBinaryError> {
+        let header_size = OFFLOAD_BUNDLE_MAGIC.len() as u64
+            + 8
+            + self
+                .entries
+                .iter()
+                .map(|entry| 24 + entry.triple.len() as u64)
+                .sum::<u64>();
+
+        // compute offsets of code objects
+        let mut offsets = vec![];
+        let mut offset = header_size;
+        for entry in &self.entries {
+            offset = offset.next_multiple_of(OFFLOAD_BUNDLE_ALIGNMENT);
+            offsets.push(offset);
+            offset += entry.payload.len() as u64;
+        }
+
+        writer.write_all(OFFLOAD_BUNDLE_MAGIC)?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for (entry, offset) in self.entries.iter().zip(&offsets) {
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&(entry.payload.len() as u64).to_le_bytes())?;
+            writer.write_all(&(entry.triple.len() as u64).to_le_bytes())?;
+            writer.write_all(entry.triple.as_bytes())?;
+        }
+
+        let mut current = header_size;
+        for (entry, offset) in self.entries.iter().zip(&offsets) {
+            writer.write_all(&vec![0u8; (offset - current) as usize])?;
+            writer.write_all(&entry.payload)?;
+            current = offset + entry.payload.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Convert fatbinary entries into `cuda-nvptx64-nvidia-cuda-sm_XX` bundle
+    /// entries with decompressed payloads
+    pub fn from_fatbinary(fatbin: &FatBinary) -> Self {
+        let entries = fatbin
+            .entries()
+            .iter()
+            .map(|entry| OffloadBundleEntry {
+                triple: format!("cuda-nvptx64-nvidia-cuda-sm_{}", entry.get_sm_arch()),
+                payload: entry.get_decompressed_payload().into_owned(),
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Convert CUDA entries (`cuda-nvptx*-...-sm_XX`) into fatbinary borrowing
+    /// their payloads, skipping entries for other offload kinds and targets
+    pub fn to_fatbinary(&self) -> FatBinary<'_> {
+        let mut res = FatBinary::new();
+        for entry in &self.entries {
+            if entry.offload_kind() != "cuda" || !entry.target().starts_with("nvptx") {
+                continue;
+            }
+            let Some(arch) = entry
+                .triple
+                .rsplit_once("-sm_")
+                .and_then(|(_, arch)| arch.parse().ok())
+            else {
+                continue;
+            };
+            res.entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, &entry.payload[..]));
+        }
+        res
+    }
+}
+
+impl From<&FatBinary<'_>> for OffloadBundle {
+    fn from(fatbin: &FatBinary<'_>) -> Self {
+        Self::from_fatbinary(fatbin)
+    }
+}
+
+impl<'a> From<&'a OffloadBundle> for FatBinary<'a> {
+    fn from(bundle: &'a OffloadBundle) -> Self {
+        bundle.to_fatbinary()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, OffloadBundle, OffloadBundleEntry};
+
+    #[test]
+    fn bundle_roundtrip() {
+        let mut bundle = OffloadBundle::new();
+        bundle.entries_mut().push(OffloadBundleEntry::new(
+            "host-x86_64-unknown-linux-gnu",
+            vec![],
+        ));
+        bundle.entries_mut().push(OffloadBundleEntry::new(
+            "hipv4-amdgcn-amd-amdhsa--gfx90a",
+            b"\x7fELF".to_vec(),
+        ));
+
+        let mut buffer = vec![];
+        bundle.write(&mut buffer).unwrap();
+        assert!(OffloadBundle::is_offload_bundle(&buffer));
+        let read = OffloadBundle::parse(&buffer).unwrap();
+        assert_eq!(read, bundle);
+        assert_eq!(read.entries()[1].offload_kind(), "hipv4");
+        assert_eq!(read.entries()[1].target(), "amdgcn-amd-amdhsa--gfx90a");
+
+        assert!(OffloadBundle::parse(&buffer[..40]).is_err());
+    }
+
+    #[test]
+    fn bundle_fatbinary_conversion() {
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            70,
+            ".version 7.0\n.target sm_70\n".as_bytes(),
+        ));
+
+        let bundle = OffloadBundle::from(&fatbin);
+        assert_eq!(bundle.entries()[0].triple, "cuda-nvptx64-nvidia-cuda-sm_70");
+        let converted = FatBinary::from(&bundle);
+        assert_eq!(converted, fatbin);
+    }
+}
@@ -0,0 +1,110 @@
+//! Cooperative cancellation of long operations
+//!
+//! Operations check the token between entries or fatbinaries and fail with
+//! [FatBinaryError::Cancelled], so work already done is not interrupted
+//! halfway.
+
+use crate::FatBinaryError;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Flag cancelling operations it is passed to, clones share the flag, e.g.
+/// to cancel from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation, operations stop at their next check
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail with [FatBinaryError::Cancelled] if cancellation was requested
+    pub fn check(&self) -> Result<(), FatBinaryError> {
+        if self.is_cancelled() {
+            return Err(FatBinaryError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Check optional token of options
+pub(crate) fn check(cancel: &Option<CancellationToken>) -> Result<(), FatBinaryError> {
+    cancel.as_ref().map_or(Ok(()), CancellationToken::check)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CancellationToken, FatBinary, FatBinaryEntry, FatBinaryError, ParseOptions, WriteOptions,
+    };
+
+    #[test]
+    fn cancellation() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80] {
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, ptx.as_bytes()));
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let cancel = CancellationToken::new();
+        let parse_options = ParseOptions {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let write_options = WriteOptions {
+            compress: true,
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        assert!(FatBinary::parse_with(&buffer, parse_options.clone()).is_ok());
+        assert!(fatbin
+            .write_with_options(&mut vec![], &write_options)
+            .is_ok());
+        assert_eq!(fatbin.clone().compress_cancellable(&cancel).unwrap(), 2);
+
+        cancel.cancel();
+        assert!(cancel.is_cancelled());
+        assert!(matches!(
+            FatBinary::parse_with(&buffer, parse_options.clone()),
+            Err(FatBinaryError::Cancelled)
+        ));
+        assert!(matches!(
+            FatBinary::read_with(std::io::Cursor::new(&buffer), parse_options.clone()),
+            Err(FatBinaryError::Cancelled)
+        ));
+        assert!(matches!(
+            FatBinary::parse_all_with(&buffer, parse_options),
+            Err(FatBinaryError::Cancelled)
+        ));
+        assert!(matches!(
+            fatbin.write_with_options(&mut vec![], &write_options),
+            Err(FatBinaryError::Cancelled)
+        ));
+        assert!(matches!(
+            fatbin.compress_cancellable(&cancel),
+            Err(FatBinaryError::Cancelled)
+        ));
+        assert!(!fatbin.entries()[0].is_compressed());
+        fatbin.compress();
+        assert!(matches!(
+            fatbin.decompress_cancellable(&cancel),
+            Err(FatBinaryError::Cancelled)
+        ));
+        assert!(fatbin.entries()[0].is_compressed());
+    }
+}
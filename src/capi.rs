@@ -0,0 +1,337 @@
+//! C API, enabled by the `capi` feature
+//!
+//! The header is generated by cbindgen: `cbindgen --config cbindgen.toml --output include/fatbinary.h`
+
+use crate::{try_decompress, FatBinary, FatBinaryEntry};
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::ptr::null;
+use std::ptr::null_mut;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error<T: ToString>(err: T) {
+    let message = CString::new(err.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Convert owned bytes into a buffer to be freed by `fatbinary_buffer_free`
+///
+/// # Safety
+///
+/// `out_len` must be NULL or writable.
+unsafe fn into_buffer(data: Vec<u8>, out_len: *mut usize) -> *mut u8 {
+    let data = data.into_boxed_slice();
+    if !out_len.is_null() {
+        *out_len = data.len();
+    }
+    Box::into_raw(data) as *mut u8
+}
+
+unsafe fn entry<'a>(
+    fatbin: *const FatBinary<'static>,
+    index: usize,
+) -> Option<&'a FatBinaryEntry<'static>> {
+    let res = fatbin.as_ref().and_then(|fatbin| fatbin.entries.get(index));
+    if res.is_none() {
+        set_last_error(format!("Entry {} does not exist", index));
+    }
+    res
+}
+
+/// Get message of the last error in this thread, or NULL if none.
+/// The string is valid until the next failing call in this thread.
+#[no_mangle]
+pub extern "C" fn fatbinary_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(null())
+    })
+}
+
+/// Create an empty fatbinary, free with `fatbinary_free`
+#[no_mangle]
+pub extern "C" fn fatbinary_new() -> *mut FatBinary<'static> {
+    Box::into_raw(Box::new(FatBinary::new()))
+}
+
+/// Parse fatbinary from memory, return NULL on error. Free with `fatbinary_free`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_parse(data: *const u8, len: usize) -> *mut FatBinary<'static> {
+    if data.is_null() {
+        set_last_error("data is NULL");
+        return null_mut();
+    }
+    let data = std::slice::from_raw_parts(data, len);
+    match FatBinary::parse(data) {
+        Ok(fatbin) => Box::into_raw(Box::new(fatbin.into_owned())),
+        Err(err) => {
+            set_last_error(err);
+            null_mut()
+        }
+    }
+}
+
+/// Free fatbinary returned by `fatbinary_new` or `fatbinary_parse`
+///
+/// # Safety
+///
+/// `fatbin` must be NULL or returned by this library and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_free(fatbin: *mut FatBinary<'static>) {
+    if !fatbin.is_null() {
+        drop(Box::from_raw(fatbin));
+    }
+}
+
+/// Get number of entries
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_entry_count(fatbin: *const FatBinary<'static>) -> usize {
+    fatbin
+        .as_ref()
+        .map(|fatbin| fatbin.entries.len())
+        .unwrap_or(0)
+}
+
+/// Check if entry contains ELF, return -1 if entry does not exist
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_entry_is_elf(
+    fatbin: *const FatBinary<'static>,
+    index: usize,
+) -> i32 {
+    entry(fatbin, index)
+        .map(|entry| entry.contains_elf() as i32)
+        .unwrap_or(-1)
+}
+
+/// Get CUDA SM architecture of entry, return 0 if entry does not exist
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_entry_sm_arch(
+    fatbin: *const FatBinary<'static>,
+    index: usize,
+) -> u32 {
+    entry(fatbin, index)
+        .map(|entry| entry.get_sm_arch())
+        .unwrap_or(0)
+}
+
+/// Check if payload of entry is compressed, return -1 if entry does not exist
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_entry_is_compressed(
+    fatbin: *const FatBinary<'static>,
+    index: usize,
+) -> i32 {
+    entry(fatbin, index)
+        .map(|entry| entry.is_compressed() as i32)
+        .unwrap_or(-1)
+}
+
+/// Get (possibly compressed) payload of entry, return NULL if entry does not exist.
+/// The payload is owned by the fatbinary.
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer, `out_len` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_entry_payload(
+    fatbin: *const FatBinary<'static>,
+    index: usize,
+    out_len: *mut usize,
+) -> *const u8 {
+    match entry(fatbin, index) {
+        Some(entry) => {
+            let payload = entry.get_payload();
+            if !out_len.is_null() {
+                *out_len = payload.len();
+            }
+            payload.as_ptr()
+        }
+        None => null(),
+    }
+}
+
+/// Get decompressed payload of entry, return NULL on error.
+/// Free with `fatbinary_buffer_free`.
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer, `out_len` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_entry_decompressed_payload(
+    fatbin: *const FatBinary<'static>,
+    index: usize,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let Some(entry) = entry(fatbin, index) else {
+        return null_mut();
+    };
+    let payload = if entry.is_compressed() {
+        match try_decompress(
+            entry.get_payload(),
+            entry.entry_header.decompressed_size as usize,
+        ) {
+            Some(payload) => payload,
+            None => {
+                set_last_error("Invalid compressed payload");
+                return null_mut();
+            }
+        }
+    } else {
+        entry.get_payload().to_vec()
+    };
+    into_buffer(payload, out_len)
+}
+
+/// Append a new entry, kind is detected from payload. Return 0 on success.
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_add_entry(
+    fatbin: *mut FatBinary<'static>,
+    sm_arch: u32,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    let Some(fatbin) = fatbin.as_mut() else {
+        set_last_error("fatbin is NULL");
+        return -1;
+    };
+    if data.is_null() {
+        set_last_error("data is NULL");
+        return -1;
+    }
+    let payload = std::slice::from_raw_parts(data, len);
+    fatbin
+        .entries
+        .push(FatBinaryEntry::new_auto(sm_arch, payload.to_vec()));
+    0
+}
+
+/// Serialize fatbinary, return NULL on error. Free with `fatbinary_buffer_free`.
+///
+/// # Safety
+///
+/// `fatbin` must be a valid fatbinary pointer, `out_len` must be NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_build(
+    fatbin: *const FatBinary<'static>,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let Some(fatbin) = fatbin.as_ref() else {
+        set_last_error("fatbin is NULL");
+        return null_mut();
+    };
+    let mut res = vec![];
+    match fatbin.write(&mut res) {
+        Ok(()) => into_buffer(res, out_len),
+        Err(err) => {
+            set_last_error(err);
+            null_mut()
+        }
+    }
+}
+
+/// Free buffer returned by this library
+///
+/// # Safety
+///
+/// `data` must be NULL or returned by this library with length `len` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fatbinary_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn capi_roundtrip() {
+        let mut fatbin = FatBinary::new();
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(16);
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.as_bytes().to_vec());
+        assert!(entry.compress());
+        fatbin.entries.push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        unsafe {
+            let parsed = fatbinary_parse(buffer.as_ptr(), buffer.len());
+            assert!(!parsed.is_null());
+            assert_eq!(fatbinary_entry_count(parsed), 1);
+            assert_eq!(fatbinary_entry_sm_arch(parsed, 0), 70);
+            assert_eq!(fatbinary_entry_is_elf(parsed, 0), 0);
+            assert_eq!(fatbinary_entry_is_compressed(parsed, 0), 1);
+
+            let mut len = 0;
+            let payload = fatbinary_entry_payload(parsed, 0, &mut len);
+            assert_eq!(
+                std::slice::from_raw_parts(payload, len),
+                fatbin.entries[0].get_payload()
+            );
+            let payload = fatbinary_entry_decompressed_payload(parsed, 0, &mut len);
+            assert_eq!(std::slice::from_raw_parts(payload, len), ptx.as_bytes());
+            fatbinary_buffer_free(payload, len);
+
+            let elf = b"\x7fELF";
+            assert_eq!(fatbinary_add_entry(parsed, 80, elf.as_ptr(), elf.len()), 0);
+            assert_eq!(fatbinary_entry_is_elf(parsed, 1), 1);
+            let data = fatbinary_build(parsed, &mut len);
+            let built = FatBinary::parse(std::slice::from_raw_parts(data, len)).unwrap();
+            assert_eq!(built.entries.len(), 2);
+            assert_eq!(built.entries[0], fatbin.entries[0]);
+            assert_eq!(built.entries[1].get_payload(), elf);
+            fatbinary_buffer_free(data, len);
+            fatbinary_free(parsed);
+        }
+    }
+
+    #[test]
+    fn capi_errors() {
+        unsafe {
+            assert!(fatbinary_parse(b"fatbin".as_ptr(), 6).is_null());
+            assert!(!fatbinary_last_error().is_null());
+            assert!(fatbinary_parse(null(), 0).is_null());
+            let message = CStr::from_ptr(fatbinary_last_error());
+            assert_eq!(message.to_str().unwrap(), "data is NULL");
+
+            let fatbin = fatbinary_new();
+            assert_eq!(fatbinary_entry_is_elf(fatbin, 0), -1);
+            assert!(fatbinary_entry_payload(fatbin, 0, null_mut()).is_null());
+            let message = CStr::from_ptr(fatbinary_last_error());
+            assert_eq!(message.to_str().unwrap(), "Entry 0 does not exist");
+            fatbinary_free(fatbin);
+            fatbinary_free(null_mut());
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Forward compatibility of entries with GPU architectures
+//!
+//! A cubin runs on GPUs of the same major version and equal or higher minor
+//! version, PTX is JIT-compiled for any GPU of equal or higher version.
+//! Arch-specific targets like `sm_90a` only run on exactly that GPU.
+//!
+//! Independent of the GPU, a driver loads a cubin only if its CUDA version
+//! knows the architecture of the cubin and its cubin ABI version, otherwise
+//! loading fails with `CUDA_ERROR_INVALID_SOURCE` or
+//! `CUDA_ERROR_NO_BINARY_FOR_GPU`.
+
+use crate::{EntryKind, FatBinary, FatBinaryEntry, SmArch};
+use object::elf;
+use object::read::elf::{ElfFile, FileHeader};
+
+/// e_flags bit of cubins built for arch-specific targets, e.g. `sm_90a`
+const EF_CUDA_ACCELERATORS: u32 = 0x800;
+
+/// How a fatbinary runs on a GPU, returned by [FatBinary::supports]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Support {
+    /// A compatible cubin is loaded directly
+    NativeCubin,
+    /// PTX of `from_arch` is JIT-compiled by the driver
+    PtxJit { from_arch: SmArch },
+    /// No entry runs on the GPU
+    Unsupported,
+}
+
+/// Whether a driver loads a cubin, returned by
+/// [FatBinaryEntry::driver_support]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DriverSupport {
+    /// The driver loads the cubin
+    Supported,
+    /// The driver predates the architecture of the cubin, which needs CUDA
+    /// `required`
+    ArchTooNew { arch: SmArch, required: (u16, u16) },
+    /// The driver predates the cubin ABI version, which needs CUDA
+    /// `required`, or is unknown to this crate if `required` is `None`
+    AbiTooNew {
+        abi_version: u8,
+        required: Option<(u16, u16)>,
+    },
+    /// The entry is not a cubin with a valid ELF header, or its
+    /// architecture is unknown
+    Unknown,
+}
+
+/// CUDA version first loading cubins of ABI version `abi_version`, `None`
+/// if unknown
+fn abi_cuda_version(abi_version: u8) -> Option<(u16, u16)> {
+    match abi_version {
+        0..=7 => Some((1, 0)),
+        8 => Some((12, 8)),
+        _ => None,
+    }
+}
+
+/// Whether the `.target` directive of PTX names an arch-specific target
+fn ptx_arch_specific(ptx: &str) -> bool {
+    ptx.lines()
+        .filter_map(|line| line.trim_start().strip_prefix(".target"))
+        .filter_map(|targets| targets.split([',', ' ', '\t']).find(|t| !t.is_empty()))
+        .any(|target| target.starts_with("sm_") && target.ends_with('a'))
+}
+
+/// Whether e_flags of cubin mark it arch-specific
+fn elf_arch_specific<Elf: FileHeader<Endian = object::Endianness>>(payload: &[u8]) -> bool {
+    match ElfFile::<Elf>::parse(payload) {
+        Ok(file) => file.elf_header().e_flags(file.endian()) & EF_CUDA_ACCELERATORS != 0,
+        Err(_) => false,
+    }
+}
+
+impl FatBinaryEntry<'_> {
+    /// Whether the entry targets an arch-specific variant like `sm_90a`,
+    /// which is not forward compatible
+    pub fn is_arch_specific(&self) -> bool {
+        let payload = self.get_decompressed_payload();
+        match self.kind() {
+            EntryKind::Ptx => core::str::from_utf8(crate::trim_nul(&payload))
+                .map(ptx_arch_specific)
+                .unwrap_or(false),
+            // e_ident[EI_CLASS]
+            EntryKind::Elf => match payload.get(4) {
+                Some(&elf::ELFCLASS64) => {
+                    elf_arch_specific::<elf::FileHeader64<object::Endianness>>(&payload)
+                }
+                Some(&elf::ELFCLASS32) => {
+                    elf_arch_specific::<elf::FileHeader32<object::Endianness>>(&payload)
+                }
+                _ => false,
+            },
+            EntryKind::Unknown(_) => false,
+        }
+    }
+
+    /// Check whether a driver of CUDA version `driver_version` as (major,
+    /// minor) loads this cubin, e.g. `(12, 4)`. `cuDriverGetVersion`
+    /// returns `1000 * major + 10 * minor`.
+    pub fn driver_support(&self, driver_version: (u16, u16)) -> DriverSupport {
+        let Some(cubin) = self.cubin_header() else {
+            return DriverSupport::Unknown;
+        };
+        match abi_cuda_version(cubin.abi_version) {
+            Some(required) if driver_version >= required => {}
+            required => {
+                return DriverSupport::AbiTooNew {
+                    abi_version: cubin.abi_version,
+                    required,
+                }
+            }
+        }
+        let arch = cubin.sm_arch();
+        match arch.cuda_version() {
+            Some(required) if driver_version < required => {
+                DriverSupport::ArchTooNew { arch, required }
+            }
+            Some(_) => DriverSupport::Supported,
+            None => DriverSupport::Unknown,
+        }
+    }
+
+    /// Whether the entry runs on GPU of `sm`, natively or after JIT
+    pub fn runs_on(&self, sm: u32) -> bool {
+        let arch = self.get_sm_arch();
+        if self.is_arch_specific() {
+            return sm == arch;
+        }
+        match self.kind() {
+            EntryKind::Elf => sm / 10 == arch / 10 && sm >= arch,
+            EntryKind::Ptx => sm >= arch,
+            EntryKind::Unknown(_) => false,
+        }
+    }
+}
+
+impl FatBinary<'_> {
+    /// Check how the fatbinary runs on GPU of `sm` (e.g. 86 for sm_86): a
+    /// compatible cubin is preferred, otherwise the PTX of the highest
+    /// compatible arch is JIT-compiled
+    pub fn supports(&self, sm: u32) -> Support {
+        let compatible = self.entries.iter().filter(|entry| entry.runs_on(sm));
+        if compatible
+            .clone()
+            .any(|entry| entry.kind() == EntryKind::Elf)
+        {
+            return Support::NativeCubin;
+        }
+        match compatible
+            .filter(|entry| entry.kind() == EntryKind::Ptx)
+            .map(|entry| entry.get_sm_arch())
+            .max()
+        {
+            Some(arch) => Support::PtxJit {
+                from_arch: SmArch(arch),
+            },
+            None => Support::Unsupported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DriverSupport, FatBinary, FatBinaryEntry, SmArch, Support, ELFOSABI_CUDA};
+
+    fn ptx(arch: &str) -> Vec<u8> {
+        format!(".version 8.0\n.target {}\n", arch).into_bytes()
+    }
+
+    #[test]
+    fn supports() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ptx("sm_70")));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(75, ptx("sm_75")));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(90, ptx("sm_90a")));
+
+        assert_eq!(fatbin.supports(60), Support::Unsupported);
+        assert_eq!(
+            fatbin.supports(70),
+            Support::PtxJit {
+                from_arch: SmArch(70)
+            }
+        );
+        assert_eq!(fatbin.supports(80), Support::NativeCubin);
+        assert_eq!(fatbin.supports(86), Support::NativeCubin);
+        assert_eq!(
+            fatbin.supports(90),
+            Support::PtxJit {
+                from_arch: SmArch(90)
+            }
+        );
+        // sm_90a is not forward compatible
+        assert_eq!(
+            fatbin.supports(100),
+            Support::PtxJit {
+                from_arch: SmArch(75)
+            }
+        );
+        assert!(fatbin.entries()[3].is_arch_specific());
+        assert!(!fatbin.entries()[2].is_arch_specific());
+    }
+
+    /// Cubin ELF header with ABI version and `e_flags`
+    fn cubin(abi_version: u8, flags: u32) -> Vec<u8> {
+        let mut res = b"\x7fELF\x02\x01\x01".to_vec();
+        res.push(ELFOSABI_CUDA);
+        res.push(abi_version);
+        res.resize(18, 0);
+        res.extend_from_slice(&object::elf::EM_CUDA.to_le_bytes());
+        res.resize(48, 0);
+        res.extend_from_slice(&flags.to_le_bytes());
+        res.resize(64, 0);
+        res
+    }
+
+    #[test]
+    fn driver_support() {
+        let sm_80 = FatBinaryEntry::new_auto(80, cubin(7, 0x0050_0550));
+        assert_eq!(sm_80.driver_support((11, 0)), DriverSupport::Supported);
+        assert_eq!(
+            sm_80.driver_support((10, 2)),
+            DriverSupport::ArchTooNew {
+                arch: SmArch(80),
+                required: (11, 0)
+            }
+        );
+
+        let sm_120 = FatBinaryEntry::new_auto(120, cubin(8, 0x7800));
+        assert_eq!(sm_120.driver_support((12, 8)), DriverSupport::Supported);
+        assert_eq!(
+            sm_120.driver_support((12, 4)),
+            DriverSupport::AbiTooNew {
+                abi_version: 8,
+                required: Some((12, 8))
+            }
+        );
+        assert_eq!(
+            FatBinaryEntry::new_auto(80, cubin(9, 0x5000)).driver_support((13, 0)),
+            DriverSupport::AbiTooNew {
+                abi_version: 9,
+                required: None
+            }
+        );
+        assert_eq!(
+            FatBinaryEntry::new_auto(99, cubin(7, 0x63)).driver_support((13, 0)),
+            DriverSupport::Unknown
+        );
+        assert_eq!(
+            FatBinaryEntry::new_auto(80, ptx("sm_80")).driver_support((13, 0)),
+            DriverSupport::Unknown
+        );
+    }
+}
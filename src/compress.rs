@@ -0,0 +1,293 @@
+//! Payload compression, the inverse of [crate::try_decompress]
+//!
+//! Compressed payloads use the LZ4 block format: a token with literal length
+//! and match length nibbles, literals, then a 2-byte match offset. Entries
+//! are compressed as one block, large payloads of other producers may be
+//! several blocks, which decompression detects.
+
+use crate::{
+    CancellationToken, FatBinary, FatBinaryEntry, FatBinaryEntryHeader, FatBinaryError, Payload,
+    Phase, FATBINARY_FLAG_COMPRESSED,
+};
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+const MIN_MATCH: usize = 4;
+// the last match must start at least 12 bytes before end of input
+const MF_LIMIT: usize = 12;
+// the last 5 bytes are always literals
+const LAST_LITERALS: usize = 5;
+const HASH_LOG: u32 = 16;
+const MAX_OFFSET: usize = 0xffff;
+
+/// Write length beyond the 4-bit token field
+fn write_length(res: &mut Vec<u8>, mut len: usize) {
+    while len >= 0xff {
+        res.push(0xff);
+        len -= 0xff;
+    }
+    res.push(len as u8);
+}
+
+/// Write literals followed by a match, or only literals if `offset` is 0
+fn write_sequence(res: &mut Vec<u8>, literals: &[u8], offset: usize, match_len: usize) {
+    let literal_len = literals.len();
+    let match_len = match_len.saturating_sub(MIN_MATCH);
+    res.push(((literal_len.min(0xf) as u8) << 4) | match_len.min(0xf) as u8);
+    if literal_len >= 0xf {
+        write_length(res, literal_len - 0xf);
+    }
+    res.extend_from_slice(literals);
+
+    if offset != 0 {
+        res.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 0xf {
+            write_length(res, match_len - 0xf);
+        }
+    }
+}
+
+/// Compress data with greedy hash-table matching
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(input.len() / 2 + 16);
+    // position + 1 of last occurrence of each hashed 4-byte sequence
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - MF_LIMIT;
+        let end_limit = input.len() - LAST_LITERALS;
+        while pos < match_limit {
+            let sequence = u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap());
+            let hash = (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize;
+            let candidate = table[hash];
+            table[hash] = pos + 1;
+
+            if candidate != 0 {
+                let candidate = candidate - 1;
+                if pos - candidate <= MAX_OFFSET
+                    && input[candidate..candidate + MIN_MATCH] == input[pos..pos + MIN_MATCH]
+                {
+                    let mut len = MIN_MATCH;
+                    while pos + len < end_limit && input[candidate + len] == input[pos + len] {
+                        len += 1;
+                    }
+                    write_sequence(&mut res, &input[anchor..pos], pos - candidate, len);
+                    pos += len;
+                    anchor = pos;
+                    continue;
+                }
+            }
+            pos += 1;
+        }
+    }
+
+    write_sequence(&mut res, &input[anchor..], 0, 0);
+    res
+}
+
+impl FatBinaryEntry<'_> {
+    /// Compress payload, `None` if already compressed or compression does not
+    /// make it smaller. Returns compressed payload padded to 8 bytes and its
+    /// unpadded length.
+    pub(crate) fn compressed_payload(&self) -> Option<(Vec<u8>, u32)> {
+        if self.is_compressed() {
+            return None;
+        }
+        let mut compressed = compress(&self.payload);
+        let compressed_size = u32::try_from(compressed.len()).ok()?;
+        compressed.resize(compressed.len().next_multiple_of(8), 0);
+        if compressed.len() >= self.payload.len() {
+            return None;
+        }
+        Some((compressed, compressed_size))
+    }
+
+    /// Entry header describing the given compressed payload
+    fn compressed_header(&self, payload: &[u8], compressed_size: u32) -> FatBinaryEntryHeader {
+        let mut entry_header = self.entry_header;
+        entry_header.flags |= FATBINARY_FLAG_COMPRESSED;
+        entry_header.decompressed_size = self.payload.len() as u64;
+        entry_header.compressed_size = compressed_size;
+        entry_header.size = payload.len() as u64;
+        entry_header
+    }
+
+    /// Copy of this entry with the given compressed payload, without copying
+    /// the original payload
+    #[cfg(feature = "std")]
+    pub(crate) fn with_compressed_payload(&self, payload: Vec<u8>, compressed_size: u32) -> Self {
+        Self {
+            entry_header: self.compressed_header(&payload, compressed_size),
+            ptxas_options: self.ptxas_options.clone(),
+            ptxas_options_offset: self.ptxas_options_offset,
+            identifier: self.identifier.clone(),
+            extra_header: self.extra_header.clone(),
+            payload: Payload::Owned(payload).into_aligned(),
+        }
+    }
+
+    /// Compress the payload if it makes it smaller, return whether the
+    /// payload is compressed afterwards
+    pub fn compress(&mut self) -> bool {
+        if let Some((payload, compressed_size)) = self.compressed_payload() {
+            self.entry_header = self.compressed_header(&payload, compressed_size);
+            self.payload = Payload::Owned(payload).into_aligned();
+        }
+        self.is_compressed()
+    }
+}
+
+impl FatBinary<'_> {
+    /// Compress every entry if it makes it smaller, return the number of
+    /// entries compressed by this call
+    pub fn compress(&mut self) -> usize {
+        self.compress_entries(None)
+            .expect("compression without cancellation cannot fail")
+    }
+
+    /// Compress entries like [FatBinary::compress], stopping with
+    /// [FatBinaryError::Cancelled] before the next entry once `cancel` is
+    /// cancelled. Entries compressed so far stay compressed.
+    pub fn compress_cancellable(
+        &mut self,
+        cancel: &CancellationToken,
+    ) -> Result<usize, FatBinaryError> {
+        self.compress_entries(Some(cancel))
+    }
+
+    fn compress_entries(
+        &mut self,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<usize, FatBinaryError> {
+        let mut res = 0;
+        for entry in &mut self.entries {
+            if let Some(cancel) = cancel {
+                cancel.check()?;
+            }
+            if !entry.is_compressed() && entry.compress() {
+                res += 1;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Decompress every entry, return the number of entries decompressed by
+    /// this call. Entries whose compressed payload is malformed stay
+    /// compressed.
+    pub fn decompress(&mut self) -> usize {
+        self.decompress_with_progress(|_, _, _| {})
+    }
+
+    /// Decompress every entry like [FatBinary::decompress], reporting
+    /// [Phase::Decompress] after each compressed entry
+    pub fn decompress_with_progress(&mut self, progress: impl Fn(u64, u64, Phase)) -> usize {
+        self.decompress_entries(progress, None)
+            .expect("decompression without cancellation cannot fail")
+    }
+
+    /// Decompress entries like [FatBinary::decompress], stopping with
+    /// [FatBinaryError::Cancelled] before the next entry once `cancel` is
+    /// cancelled. Entries decompressed so far stay decompressed.
+    pub fn decompress_cancellable(
+        &mut self,
+        cancel: &CancellationToken,
+    ) -> Result<usize, FatBinaryError> {
+        self.decompress_entries(|_, _, _| {}, Some(cancel))
+    }
+
+    fn decompress_entries(
+        &mut self,
+        progress: impl Fn(u64, u64, Phase),
+        cancel: Option<&CancellationToken>,
+    ) -> Result<usize, FatBinaryError> {
+        let total = self
+            .entries
+            .iter()
+            .filter(|entry| entry.is_compressed())
+            .map(|entry| entry.entry_header.decompressed_size)
+            .fold(0, u64::saturating_add);
+        let mut done = 0u64;
+        let mut res = 0;
+        for entry in &mut self.entries {
+            if let Some(cancel) = cancel {
+                cancel.check()?;
+            }
+            if entry.is_compressed() {
+                let decompressed_size = entry.entry_header.decompressed_size;
+                if entry.try_decompress().is_ok() {
+                    res += 1;
+                }
+                done = done.saturating_add(decompressed_size);
+                progress(done, total, Phase::Decompress);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compress::compress;
+    use crate::{try_decompress, FatBinary, FatBinaryEntry};
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            b"short".to_vec(),
+            b"abcdabcdabcdabcdabcdabcdabcd".to_vec(),
+            vec![0u8; 100000],
+        ];
+        // long literal run followed by repetition
+        let mut data: Vec<u8> = (0..1000u32).map(|i| (i * 7919 % 251) as u8).collect();
+        data.extend_from_within(..);
+        inputs.push(data);
+
+        for input in inputs {
+            assert_eq!(
+                try_decompress(&compress(&input), input.len()).unwrap(),
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn compress_entry() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.as_bytes());
+        assert!(entry.compress());
+        assert!(entry.get_payload().len() < ptx.len());
+        assert_eq!(entry.get_decompressed_payload(), ptx.as_bytes());
+
+        // incompressible payloads stay uncompressed
+        let mut entry = FatBinaryEntry::new_auto(70, b"\x7fELF".to_vec());
+        assert!(!entry.compress());
+    }
+
+    #[test]
+    fn compress_fatbin() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ptx.as_bytes()));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+        let original = fatbin.clone();
+
+        assert_eq!(fatbin.compress(), 1);
+        assert_eq!(fatbin.compress(), 0);
+        assert!(fatbin.entries()[0].is_compressed());
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        assert_eq!(FatBinary::parse(&buffer).unwrap(), fatbin);
+
+        assert_eq!(fatbin.decompress(), 1);
+        assert_eq!(fatbin.decompress(), 0);
+        assert_eq!(fatbin, original);
+    }
+}
@@ -0,0 +1,264 @@
+//! Concatenating PTX modules of the same target
+//!
+//! A fatbinary linked from many translation units carries one PTX module
+//! per unit and arch. Modules with the same `.target` and `.address_size`
+//! and `.version` of the same major version are concatenated into one
+//! module, keeping a single header with the highest `.version`.
+
+use crate::{trim_nul, EntryKind, FatBinary, FatBinaryError};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Header directives and remaining lines of a PTX module
+#[derive(Debug)]
+struct PtxModule<'p> {
+    version: Option<(u32, u32)>,
+    /// Targets like `sm_80` or `sm_52, debug`, split at commas
+    target: Option<Vec<&'p str>>,
+    address_size: Option<&'p str>,
+    body: Vec<&'p str>,
+}
+
+/// Operands of directive `name` on `line`, without comment
+fn directive<'p>(line: &'p str, name: &str) -> Option<&'p str> {
+    let line = line.split("//").next().unwrap_or_default().trim();
+    let operands = line.strip_prefix(name)?;
+    (operands.is_empty() || operands.starts_with(char::is_whitespace)).then(|| operands.trim())
+}
+
+impl<'p> PtxModule<'p> {
+    fn parse(ptx: &'p str) -> Self {
+        let mut res = PtxModule {
+            version: None,
+            target: None,
+            address_size: None,
+            body: Vec::new(),
+        };
+        for line in ptx.lines() {
+            if let Some(version) = directive(line, ".version") {
+                res.version = version
+                    .split_once('.')
+                    .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+            } else if let Some(target) = directive(line, ".target") {
+                res.target = Some(target.split(',').map(str::trim).collect());
+            } else if let Some(address_size) = directive(line, ".address_size") {
+                res.address_size = Some(address_size);
+            } else {
+                res.body.push(line);
+            }
+        }
+        res
+    }
+
+    /// Modules with equal keys can be concatenated, `None` without
+    /// `.version` or `.target`
+    fn key(&self) -> Option<(Vec<&'p str>, u32, Option<&'p str>)> {
+        Some((self.target.clone()?, self.version?.0, self.address_size))
+    }
+}
+
+/// Concatenate PTX modules into one, keeping a single `.version`,
+/// `.target` and `.address_size` header. Fails if the modules lack a
+/// `.version` or `.target`, or their targets, address sizes or major
+/// versions differ.
+pub fn concat_ptx<'p>(
+    modules: impl IntoIterator<Item = &'p str>,
+) -> Result<String, FatBinaryError> {
+    let incompatible = |message: &str| FatBinaryError::IncompatiblePtx {
+        message: message.to_string(),
+    };
+    let modules: Vec<_> = modules.into_iter().map(PtxModule::parse).collect();
+    let Some(first) = modules.first() else {
+        return Err(incompatible("no modules"));
+    };
+    let key = first
+        .key()
+        .ok_or_else(|| incompatible("missing .version or .target"))?;
+    let mut version = first.version.unwrap_or_default();
+    for module in &modules[1..] {
+        match module.key() {
+            None => return Err(incompatible("missing .version or .target")),
+            Some(other) if other.0 != key.0 => return Err(incompatible("different .target")),
+            Some(other) if other.1 != key.1 => {
+                return Err(incompatible("different major .version"))
+            }
+            Some(other) if other.2 != key.2 => return Err(incompatible("different .address_size")),
+            Some(_) => version = version.max(module.version.unwrap_or_default()),
+        }
+    }
+
+    let mut res = format!(
+        ".version {}.{}\n.target {}\n",
+        version.0,
+        version.1,
+        key.0.join(", ")
+    );
+    if let Some(address_size) = key.2 {
+        res += &format!(".address_size {}\n", address_size);
+    }
+    for module in &modules {
+        for line in &module.body {
+            res += line;
+            res.push('\n');
+        }
+    }
+    Ok(res)
+}
+
+impl FatBinary<'_> {
+    /// Concatenate PTX entries of the same arch which [concat_ptx] accepts
+    /// into the first of them, e.g. to get one JIT-compilable module per
+    /// arch. Its identifier and ptxas options are kept if all entries
+    /// agree, and dropped otherwise. PTX without `.version` or `.target` is
+    /// left alone. Returns number of entries removed.
+    pub fn bundle_ptx(&mut self) -> Result<usize, FatBinaryError> {
+        let mut sources = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            sources.push(if entry.kind() == EntryKind::Ptx {
+                let payload = entry.try_get_decompressed_payload()?;
+                Some(String::from_utf8(trim_nul(&payload).to_vec())?)
+            } else {
+                None
+            });
+        }
+
+        let mut groups: BTreeMap<_, Vec<usize>> = BTreeMap::new();
+        for (index, source) in sources.iter().enumerate() {
+            let Some(key) = source
+                .as_deref()
+                .and_then(|source| PtxModule::parse(source).key())
+            else {
+                continue;
+            };
+            groups
+                .entry((self.entries[index].get_sm_arch(), key))
+                .or_default()
+                .push(index);
+        }
+
+        let mut removed = alloc::vec![false; self.entries.len()];
+        for indices in groups.values().filter(|indices| indices.len() > 1) {
+            let ptx = concat_ptx(indices.iter().filter_map(|&i| sources[i].as_deref()))?;
+            let (first, rest) = (indices[0], &indices[1..]);
+            let version = indices
+                .iter()
+                .map(|&i| {
+                    let entry = &self.entries[i];
+                    (entry.get_version_major(), entry.get_version_minor())
+                })
+                .max()
+                .unwrap_or_default();
+            let same_identifier = rest
+                .iter()
+                .all(|&i| self.entries[i].identifier == self.entries[first].identifier);
+            let same_options = rest
+                .iter()
+                .all(|&i| self.entries[i].ptxas_options == self.entries[first].ptxas_options);
+
+            let entry = &mut self.entries[first];
+            entry.set_ptx(&ptx)?;
+            entry.set_version(version.0, version.1);
+            if !same_identifier {
+                entry.identifier = None;
+            }
+            if !same_options {
+                entry.ptxas_options = None;
+            }
+            entry.update_layout();
+            for &i in rest {
+                removed[i] = true;
+            }
+        }
+
+        let count = self.entries.len();
+        let mut removed = removed.into_iter();
+        self.entries.retain(|_| !removed.next().unwrap_or(false));
+        Ok(count - self.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{concat_ptx, FatBinary, FatBinaryEntry, FatBinaryError};
+
+    const A: &str = "//\n// a.cu\n//\n\n.version 7.0\n.target sm_80\n.address_size 64\n\n.visible .entry a()\n{\n}\n";
+    const B: &str =
+        ".version 7.4 // b.cu\n.target sm_80\n.address_size 64\n.visible .entry b()\n{\n}\n";
+
+    #[test]
+    fn concat() {
+        let ptx = concat_ptx([A, B]).unwrap();
+        assert_eq!(
+            ptx,
+            ".version 7.4\n.target sm_80\n.address_size 64\n//\n// a.cu\n//\n\n\n.visible .entry a()\n{\n}\n.visible .entry b()\n{\n}\n"
+        );
+        assert_eq!(concat_ptx([A]).unwrap().matches(".version").count(), 1);
+
+        for (other, message) in [
+            (B.replace("sm_80", "sm_80, debug"), "different .target"),
+            (B.replace("7.4", "8.0"), "different major .version"),
+            (B.replace("64", "32"), "different .address_size"),
+            (
+                B.replace(".target sm_80\n", ""),
+                "missing .version or .target",
+            ),
+        ] {
+            assert!(matches!(
+                concat_ptx([A, other.as_str()]),
+                Err(FatBinaryError::IncompatiblePtx { message: m }) if m == message
+            ));
+        }
+        assert!(concat_ptx([]).is_err());
+    }
+
+    #[test]
+    fn bundle_ptx() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(80, A.as_bytes());
+        entry.set_identifier(Some("a.cu"));
+        entry.set_ptxas_options(Some("-O3"));
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".as_slice()));
+        let mut entry = FatBinaryEntry::new_auto(80, B.as_bytes());
+        entry.set_identifier(Some("b.cu"));
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_version(7, 4);
+        fatbin.entries_mut().push(entry);
+        let sm_90 = A.replace("sm_80", "sm_90");
+        for ptx in [
+            sm_90.as_str(),
+            sm_90.as_str(),
+            B.replace("7.4", "8.0").as_str(),
+        ] {
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(90, ptx.as_bytes().to_vec()));
+        }
+
+        assert_eq!(fatbin.bundle_ptx().unwrap(), 2);
+        let entries = fatbin.entries();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[0].ptx_source().unwrap(),
+            concat_ptx([A, B]).unwrap()
+        );
+        assert_eq!(entries[0].get_identifier(), None);
+        assert_eq!(entries[0].get_ptxas_options(), Some("-O3"));
+        assert_eq!(entries[0].get_version_minor(), 4);
+        assert_eq!(entries[1].get_payload(), b"\x7fELF");
+        assert_eq!(
+            entries[2].ptx_source().unwrap().matches(".entry a").count(),
+            2
+        );
+        assert!(entries[3].ptx_source().unwrap().contains(".version 8.0"));
+
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        assert_eq!(FatBinary::parse(&buffer).unwrap(), fatbin);
+        assert_eq!(fatbin.bundle_ptx().unwrap(), 0);
+    }
+}
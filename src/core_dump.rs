@@ -0,0 +1,268 @@
+//! Carving fatbinaries out of ELF core dumps
+//!
+//! Every `PT_LOAD` segment with data in the core dump is searched for
+//! fatbinary headers at 8-byte aligned addresses, and fatbinaries are
+//! associated with the file mapped at their address by the `NT_FILE` note.
+//! Fatbinaries in anonymous memory, e.g. those created at runtime, have no
+//! path.
+
+use crate::{FatBinary, FatBinaryError, FAT_BINARY_MAGIC};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::{elf, Endianness};
+
+/// Fatbinary found by [FatBinary::find_in_core_dump]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreDumpFatBinary<'a> {
+    /// Virtual address of the fatbinary in the dumped process
+    pub address: u64,
+    /// Offset of the fatbinary in the core dump
+    pub offset: u64,
+    /// Path of the file mapped at `address`, `None` for anonymous memory
+    pub path: Option<String>,
+    pub fatbin: FatBinary<'a>,
+}
+
+/// File mapping from the `NT_FILE` note
+struct FileMapping {
+    start: u64,
+    end: u64,
+    path: String,
+}
+
+/// Parse descriptor of `NT_FILE` note: count and page size, then start, end
+/// and file offset of each mapping, then NUL-terminated paths
+fn parse_nt_file(desc: &[u8], word_size: usize, endian: Endianness) -> Option<Vec<FileMapping>> {
+    let word = |index: usize| -> Option<u64> {
+        let bytes = desc.get(index * word_size..(index + 1) * word_size)?;
+        let mut buf = [0u8; 8];
+        match endian {
+            Endianness::Little => buf[..word_size].copy_from_slice(bytes),
+            Endianness::Big => buf[8 - word_size..].copy_from_slice(bytes),
+        }
+        Some(match endian {
+            Endianness::Little => u64::from_le_bytes(buf),
+            Endianness::Big => u64::from_be_bytes(buf),
+        })
+    };
+    let count = usize::try_from(word(0)?).ok()?;
+    let mut paths = desc
+        .get(
+            count
+                .checked_mul(3)?
+                .checked_add(2)?
+                .checked_mul(word_size)?..,
+        )?
+        .split(|&byte| byte == 0);
+    let mut res = Vec::new();
+    for index in 0..count {
+        res.push(FileMapping {
+            start: word(2 + index * 3)?,
+            end: word(3 + index * 3)?,
+            path: String::from_utf8_lossy(paths.next()?).to_string(),
+        });
+    }
+    Some(res)
+}
+
+/// Fatbinaries in `data`, a segment loaded at `address`
+fn carve(data: &[u8], address: u64) -> Vec<(usize, FatBinary<'_>)> {
+    let mut res = Vec::new();
+    // distance to the next multiple of 8, addresses come from untrusted
+    // program headers and may be close to overflowing
+    let mut offset = (address.wrapping_neg() % 8) as usize;
+    while offset + 16 <= data.len() {
+        if data[offset..offset + 4] != FAT_BINARY_MAGIC.to_le_bytes() {
+            offset += 8;
+            continue;
+        }
+        // header_size at offset 6, size of entries at offset 8
+        let header_size = u16::from_le_bytes([data[offset + 6], data[offset + 7]]) as u64;
+        let size = u64::from_le_bytes(data[offset + 8..offset + 16].try_into().unwrap());
+        let fatbin = header_size
+            .checked_add(size)
+            .and_then(|len| usize::try_from(len).ok())
+            .and_then(|len| data.get(offset..offset.checked_add(len)?))
+            .and_then(|data| Some((data.len(), FatBinary::parse(data).ok()?)));
+        match fatbin {
+            Some((len, fatbin)) => {
+                res.push((offset, fatbin));
+                offset = (offset + len).next_multiple_of(8);
+            }
+            // magic by chance, or a truncated fatbinary
+            None => offset += 8,
+        }
+    }
+    res
+}
+
+fn find_in_core_dump<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Result<Vec<CoreDumpFatBinary<'_>>, FatBinaryError> {
+    let invalid = |message: String| FatBinaryError::InvalidCoreDump { message };
+    let header = Elf::parse(data).map_err(|err| invalid(err.to_string()))?;
+    let endian = header.endian().map_err(|err| invalid(err.to_string()))?;
+    if header.e_type(endian) != elf::ET_CORE {
+        return Err(invalid("not a core dump".to_string()));
+    }
+    let segments = header
+        .program_headers(endian, data)
+        .map_err(|err| invalid(err.to_string()))?;
+
+    let word_size = if header.is_class_64() { 8 } else { 4 };
+    let mut mappings = Vec::new();
+    for segment in segments {
+        let Some(mut notes) = segment
+            .notes(endian, data)
+            .map_err(|err| invalid(err.to_string()))?
+        else {
+            continue;
+        };
+        while let Some(note) = notes.next().map_err(|err| invalid(err.to_string()))? {
+            if note.name() == b"CORE" && note.n_type(endian) == elf::NT_FILE {
+                mappings = parse_nt_file(note.desc(), word_size, endian)
+                    .ok_or_else(|| invalid("malformed NT_FILE note".to_string()))?;
+            }
+        }
+    }
+
+    let mut res = Vec::new();
+    for segment in segments {
+        if segment.p_type(endian) != elf::PT_LOAD {
+            continue;
+        }
+        let address: u64 = segment.p_vaddr(endian).into();
+        let Ok(segment_data) = segment.data(endian, data) else {
+            continue;
+        };
+        let segment_offset: u64 = segment.p_offset(endian).into();
+        for (offset, fatbin) in carve(segment_data, address) {
+            let address = address.wrapping_add(offset as u64);
+            let path = mappings
+                .iter()
+                .find(|mapping| (mapping.start..mapping.end).contains(&address))
+                .map(|mapping| mapping.path.clone());
+            res.push(CoreDumpFatBinary {
+                address,
+                offset: segment_offset + offset as u64,
+                path,
+                fatbin,
+            });
+        }
+    }
+    Ok(res)
+}
+
+impl<'a> FatBinary<'a> {
+    /// Find all fatbinaries in memory saved in ELF core dump `data`, in
+    /// order of segments and addresses. Payloads are borrowed from `data`.
+    pub fn find_in_core_dump(data: &'a [u8]) -> Result<Vec<CoreDumpFatBinary<'a>>, FatBinaryError> {
+        // e_ident[EI_CLASS]
+        match data.get(4) {
+            Some(&elf::ELFCLASS32) => find_in_core_dump::<elf::FileHeader32<Endianness>>(data),
+            _ => find_in_core_dump::<elf::FileHeader64<Endianness>>(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+
+    /// Little endian 64-bit core dump with a note segment and two load
+    /// segments, the first mapped from `path`
+    fn core_dump(path: &str, segments: &[(u64, &[u8])]) -> Vec<u8> {
+        let word = |res: &mut Vec<u8>, value: u64| res.extend_from_slice(&value.to_le_bytes());
+
+        let mut desc = Vec::new();
+        word(&mut desc, 1);
+        word(&mut desc, 0x1000);
+        word(&mut desc, segments[0].0);
+        word(&mut desc, segments[0].0 + segments[0].1.len() as u64);
+        word(&mut desc, 0);
+        desc.extend_from_slice(path.as_bytes());
+        desc.push(0);
+        desc.resize(desc.len().next_multiple_of(4), 0);
+        let mut note = Vec::new();
+        note.extend_from_slice(&5u32.to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&object::elf::NT_FILE.to_le_bytes());
+        note.extend_from_slice(b"CORE\0\0\0\0");
+        note.extend_from_slice(&desc);
+
+        let phnum = 1 + segments.len();
+        let mut offset = 64 + 56 * phnum as u64;
+        let mut res = Vec::new();
+        res.extend_from_slice(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0");
+        res.extend_from_slice(&object::elf::ET_CORE.to_le_bytes());
+        res.extend_from_slice(&object::elf::EM_X86_64.to_le_bytes());
+        res.extend_from_slice(&1u32.to_le_bytes());
+        word(&mut res, 0); // e_entry
+        word(&mut res, 64); // e_phoff
+        word(&mut res, 0); // e_shoff
+        res.extend_from_slice(&0u32.to_le_bytes());
+        for value in [64u16, 56, phnum as u16, 64, 0, 0] {
+            res.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut program_header = |res: &mut Vec<u8>, p_type: u32, vaddr: u64, size: u64| {
+            res.extend_from_slice(&p_type.to_le_bytes());
+            res.extend_from_slice(&4u32.to_le_bytes()); // p_flags
+            for value in [offset, vaddr, 0, size, size, 4] {
+                word(res, value);
+            }
+            offset += size;
+        };
+        program_header(&mut res, object::elf::PT_NOTE, 0, note.len() as u64);
+        for (address, data) in segments {
+            program_header(&mut res, object::elf::PT_LOAD, *address, data.len() as u64);
+        }
+        res.extend_from_slice(&note);
+        for (_, data) in segments {
+            res.extend_from_slice(data);
+        }
+        res
+    }
+
+    #[test]
+    fn find_in_core_dump() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(4)));
+        let mut bytes = vec![];
+        fatbin.write(&mut bytes).unwrap();
+
+        // fatbinary after unrelated data in a mapped library, and twice on
+        // the heap with a truncated copy in between
+        let mut library = b"\x50\xed\x55\xba garbage".to_vec();
+        library.resize(32, 0);
+        library.extend_from_slice(&bytes);
+        let mut heap = bytes.clone();
+        heap.resize(heap.len().next_multiple_of(8), 0);
+        heap.extend_from_slice(&bytes[..40]);
+        heap.resize(heap.len().next_multiple_of(8), 0);
+        heap.extend_from_slice(&bytes);
+        let data = core_dump(
+            "/usr/lib/libkernels.so",
+            &[(0x7f0000001000, &library), (0x5500000000, &heap)],
+        );
+
+        let found = FatBinary::find_in_core_dump(&data).unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].address, 0x7f0000001000 + 32);
+        assert_eq!(found[0].path.as_deref(), Some("/usr/lib/libkernels.so"));
+        assert_eq!(found[0].fatbin, fatbin);
+        let offset = found[0].offset as usize;
+        assert_eq!(&data[offset..offset + bytes.len()], bytes);
+        assert_eq!(found[1].address, 0x5500000000);
+        assert_eq!(found[1].path, None);
+        assert_eq!(found[2].fatbin, fatbin);
+
+        assert!(matches!(
+            FatBinary::find_in_core_dump(b"not elf"),
+            Err(FatBinaryError::InvalidCoreDump { .. })
+        ));
+    }
+}
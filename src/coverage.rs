@@ -0,0 +1,177 @@
+//! Which code a fatbinary carries for each architecture
+//!
+//! Entries are native SASS (cubins), PTX or LTO-IR, which `nvcc -dlto`
+//! embeds with kind [LTO_IR_KIND] for link-time optimization. Versions are
+//! those recorded in entry headers, e.g. the PTX ISA version of PTX.
+
+use crate::{EntryKind, FatBinary, SmArch};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Entry kind of LTO-IR, see [EntryKind::Unknown]
+pub const LTO_IR_KIND: u16 = 0x8;
+
+/// Versions as (major, minor) of each kind of code for one architecture,
+/// empty if the kind is absent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ArchCoverage {
+    pub sass: BTreeSet<(u16, u16)>,
+    pub ptx: BTreeSet<(u16, u16)>,
+    pub lto_ir: BTreeSet<(u16, u16)>,
+}
+
+impl ArchCoverage {
+    pub fn has_sass(&self) -> bool {
+        !self.sass.is_empty()
+    }
+
+    pub fn has_ptx(&self) -> bool {
+        !self.ptx.is_empty()
+    }
+
+    pub fn has_lto_ir(&self) -> bool {
+        !self.lto_ir.is_empty()
+    }
+}
+
+/// Coverage of each architecture of a fatbinary, returned by
+/// [FatBinary::coverage]. Architectures of entries of other kinds are
+/// listed with nothing present.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CoverageMatrix {
+    archs: BTreeMap<SmArch, ArchCoverage>,
+}
+
+impl CoverageMatrix {
+    /// Coverage of `arch`, `None` if no entry targets it
+    pub fn get(&self, arch: SmArch) -> Option<&ArchCoverage> {
+        self.archs.get(&arch)
+    }
+
+    /// Coverage of each architecture in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (SmArch, &ArchCoverage)> {
+        self.archs.iter().map(|(arch, coverage)| (*arch, coverage))
+    }
+
+    /// Number of architectures
+    pub fn len(&self) -> usize {
+        self.archs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archs.is_empty()
+    }
+}
+
+/// Versions like `7.0,8.0`, `-` if none
+fn versions(versions: &BTreeSet<(u16, u16)>) -> String {
+    if versions.is_empty() {
+        return "-".to_string();
+    }
+    versions
+        .iter()
+        .map(|(major, minor)| alloc::format!("{}.{}", major, minor))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl core::fmt::Display for CoverageMatrix {
+    /// One line per architecture, e.g. `sm_80    8.0      8.0      -`
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "{:<8} {:<8} {:<8} LTO-IR", "arch", "SASS", "PTX")?;
+        for (arch, coverage) in self.iter() {
+            writeln!(
+                f,
+                "{:<8} {:<8} {:<8} {}",
+                arch.to_string(),
+                versions(&coverage.sass),
+                versions(&coverage.ptx),
+                versions(&coverage.lto_ir)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FatBinary<'_> {
+    /// Summarize which architectures have native SASS, PTX and LTO-IR, and
+    /// at which versions
+    pub fn coverage(&self) -> CoverageMatrix {
+        let mut res = CoverageMatrix::default();
+        for entry in &self.entries {
+            let coverage = res.archs.entry(SmArch(entry.get_sm_arch())).or_default();
+            let version = (entry.get_version_major(), entry.get_version_minor());
+            match entry.kind() {
+                EntryKind::Elf => coverage.sass.insert(version),
+                EntryKind::Ptx => coverage.ptx.insert(version),
+                EntryKind::Unknown(LTO_IR_KIND) => coverage.lto_ir.insert(version),
+                EntryKind::Unknown(_) => false,
+            };
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryEntryHeader, SmArch, LTO_IR_KIND};
+
+    #[test]
+    fn coverage() {
+        let mut fatbin = FatBinary::new();
+        for (arch, payload) in [
+            (70, b".target sm_70\n".as_slice()),
+            (70, b"\x7fELF".as_slice()),
+            (80, b".target sm_80\n".as_slice()),
+        ] {
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, payload));
+        }
+        let mut entry = FatBinaryEntry::new_auto(80, b".target sm_80\n".as_slice());
+        entry.set_version(8, 0);
+        fatbin.entries_mut().push(entry);
+        let header = FatBinaryEntryHeader {
+            kind: LTO_IR_KIND,
+            arch: 90,
+            major: 1,
+            minor: 2,
+            ..Default::default()
+        };
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::from_raw_header(header, b"lto".as_slice()));
+        let header = FatBinaryEntryHeader {
+            kind: 0x10,
+            arch: 100,
+            ..Default::default()
+        };
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::from_raw_header(header, Vec::new()));
+
+        let coverage = fatbin.coverage();
+        assert_eq!(coverage.len(), 4);
+        let sm_70 = coverage.get(SmArch(70)).unwrap();
+        assert!(sm_70.has_sass() && sm_70.has_ptx() && !sm_70.has_lto_ir());
+        let sm_80 = coverage.get(SmArch(80)).unwrap();
+        assert!(!sm_80.has_sass());
+        assert_eq!(sm_80.ptx.len(), 2);
+        assert!(coverage.get(SmArch(90)).unwrap().has_lto_ir());
+        assert_eq!(coverage.get(SmArch(100)).unwrap(), &Default::default());
+        assert_eq!(coverage.get(SmArch(75)), None);
+
+        let table = coverage.to_string();
+        let ptx_80 = fatbin.entries()[2].get_version_major();
+        assert!(table.starts_with("arch     SASS     PTX      LTO-IR\n"));
+        assert!(table.contains(&format!(
+            "sm_80    -        {}.{},8.0  -\n",
+            ptx_80,
+            fatbin.entries()[2].get_version_minor()
+        )));
+        assert!(table.contains("sm_90    -        -        1.2\n"));
+        assert!(table.ends_with("sm_100   -        -        -\n"));
+        assert!(FatBinary::new().coverage().is_empty());
+    }
+}
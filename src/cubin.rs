@@ -0,0 +1,300 @@
+//! Decoding ELF headers of cubins
+//!
+//! Cubins have OS ABI `ELFOSABI_CUDA` and machine `EM_CUDA`. The ABI version
+//! in `e_ident` selects the layout of `e_flags`, which record the SM the
+//! cubin was compiled for:
+//!
+//! - version 7: SM in bits 0-7, virtual SM of the PTX in bits 16-23,
+//!   `0x400` for 64-bit addresses and `0x800` for arch-specific targets
+//! - version 8 (CUDA 12.8 and later): SM in bits 8-15, `0x8` for
+//!   arch-specific targets
+
+use crate::{EntryKind, FatBinaryEntry, SmArch};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::{elf, Object, ObjectSection};
+
+/// `e_ident[EI_OSABI]` of cubins
+pub const ELFOSABI_CUDA: u8 = 0x33;
+
+/// `e_ident[EI_ABIVERSION]` of cubins with the original `e_flags` layout
+const ABI_VERSION_V1: u8 = 7;
+
+/// Decoded ELF header of a cubin, see [FatBinaryEntry::cubin_header]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CubinHeader {
+    /// `ELFCLASS64` rather than `ELFCLASS32`
+    pub is_64bit: bool,
+    /// `e_ident[EI_OSABI]`, [ELFOSABI_CUDA] for cubins
+    pub os_abi: u8,
+    /// `e_ident[EI_ABIVERSION]`, the cubin ABI version
+    pub abi_version: u8,
+    /// `e_type`, e.g. `ET_EXEC` or `ET_REL` for relocatable device code
+    pub e_type: u16,
+    /// `e_machine`, `EM_CUDA` for cubins
+    pub machine: u16,
+    /// `e_flags`
+    pub flags: u32,
+}
+
+impl CubinHeader {
+    /// Decode ELF header at the start of `payload`, `None` if it is not
+    /// ELF or too short
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.len() < 20 || !payload.starts_with(b"\x7fELF") {
+            return None;
+        }
+        // e_ident[EI_CLASS]
+        let is_64bit = match payload[4] {
+            elf::ELFCLASS32 => false,
+            elf::ELFCLASS64 => true,
+            _ => return None,
+        };
+        // e_ident[EI_DATA]
+        let big_endian = payload[5] == elf::ELFDATA2MSB;
+        let u16_at = |offset: usize| {
+            let bytes = [payload[offset], payload[offset + 1]];
+            if big_endian {
+                u16::from_be_bytes(bytes)
+            } else {
+                u16::from_le_bytes(bytes)
+            }
+        };
+        // e_flags follows e_entry, e_phoff and e_shoff, which are words
+        let flags_offset = if is_64bit { 48 } else { 36 };
+        if payload.len() < flags_offset + 4 {
+            return None;
+        }
+        let bytes: [u8; 4] = payload[flags_offset..flags_offset + 4].try_into().unwrap();
+        Some(CubinHeader {
+            is_64bit,
+            // e_ident[EI_OSABI] and e_ident[EI_ABIVERSION]
+            os_abi: payload[7],
+            abi_version: payload[8],
+            e_type: u16_at(16),
+            machine: u16_at(18),
+            flags: if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            },
+        })
+    }
+
+    /// Whether OS ABI and machine are those of cubins
+    pub fn is_cuda(&self) -> bool {
+        self.os_abi == ELFOSABI_CUDA && self.machine == elf::EM_CUDA
+    }
+
+    /// SM the cubin was compiled for, from `e_flags`
+    pub fn sm_arch(&self) -> SmArch {
+        if self.abi_version <= ABI_VERSION_V1 {
+            SmArch(self.flags & 0xff)
+        } else {
+            SmArch((self.flags >> 8) & 0xff)
+        }
+    }
+
+    /// Virtual SM of the PTX the cubin was compiled from, only recorded
+    /// by ABI version 7 and earlier
+    pub fn virtual_arch(&self) -> Option<SmArch> {
+        let arch = (self.flags >> 16) & 0xff;
+        (self.abi_version <= ABI_VERSION_V1 && arch != 0).then_some(SmArch(arch))
+    }
+
+    /// Whether the cubin targets an arch-specific variant like `sm_90a`
+    pub fn is_arch_specific(&self) -> bool {
+        if self.abi_version <= ABI_VERSION_V1 {
+            self.flags & 0x800 != 0
+        } else {
+            self.flags & 0x8 != 0
+        }
+    }
+}
+
+/// Debug sections of a cubin, returned by
+/// [FatBinaryEntry::debug_info_summary]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DebugInfoSummary {
+    /// Names and sizes of DWARF `.debug_*` sections and NVIDIA's
+    /// `.nv_debug_*` sections, e.g. `.nv_debug_line_sass`, in file order
+    pub sections: Vec<(String, u64)>,
+}
+
+impl DebugInfoSummary {
+    /// Size of section `name`, `None` if absent
+    pub fn size(&self, name: &str) -> Option<u64> {
+        self.sections
+            .iter()
+            .find(|(section, _)| section == name)
+            .map(|&(_, size)| size)
+    }
+
+    /// Total size of the debug sections
+    pub fn total_size(&self) -> u64 {
+        self.sections.iter().map(|&(_, size)| size).sum()
+    }
+
+    /// Whether line tables are present, as with `-lineinfo` or `-G`
+    pub fn has_line_info(&self) -> bool {
+        self.size(".debug_line").is_some()
+    }
+
+    /// Whether full DWARF is present, as with `-G`
+    pub fn has_full_debug_info(&self) -> bool {
+        self.size(".debug_info").is_some()
+    }
+}
+
+impl FatBinaryEntry<'_> {
+    /// Decoded ELF header of the decompressed payload, `None` if the entry
+    /// is not ELF or the payload has no valid ELF header
+    pub fn cubin_header(&self) -> Option<CubinHeader> {
+        if self.kind() != EntryKind::Elf {
+            return None;
+        }
+        CubinHeader::parse(&self.get_decompressed_payload())
+    }
+
+    /// Whether the decompressed payload is ELF with DWARF `.debug_*`
+    /// sections, which cubins compiled with `-G` carry
+    pub fn has_dwarf(&self) -> bool {
+        if self.kind() != EntryKind::Elf {
+            return false;
+        }
+        let payload = self.get_decompressed_payload();
+        let Ok(file) = object::File::parse(&*payload) else {
+            return false;
+        };
+        file.sections()
+            .any(|section| section.name().is_ok_and(|name| name.starts_with(".debug_")))
+    }
+
+    /// Debug sections of the decompressed payload and their sizes, `None` if
+    /// the entry is not ELF or the payload is malformed. Unlike the debug
+    /// flag, this shows what `-G` or `-lineinfo` actually embedded.
+    pub fn debug_info_summary(&self) -> Option<DebugInfoSummary> {
+        if self.kind() != EntryKind::Elf {
+            return None;
+        }
+        let payload = self.get_decompressed_payload();
+        let file = object::File::parse(&*payload).ok()?;
+        let sections = file
+            .sections()
+            .filter_map(|section| {
+                let name = section.name().ok()?;
+                (name.starts_with(".debug_") || name.starts_with(".nv_debug_"))
+                    .then(|| (name.to_string(), section.size()))
+            })
+            .collect();
+        Some(DebugInfoSummary { sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CubinHeader, FatBinaryEntry, SmArch, ELFOSABI_CUDA};
+    use object::elf;
+
+    /// 64-bit little endian ELF header
+    fn header(abi_version: u8, flags: u32) -> Vec<u8> {
+        let mut res = b"\x7fELF\x02\x01\x01".to_vec();
+        res.push(ELFOSABI_CUDA);
+        res.push(abi_version);
+        res.resize(16, 0);
+        res.extend_from_slice(&elf::ET_EXEC.to_le_bytes());
+        res.extend_from_slice(&elf::EM_CUDA.to_le_bytes());
+        res.resize(48, 0);
+        res.extend_from_slice(&flags.to_le_bytes());
+        res.resize(64, 0);
+        res
+    }
+
+    #[test]
+    fn cubin_header() {
+        let v1 = CubinHeader::parse(&header(7, 0x0050_0550)).unwrap();
+        assert!(v1.is_64bit && v1.is_cuda());
+        assert_eq!(v1.abi_version, 7);
+        assert_eq!(v1.e_type, elf::ET_EXEC);
+        assert_eq!(v1.sm_arch(), SmArch(80));
+        assert_eq!(v1.virtual_arch(), Some(SmArch(80)));
+        assert!(!v1.is_arch_specific());
+        assert!(CubinHeader::parse(&header(7, 0x5a0d5a))
+            .unwrap()
+            .is_arch_specific());
+
+        let v2 = CubinHeader::parse(&header(8, 0x7808)).unwrap();
+        assert_eq!(v2.sm_arch(), SmArch(120));
+        assert_eq!(v2.virtual_arch(), None);
+        assert!(v2.is_arch_specific());
+
+        let mut entry = FatBinaryEntry::new_auto(80, header(7, 0x0050_0550));
+        assert!(entry.compress());
+        assert_eq!(entry.cubin_header(), Some(v1));
+        assert_eq!(
+            FatBinaryEntry::new_auto(80, b".target sm_80\n".to_vec()).cubin_header(),
+            None
+        );
+        assert_eq!(CubinHeader::parse(b"\x7fELF\x02"), None);
+    }
+
+    #[cfg(feature = "object-write")]
+    #[test]
+    fn has_dwarf() {
+        use object::write::Object;
+        use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+
+        let cubin = |section: &[u8]| {
+            let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+            let id = obj.add_section(vec![], section.to_vec(), SectionKind::Debug);
+            obj.append_section_data(id, &[0; 16], 1);
+            FatBinaryEntry::new_auto(80, obj.write().unwrap())
+        };
+        let mut entry = cubin(b".debug_info");
+        assert!(entry.has_dwarf());
+        assert!(entry.compress());
+        assert!(entry.has_dwarf());
+        assert!(!cubin(b".nv.info").has_dwarf());
+        assert!(!FatBinaryEntry::new_auto(80, header(7, 0)).has_dwarf());
+        assert!(!FatBinaryEntry::new_auto(80, b".section .debug_info\n".to_vec()).has_dwarf());
+    }
+
+    #[cfg(feature = "object-write")]
+    #[test]
+    fn debug_info_summary() {
+        use object::write::Object;
+        use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        for (name, size) in [
+            (".debug_line", 16),
+            (".nv.info", 8),
+            (".nv_debug_line_sass", 4),
+        ] {
+            let id = obj.add_section(vec![], name.as_bytes().to_vec(), SectionKind::Debug);
+            obj.append_section_data(id, &vec![0; size], 1);
+        }
+        let mut entry = FatBinaryEntry::new_auto(80, obj.write().unwrap());
+        assert!(entry.compress());
+        let summary = entry.debug_info_summary().unwrap();
+        assert_eq!(
+            summary.sections,
+            [
+                (".debug_line".to_string(), 16),
+                (".nv_debug_line_sass".to_string(), 4)
+            ]
+        );
+        assert_eq!(summary.total_size(), 20);
+        assert!(summary.has_line_info() && !summary.has_full_debug_info());
+        assert_eq!(
+            FatBinaryEntry::new_auto(80, header(7, 0))
+                .debug_info_summary()
+                .unwrap(),
+            Default::default()
+        );
+        assert_eq!(
+            FatBinaryEntry::new_auto(80, b".target sm_80\n".to_vec()).debug_info_summary(),
+            None
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Load entries with the CUDA driver, enabled by the `cudarc` feature
+
+use crate::{EntryKind, FatBinary, FatBinaryEntry, Payload};
+use cudarc::driver::{result, sys, CudaContext, DriverError};
+use std::ffi::{c_void, CString};
+use std::sync::Arc;
+
+/// A module loaded by `cuModuleLoadData`, unloaded on drop
+#[derive(Debug)]
+pub struct LoadedModule {
+    module: sys::CUmodule,
+    ctx: Arc<CudaContext>,
+}
+
+impl LoadedModule {
+    fn load(ctx: &Arc<CudaContext>, image: &[u8]) -> Result<Self, DriverError> {
+        ctx.bind_to_thread()?;
+        let module = unsafe { result::module::load_data(image.as_ptr() as *const c_void) }?;
+        Ok(Self {
+            module,
+            ctx: ctx.clone(),
+        })
+    }
+
+    /// Get raw module handle
+    pub fn cu_module(&self) -> sys::CUmodule {
+        self.module
+    }
+
+    /// Get context the module is loaded in
+    pub fn context(&self) -> &Arc<CudaContext> {
+        &self.ctx
+    }
+
+    /// Get raw handle of kernel by its (mangled) name, `None` if the name contains NUL
+    pub fn get_function(&self, name: &str) -> Result<Option<sys::CUfunction>, DriverError> {
+        let Ok(name) = CString::new(name) else {
+            return Ok(None);
+        };
+        self.ctx.bind_to_thread()?;
+        unsafe { result::module::get_function(self.module, name) }.map(Some)
+    }
+}
+
+impl Drop for LoadedModule {
+    fn drop(&mut self) {
+        if self.ctx.bind_to_thread().is_ok() {
+            let _ = unsafe { result::module::unload(self.module) };
+        }
+    }
+}
+
+impl FatBinaryEntry<'_> {
+    /// Load this entry as a module in the given context, uncompressed
+    /// cubins with aligned payloads are loaded in place
+    pub fn load_module(&self, ctx: &Arc<CudaContext>) -> Result<LoadedModule, DriverError> {
+        if self.kind() == EntryKind::Elf && !self.is_compressed() && self.payload().is_aligned() {
+            return LoadedModule::load(ctx, self.payload());
+        }
+        let image = Payload::Owned(self.to_module_image()).into_aligned();
+        LoadedModule::load(ctx, &image)
+    }
+}
+
+impl FatBinary<'_> {
+    /// Load the whole fatbinary as a module, the driver picks the best entry
+    /// for the device
+    pub fn load_module(&self, ctx: &Arc<CudaContext>) -> Result<LoadedModule, DriverError> {
+        let mut image = vec![];
+        self.write(&mut image)
+            .expect("writing to memory should not fail");
+        LoadedModule::load(ctx, &Payload::Owned(image).into_aligned())
+    }
+}
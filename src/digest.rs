@@ -0,0 +1,283 @@
+//! Per-entry digests of decompressed payloads, enabled by the `digest` feature
+//!
+//! The manifest is line-based text with a fixed layout, so the same
+//! fatbinary always produces the same bytes, ready to be signed:
+//!
+//! ```text
+//! fatbinary-digests v1
+//! algorithm sha256
+//! 0 elf sm_80 <hex digest>
+//! 1 ptx sm_80 <hex digest>
+//! ```
+
+use crate::{EntryKind, FatBinary, FatBinaryError, SmArch, VerifyIssue};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+const MANIFEST_MAGIC: &str = "fatbinary-digests v1";
+
+/// Hash function used for digests
+pub trait PayloadDigest {
+    /// Name of the algorithm recorded in manifest, e.g. `sha256`
+    fn algorithm(&self) -> &str;
+
+    /// Compute digest of data
+    fn digest(&self, data: &[u8]) -> Vec<u8>;
+}
+
+/// SHA-256 digests
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Digest;
+
+impl PayloadDigest for Sha256Digest {
+    fn algorithm(&self) -> &str {
+        "sha256"
+    }
+
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        use sha2::Digest;
+        sha2::Sha256::digest(data).to_vec()
+    }
+}
+
+/// Digest of an entry in [DigestManifest]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDigest {
+    pub entry_index: usize,
+    pub kind: EntryKind,
+    pub arch: SmArch,
+    /// Digest of decompressed payload
+    pub digest: Vec<u8>,
+}
+
+/// Digests of all entries of a fatbinary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestManifest {
+    pub algorithm: String,
+    pub entries: Vec<EntryDigest>,
+}
+
+fn kind_to_text(kind: EntryKind) -> String {
+    match kind {
+        EntryKind::Ptx => "ptx".to_string(),
+        EntryKind::Elf => "elf".to_string(),
+        EntryKind::Unknown(kind) => kind.to_string(),
+    }
+}
+
+fn kind_from_text(text: &str) -> Option<EntryKind> {
+    match text {
+        "ptx" => Some(EntryKind::Ptx),
+        "elf" => Some(EntryKind::Elf),
+        _ => text.parse().ok().map(EntryKind::from_raw),
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // writing to String never fails
+        let _ = write!(res, "{:02x}", byte);
+    }
+    res
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Parse `{index} {kind} {arch} {digest}` line of manifest
+fn parse_entry(line: &str) -> Option<EntryDigest> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields[..] {
+        [index, kind, arch, digest] => Some(EntryDigest {
+            entry_index: index.parse().ok()?,
+            kind: kind_from_text(kind)?,
+            arch: arch.parse().ok()?,
+            digest: from_hex(digest)?,
+        }),
+        _ => None,
+    }
+}
+
+impl DigestManifest {
+    /// Serialize manifest into text
+    pub fn to_text(&self) -> String {
+        let mut res = String::new();
+        let _ = writeln!(res, "{}", MANIFEST_MAGIC);
+        let _ = writeln!(res, "algorithm {}", self.algorithm);
+        for entry in &self.entries {
+            let _ = writeln!(
+                res,
+                "{} {} {} {}",
+                entry.entry_index,
+                kind_to_text(entry.kind),
+                entry.arch,
+                to_hex(&entry.digest)
+            );
+        }
+        res
+    }
+
+    /// Parse manifest from text produced by [DigestManifest::to_text]
+    pub fn from_text(text: &str) -> Result<Self, FatBinaryError> {
+        let invalid = |line: usize| FatBinaryError::InvalidDigestManifest { line };
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+
+        match lines.next() {
+            Some((_, MANIFEST_MAGIC)) => {}
+            _ => return Err(invalid(1)),
+        }
+        let algorithm = match lines.next() {
+            Some((_, line)) if line.starts_with("algorithm ") => {
+                line["algorithm ".len()..].to_string()
+            }
+            _ => return Err(invalid(2)),
+        };
+
+        let mut entries = Vec::new();
+        for (line_number, line) in lines {
+            entries.push(parse_entry(line).ok_or(invalid(line_number))?);
+        }
+        Ok(DigestManifest { algorithm, entries })
+    }
+}
+
+impl FatBinary<'_> {
+    /// Compute digests of decompressed payloads of all entries
+    pub fn digests<D: PayloadDigest>(&self, digest: &D) -> DigestManifest {
+        DigestManifest {
+            algorithm: digest.algorithm().to_string(),
+            entries: self
+                .entries
+                .iter()
+                .enumerate()
+                .map(|(entry_index, entry)| EntryDigest {
+                    entry_index,
+                    kind: entry.kind(),
+                    arch: SmArch(entry.get_sm_arch()),
+                    digest: digest.digest(&entry.get_decompressed_payload()),
+                })
+                .collect(),
+        }
+    }
+
+    /// Check the fatbinary against a manifest, returning entries which are
+    /// missing, unexpected or differ from the manifest
+    pub fn verify_digests<D: PayloadDigest>(
+        &self,
+        manifest: &DigestManifest,
+        digest: &D,
+    ) -> Vec<VerifyIssue> {
+        let issue = |entry_index: usize, message: String| VerifyIssue {
+            entry_index,
+            message,
+        };
+        if manifest.algorithm != digest.algorithm() {
+            return alloc::vec![issue(
+                0,
+                format!(
+                    "manifest uses {} instead of {}",
+                    manifest.algorithm,
+                    digest.algorithm()
+                ),
+            )];
+        }
+
+        let actual = self.digests(digest);
+        let mut res = Vec::new();
+        for expected in &manifest.entries {
+            let message = match actual.entries.get(expected.entry_index) {
+                Some(entry) if entry == expected => continue,
+                Some(entry) if (entry.kind, entry.arch) != (expected.kind, expected.arch) => {
+                    format!(
+                        "expected {} {}, got {} {}",
+                        kind_to_text(expected.kind),
+                        expected.arch,
+                        kind_to_text(entry.kind),
+                        entry.arch
+                    )
+                }
+                Some(entry) => format!(
+                    "digest mismatch (expected {}, got {})",
+                    to_hex(&expected.digest),
+                    to_hex(&entry.digest)
+                ),
+                None => "missing entry".to_string(),
+            };
+            res.push(issue(expected.entry_index, message));
+        }
+        for entry in &actual.entries {
+            if !manifest
+                .entries
+                .iter()
+                .any(|expected| expected.entry_index == entry.entry_index)
+            {
+                res.push(issue(
+                    entry.entry_index,
+                    "entry not in manifest".to_string(),
+                ));
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::digest::{DigestManifest, Sha256Digest};
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+
+    #[test]
+    fn digests() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+        let mut entry = FatBinaryEntry::new_auto(80, ".target sm_80\n".repeat(16).into_bytes());
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+
+        let manifest = fatbin.digests(&Sha256Digest);
+        let text = manifest.to_text();
+        assert!(text.starts_with("fatbinary-digests v1\nalgorithm sha256\n0 elf sm_80 "));
+        assert_eq!(DigestManifest::from_text(&text).unwrap(), manifest);
+        assert_eq!(fatbin.verify_digests(&manifest, &Sha256Digest), vec![]);
+
+        // digest covers decompressed payload
+        let mut decompressed = fatbin.clone();
+        decompressed.entries_mut()[1].decompress();
+        assert_eq!(
+            decompressed.verify_digests(&manifest, &Sha256Digest),
+            vec![]
+        );
+
+        let mut tampered = fatbin.clone();
+        tampered.entries_mut()[0] = FatBinaryEntry::new_auto(80, b"\x7fELF\0".to_vec());
+        tampered
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(90, b"\x7fELF".to_vec()));
+        let issues = tampered.verify_digests(&manifest, &Sha256Digest);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].entry_index, 0);
+        assert!(issues[0].message.starts_with("digest mismatch"));
+        assert_eq!(issues[1].entry_index, 2);
+        assert_eq!(issues[1].message, "entry not in manifest");
+
+        assert!(matches!(
+            DigestManifest::from_text("fatbinary-digests v1\nalgorithm sha256\n0 elf sm_80 xyz\n"),
+            Err(FatBinaryError::InvalidDigestManifest { line: 3 })
+        ));
+    }
+}
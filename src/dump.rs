@@ -0,0 +1,434 @@
+//! Lossless text dump of fatbinary, for reviewing and diffing changes
+//!
+//! Each entry is a `[entry N]` section of `key=value` lines holding every
+//! header field. Strings are escaped like [`<[u8]>::escape_ascii`], the
+//! extra header is hex encoded, payloads are hex encoded inline (`hex:...`)
+//! or stored in files (`file:...`).
+//!
+//! ```text
+//! fatbinary-dump v1
+//!
+//! [entry 0]
+//! kind=1
+//! ...
+//! identifier=axpy.cu
+//! payload=file:axpy.0.sm_70.ptx
+//! ```
+
+use crate::{FatBinary, FatBinaryEntry, FatBinaryEntryHeader, FatBinaryError, Payload};
+use std::fmt::Write;
+use std::path::Path;
+
+const DUMP_MAGIC: &str = "fatbinary-dump v1";
+
+/// Payload of an entry in text dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpPayload {
+    /// Payload hex encoded in the dump
+    Inline,
+    /// Payload stored in file at the path, relative to the dump
+    File(String),
+}
+
+/// Escape bytes, keeping printable ASCII
+fn escape(bytes: &[u8]) -> String {
+    bytes.escape_ascii().to_string()
+}
+
+/// Inverse of [escape]
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            res.push(byte);
+            continue;
+        }
+        res.push(match bytes.next()? {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(core::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            escaped @ (b'\\' | b'\'' | b'"') => escaped,
+            _ => return None,
+        });
+    }
+    Some(res)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // writing to String never fails
+        let _ = write!(res, "{:02x}", byte);
+    }
+    res
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Parse decimal or `0x` prefixed hexadecimal number
+fn parse_number<T: TryFrom<u64>>(text: &str) -> Option<T> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    value.try_into().ok()
+}
+
+/// Entry being assembled from dump
+struct DumpEntry {
+    /// Line of the `[entry N]` header
+    line: usize,
+    header: FatBinaryEntryHeader,
+    ptxas_options_offset: u32,
+    ptxas_options: Option<Vec<u8>>,
+    identifier: Option<Vec<u8>>,
+    extra_header: Vec<u8>,
+    payload: Option<Vec<u8>>,
+}
+
+impl DumpEntry {
+    fn new(line: usize) -> Self {
+        DumpEntry {
+            line,
+            header: FatBinaryEntryHeader {
+                kind: 0,
+                version: 0,
+                header_size: 0,
+                size: 0,
+                compressed_size: 0,
+                options_offset: 0,
+                minor: 0,
+                major: 0,
+                arch: 0,
+                obj_name_offset: 0,
+                obj_name_len: 0,
+                flags: 0,
+                reserved: 0,
+                decompressed_size: 0,
+            },
+            ptxas_options_offset: 0,
+            ptxas_options: None,
+            identifier: None,
+            extra_header: Vec::new(),
+            payload: None,
+        }
+    }
+
+    /// Set field from `key=value` line, `None` if key or value is invalid
+    fn set(&mut self, key: &str, value: &str, base_dir: &Path) -> Option<()> {
+        let header = &mut self.header;
+        match key {
+            "kind" => header.kind = parse_number(value)?,
+            "version" => header.version = parse_number(value)?,
+            "header_size" => header.header_size = parse_number(value)?,
+            "size" => header.size = parse_number(value)?,
+            "compressed_size" => header.compressed_size = parse_number(value)?,
+            "options_offset" => header.options_offset = parse_number(value)?,
+            "minor" => header.minor = parse_number(value)?,
+            "major" => header.major = parse_number(value)?,
+            "arch" => header.arch = parse_number(value)?,
+            "obj_name_offset" => header.obj_name_offset = parse_number(value)?,
+            "obj_name_len" => header.obj_name_len = parse_number(value)?,
+            "flags" => header.flags = parse_number(value)?,
+            "reserved" => header.reserved = parse_number(value)?,
+            "decompressed_size" => header.decompressed_size = parse_number(value)?,
+            "ptxas_options_offset" => self.ptxas_options_offset = parse_number(value)?,
+            "ptxas_options" => self.ptxas_options = Some(unescape(value)?),
+            "identifier" => self.identifier = Some(unescape(value)?),
+            "extra_header" => self.extra_header = from_hex(value)?,
+            "payload" => {
+                self.payload = Some(match value.split_once(':')? {
+                    ("hex", hex) => from_hex(hex)?,
+                    ("file", path) => std::fs::read(base_dir.join(path)).ok()?,
+                    _ => return None,
+                })
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Check that strings fit in the header and payload matches its size,
+    /// so that the entry can be written
+    fn finish(self) -> Result<FatBinaryEntry<'static>, FatBinaryError> {
+        let invalid = |message: &str| FatBinaryError::InvalidTextDump {
+            line: self.line,
+            message: message.to_string(),
+        };
+        let header = self.header;
+        let base = core::mem::size_of::<FatBinaryEntryHeader>() as u64;
+        let header_size = header.header_size as u64;
+        let fits = |offset: u32, len: usize| {
+            offset as u64 >= base && offset as u64 + len as u64 <= header_size
+        };
+
+        if header_size < base {
+            return Err(invalid("header size is smaller than 64"));
+        }
+        if header.options_offset == 0x40 && header_size >= base + 8 {
+            let len = self.ptxas_options.as_ref().map_or(0, Vec::len);
+            if self.ptxas_options_offset != 0 && !fits(self.ptxas_options_offset, len) {
+                return Err(invalid("ptxas options out of header"));
+            }
+        } else if self.ptxas_options.is_some() {
+            return Err(invalid("ptxas options without options offset"));
+        }
+        if let Some(identifier) = &self.identifier {
+            if identifier.len() != header.obj_name_len as usize
+                || !fits(header.obj_name_offset, identifier.len())
+            {
+                return Err(invalid("identifier out of header"));
+            }
+        }
+        let payload = self.payload.ok_or_else(|| invalid("missing payload"))?;
+        if payload.len() as u64 != header.size {
+            return Err(invalid("payload length differs from size"));
+        }
+
+        let entry = FatBinaryEntry {
+            entry_header: header,
+            ptxas_options: self.ptxas_options,
+            ptxas_options_offset: self.ptxas_options_offset,
+            identifier: self.identifier,
+            extra_header: self.extra_header,
+            payload: Payload::Owned(payload).into_aligned(),
+        };
+        if (entry.known_header_end() + entry.extra_header.len()) as u64 > header_size {
+            return Err(invalid("extra header out of header"));
+        }
+        Ok(entry)
+    }
+}
+
+impl FatBinary<'_> {
+    /// Dump fatbinary as text with payloads hex encoded inline
+    pub fn dump_text(&self) -> String {
+        let res =
+            self.dump_text_with(|_, _| Ok::<_, std::convert::Infallible>(DumpPayload::Inline));
+        match res {
+            Ok(res) => res,
+            Err(err) => match err {},
+        }
+    }
+
+    /// Dump fatbinary as text, the callback decides where payloads go e.g.
+    /// saves them in external files
+    pub fn dump_text_with<F, E>(&self, mut f: F) -> Result<String, E>
+    where
+        F: FnMut(usize, &FatBinaryEntry) -> Result<DumpPayload, E>,
+    {
+        let mut res = String::new();
+        let _ = writeln!(res, "{}", DUMP_MAGIC);
+        for (index, entry) in self.entries.iter().enumerate() {
+            let header = &entry.entry_header;
+            let _ = writeln!(res);
+            let _ = writeln!(res, "[entry {}]", index);
+            let fields: [(&str, String); 15] = [
+                ("kind", { header.kind }.to_string()),
+                ("version", format!("{:#x}", { header.version })),
+                ("header_size", { header.header_size }.to_string()),
+                ("size", { header.size }.to_string()),
+                ("compressed_size", { header.compressed_size }.to_string()),
+                (
+                    "options_offset",
+                    format!("{:#x}", { header.options_offset }),
+                ),
+                ("minor", { header.minor }.to_string()),
+                ("major", { header.major }.to_string()),
+                ("arch", { header.arch }.to_string()),
+                ("obj_name_offset", { header.obj_name_offset }.to_string()),
+                ("obj_name_len", { header.obj_name_len }.to_string()),
+                ("flags", format!("{:#x}", { header.flags })),
+                ("reserved", format!("{:#x}", { header.reserved })),
+                (
+                    "decompressed_size",
+                    { header.decompressed_size }.to_string(),
+                ),
+                (
+                    "ptxas_options_offset",
+                    entry.ptxas_options_offset.to_string(),
+                ),
+            ];
+            for (key, value) in fields {
+                let _ = writeln!(res, "{}={}", key, value);
+            }
+            if let Some(ptxas_options) = &entry.ptxas_options {
+                let _ = writeln!(res, "ptxas_options={}", escape(ptxas_options));
+            }
+            if let Some(identifier) = &entry.identifier {
+                let _ = writeln!(res, "identifier={}", escape(identifier));
+            }
+            if !entry.extra_header.is_empty() {
+                let _ = writeln!(res, "extra_header={}", to_hex(&entry.extra_header));
+            }
+            match f(index, entry)? {
+                DumpPayload::Inline => {
+                    let _ = writeln!(res, "payload=hex:{}", to_hex(&entry.payload));
+                }
+                DumpPayload::File(path) => {
+                    let _ = writeln!(res, "payload=file:{}", path);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Rebuild fatbinary from text produced by [FatBinary::dump_text],
+    /// payload files are relative to `base_dir`
+    pub fn from_text_dump(text: &str, base_dir: &Path) -> Result<Self, FatBinaryError> {
+        let invalid = |line: usize, message: &str| FatBinaryError::InvalidTextDump {
+            line,
+            message: message.to_string(),
+        };
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        if lines.next().map(|(_, line)| line) != Some(DUMP_MAGIC) {
+            return Err(invalid(1, "missing header"));
+        }
+
+        let mut entries = vec![];
+        let mut current: Option<DumpEntry> = None;
+        for (line_number, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(index) = line
+                .strip_prefix("[entry ")
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                if index.parse() != Ok(entries.len() + current.is_some() as usize) {
+                    return Err(invalid(line_number, "entries out of order"));
+                }
+                if let Some(entry) = current.replace(DumpEntry::new(line_number)) {
+                    entries.push(entry.finish()?);
+                }
+                continue;
+            }
+
+            let entry = current
+                .as_mut()
+                .ok_or_else(|| invalid(line_number, "field outside of entry"))?;
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(line_number, "expected key=value"))?;
+            entry
+                .set(key, value, base_dir)
+                .ok_or_else(|| invalid(line_number, &format!("invalid {}", key)))?;
+        }
+        if let Some(entry) = current {
+            entries.push(entry.finish()?);
+        }
+        Ok(FatBinary { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::{escape, unescape, DumpPayload};
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+    use std::path::Path;
+
+    fn fatbin() -> FatBinary<'static> {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".target sm_70\n".repeat(16).into_bytes());
+        entry.set_identifier_bytes(Some(&b"axpy\xff.cu\0"[..]));
+        entry.set_ptxas_options(Some("-O3 \"-v\""));
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+        fatbin
+    }
+
+    #[test]
+    fn escaping() {
+        let bytes = b"a\\b\"c'\n\t\r\0\xff";
+        assert_eq!(unescape(&escape(bytes)).unwrap(), bytes);
+        assert_eq!(unescape("\\q"), None);
+    }
+
+    #[test]
+    fn dump_roundtrip() {
+        let fatbin = fatbin();
+        let text = fatbin.dump_text();
+        assert!(text.contains("\n[entry 1]\nkind=2\n"));
+        assert!(text.contains("\nidentifier=axpy\\xff.cu\\x00\n"));
+        assert!(text.contains("\nptxas_options=-O3 \\\"-v\\\"\n"));
+        assert!(text.contains("\npayload=hex:7f454c46\n"));
+
+        let read = FatBinary::from_text_dump(&text, Path::new(".")).unwrap();
+        assert_eq!(read, fatbin);
+        let mut expected = vec![];
+        fatbin.write(&mut expected).unwrap();
+        let mut written = vec![];
+        read.write(&mut written).unwrap();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn dump_extra_header() {
+        let mut fatbin = fatbin();
+        fatbin.entries_mut()[1].set_extra_header(b"\x01\x02".as_slice());
+        let text = fatbin.dump_text();
+        assert!(text.contains("\nextra_header=0102\n"));
+        assert_eq!(
+            FatBinary::from_text_dump(&text, Path::new(".")).unwrap(),
+            fatbin
+        );
+        let text = text.replace("header_size=72", "header_size=64");
+        assert!(FatBinary::from_text_dump(&text, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn dump_files() {
+        let fatbin = fatbin();
+        let dir = std::env::temp_dir().join(format!("fatbinary-dump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = fatbin
+            .dump_text_with(|index, entry| {
+                let name = format!("{}.bin", index);
+                std::fs::write(dir.join(&name), entry.payload())?;
+                Ok::<_, std::io::Error>(DumpPayload::File(name))
+            })
+            .unwrap();
+        assert!(text.contains("\npayload=file:1.bin\n"));
+        let read = FatBinary::from_text_dump(&text, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read, fatbin);
+    }
+
+    #[test]
+    fn invalid_dump() {
+        let text = fatbin().dump_text();
+        let check = |text: &str, expected_line: usize| match FatBinary::from_text_dump(
+            text,
+            Path::new("."),
+        ) {
+            Err(FatBinaryError::InvalidTextDump { line, .. }) => {
+                assert_eq!(line, expected_line)
+            }
+            res => panic!("expected invalid dump, got {:?}", res),
+        };
+        check(&text.replace("kind=2", "kind=x"), 24);
+        check(&text.replace("\nsize=4\n", "\nsize=5\n"), 23);
+        check(&text.replace("obj_name_offset", "object_name_offset"), 13);
+        check(&text.replace("[entry 1]", "[entry 2]"), 23);
+        check(&text[1..], 1);
+    }
+}
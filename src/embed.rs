@@ -0,0 +1,103 @@
+//! Rust source embedding fatbinary, for `include!` in Rust programs
+
+use crate::{EntryKind, FatBinary};
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use core::fmt::Write;
+
+impl FatBinary<'_> {
+    /// Generate Rust source embedding the fatbinary written to `fatbin_path`
+    /// with `include_bytes!`, along with constants describing its content:
+    ///
+    /// - `FATBIN: &[u8]`, aligned to 8 bytes for loading with the driver
+    /// - `CUBIN_ARCHES` and `PTX_ARCHES: &[u32]`, sorted
+    /// - `KERNELS: &[&str]`, sorted kernel names
+    ///
+    /// `fatbin_path` is embedded as a string literal, which `include_bytes!`
+    /// resolves relative to the including file, so pass an absolute path,
+    /// e.g. under `OUT_DIR` in build scripts.
+    pub fn generate_rust_module(&self, fatbin_path: &str) -> String {
+        let mut cubin_arches = BTreeSet::new();
+        let mut ptx_arches = BTreeSet::new();
+        for entry in &self.entries {
+            match entry.kind() {
+                EntryKind::Elf => cubin_arches.insert(entry.get_sm_arch()),
+                EntryKind::Ptx => ptx_arches.insert(entry.get_sm_arch()),
+                EntryKind::Unknown(_) => false,
+            };
+        }
+        let list = |arches: &BTreeSet<u32>| {
+            let mut res = String::new();
+            for (index, arch) in arches.iter().enumerate() {
+                if index > 0 {
+                    res += ", ";
+                }
+                let _ = write!(res, "{}", arch);
+            }
+            res
+        };
+
+        let mut res = String::new();
+        // writing to String never fails
+        res += "// Generated by fatbinary crate, do not edit\n\n";
+        res += "#[repr(C, align(8))]\n";
+        res += "struct FatbinAligned<T: ?Sized>(T);\n\n";
+        let _ = writeln!(
+            res,
+            "static FATBIN_ALIGNED: &FatbinAligned<[u8]> = &FatbinAligned(*include_bytes!({:?}));\n",
+            fatbin_path
+        );
+        res += "/// Fatbinary data, aligned to 8 bytes\n";
+        res += "pub static FATBIN: &[u8] = &FATBIN_ALIGNED.0;\n\n";
+        res += "/// Archs of cubins in the fatbinary\n";
+        let _ = writeln!(
+            res,
+            "pub const CUBIN_ARCHES: &[u32] = &[{}];\n",
+            list(&cubin_arches)
+        );
+        res += "/// Archs of PTX in the fatbinary\n";
+        let _ = writeln!(
+            res,
+            "pub const PTX_ARCHES: &[u32] = &[{}];\n",
+            list(&ptx_arches)
+        );
+        res += "/// Names of kernels in the fatbinary\n";
+        res += "pub const KERNELS: &[&str] = &[";
+        for (index, name) in self.kernels().keys().enumerate() {
+            if index > 0 {
+                res += ", ";
+            }
+            let _ = write!(res, "{:?}", name);
+        }
+        res += "];\n";
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+
+    #[test]
+    fn rust_module() {
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            80,
+            ".version 7.0\n.target sm_80\n.entry foo()\n{\n}\n.entry bar()\n{\n}\n".as_bytes(),
+        ));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, b"\x7fELF".to_vec()));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(90, b"\x7fELF".to_vec()));
+
+        let module = fatbin.generate_rust_module("/tmp/out dir/kernels.fatbin");
+        assert!(
+            module.contains("&FatbinAligned(*include_bytes!(\"/tmp/out dir/kernels.fatbin\"));\n")
+        );
+        assert!(module.contains("pub const CUBIN_ARCHES: &[u32] = &[70, 90];\n"));
+        assert!(module.contains("pub const PTX_ARCHES: &[u32] = &[80];\n"));
+        assert!(module.contains("pub const KERNELS: &[&str] = &[\"bar\", \"foo\"];\n"));
+    }
+}
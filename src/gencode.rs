@@ -0,0 +1,264 @@
+//! Expanding nvcc `-gencode` options into entries
+//!
+//! `-gencode arch=compute_80,code=[sm_80,compute_80]` compiles for virtual
+//! arch compute_80, then embeds a cubin for sm_80 and the PTX of compute_80.
+//! `-arch=all` and `-arch=all-major` stand for the real architectures of
+//! [KNOWN_SM_ARCHS].
+
+use crate::{EntryKind, FatBinary, FatBinaryEntry, FatBinaryError, SmArch, KNOWN_SM_ARCHS};
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+impl SmArch {
+    /// Expand `all` into [KNOWN_SM_ARCHS], `all-major` into the first
+    /// known arch of each major version (sm_50, sm_60, ...), otherwise
+    /// parse a single arch like `sm_86`
+    pub fn expand(spec: &str) -> Result<Vec<SmArch>, FatBinaryError> {
+        match spec {
+            "all" => Ok(KNOWN_SM_ARCHS.to_vec()),
+            "all-major" => {
+                let mut res: Vec<SmArch> = Vec::new();
+                for &arch in KNOWN_SM_ARCHS {
+                    if res.last().map(|last| last.0 / 10) != Some(arch.0 / 10) {
+                        res.push(arch);
+                    }
+                }
+                Ok(res)
+            }
+            spec => Ok(alloc::vec![spec.parse()?]),
+        }
+    }
+}
+
+/// Code embedded for a `-gencode` option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GencodeTarget {
+    /// Cubin for `sm_XX`
+    Cubin(SmArch),
+    /// PTX for `compute_XX`
+    Ptx(SmArch),
+}
+
+impl GencodeTarget {
+    /// Kind of entry for this target
+    pub fn kind(&self) -> EntryKind {
+        match self {
+            GencodeTarget::Cubin(_) => EntryKind::Elf,
+            GencodeTarget::Ptx(_) => EntryKind::Ptx,
+        }
+    }
+
+    /// Arch of entry for this target
+    pub fn arch(&self) -> SmArch {
+        match self {
+            GencodeTarget::Cubin(arch) | GencodeTarget::Ptx(arch) => *arch,
+        }
+    }
+}
+
+/// Parsed `-gencode` option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gencode {
+    /// Virtual arch PTX is generated for
+    pub virtual_arch: SmArch,
+    /// Code embedded into fatbinary
+    pub targets: Vec<GencodeTarget>,
+}
+
+impl core::str::FromStr for Gencode {
+    type Err = FatBinaryError;
+
+    /// Parse `arch=compute_XX,code=...`, optionally prefixed by `-gencode`
+    /// or `--generate-code=`. `code` is a single arch or a list in brackets
+    /// or quotes, and defaults to `[sm_XX,compute_XX]` if omitted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FatBinaryError::InvalidGencode {
+            gencode: s.to_string(),
+        };
+        let options = s.trim();
+        let options = options
+            .strip_prefix("-gencode")
+            .or_else(|| options.strip_prefix("--generate-code"))
+            .map(|options| options.trim_start_matches([' ', '=']))
+            .unwrap_or(options);
+        let (arch, code) = match options.split_once(",code=") {
+            Some((arch, code)) => (arch, Some(code)),
+            None => (options, None),
+        };
+        let virtual_arch: SmArch = arch
+            .strip_prefix("arch=compute_")
+            .ok_or_else(invalid)?
+            .parse()?;
+
+        let targets = match code {
+            Some(code) => code
+                .trim_matches(['[', ']', '"', '\''])
+                .split(',')
+                .map(|code| {
+                    if code.starts_with("sm_") {
+                        let arch: SmArch = code.parse()?;
+                        // cubins can only be compiled from older or same PTX
+                        if arch < virtual_arch {
+                            return Err(invalid());
+                        }
+                        Ok(GencodeTarget::Cubin(arch))
+                    } else if code.starts_with("compute_") {
+                        // only PTX of the virtual arch is available
+                        if code.parse::<SmArch>()? != virtual_arch {
+                            return Err(invalid());
+                        }
+                        Ok(GencodeTarget::Ptx(virtual_arch))
+                    } else {
+                        Err(invalid())
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => alloc::vec![
+                GencodeTarget::Cubin(virtual_arch),
+                GencodeTarget::Ptx(virtual_arch)
+            ],
+        };
+        Ok(Gencode {
+            virtual_arch,
+            targets,
+        })
+    }
+}
+
+impl core::fmt::Display for Gencode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "arch=compute_{},code=[", self.virtual_arch.0)?;
+        for (index, target) in self.targets.iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            match target {
+                GencodeTarget::Cubin(arch) => write!(f, "sm_{}", arch.0)?,
+                GencodeTarget::Ptx(arch) => write!(f, "compute_{}", arch.0)?,
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+impl FatBinary<'static> {
+    /// Create fatbinary for `gencodes` like nvcc does, with payloads from
+    /// `provide(virtual_arch, target)`. Targets repeated across options are
+    /// embedded once, in order of first appearance.
+    pub fn from_gencodes<F, E>(gencodes: &[Gencode], mut provide: F) -> Result<Self, E>
+    where
+        F: FnMut(SmArch, GencodeTarget) -> Result<Vec<u8>, E>,
+    {
+        let mut seen: Vec<GencodeTarget> = Vec::new();
+        let mut entries = Vec::new();
+        for gencode in gencodes {
+            for &target in &gencode.targets {
+                if seen.contains(&target) {
+                    continue;
+                }
+                seen.push(target);
+                let payload = provide(gencode.virtual_arch, target)?;
+                entries.push(FatBinaryEntry::new(
+                    target.kind() == EntryKind::Elf,
+                    target.arch().0,
+                    0,
+                    0,
+                    true,
+                    payload,
+                ));
+            }
+        }
+        Ok(FatBinary { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        EntryKind, FatBinary, FatBinaryError, Gencode, GencodeTarget, SmArch, KNOWN_SM_ARCHS,
+    };
+
+    #[test]
+    fn expand() {
+        assert_eq!(SmArch::expand("all").unwrap(), KNOWN_SM_ARCHS);
+        assert!(KNOWN_SM_ARCHS.windows(2).all(|archs| archs[0] < archs[1]));
+        let major: Vec<u32> = SmArch::expand("all-major")
+            .unwrap()
+            .into_iter()
+            .map(|arch| arch.0)
+            .collect();
+        assert_eq!(major, vec![50, 60, 70, 80, 90, 100, 120]);
+        assert_eq!(SmArch::expand("sm_86").unwrap(), vec![SmArch(86)]);
+        assert!(matches!(
+            SmArch::expand("all-minor"),
+            Err(FatBinaryError::InvalidArch { .. })
+        ));
+    }
+
+    #[test]
+    fn gencode() {
+        let gencode: Gencode = "-gencode arch=compute_80,code=[sm_80,sm_86,compute_80]"
+            .parse()
+            .unwrap();
+        assert_eq!(gencode.virtual_arch, SmArch(80));
+        assert_eq!(
+            gencode.targets,
+            vec![
+                GencodeTarget::Cubin(SmArch(80)),
+                GencodeTarget::Cubin(SmArch(86)),
+                GencodeTarget::Ptx(SmArch(80)),
+            ]
+        );
+        assert_eq!(
+            gencode.to_string(),
+            "arch=compute_80,code=[sm_80,sm_86,compute_80]"
+        );
+        let quoted: Gencode = "--generate-code=arch=compute_70,code=\"sm_70,compute_70\""
+            .parse()
+            .unwrap();
+        assert_eq!(quoted, "arch=compute_70".parse().unwrap());
+        for invalid in [
+            "arch=sm_80,code=sm_80",
+            "arch=compute_80,code=sm_75",
+            "arch=compute_80,code=compute_86",
+            "arch=compute_80,code=lto_80",
+        ] {
+            assert!(matches!(
+                invalid.parse::<Gencode>(),
+                Err(FatBinaryError::InvalidGencode { .. })
+            ));
+        }
+
+        let gencodes = [
+            gencode,
+            quoted,
+            "arch=compute_80,code=sm_80".parse().unwrap(),
+        ];
+        let mut calls = vec![];
+        let fatbin = FatBinary::from_gencodes(&gencodes, |virtual_arch, target| {
+            calls.push((virtual_arch, target));
+            Ok::<_, ()>(match target {
+                GencodeTarget::Cubin(_) => b"\x7fELF".to_vec(),
+                GencodeTarget::Ptx(arch) => format!(".target {}\n", arch).into_bytes(),
+            })
+        })
+        .unwrap();
+        assert_eq!(calls.len(), 5);
+        assert_eq!(calls[1], (SmArch(80), GencodeTarget::Cubin(SmArch(86))));
+        let summary: Vec<_> = fatbin
+            .entries()
+            .iter()
+            .map(|entry| (entry.kind(), entry.get_sm_arch()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (EntryKind::Elf, 80),
+                (EntryKind::Elf, 86),
+                (EntryKind::Ptx, 80),
+                (EntryKind::Elf, 70),
+                (EntryKind::Ptx, 70),
+            ]
+        );
+    }
+}
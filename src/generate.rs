@@ -0,0 +1,43 @@
+//! Structured generation of fatbinaries, enabled by the `arbitrary` feature
+//!
+//! Generated entries are built with the public constructors and setters, so
+//! headers are always consistent with strings and payloads.
+
+use crate::{FatBinary, FatBinaryEntry, Payload};
+use arbitrary::{Arbitrary, Unstructured};
+
+impl<'a> Arbitrary<'a> for FatBinaryEntry<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let is_elf = u.arbitrary()?;
+        let sm_arch = u.arbitrary()?;
+        let major = u.arbitrary()?;
+        let minor = u.arbitrary()?;
+        let is_64bit = u.arbitrary()?;
+        let payload: &'a [u8] = u.arbitrary()?;
+        let mut entry = FatBinaryEntry::new(
+            is_elf,
+            sm_arch,
+            major,
+            minor,
+            is_64bit,
+            Payload::Borrowed(payload),
+        );
+        entry.set_debug_info(u.arbitrary()?);
+        entry.set_host(u.arbitrary()?);
+        entry.set_producer(u.arbitrary()?);
+        entry.set_ptxas_options_bytes(u.arbitrary::<Option<&[u8]>>()?);
+        entry.set_identifier_bytes(u.arbitrary::<Option<&[u8]>>()?);
+        if u.arbitrary()? {
+            entry.compress();
+        }
+        Ok(entry)
+    }
+}
+
+impl<'a> Arbitrary<'a> for FatBinary<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut res = FatBinary::new();
+        *res.entries_mut() = u.arbitrary()?;
+        Ok(res)
+    }
+}
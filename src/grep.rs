@@ -0,0 +1,285 @@
+//! Searching for strings across entries, in PTX text and cubin sections
+
+use crate::{EntryKind, FatBinary, FatBinaryEntry, SmArch};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::read::elf::{ElfFile, FileHeader, Sym};
+use object::{elf, Object, ObjectSection, ObjectSymbol};
+
+/// Where a match of [FatBinary::grep] was found
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GrepLocation {
+    /// Line of PTX, starting from 1
+    PtxLine(usize),
+    /// Name of cubin symbol
+    Symbol,
+    /// Cubin section of the given name
+    Section(String),
+    /// Payload which is neither PTX nor a parsable cubin
+    Payload,
+}
+
+impl core::fmt::Display for GrepLocation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GrepLocation::PtxLine(line) => write!(f, "line {}", line),
+            GrepLocation::Symbol => write!(f, "symbol"),
+            GrepLocation::Section(name) => write!(f, "section {}", name),
+            GrepLocation::Payload => write!(f, "payload"),
+        }
+    }
+}
+
+/// Match found by [FatBinary::grep]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GrepMatch {
+    /// Index of the entry in the fatbinary
+    pub entry_index: usize,
+    pub arch: SmArch,
+    pub kind: EntryKind,
+    pub location: GrepLocation,
+    /// Offset of the match in the decompressed payload
+    pub offset: u64,
+    /// Line or NUL-terminated string containing the match
+    pub text: String,
+}
+
+/// Offsets of `pattern` in `data`, at most one per piece delimited by
+/// `delimiter`, along with the range of the piece
+fn find_pieces(data: &[u8], pattern: &[u8], delimiter: u8) -> Vec<(usize, usize, usize)> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = data[start..]
+            .iter()
+            .position(|&byte| byte == delimiter)
+            .map_or(data.len(), |pos| start + pos);
+        if let Some(pos) = data[start..end]
+            .windows(pattern.len())
+            .position(|window| window == pattern)
+        {
+            res.push((start + pos, start, end));
+        }
+        start = end + 1;
+    }
+    res
+}
+
+/// Matches in symbol names and section contents of cubin, `None` if the
+/// payload is malformed
+fn grep_elf<Elf: FileHeader<Endian = object::Endianness>>(
+    payload: &[u8],
+    pattern: &[u8],
+) -> Option<Vec<(GrepLocation, usize, String)>> {
+    let file = ElfFile::<Elf>::parse(payload).ok()?;
+    let mut res = Vec::new();
+
+    // symbol names are reported once as symbols instead of as strings of
+    // the symbol string table
+    let symbols = file.elf_symbol_table();
+    let strtab = symbols.string_section();
+    let strtab_offset = file
+        .section_by_index(strtab)
+        .ok()
+        .and_then(|section| section.file_range())
+        .map(|(offset, _)| offset as usize);
+    for symbol in file.symbols() {
+        let name = match symbol.name_bytes() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if let (Some(pos), Some(strtab_offset)) = (
+            name.windows(pattern.len())
+                .position(|window| window == pattern),
+            strtab_offset,
+        ) {
+            let st_name = symbol.elf_symbol().st_name(file.endian()) as usize;
+            res.push((
+                GrepLocation::Symbol,
+                // st_name is not checked against the string table
+                strtab_offset.saturating_add(st_name).saturating_add(pos),
+                String::from_utf8_lossy(name).to_string(),
+            ));
+        }
+    }
+
+    for section in file.sections() {
+        if section.index() == strtab && !symbols.is_empty() {
+            continue;
+        }
+        let (offset, data) = match (section.file_range(), section.data()) {
+            (Some((offset, _)), Ok(data)) => (offset as usize, data),
+            _ => continue,
+        };
+        let name = section.name().unwrap_or("<unknown>");
+        for (pos, start, end) in find_pieces(data, pattern, 0) {
+            res.push((
+                GrepLocation::Section(name.to_string()),
+                offset + pos,
+                String::from_utf8_lossy(&data[start..end]).to_string(),
+            ));
+        }
+    }
+    Some(res)
+}
+
+impl FatBinaryEntry<'_> {
+    /// Search decompressed payload for `pattern`: lines of PTX, symbol
+    /// names and NUL-terminated strings in sections of cubins. Returns
+    /// location, offset in the payload and the containing text of matches,
+    /// at most one per line or string. An empty pattern matches nothing.
+    pub fn grep(&self, pattern: &[u8]) -> Vec<(GrepLocation, usize, String)> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        let payload = self.get_decompressed_payload();
+        let elf = match self.kind() {
+            EntryKind::Ptx => {
+                let payload = crate::trim_nul(&payload);
+                return find_pieces(payload, pattern, b'\n')
+                    .into_iter()
+                    .map(|(pos, start, end)| {
+                        let line = payload[..start]
+                            .iter()
+                            .filter(|&&byte| byte == b'\n')
+                            .count();
+                        let text = String::from_utf8_lossy(&payload[start..end]);
+                        (
+                            GrepLocation::PtxLine(line + 1),
+                            pos,
+                            text.trim_end().to_string(),
+                        )
+                    })
+                    .collect();
+            }
+            // e_ident[EI_CLASS]
+            EntryKind::Elf => match payload.get(4) {
+                Some(&elf::ELFCLASS64) => {
+                    grep_elf::<elf::FileHeader64<object::Endianness>>(&payload, pattern)
+                }
+                Some(&elf::ELFCLASS32) => {
+                    grep_elf::<elf::FileHeader32<object::Endianness>>(&payload, pattern)
+                }
+                _ => None,
+            },
+            EntryKind::Unknown(_) => None,
+        };
+        elf.unwrap_or_else(|| {
+            find_pieces(&payload, pattern, 0)
+                .into_iter()
+                .map(|(pos, start, end)| {
+                    let text = String::from_utf8_lossy(&payload[start..end]);
+                    (GrepLocation::Payload, pos, text.to_string())
+                })
+                .collect()
+        })
+    }
+}
+
+impl FatBinary<'_> {
+    /// Search all entries for `pattern`, see [FatBinaryEntry::grep]
+    pub fn grep(&self, pattern: &[u8]) -> Vec<GrepMatch> {
+        let mut res = Vec::new();
+        for (entry_index, entry) in self.entries.iter().enumerate() {
+            for (location, offset, text) in entry.grep(pattern) {
+                res.push(GrepMatch {
+                    entry_index,
+                    arch: SmArch(entry.get_sm_arch()),
+                    kind: entry.kind(),
+                    location,
+                    offset: offset as u64,
+                    text,
+                });
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grep::find_pieces;
+    use crate::{EntryKind, FatBinary, FatBinaryEntry, GrepLocation, SmArch};
+
+    #[test]
+    fn pieces() {
+        assert_eq!(
+            find_pieces(b"foo bar\nbaz\nbarbar", b"bar", b'\n'),
+            vec![(4, 0, 7), (12, 12, 18)]
+        );
+        assert_eq!(find_pieces(b"ba\nr", b"bar", b'\n'), vec![]);
+    }
+
+    #[test]
+    fn grep() {
+        let ptx = ".version 7.0\n.target sm_70\n// secret\n.entry secret_kernel()\n{\n}\n";
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ptx.as_bytes()));
+        let mut entry = FatBinaryEntry::new_auto(80, ptx.repeat(4).into_bytes());
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            80,
+            b"\x7fELF\x02\0secret\0".to_vec(),
+        ));
+
+        let matches = fatbin.grep(b"secret");
+        assert_eq!(matches.len(), 2 + 8 + 1);
+        assert_eq!(matches[0].location, GrepLocation::PtxLine(3));
+        assert_eq!(matches[0].offset, 30);
+        assert_eq!(matches[0].text, "// secret");
+        assert_eq!(matches[1].location, GrepLocation::PtxLine(4));
+        assert_eq!(matches[1].text, ".entry secret_kernel()");
+        assert_eq!(matches[9].entry_index, 1);
+        assert_eq!(matches[9].arch, SmArch(80));
+        assert_eq!(matches[9].location, GrepLocation::PtxLine(22));
+        // malformed cubin is searched as raw payload
+        assert_eq!(matches[10].kind, EntryKind::Elf);
+        assert_eq!(matches[10].location, GrepLocation::Payload);
+        assert_eq!(matches[10].offset, 6);
+        assert_eq!(matches[10].text, "secret");
+
+        assert!(fatbin.grep(b"").is_empty());
+        assert!(fatbin.grep(b"missing").is_empty());
+    }
+
+    #[cfg(feature = "object-write")]
+    #[test]
+    fn grep_elf() {
+        use object::write::{Object, Symbol, SymbolSection};
+        use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+        use object::{SymbolFlags, SymbolKind, SymbolScope};
+
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        obj.append_section_data(text, &[0; 16], 8);
+        let rodata = obj.add_section(vec![], b".nv.global.init".to_vec(), SectionKind::Data);
+        obj.append_section_data(rodata, b"\0needle in data\0", 8);
+        obj.add_symbol(Symbol {
+            name: b"needle_kernel".to_vec(),
+            value: 0,
+            size: 16,
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Section(text),
+            flags: SymbolFlags::None,
+        });
+        let payload = obj.write().unwrap();
+        let entry = FatBinaryEntry::new_auto(80, payload.clone());
+
+        let matches = entry.grep(b"needle");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].0, GrepLocation::Symbol);
+        assert_eq!(matches[0].2, "needle_kernel");
+        assert_eq!(&payload[matches[0].1..matches[0].1 + 6], b"needle");
+        assert_eq!(
+            matches[1].0,
+            GrepLocation::Section(".nv.global.init".to_string())
+        );
+        assert_eq!(matches[1].2, "needle in data");
+        assert_eq!(&payload[matches[1].1..matches[1].1 + 6], b"needle");
+    }
+}
@@ -0,0 +1,197 @@
+//! Replacing entries of fatbinaries embedded in host ELF binaries in place
+//!
+//! Each rewritten fatbinary keeps its size and position in the `.nv_fatbin`
+//! section, so `__fatBinC_Wrapper_t` pointers, section layout and symbols
+//! stay valid. A smaller fatbinary is padded with NULs after the last
+//! replaced payload.
+
+use crate::{
+    EntryKind, FatBinary, FatBinaryEntry, FatBinaryError, Payload, SmArch,
+    FATBINARY_FLAG_COMPRESSED, FAT_BINARY_MAGIC,
+};
+use object::{Object, ObjectSection};
+
+/// Offset and size of `.nv_fatbin` section in host ELF
+fn nv_fatbin_range(image: &[u8]) -> Result<(usize, usize), FatBinaryError> {
+    let invalid = |message: String| FatBinaryError::InvalidHostElf { message };
+    let file = object::File::parse(image).map_err(|err| invalid(err.to_string()))?;
+    let section = file
+        .section_by_name(".nv_fatbin")
+        .ok_or_else(|| invalid("no .nv_fatbin section".to_string()))?;
+    let (offset, size) = section
+        .file_range()
+        .ok_or_else(|| invalid(".nv_fatbin section has no data in file".to_string()))?;
+    match (usize::try_from(offset), usize::try_from(size)) {
+        (Ok(offset), Ok(size)) => Ok((offset, size)),
+        _ => Err(invalid(".nv_fatbin section out of file".to_string())),
+    }
+}
+
+/// Replace payload of entry, compressing it again if the entry was
+/// compressed. Payload is padded with NULs to 8 bytes.
+fn replace_payload(entry: &mut FatBinaryEntry, payload: &[u8]) {
+    let was_compressed = entry.is_compressed();
+    entry.entry_header.flags &= !FATBINARY_FLAG_COMPRESSED;
+    entry.entry_header.compressed_size = 0;
+    entry.entry_header.decompressed_size = 0;
+    let mut payload = payload.to_vec();
+    payload.resize(payload.len().next_multiple_of(8), 0);
+    entry.entry_header.size = payload.len() as u64;
+    entry.payload = Payload::Owned(payload).into_aligned();
+    if was_compressed {
+        entry.compress();
+    }
+}
+
+impl FatBinary<'_> {
+    /// Replace payloads of entries in all fatbinaries of the `.nv_fatbin`
+    /// section of host ELF `image`, e.g. a shared library, in place.
+    /// `replacements` pairs arch with new payload, which replaces entries
+    /// of the same arch and kind (cubin if it starts with the ELF magic, PTX
+    /// otherwise). Fails if a rewritten fatbinary does not fit in the space
+    /// of the original. Returns number of entries replaced.
+    pub fn patch_host_elf(
+        image: &mut [u8],
+        replacements: &[(SmArch, &[u8])],
+    ) -> Result<usize, FatBinaryError> {
+        let (start, size) = nv_fatbin_range(image)?;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= image.len())
+            .ok_or_else(|| FatBinaryError::InvalidHostElf {
+                message: ".nv_fatbin section out of file".to_string(),
+            })?;
+
+        let mut res = 0;
+        let mut offset = start;
+        // fatbinaries are aligned to 8 bytes with NUL padding in between
+        while offset + 16 <= end {
+            if image[offset..offset + 4] != FAT_BINARY_MAGIC.to_le_bytes() {
+                offset += 8;
+                continue;
+            }
+            let header_size = u16::from_le_bytes([image[offset + 6], image[offset + 7]]) as usize;
+            let payload_size =
+                u64::from_le_bytes(image[offset + 8..offset + 16].try_into().unwrap());
+            let len = usize::try_from(payload_size)
+                .ok()
+                .and_then(|size| size.checked_add(header_size))
+                .filter(|&len| len <= end - offset)
+                .ok_or(FatBinaryError::Truncated {
+                    entry_index: 0,
+                    header_offset: offset as u64,
+                    needed: (header_size as u64).saturating_add(payload_size),
+                    available: (end - offset) as u64,
+                })?;
+
+            let mut fatbin = FatBinary::parse(&image[offset..offset + len])?.into_owned();
+            let mut last_replaced = None;
+            for (index, entry) in fatbin.entries.iter_mut().enumerate() {
+                let replacement = replacements.iter().find(|(arch, payload)| {
+                    let kind = if payload.starts_with(b"\x7fELF") {
+                        EntryKind::Elf
+                    } else {
+                        EntryKind::Ptx
+                    };
+                    entry.get_sm_arch() == arch.0 && entry.kind() == kind
+                });
+                if let Some((_, payload)) = replacement {
+                    replace_payload(entry, payload);
+                    last_replaced = Some(index);
+                    res += 1;
+                }
+            }
+
+            if let Some(index) = last_replaced {
+                let written =
+                    Self::header_of(fatbin.entries.iter().map(|entry| &entry.entry_header));
+                let needed = written.header_size as usize + written.size as usize;
+                if needed > len {
+                    return Err(FatBinaryError::PatchTooLarge {
+                        offset: offset as u64,
+                        needed: needed as u64,
+                        available: len as u64,
+                    });
+                }
+                let entry = &mut fatbin.entries[index];
+                let mut payload = entry.payload.to_vec();
+                payload.resize(payload.len() + len - needed, 0);
+                entry.entry_header.size = payload.len() as u64;
+                entry.payload = Payload::Owned(payload).into_aligned();
+
+                let mut buffer = alloc::vec::Vec::with_capacity(len);
+                fatbin.write(&mut buffer)?;
+                debug_assert_eq!(buffer.len(), len);
+                image[offset..offset + len].copy_from_slice(&buffer);
+            }
+            offset = (offset + len).next_multiple_of(8);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(all(test, feature = "object-write"))]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError, SmArch};
+    use object::{Architecture, Object, ObjectSection};
+
+    #[test]
+    fn patch_host_elf() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.as_bytes());
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        let mut entry = FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(16));
+        entry.set_identifier(Some("kernels.cu"));
+        fatbin.entries_mut().push(entry);
+        let image = fatbin.to_relocatable_object(Architecture::X86_64).unwrap();
+        let section = |image: &[u8]| {
+            let file = object::File::parse(image).unwrap();
+            let range = file
+                .section_by_name(".nv_fatbin")
+                .unwrap()
+                .file_range()
+                .unwrap();
+            (range.0 as usize, range.1 as usize)
+        };
+        let (start, size) = section(&image);
+
+        // smaller cubin is padded, compressed PTX stays compressed
+        let mut patched = image.clone();
+        let new_ptx = ".version 7.0\n.target sm_70\n".repeat(50);
+        let replaced = FatBinary::patch_host_elf(
+            &mut patched,
+            &[
+                (SmArch(80), b"\x7fELF\x02"),
+                (SmArch(70), new_ptx.as_bytes()),
+                (SmArch(90), b"\x7fELF"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(replaced, 2);
+        assert_eq!(patched.len(), image.len());
+        assert_eq!(section(&patched), (start, size));
+        assert_eq!(patched[..start], image[..start]);
+        assert_eq!(patched[start + size..], image[start + size..]);
+        let read = FatBinary::parse(&patched[start..start + size]).unwrap();
+        assert!(read.entries()[0].is_compressed());
+        assert_eq!(read.entries()[0].ptx_source().unwrap(), new_ptx);
+        let cubin = read.entries()[1].get_decompressed_payload();
+        assert!(cubin.starts_with(b"\x7fELF\x02\0\0\0"));
+        assert!(cubin[5..].iter().all(|&byte| byte == 0));
+        assert_eq!(read.entries()[1].get_identifier(), Some("kernels.cu"));
+
+        // larger cubin does not fit
+        let mut patched = image.clone();
+        assert!(matches!(
+            FatBinary::patch_host_elf(&mut patched, &[(SmArch(80), &b"\x7fELF".repeat(32))]),
+            Err(FatBinaryError::PatchTooLarge { .. })
+        ));
+
+        assert!(matches!(
+            FatBinary::patch_host_elf(&mut b"\x7fELF".to_vec(), &[]),
+            Err(FatBinaryError::InvalidHostElf { .. })
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+//! Kernels provided by entries, from `.entry` directives of PTX and entry
+//! symbols of cubins
+
+use crate::{EntryKind, FatBinary, FatBinaryEntry, SmArch};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::read::elf::{ElfFile, FileHeader, Sym};
+use object::{elf, Object, ObjectSymbol};
+
+/// `st_other` flag of kernel symbols in cubins
+const STO_CUDA_ENTRY: u8 = 0x10;
+
+/// Entry providing a kernel, returned by [FatBinary::kernels]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KernelEntry {
+    pub arch: SmArch,
+    pub kind: EntryKind,
+    /// Index of the entry in the fatbinary
+    pub entry_index: usize,
+}
+
+/// Names of kernels declared by `.entry` directives in PTX
+fn ptx_kernels(ptx: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    for line in ptx.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            // name may be followed by parameter list without whitespace
+            let rest = match token.strip_prefix(".entry") {
+                Some("") => tokens.next().unwrap_or_default(),
+                _ => continue,
+            };
+            let name = rest.split('(').next().unwrap_or_default();
+            if !name.is_empty() {
+                res.push(name.to_string());
+            }
+        }
+    }
+    res
+}
+
+/// Names of kernel symbols in cubin
+fn elf_kernels<Elf: FileHeader<Endian = object::Endianness>>(payload: &[u8]) -> Vec<String> {
+    let file = match ElfFile::<Elf>::parse(payload) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    file.symbols()
+        .filter(|symbol| {
+            let sym = symbol.elf_symbol();
+            sym.st_type() == elf::STT_FUNC && sym.st_other() & STO_CUDA_ENTRY != 0
+        })
+        .filter_map(|symbol| symbol.name().ok().map(str::to_string))
+        .collect()
+}
+
+impl FatBinaryEntry<'_> {
+    /// Names of kernels provided by this entry, empty if the payload is
+    /// malformed
+    pub fn kernels(&self) -> Vec<String> {
+        let payload = self.get_decompressed_payload();
+        match self.kind() {
+            EntryKind::Ptx => match core::str::from_utf8(crate::trim_nul(&payload)) {
+                Ok(ptx) => ptx_kernels(ptx),
+                Err(_) => Vec::new(),
+            },
+            // e_ident[EI_CLASS]
+            EntryKind::Elf => match payload.get(4) {
+                Some(&elf::ELFCLASS64) => {
+                    elf_kernels::<elf::FileHeader64<object::Endianness>>(&payload)
+                }
+                Some(&elf::ELFCLASS32) => {
+                    elf_kernels::<elf::FileHeader32<object::Endianness>>(&payload)
+                }
+                _ => Vec::new(),
+            },
+            EntryKind::Unknown(_) => Vec::new(),
+        }
+    }
+}
+
+impl FatBinary<'_> {
+    /// Map kernel names to the entries providing them, across all PTX and
+    /// cubin entries
+    pub fn kernels(&self) -> BTreeMap<String, Vec<KernelEntry>> {
+        let mut res: BTreeMap<String, Vec<KernelEntry>> = BTreeMap::new();
+        for (entry_index, entry) in self.entries.iter().enumerate() {
+            for name in entry.kernels() {
+                let kernel = KernelEntry {
+                    arch: SmArch(entry.get_sm_arch()),
+                    kind: entry.kind(),
+                    entry_index,
+                };
+                let entries = res.entry(name).or_default();
+                if entries.last() != Some(&kernel) {
+                    entries.push(kernel);
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernels::ptx_kernels;
+    use crate::{EntryKind, FatBinary, FatBinaryEntry, KernelEntry, SmArch};
+
+    const PTX: &str = "
+.version 7.0
+.target sm_70
+// .entry commented(
+.visible .entry _Z4axpyfPfS_(
+    .param .f32 _Z4axpyfPfS__param_0
+)
+{
+    ret;
+}
+.entry bar ()
+{
+    ret;
+}
+.func baz()
+{
+    ret;
+}
+";
+
+    #[test]
+    fn ptx_entries() {
+        assert_eq!(ptx_kernels(PTX), vec!["_Z4axpyfPfS_", "bar"]);
+    }
+
+    #[test]
+    fn kernels() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, PTX.as_bytes()));
+        let mut entry = FatBinaryEntry::new_auto(80, PTX.repeat(4).into_bytes());
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF\x02".to_vec()));
+
+        let kernels = fatbin.kernels();
+        assert_eq!(kernels.len(), 2);
+        let expected: Vec<_> = [(70, 0), (80, 1)]
+            .into_iter()
+            .map(|(arch, entry_index)| KernelEntry {
+                arch: SmArch(arch),
+                kind: EntryKind::Ptx,
+                entry_index,
+            })
+            .collect();
+        assert_eq!(kernels["bar"], expected);
+        assert_eq!(kernels["_Z4axpyfPfS_"], expected);
+    }
+
+    #[cfg(feature = "object-write")]
+    #[test]
+    fn elf_entries() {
+        use object::write::{Object, Symbol, SymbolSection};
+        use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+        use object::{SymbolFlags, SymbolKind, SymbolScope};
+
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        obj.append_section_data(text, &[0; 16], 8);
+        for (name, st_other) in [("kernel", 0x10), ("device_function", 0)] {
+            obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 16,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Linkage,
+                weak: false,
+                section: SymbolSection::Section(text),
+                flags: SymbolFlags::Elf {
+                    st_info: (object::elf::STB_GLOBAL << 4) | object::elf::STT_FUNC,
+                    st_other,
+                },
+            });
+        }
+        let entry = FatBinaryEntry::new_auto(80, obj.write().unwrap());
+        assert_eq!(entry.kernels(), vec!["kernel"]);
+    }
+}
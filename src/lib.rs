@@ -4,16 +4,129 @@
 //! contains multiple entries containing ELF or PTX files, and each entry can be
 //! accessed via [FatBinaryEntry].
 //!
+//! # Untrusted input
+//!
+//! No input makes the library panic, this is part of its API contract:
+//! parsing malformed or crafted data fails with [FatBinaryError], and
+//! allocations are bounded by the size of input rather than sizes claimed
+//! by headers. Accessors which cannot fail degrade instead, e.g.
+//! [FatBinaryEntry::get_decompressed_payload] on a malformed compressed
+//! payload, and have `try_` variants reporting the error. The fuzz target in
+//! `fuzz/` checks this guarantee, which does not extend to the command line
+//! tools.
+//!
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use binread::io::Read;
+use binread::io::Seek;
 use binread::BinRead;
 use binread::BinReaderExt;
-use std::borrow::Cow;
-use std::io::Read;
-use std::io::Seek;
-use std::io::SeekFrom;
-use std::io::Write;
+#[cfg(feature = "std")]
+use std::io::{IoSlice, Write};
 use thiserror::Error;
 
+mod arch;
+#[cfg(feature = "tokio")]
+mod asyncio;
+mod audit;
+#[cfg(feature = "std")]
+pub mod build_support;
+mod bundle;
+mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod compat;
+mod compress;
+mod concat;
+mod core_dump;
+mod coverage;
+mod cubin;
+#[cfg(feature = "cudarc")]
+mod cuda;
+#[cfg(feature = "digest")]
+pub mod digest;
+#[cfg(feature = "std")]
+mod dump;
+mod embed;
+mod gencode;
+#[cfg(feature = "arbitrary")]
+mod generate;
+mod grep;
+#[cfg(feature = "std")]
+mod host_patch;
+mod kernels;
+mod line_info;
+mod naming;
+#[cfg(feature = "std")]
+mod patch;
+mod payload;
+#[cfg(all(feature = "process", target_os = "linux"))]
+mod process;
+#[cfg(feature = "object-write")]
+mod relocatable;
+mod report;
+#[cfg(feature = "serde")]
+mod repr;
+mod rewrite;
+#[cfg(feature = "digest")]
+mod sbom;
+mod scan;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod stub;
+#[cfg(feature = "toolchain")]
+mod toolchain;
+mod transform;
+mod trim;
+mod verify;
+#[cfg(feature = "std")]
+mod view;
+mod wrapper;
+pub use arch::{ArchInfo, ARCH_INFOS, KNOWN_SM_ARCHS};
+pub use audit::{AuditCategory, AuditFinding};
+pub use bundle::{OffloadBundle, OffloadBundleEntry};
+pub use cancel::CancellationToken;
+pub use compat::{DriverSupport, Support};
+pub use concat::concat_ptx;
+pub use core_dump::CoreDumpFatBinary;
+pub use coverage::{ArchCoverage, CoverageMatrix, LTO_IR_KIND};
+pub use cubin::{CubinHeader, DebugInfoSummary, ELFOSABI_CUDA};
+#[cfg(feature = "cudarc")]
+pub use cuda::LoadedModule;
+#[cfg(feature = "std")]
+pub use dump::DumpPayload;
+pub use gencode::{Gencode, GencodeTarget};
+pub use grep::{GrepLocation, GrepMatch};
+pub use kernels::KernelEntry;
+pub use line_info::LineRow;
+pub use naming::{EntryFileName, NameTemplate};
+#[cfg(feature = "std")]
+pub use patch::{DeltaOp, EntryPatch, FatBinPatch};
+pub use payload::{AlignedBuffer, Payload, PAYLOAD_ALIGNMENT};
+#[cfg(feature = "object-write")]
+pub use relocatable::{NV_FATBIN_SECTION, NV_FATBIN_SEGMENT_SECTION};
+#[cfg(feature = "serde")]
+pub use repr::{EntryRepr, FatBinaryRepr, ManifestFormat, PayloadRepr};
+pub use scan::{ArchiveMember, AR_MAGIC};
+#[cfg(feature = "std")]
+pub use stream::PayloadReader;
+pub use transform::{PayloadPipeline, PayloadTransform};
+pub use trim::TrimReport;
+pub use verify::VerifyIssue;
+#[cfg(feature = "std")]
+pub use view::FatBinaryReader;
+pub use wrapper::FatBinaryWrapper;
+
 /// Errors from fatbinary crate
 #[derive(Error, Debug)]
 pub enum FatBinaryError {
@@ -33,28 +146,255 @@ pub enum FatBinaryError {
     #[error("Invalid offset (expected {expected:?}, got {got:?})")]
     InvalidOffset { expected: u32, got: u32 },
 
+    /// Got entry kind other than PTX or ELF in strict mode
+    #[error("Invalid entry kind {kind:?}")]
+    InvalidKind { kind: u16 },
+
+    /// Got entries not adding up to size in fatbinary header in strict mode
+    #[error("Size mismatch (expected {expected:?}, got {got:?})")]
+    SizeMismatch { expected: u64, got: u64 },
+
+    /// Got compressed payload which is not valid LZ4
+    #[error("Invalid compressed payload")]
+    InvalidCompressedPayload,
+
+    /// Got compressed payload decompressing to a size other than the
+    /// decompressed size in entry header
+    #[error("Decompressed size mismatch (expected {expected:?}, got {got:?})")]
+    DecompressedSizeMismatch { expected: u64, got: u64 },
+
+    /// Got error when parsing the entry at `index`, whose header starts at
+    /// `offset` in the input
+    #[error("Entry {index} at offset {offset:#x}: {source}")]
+    AtEntry {
+        index: usize,
+        offset: u64,
+        #[source]
+        source: Box<FatBinaryError>,
+    },
+
+    /// Got error when parsing the fatbinary starting at `offset` of input
+    /// containing multiple fatbinaries
+    #[error("Fatbinary at offset {offset:#x}: {source}")]
+    AtContainer {
+        offset: u64,
+        #[source]
+        source: Box<FatBinaryError>,
+    },
+
+    /// Input ends before the entry at `entry_index` does
+    #[error("Truncated entry {entry_index} at offset {header_offset:#x} (needed {needed} bytes, available {available})")]
+    Truncated {
+        entry_index: usize,
+        /// Offset of entry header in the input
+        header_offset: u64,
+        /// Bytes needed from the entry header to the end of entry
+        needed: u64,
+        /// Bytes available from the entry header to the end of input
+        available: u64,
+    },
+
+    /// Got field located outside of entry header
+    #[error("Out of header bounds (offset {offset:?}, len {len:?}, header size {header_size:?})")]
+    OutOfHeaderBounds {
+        offset: u32,
+        len: u32,
+        header_size: u32,
+    },
+
+    /// Got invalid clang offload bundle magic
+    #[error("Invalid offload bundle magic")]
+    InvalidBundleMagic,
+
+    /// Got field located outside of clang offload bundle
+    #[error("Out of bundle bounds (offset {offset:?}, size {size:?}, len {len:?})")]
+    OutOfBundleBounds { offset: u64, size: u64, len: u64 },
+
+    /// Got malformed digest manifest
+    #[cfg(feature = "digest")]
+    #[error("Invalid digest manifest at line {line}")]
+    InvalidDigestManifest { line: usize },
+
+    /// Got malformed text dump
+    #[error("Invalid text dump at line {line}: {message}")]
+    InvalidTextDump { line: usize, message: String },
+
+    /// Got entry other than PTX where PTX is required
+    #[error("Entry of kind {kind:?} is not PTX")]
+    NotPtx { kind: u16 },
+
+    /// Got failure from ptxas
+    #[cfg(feature = "toolchain")]
+    #[error("ptxas failed with status {status:?}: {stderr}")]
+    Ptxas { status: Option<i32>, stderr: String },
+
+    /// Got `__fatBinC_Wrapper_t` out of input or pointing out of it
+    #[error("Wrapper out of bounds (address {address:#x}, len {len})")]
+    WrapperOutOfBounds { address: u64, len: u64 },
+
+    /// Got malformed patch or patch not matching the old fatbinary
+    #[error("Invalid patch: {message}")]
+    InvalidPatch { message: String },
+
+    /// Got host binary which is not ELF or has no `.nv_fatbin` section
+    #[error("Invalid host ELF: {message}")]
+    InvalidHostElf { message: String },
+
+    /// Got file which is not an ELF core dump
+    #[error("Invalid core dump: {message}")]
+    InvalidCoreDump { message: String },
+
+    /// Got cubin with malformed `.debug_line` section
+    #[error("Invalid line info: {message}")]
+    InvalidLineInfo { message: String },
+
+    /// Got malformed `ar` archive
+    #[error("Invalid archive: {message}")]
+    InvalidArchive { message: String },
+
+    /// Got entry whose payload does not fit in the address space, e.g. an
+    /// entry larger than 4 GiB on 32-bit targets. Use
+    /// [FatBinary::read_metadata] to inspect such entries without loading
+    /// payloads.
+    #[error("Entry of {size} bytes is too large for this platform")]
+    EntryTooLargeForPlatform { size: u64 },
+
+    /// Operation was cancelled with a [CancellationToken]
+    #[error("Cancelled")]
+    Cancelled,
+
+    /// Got rewritten fatbinary larger than the original in host binary
+    #[error("Patched fatbinary at offset {offset:#x} does not fit (needed {needed} bytes, available {available})")]
+    PatchTooLarge {
+        offset: u64,
+        needed: u64,
+        available: u64,
+    },
+
+    /// Got invalid nvcc `-gencode` option
+    #[error("Invalid gencode {gencode:?}")]
+    InvalidGencode { gencode: String },
+
+    /// Got invalid SM architecture name
+    #[error("Invalid arch {arch:?}")]
+    InvalidArch { arch: String },
+
+    /// Got output file name template with unknown placeholder or unmatched
+    /// brace
+    #[error("Invalid name template {template:?}")]
+    InvalidNameTemplate { template: String },
+
+    /// Got PTX modules which cannot be concatenated, see [concat_ptx]
+    #[error("Cannot concatenate PTX: {message}")]
+    IncompatiblePtx { message: String },
+
+    /// Got invalid base64 payload
+    #[cfg(feature = "serde")]
+    #[error("Got base64::DecodeError {source:?}")]
+    Base64 {
+        #[from]
+        source: base64::DecodeError,
+    },
+
+    /// Got invalid JSON manifest
+    #[cfg(feature = "serde")]
+    #[error("Got serde_json::Error {source:?}")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+
+    /// Got invalid YAML manifest
+    #[cfg(feature = "serde")]
+    #[error("Got serde_yaml::Error {source:?}")]
+    Yaml {
+        #[from]
+        source: serde_yaml::Error,
+    },
+
+    /// Got host architecture without known address size
+    #[cfg(feature = "object-write")]
+    #[error("Unsupported architecture {architecture:?}")]
+    UnsupportedArchitecture { architecture: String },
+
+    /// Got error from object crate when writing object files
+    #[cfg(feature = "object-write")]
+    #[error("Got object::write::Error {source:?}")]
+    ObjectWrite {
+        #[from]
+        source: object::write::Error,
+    },
+
     /// Got error from binread crate
+    #[cfg(feature = "std")]
     #[error("Got binread::Error {source:?}")]
     Binread {
         #[from]
         source: binread::Error,
     },
 
+    /// Got error from binread crate
+    #[cfg(not(feature = "std"))]
+    #[error("Got binread::Error {error:?}")]
+    Binread { error: binread::Error },
+
     /// Got error from std::io module
+    #[cfg(feature = "std")]
     #[error("Got std::io::Error {source:?}")]
     Io {
         #[from]
         source: std::io::Error,
     },
 
+    /// Got error from binread::io module
+    #[cfg(not(feature = "std"))]
+    #[error("Got binread::io::Error {error:?}")]
+    Io { error: binread::io::Error },
+
     /// Got error std::string::FromUtf8Error
     #[error("Got std::string::FromUtf8Error {source:?}")]
     FromUtf8 {
         #[from]
-        source: std::string::FromUtf8Error,
+        source: alloc::string::FromUtf8Error,
     },
 }
 
+impl FatBinaryError {
+    /// Underlying error without [FatBinaryError::AtEntry] and
+    /// [FatBinaryError::AtContainer] context
+    pub fn inner(&self) -> &FatBinaryError {
+        match self {
+            FatBinaryError::AtEntry { source, .. } => source.inner(),
+            FatBinaryError::AtContainer { source, .. } => source.inner(),
+            err => err,
+        }
+    }
+
+    /// Wrap error with index and header offset of the entry being parsed
+    pub(crate) fn at_entry(self, index: usize, offset: u64) -> Self {
+        FatBinaryError::AtEntry {
+            index,
+            offset,
+            source: Box::new(self),
+        }
+    }
+}
+
+// binread errors do not implement Error without std
+#[cfg(not(feature = "std"))]
+impl From<binread::Error> for FatBinaryError {
+    fn from(error: binread::Error) -> Self {
+        FatBinaryError::Binread { error }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<binread::io::Error> for FatBinaryError {
+    fn from(error: binread::io::Error) -> Self {
+        FatBinaryError::Io { error }
+    }
+}
+
 // learned from https://github.com/n-eiling/cuda-fatbin-decompression/blob/9b194a9aa526b71131990ddd97ff5c41a273ace5/fatbin-decompress.h#L13
 #[repr(C, packed)]
 #[derive(BinRead, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -66,6 +406,46 @@ struct FatBinaryHeader {
     pub size: u64,
 }
 
+impl FatBinaryHeader {
+    fn check(&self, options: &ParseOptions) -> Result<(), FatBinaryError> {
+        if self.magic != FAT_BINARY_MAGIC {
+            return Err(FatBinaryError::InvalidMagic {
+                expected: FAT_BINARY_MAGIC,
+                got: self.magic,
+            });
+        }
+
+        let permissive = options.level == ValidationLevel::Permissive;
+        if self.version != 1 && !permissive {
+            return Err(FatBinaryError::InvalidVersion {
+                expected: 1,
+                got: self.version,
+            });
+        }
+
+        // permissive mode skips the rest of a larger header
+        if self.header_size != core::mem::size_of::<FatBinaryHeader>() as u16
+            && !(permissive && self.header_size > core::mem::size_of::<FatBinaryHeader>() as u16)
+        {
+            return Err(FatBinaryError::InvalidHeaderSize {
+                expected: core::mem::size_of::<FatBinaryHeader>() as u16,
+                got: self.header_size,
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn to_bytes(self) -> [u8; 16] {
+        let mut res = [0u8; 16];
+        res[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        res[4..6].copy_from_slice(&self.version.to_le_bytes());
+        res[6..8].copy_from_slice(&self.header_size.to_le_bytes());
+        res[8..16].copy_from_slice(&self.size.to_le_bytes());
+        res
+    }
+}
+
 // learned from https://github.com/n-eiling/cuda-fatbin-decompression/blob/9b194a9aa526b71131990ddd97ff5c41a273ace5/fatbin-decompress.c#L22
 
 const FATBINARY_FLAG_COMPILE_SIZE_64BIT: u64 = 0x00000001;
@@ -76,9 +456,22 @@ const FATBINARY_FLAG_HOST_LINUX: u64 = 0x00000010;
 const FATBINARY_FLAG_HOST_MAC: u64 = 0x00000020;
 const FATBINARY_FLAG_HOST_WINDOWS: u64 = 0x00000040;
 const FATBINARY_FLAG_COMPRESSED: u64 = 0x00002000;
+// optimization level, not interpreted
+const FATBINARY_FLAG_OPT_MASK: u64 = 0x00000f00;
+const FATBINARY_FLAG_KNOWN: u64 = FATBINARY_FLAG_COMPILE_SIZE_64BIT
+    | FATBINARY_FLAG_DEBUG
+    | FATBINARY_FLAG_PRODUCER_CUDA
+    | FATBINARY_FLAG_PRODUCER_OPENCL
+    | FATBINARY_FLAG_HOST_LINUX
+    | FATBINARY_FLAG_HOST_MAC
+    | FATBINARY_FLAG_HOST_WINDOWS
+    | FATBINARY_FLAG_COMPRESSED
+    | FATBINARY_FLAG_OPT_MASK;
 
 /// Host platform of [FatBinaryEntry]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Host {
     Linux,
     Mac,
@@ -88,50 +481,453 @@ pub enum Host {
 
 /// Producer of the [FatBinaryEntry]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Producer {
     CUDA,
     OpenCL,
     Unknown,
 }
 
-/// Header of an entry in fat binary
+/// Kind of payload in [FatBinaryEntry]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EntryKind {
+    Ptx,
+    Elf,
+    Unknown(u16),
+}
+
+impl EntryKind {
+    fn from_raw(kind: u16) -> Self {
+        match kind {
+            1 => EntryKind::Ptx,
+            2 => EntryKind::Elf,
+            kind => EntryKind::Unknown(kind),
+        }
+    }
+
+    fn to_raw(self) -> u16 {
+        match self {
+            EntryKind::Ptx => 1,
+            EntryKind::Elf => 2,
+            EntryKind::Unknown(kind) => kind,
+        }
+    }
+}
+
+impl core::fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EntryKind::Ptx => write!(f, "ptx"),
+            EntryKind::Elf => write!(f, "elf"),
+            EntryKind::Unknown(kind) => write!(f, "unknown({})", kind),
+        }
+    }
+}
+
+/// CUDA SM architecture, e.g. `SmArch(70)` for sm_70
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct SmArch(pub u32);
+
+impl From<u32> for SmArch {
+    fn from(value: u32) -> Self {
+        SmArch(value)
+    }
+}
+
+impl core::fmt::Display for SmArch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "sm_{}", self.0)
+    }
+}
+
+impl core::str::FromStr for SmArch {
+    type Err = FatBinaryError;
+
+    /// Parse `sm_XX`, `compute_XX` or `XX`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let arch = s
+            .strip_prefix("sm_")
+            .or_else(|| s.strip_prefix("compute_"))
+            .unwrap_or(s);
+        arch.parse()
+            .map(SmArch)
+            .map_err(|_| FatBinaryError::InvalidArch {
+                arch: s.to_string(),
+            })
+    }
+}
+
+/// Metadata of a [FatBinaryEntry], without payload
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryInfo {
+    pub kind: EntryKind,
+    pub arch: SmArch,
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub host: Host,
+    pub producer: Producer,
+    pub is_64bit: bool,
+    pub has_debug_info: bool,
+    pub is_compressed: bool,
+    /// Raw flags, including bits not covered above
+    pub flags: u64,
+    pub identifier: Option<String>,
+    pub ptxas_options: Option<String>,
+    /// Size of (possibly compressed and padded) payload
+    pub size: u64,
+    /// Size of compressed payload, 0 if not compressed
+    pub compressed_size: u32,
+    /// Size of payload after decompression, 0 if not compressed
+    pub decompressed_size: u64,
+}
+
+impl EntryInfo {
+    /// Size of payload after decompression, the stored size if not
+    /// compressed
+    pub fn payload_size(&self) -> u64 {
+        if self.is_compressed {
+            self.decompressed_size
+        } else {
+            self.size
+        }
+    }
+
+    /// Bytes stored after the compressed payload to align the entry, 0 if
+    /// not compressed
+    pub fn padding(&self) -> u64 {
+        if self.is_compressed {
+            self.size.saturating_sub(self.compressed_size as u64)
+        } else {
+            0
+        }
+    }
+
+    /// Ratio of decompressed size to stored size including padding, `None`
+    /// if nothing is stored
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.size != 0).then(|| self.payload_size() as f64 / self.size as f64)
+    }
+}
+
+/// Header of an entry in fat binary. Fields are public to craft entries
+/// with undocumented kinds or flags, see [FatBinaryEntry::from_raw_header].
+/// The struct is packed, so copy fields out like `{ header.flags }` instead
+/// of borrowing them.
 #[repr(C, packed)]
-#[derive(BinRead, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(BinRead, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FatBinaryEntryHeader {
     /// 0x02 if ELF, 0x01 if PTX
-    kind: u16,
+    pub kind: u16,
     /// 0x101
-    __unknown1: u16,
+    pub version: u16,
     /// 0x40 if ELF, >=0x48 if PTX
-    header_size: u32,
-    size: u64,
-    compressed_size: u32,
+    pub header_size: u32,
+    /// Size of stored payload
+    pub size: u64,
+    pub compressed_size: u32,
     /// 0x00 if ELF, 0x40 if PTX
-    options_offset: u32,
-    minor: u16,
-    major: u16,
-    arch: u32,
-    obj_name_offset: u32,
-    obj_name_len: u32,
-    flags: u64,
-    zero: u64,
-    decompressed_size: u64,
+    pub options_offset: u32,
+    pub minor: u16,
+    pub major: u16,
+    pub arch: u32,
+    pub obj_name_offset: u32,
+    pub obj_name_len: u32,
+    pub flags: u64,
+    /// 0
+    pub reserved: u64,
+    pub decompressed_size: u64,
     // additional 8 bytes here if PTX
     // ptxas_options_offset: u4,
     // ptxas_options_size: u4
+    // followed by ptxas options and identifier strings
+}
+
+impl FatBinaryEntryHeader {
+    #[cfg(feature = "std")]
+    fn to_bytes(self) -> [u8; 64] {
+        let mut res = [0u8; 64];
+        res[0x00..0x02].copy_from_slice(&self.kind.to_le_bytes());
+        res[0x02..0x04].copy_from_slice(&self.version.to_le_bytes());
+        res[0x04..0x08].copy_from_slice(&self.header_size.to_le_bytes());
+        res[0x08..0x10].copy_from_slice(&self.size.to_le_bytes());
+        res[0x10..0x14].copy_from_slice(&self.compressed_size.to_le_bytes());
+        res[0x14..0x18].copy_from_slice(&self.options_offset.to_le_bytes());
+        res[0x18..0x1a].copy_from_slice(&self.minor.to_le_bytes());
+        res[0x1a..0x1c].copy_from_slice(&self.major.to_le_bytes());
+        res[0x1c..0x20].copy_from_slice(&self.arch.to_le_bytes());
+        res[0x20..0x24].copy_from_slice(&self.obj_name_offset.to_le_bytes());
+        res[0x24..0x28].copy_from_slice(&self.obj_name_len.to_le_bytes());
+        res[0x28..0x30].copy_from_slice(&self.flags.to_le_bytes());
+        res[0x30..0x38].copy_from_slice(&self.reserved.to_le_bytes());
+        res[0x38..0x40].copy_from_slice(&self.decompressed_size.to_le_bytes());
+        res
+    }
 }
 
-/// A fatbinary entry
+/// A fatbinary entry, the payload is either owned or borrowed from the input
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct FatBinaryEntry {
+pub struct FatBinaryEntry<'a> {
     entry_header: FatBinaryEntryHeader,
-    ptxas_options: Option<String>,
-    payload: Vec<u8>,
+    /// Raw bytes of ptxas options, not necessarily UTF-8
+    ptxas_options: Option<Vec<u8>>,
+    /// Offset of ptxas options relative to entry header
+    ptxas_options_offset: u32,
+    /// Raw bytes of identifier, not necessarily UTF-8
+    identifier: Option<Vec<u8>>,
+    /// Header bytes following ptxas options and identifier, kept verbatim
+    extra_header: Vec<u8>,
+    payload: Payload<'a>,
+}
+
+/// Locate a range of bytes in the entry header, offset is relative to entry header
+fn header_bytes<'a>(
+    entry_header: &FatBinaryEntryHeader,
+    extra_header: &'a [u8],
+    offset: u32,
+    len: u32,
+) -> Result<&'a [u8], FatBinaryError> {
+    // checked, a crafted length may overflow on 32-bit targets
+    let range = (offset as usize)
+        .checked_sub(core::mem::size_of::<FatBinaryEntryHeader>())
+        .and_then(|begin| Some(begin..begin.checked_add(len as usize)?));
+    match range {
+        Some(range) if range.end <= extra_header.len() => Ok(&extra_header[range]),
+        _ => Err(FatBinaryError::OutOfHeaderBounds {
+            offset,
+            len,
+            header_size: entry_header.header_size,
+        }),
+    }
+}
+
+/// Strip trailing NUL padding
+fn trim_nul(bytes: &[u8]) -> &[u8] {
+    let len = bytes
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |pos| pos + 1);
+    &bytes[..len]
+}
+
+/// 64-bit FNV-1a hash, to find duplicate payloads without std
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
 }
 
 // learned from https://github.com/n-eiling/cuda-fatbin-decompression/blob/9b194a9aa526b71131990ddd97ff5c41a273ace5/fatbin-decompress.c#L137
-fn decompress(compressed: &[u8]) -> Vec<u8> {
+/// Decompress payload, malformed payloads decompress to the bytes
+/// preceding the first malformed sequence
+fn decompress(compressed: &[u8], size_hint: usize) -> Vec<u8> {
     let mut res = vec![];
+    let _ = try_decompress_into(compressed, size_hint, &mut res);
+    res
+}
+
+// each byte of compressed data expands to at most 255 bytes
+const MAX_COMPRESSION_RATIO: usize = 255;
+
+/// Decompress payload, return None if it is malformed. `size_hint` is the
+/// expected decompressed size used to preallocate output, 0 if unknown.
+fn try_decompress(compressed: &[u8], size_hint: usize) -> Option<Vec<u8>> {
+    let mut res = vec![];
+    try_decompress_into(compressed, size_hint, &mut res)?;
+    Some(res)
+}
+
+/// Decompressed sizes of blocks tried for multi-block payloads, those of the
+/// LZ4 frame format
+const BLOCK_SIZES: [usize; 4] = [1 << 16, 1 << 18, 1 << 20, 1 << 22];
+
+/// Reader of a compressed payload of `len` bytes reading chunks, so
+/// scanning it in an unbuffered file needs few reads, and literals within
+/// the chunk are skipped without seeking
+struct ScanReader<'r, R> {
+    reader: &'r mut R,
+    len: u64,
+    /// Bytes of the payload consumed
+    pos: u64,
+    buffer: Vec<u8>,
+    /// Bytes of `buffer` consumed
+    consumed: usize,
+}
+
+impl<'r, R: Read + Seek> ScanReader<'r, R> {
+    const CHUNK_SIZE: u64 = 1 << 16;
+
+    fn new(reader: &'r mut R, len: u64) -> Self {
+        ScanReader {
+            reader,
+            len,
+            pos: 0,
+            buffer: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Bytes of the payload left
+    fn remaining(&self) -> u64 {
+        self.len - self.pos
+    }
+
+    /// Next byte, `None` at the end of the payload
+    fn next_byte(&mut self) -> Result<Option<u8>, FatBinaryError> {
+        if self.remaining() == 0 {
+            return Ok(None);
+        }
+        if self.consumed == self.buffer.len() {
+            self.buffer
+                .resize(self.remaining().min(Self::CHUNK_SIZE) as usize, 0);
+            self.reader.read_exact(&mut self.buffer)?;
+            self.consumed = 0;
+        }
+        self.consumed += 1;
+        self.pos += 1;
+        Ok(Some(self.buffer[self.consumed - 1]))
+    }
+
+    /// Add length beyond the 4-bit token field to `res`, `None` at the end
+    /// of the payload
+    fn extend(&mut self, mut res: u64) -> Result<Option<u64>, FatBinaryError> {
+        loop {
+            let Some(byte) = self.next_byte()? else {
+                return Ok(None);
+            };
+            res += byte as u64;
+            if byte != 0xff {
+                return Ok(Some(res));
+            }
+        }
+    }
+
+    /// Skip `count` bytes, at most [ScanReader::remaining]
+    fn skip(&mut self, count: u64) -> Result<(), FatBinaryError> {
+        let buffered = (self.buffer.len() - self.consumed) as u64;
+        if count <= buffered {
+            self.consumed += count as usize;
+        } else {
+            let unbuffered = count - buffered;
+            self.reader
+                .seek(binread::io::SeekFrom::Current(unbuffered as i64))?;
+            self.consumed = self.buffer.len();
+        }
+        self.pos += count;
+        Ok(())
+    }
+}
+
+/// Whether the compressed payload of `len` bytes at the position of
+/// `reader` decodes to exactly `decompressed_size` bytes, if every block
+/// but the last decompresses to `block_size` bytes. Only lengths and match
+/// offsets are read, literals are skipped. The position is restored.
+fn sequences_fit<R: Read + Seek>(
+    reader: &mut R,
+    len: u64,
+    decompressed_size: u64,
+    block_size: Option<usize>,
+) -> Result<bool, FatBinaryError> {
+    let start = reader.stream_position()?;
+    let mut scan = ScanReader::new(reader, len);
+    let mut out = 0u64;
+    let fits = loop {
+        let Some(token) = scan.next_byte()? else {
+            break out == decompressed_size;
+        };
+        let mut literal_len = (token >> 4) as u64;
+        if literal_len == 0xf {
+            let Some(len) = scan.extend(literal_len)? else {
+                break false;
+            };
+            literal_len = len;
+        }
+        if literal_len > scan.remaining() {
+            break false;
+        }
+        scan.skip(literal_len)?;
+        out += literal_len;
+        if scan.remaining() == 0 {
+            break out == decompressed_size;
+        }
+        if block_ends(out, block_size) {
+            continue;
+        }
+
+        let (Some(low), Some(high)) = (scan.next_byte()?, scan.next_byte()?) else {
+            break false;
+        };
+        let back_offset = u16::from_le_bytes([low, high]) as u64;
+        let mut match_len = 4 + (token & 0xf) as u64;
+        if match_len == 0xf + 4 {
+            let Some(len) = scan.extend(match_len)? else {
+                break false;
+            };
+            match_len = len;
+        }
+        if back_offset == 0 || back_offset > out {
+            break false;
+        }
+        out += match_len;
+        if out > decompressed_size {
+            break false;
+        }
+    };
+    reader.seek(binread::io::SeekFrom::Start(start))?;
+    Ok(fits)
+}
+
+/// Whether a block of a multi-block payload ends after `decoded` bytes, so
+/// a token instead of a match offset follows the literals
+pub(crate) fn block_ends(decoded: u64, block_size: Option<usize>) -> bool {
+    block_size.is_some_and(|block_size| decoded > 0 && decoded.is_multiple_of(block_size as u64))
+}
+
+/// Decompressed size of blocks of the compressed payload of `len` bytes at
+/// the position of `reader`, `None` if it is a single block or fits no
+/// block size. Large payloads, e.g. of debug cubins, may be compressed as
+/// LZ4 blocks concatenated without size prefixes, each ending with
+/// literals, which a single-block decoder stops at.
+pub(crate) fn detect_block_size<R: Read + Seek>(
+    reader: &mut R,
+    len: u64,
+    decompressed_size: u64,
+) -> Result<Option<usize>, FatBinaryError> {
+    if decompressed_size <= BLOCK_SIZES[0] as u64
+        || sequences_fit(reader, len, decompressed_size, None)?
+    {
+        return Ok(None);
+    }
+    for block_size in BLOCK_SIZES {
+        if (block_size as u64) < decompressed_size
+            && sequences_fit(reader, len, decompressed_size, Some(block_size))?
+        {
+            return Ok(Some(block_size));
+        }
+    }
+    Ok(None)
+}
+
+/// Decompress payload into `res` replacing its content, return None if it
+/// is malformed. Multi-block payloads are detected from `size_hint`, see
+/// [detect_block_size].
+fn try_decompress_into(compressed: &[u8], size_hint: usize, res: &mut Vec<u8>) -> Option<()> {
+    res.clear();
+    // do not trust the hint further than the compressed data can expand
+    res.reserve(size_hint.min(compressed.len().saturating_mul(MAX_COMPRESSION_RATIO)));
+    let block_size = detect_block_size(
+        &mut binread::io::Cursor::new(compressed),
+        compressed.len() as u64,
+        size_hint as u64,
+    )
+    .ok()?;
 
     let mut in_pos = 0;
     let mut next_non_compressed_len: usize;
@@ -144,7 +940,8 @@ fn decompress(compressed: &[u8]) -> Vec<u8> {
         if next_non_compressed_len == 0xf {
             loop {
                 in_pos += 1;
-                next_non_compressed_len += compressed[in_pos] as usize;
+                next_non_compressed_len =
+                    next_non_compressed_len.checked_add(*compressed.get(in_pos)? as usize)?;
                 if compressed[in_pos] != 0xff {
                     break;
                 }
@@ -152,18 +949,25 @@ fn decompress(compressed: &[u8]) -> Vec<u8> {
         }
 
         in_pos += 1;
-        res.extend(&compressed[in_pos..(in_pos + next_non_compressed_len)]);
+        res.extend_from_slice(
+            compressed.get(in_pos..in_pos.checked_add(next_non_compressed_len)?)?,
+        );
 
         in_pos += next_non_compressed_len;
         if in_pos >= compressed.len() {
             break;
         }
-        back_offset = compressed[in_pos] as usize + ((compressed[in_pos + 1] as usize) << 8);
+        if block_ends(res.len() as u64, block_size) {
+            continue;
+        }
+        back_offset =
+            *compressed.get(in_pos)? as usize + ((*compressed.get(in_pos + 1)? as usize) << 8);
         in_pos += 2;
 
         if next_compressed_len == 0xf + 4 {
             loop {
-                next_compressed_len += compressed[in_pos] as usize;
+                next_compressed_len =
+                    next_compressed_len.checked_add(*compressed.get(in_pos)? as usize)?;
                 in_pos += 1;
                 if compressed[in_pos - 1] != 0xff {
                     break;
@@ -172,18 +976,132 @@ fn decompress(compressed: &[u8]) -> Vec<u8> {
         }
 
         let res_len = res.len();
-        for i in 0..next_compressed_len {
-            res.push(res[res_len - back_offset + i]);
+        if back_offset == 0 || back_offset > res_len {
+            return None;
+        }
+        // copy in chunks, when the match overlaps its output the copied
+        // range is periodic, so the chunk can double each time
+        let start = res_len - back_offset;
+        let mut remaining = next_compressed_len;
+        while remaining > 0 {
+            let len = remaining.min(res.len() - start);
+            res.extend_from_within(start..start + len);
+            remaining -= len;
         }
     }
 
-    res
+    Some(())
 }
 
-impl FatBinaryEntry {
-    /// Create a new entry with autodetection
-    pub fn new_auto<T: Into<Vec<u8>>>(sm_arch: u32, payload: T) -> Self {
-        let payload: Vec<u8> = payload.into();
+impl<'a> FatBinaryEntry<'a> {
+    /// Assemble entry from parsed header, rest of the header and payload
+    fn from_parts(
+        entry_header: FatBinaryEntryHeader,
+        extra_header: &[u8],
+        payload: Payload<'a>,
+        options: &ParseOptions,
+    ) -> Result<Self, FatBinaryError> {
+        let permissive = options.level == ValidationLevel::Permissive;
+        if options.level == ValidationLevel::Strict && !matches!(entry_header.kind, 1 | 2) {
+            return Err(FatBinaryError::InvalidKind {
+                kind: entry_header.kind,
+            });
+        }
+
+        // locate string in header, strings out of bounds are ignored in permissive mode
+        let string_at = |offset: u32, len: u32| -> Result<Option<Vec<u8>>, FatBinaryError> {
+            match header_bytes(&entry_header, extra_header, offset, len) {
+                Ok(bytes) => Ok(Some(bytes.to_vec())),
+                Err(_) if permissive => Ok(None),
+                Err(err) => Err(err),
+            }
+        };
+
+        let mut ptxas_options = None;
+        let mut ptxas_options_offset = 0;
+        let mut identifier = None;
+        if !extra_header.is_empty() {
+            if entry_header.options_offset == 0x40 {
+                match header_bytes(&entry_header, extra_header, 0x40, 8) {
+                    Ok(descriptor) => {
+                        ptxas_options_offset =
+                            u32::from_le_bytes(descriptor[0..4].try_into().unwrap());
+                        let ptxas_options_size =
+                            u32::from_le_bytes(descriptor[4..8].try_into().unwrap());
+
+                        // locate ptxas options
+                        if ptxas_options_offset != 0 {
+                            ptxas_options = string_at(ptxas_options_offset, ptxas_options_size)?;
+                            if ptxas_options.is_none() {
+                                ptxas_options_offset = 0;
+                            }
+                        }
+                    }
+                    Err(_) if permissive => {}
+                    Err(err) => return Err(err),
+                }
+            } else if entry_header.options_offset != 0 && !permissive {
+                return Err(FatBinaryError::InvalidOffset {
+                    expected: 0x40,
+                    got: entry_header.options_offset,
+                });
+            }
+        }
+
+        // locate identifier, an empty identifier may end the header
+        if entry_header.obj_name_offset != 0 {
+            identifier = string_at(entry_header.obj_name_offset, entry_header.obj_name_len)?;
+        }
+
+        let mut res = FatBinaryEntry {
+            entry_header,
+            ptxas_options,
+            ptxas_options_offset,
+            identifier,
+            extra_header: Vec::new(),
+            payload,
+        };
+        // keep unknown data following the strings, without zero padding
+        let base = core::mem::size_of::<FatBinaryEntryHeader>();
+        let trailing = extra_header
+            .get(res.known_header_end() - base..)
+            .unwrap_or_default();
+        res.extra_header = trim_nul(trailing).to_vec();
+        Ok(res)
+    }
+
+    /// Collect non-fatal anomalies of the entry at `index`
+    fn collect_warnings(&self, entry_index: usize, warnings: &mut Warnings) {
+        let header = &self.entry_header;
+        if !header.size.is_multiple_of(8) {
+            warnings.push(ParseWarning::UnalignedSize {
+                entry_index,
+                size: header.size,
+            });
+        }
+        if header.flags & !FATBINARY_FLAG_KNOWN != 0 {
+            warnings.push(ParseWarning::UnknownFlags {
+                entry_index,
+                flags: header.flags & !FATBINARY_FLAG_KNOWN,
+            });
+        }
+        // PTX entries have options offset 0x40 even without options
+        let is_default = self.kind() == EntryKind::Ptx && header.options_offset == 0x40;
+        if header.options_offset != 0 && self.ptxas_options.is_none() && !is_default {
+            warnings.push(ParseWarning::OptionsOffsetWithoutOptions {
+                entry_index,
+                options_offset: header.options_offset,
+            });
+        }
+        if self.has_compressed_flag() && !self.is_compressed() {
+            warnings.push(ParseWarning::CompressedWithoutSize { entry_index });
+        }
+    }
+
+    /// Create a new entry with autodetection, payload can be borrowed
+    /// (e.g. `&[u8]`) or owned (e.g. `Vec<u8>`)
+    pub fn new_auto<T: Into<Payload<'a>>>(sm_arch: u32, payload: T) -> Self {
+        let payload: Payload<'a> = payload.into();
 
         // check ELF magic
         let is_elf = payload.starts_with(&[0x7f, 0x45, 0x4c, 0x46]);
@@ -191,7 +1109,7 @@ impl FatBinaryEntry {
     }
 
     /// Create a new entry
-    pub fn new<T: Into<Vec<u8>>>(
+    pub fn new<T: Into<Payload<'a>>>(
         is_elf: bool,
         sm_arch: u32,
         major: u16,
@@ -199,11 +1117,11 @@ impl FatBinaryEntry {
         is_64bit: bool,
         payload: T,
     ) -> Self {
-        let payload: Vec<u8> = payload.into();
+        let payload: Payload<'a> = payload.into();
         Self {
             entry_header: FatBinaryEntryHeader {
                 kind: if is_elf { 2 } else { 1 },
-                __unknown1: 0x0101,
+                version: 0x0101,
                 header_size: 64,
                 size: payload.len() as u64,
                 compressed_size: 0,
@@ -218,47 +1136,184 @@ impl FatBinaryEntry {
                 } else {
                     0
                 },
-                zero: 0,
+                reserved: 0,
                 decompressed_size: 0,
             },
             ptxas_options: None,
+            ptxas_options_offset: 0,
+            identifier: None,
+            extra_header: Vec::new(),
             payload,
         }
     }
-    /// Get (possibly compressed) payload contained in this entry
+
+    /// Convert into entry owning its payload, borrowed payloads are copied
+    /// into buffers aligned to [PAYLOAD_ALIGNMENT] bytes
+    pub fn into_owned(self) -> FatBinaryEntry<'static> {
+        FatBinaryEntry {
+            entry_header: self.entry_header,
+            ptxas_options: self.ptxas_options,
+            ptxas_options_offset: self.ptxas_options_offset,
+            identifier: self.identifier,
+            extra_header: self.extra_header,
+            payload: self.payload.into_owned(),
+        }
+    }
+
+    /// Convert into entry with reference-counted payload, so clones share it
+    pub fn into_shared(self) -> FatBinaryEntry<'static> {
+        FatBinaryEntry {
+            entry_header: self.entry_header,
+            ptxas_options: self.ptxas_options,
+            ptxas_options_offset: self.ptxas_options_offset,
+            identifier: self.identifier,
+            extra_header: self.extra_header,
+            payload: self.payload.into_shared(),
+        }
+    }
+
+    /// Get stored payload without regard to compression
+    pub fn payload(&self) -> &Payload<'a> {
+        &self.payload
+    }
+
+    /// Get stored payload aligned to [PAYLOAD_ALIGNMENT] bytes, e.g. to pass
+    /// a cubin to `cuModuleLoadData` without copying. Payloads allocated by
+    /// this crate are already aligned, others are copied once and kept.
+    /// Decompress first to get a loadable image of compressed entries.
+    pub fn payload_aligned(&mut self) -> &[u8] {
+        if !self.payload.is_aligned() {
+            let payload = core::mem::replace(&mut self.payload, Payload::Borrowed(&[]));
+            self.payload = payload.into_aligned();
+        }
+        &self.payload
+    }
+
+    /// Get (possibly compressed) payload contained in this entry. A
+    /// compressed size beyond the stored payload is clamped to it.
     pub fn get_payload(&self) -> &[u8] {
         if self.is_compressed() {
-            &self.payload[..self.entry_header.compressed_size as usize]
+            let compressed_size = self.entry_header.compressed_size as usize;
+            &self.payload[..compressed_size.min(self.payload.len())]
         } else {
             &self.payload
         }
     }
 
-    /// Get payload contained in this entry, decompress if it was compressed
+    /// Create an entry from metadata and stored (possibly compressed) payload
+    pub fn from_info<T: Into<Payload<'a>>>(info: &EntryInfo, payload: T) -> Self {
+        let mut res = Self::new(
+            info.kind == EntryKind::Elf,
+            info.arch.0,
+            info.version_major,
+            info.version_minor,
+            info.is_64bit,
+            payload,
+        );
+        res.entry_header.kind = info.kind.to_raw();
+        res.entry_header.flags = info.flags;
+        res.entry_header.compressed_size = info.compressed_size;
+        res.entry_header.decompressed_size = info.decompressed_size;
+        res.ptxas_options = info.ptxas_options.clone().map(String::into_bytes);
+        res.identifier = info.identifier.clone().map(String::into_bytes);
+        res.update_layout();
+        res
+    }
+
+    /// Create an entry from a raw header and stored payload without
+    /// checking kind, flags, versions or compressed sizes, e.g. to probe
+    /// undocumented fields. Layout fields are recomputed, see
+    /// [FatBinaryEntry::set_raw_header].
+    pub fn from_raw_header<T: Into<Payload<'a>>>(header: FatBinaryEntryHeader, payload: T) -> Self {
+        let mut res = Self::new_auto(0, payload);
+        res.set_raw_header(header);
+        res
+    }
+
+    /// Get payload contained in this entry, decompress if it was compressed.
+    /// Malformed compressed payloads decompress to the bytes preceding the
+    /// first malformed sequence, use
+    /// [FatBinaryEntry::try_get_decompressed_payload] to detect them.
     pub fn get_decompressed_payload(&self) -> Cow<'_, [u8]> {
         if self.is_compressed() {
             Cow::Owned(decompress(
-                &self.payload[..self.entry_header.compressed_size as usize],
+                self.get_payload(),
+                self.entry_header.decompressed_size as usize,
             ))
         } else {
             Cow::Borrowed(&self.payload)
         }
     }
 
-    /// Replace the payload with decompressed data
+    /// Get payload contained in this entry like
+    /// [FatBinaryEntry::get_decompressed_payload], failing if the
+    /// compressed payload is malformed or does not decompress to the
+    /// decompressed size in entry header
+    pub fn try_get_decompressed_payload(&self) -> Result<Cow<'_, [u8]>, FatBinaryError> {
+        if !self.is_compressed() {
+            return Ok(Cow::Borrowed(&self.payload));
+        }
+        let expected = self.entry_header.decompressed_size;
+        let payload = try_decompress(self.get_payload(), expected as usize)
+            .ok_or(FatBinaryError::InvalidCompressedPayload)?;
+        if payload.len() as u64 != expected {
+            return Err(FatBinaryError::DecompressedSizeMismatch {
+                expected,
+                got: payload.len() as u64,
+            });
+        }
+        Ok(Cow::Owned(payload))
+    }
+
+    /// Write payload into `buf` replacing its content, decompress if it was
+    /// compressed. Reuses the allocation of `buf`, returns the number of
+    /// bytes written. Malformed compressed payloads are handled like
+    /// [FatBinaryEntry::get_decompressed_payload].
+    pub fn decompress_into(&self, buf: &mut Vec<u8>) -> usize {
+        if self.is_compressed() {
+            let _ = try_decompress_into(
+                self.get_payload(),
+                self.entry_header.decompressed_size as usize,
+                buf,
+            );
+        } else {
+            buf.clear();
+            buf.extend_from_slice(&self.payload);
+        }
+        buf.len()
+    }
+
+    /// Get image accepted by `cuModuleLoadData`: decompressed cubin, or
+    /// NUL-terminated PTX
+    pub fn to_module_image(&self) -> Vec<u8> {
+        let mut res = self.get_decompressed_payload().into_owned();
+        if self.kind() == EntryKind::Ptx && res.last() != Some(&0) {
+            res.push(0);
+        }
+        res
+    }
+
+    /// Replace the payload with decompressed data. Entries whose compressed
+    /// payload is malformed stay compressed, see
+    /// [FatBinaryEntry::try_decompress].
     pub fn decompress(&mut self) {
+        let _ = self.try_decompress();
+    }
+
+    /// Replace the payload with decompressed data like
+    /// [FatBinaryEntry::decompress], failing and leaving the entry
+    /// unchanged if the compressed payload is malformed or does not
+    /// decompress to the decompressed size in entry header
+    pub fn try_decompress(&mut self) -> Result<(), FatBinaryError> {
         if self.is_compressed() {
-            self.payload = decompress(&self.payload[..self.entry_header.compressed_size as usize]);
+            let payload = self.try_get_decompressed_payload()?.into_owned();
+            self.payload = Payload::Owned(payload).into_aligned();
             self.entry_header.flags &= !FATBINARY_FLAG_COMPRESSED; // clear compressed flag
-
-            assert_eq!(
-                self.payload.len(),
-                self.entry_header.decompressed_size as usize
-            );
             self.entry_header.size = self.entry_header.decompressed_size;
             self.entry_header.compressed_size = 0;
             self.entry_header.decompressed_size = 0;
         }
+        Ok(())
     }
 
     /// Check if this entry contains ELF
@@ -266,11 +1321,42 @@ impl FatBinaryEntry {
         self.entry_header.kind == 2
     }
 
+    /// Get kind of payload
+    pub fn kind(&self) -> EntryKind {
+        EntryKind::from_raw(self.entry_header.kind)
+    }
+
+    /// Get metadata of this entry
+    pub fn info(&self) -> EntryInfo {
+        EntryInfo {
+            kind: self.kind(),
+            arch: SmArch(self.entry_header.arch),
+            version_major: self.entry_header.major,
+            version_minor: self.entry_header.minor,
+            host: self.host(),
+            producer: self.producer(),
+            is_64bit: self.is_64bit(),
+            has_debug_info: self.has_debug_info(),
+            is_compressed: self.is_compressed(),
+            flags: self.entry_header.flags,
+            identifier: self.get_identifier_lossy().map(Cow::into_owned),
+            ptxas_options: self.get_ptxas_options_lossy().map(Cow::into_owned),
+            size: self.entry_header.size,
+            compressed_size: self.entry_header.compressed_size,
+            decompressed_size: self.entry_header.decompressed_size,
+        }
+    }
+
     /// Get CUDA SM architecture
     pub fn get_sm_arch(&self) -> u32 {
         self.entry_header.arch
     }
 
+    /// Get raw flags
+    pub fn get_flags(&self) -> u64 {
+        self.entry_header.flags
+    }
+
     /// Get major version
     pub fn get_version_major(&self) -> u16 {
         self.entry_header.major
@@ -310,8 +1396,14 @@ impl FatBinaryEntry {
         }
     }
 
-    /// Check if payload is compressed
+    /// Check if payload is compressed. Payloads with the compressed flag but
+    /// zero compressed size are stored uncompressed.
     pub fn is_compressed(&self) -> bool {
+        self.has_compressed_flag() && self.entry_header.compressed_size != 0
+    }
+
+    /// Check if the compressed flag is set, regardless of compressed size
+    fn has_compressed_flag(&self) -> bool {
         (self.entry_header.flags & FATBINARY_FLAG_COMPRESSED) != 0
     }
 
@@ -325,28 +1417,729 @@ impl FatBinaryEntry {
         &self.entry_header
     }
 
-    /// Get ptxas options
+    /// Replace header of this entry without checking its fields. `size`,
+    /// `header_size` and the identifier location are recomputed from the
+    /// payload and strings, so the entry stays well-formed when written.
+    pub fn set_raw_header(&mut self, header: FatBinaryEntryHeader) {
+        self.entry_header = header;
+        self.entry_header.size = self.payload.len() as u64;
+        self.update_layout();
+    }
+
+    /// Get ptxas options, `None` if absent or not valid UTF-8
     pub fn get_ptxas_options(&self) -> Option<&str> {
+        core::str::from_utf8(self.ptxas_options.as_deref()?).ok()
+    }
+
+    /// Get raw bytes of ptxas options
+    pub fn get_ptxas_options_bytes(&self) -> Option<&[u8]> {
         self.ptxas_options.as_deref()
     }
-}
 
-/// A fatbinary file
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
-pub struct FatBinary {
-    entries: Vec<FatBinaryEntry>,
-}
+    /// Get ptxas options, invalid UTF-8 sequences are replaced
+    pub fn get_ptxas_options_lossy(&self) -> Option<Cow<'_, str>> {
+        self.ptxas_options.as_deref().map(String::from_utf8_lossy)
+    }
 
-const FAT_BINARY_MAGIC: u32 = 0xBA55ED50;
+    /// Get identifier, usually the name of the source file, `None` if absent
+    /// or not valid UTF-8
+    pub fn get_identifier(&self) -> Option<&str> {
+        core::str::from_utf8(self.identifier.as_deref()?).ok()
+    }
 
-impl FatBinary {
-    /// Get entries contained in the fatbinary
-    pub fn entries(&self) -> &Vec<FatBinaryEntry> {
+    /// Get raw bytes of identifier
+    pub fn get_identifier_bytes(&self) -> Option<&[u8]> {
+        self.identifier.as_deref()
+    }
+
+    /// Get identifier, invalid UTF-8 sequences are replaced
+    pub fn get_identifier_lossy(&self) -> Option<Cow<'_, str>> {
+        self.identifier.as_deref().map(String::from_utf8_lossy)
+    }
+
+    /// Get ptxas options without padding NULs and surrounding whitespace,
+    /// `None` if absent or not valid UTF-8
+    pub fn ptxas_options(&self) -> Option<&str> {
+        core::str::from_utf8(trim_nul(self.ptxas_options.as_deref()?))
+            .ok()
+            .map(str::trim)
+    }
+
+    /// Get identifier without padding NULs, `None` if absent or not valid UTF-8
+    pub fn identifier(&self) -> Option<&str> {
+        core::str::from_utf8(trim_nul(self.identifier.as_deref()?)).ok()
+    }
+
+    /// Get decompressed PTX source without padding NULs, invalid UTF-8
+    /// sequences are replaced. `None` if this entry is not PTX.
+    pub fn ptx_source(&self) -> Option<Cow<'_, str>> {
+        if self.kind() != EntryKind::Ptx {
+            return None;
+        }
+        Some(match self.get_decompressed_payload() {
+            Cow::Borrowed(payload) => String::from_utf8_lossy(trim_nul(payload)),
+            Cow::Owned(payload) => {
+                Cow::Owned(String::from_utf8_lossy(trim_nul(&payload)).into_owned())
+            }
+        })
+    }
+
+    /// Set ptxas options, header size is updated accordingly
+    pub fn set_ptxas_options<T: Into<String>>(&mut self, ptxas_options: Option<T>) {
+        self.set_ptxas_options_bytes(ptxas_options.map(|options| options.into().into_bytes()));
+    }
+
+    /// Set raw bytes of ptxas options, header size is updated accordingly
+    pub fn set_ptxas_options_bytes<T: Into<Vec<u8>>>(&mut self, ptxas_options: Option<T>) {
+        self.ptxas_options = ptxas_options.map(Into::into);
+        self.update_layout();
+    }
+
+    /// Set identifier, header size is updated accordingly
+    pub fn set_identifier<T: Into<String>>(&mut self, identifier: Option<T>) {
+        self.set_identifier_bytes(identifier.map(|identifier| identifier.into().into_bytes()));
+    }
+
+    /// Set raw bytes of identifier, header size is updated accordingly
+    pub fn set_identifier_bytes<T: Into<Vec<u8>>>(&mut self, identifier: Option<T>) {
+        self.identifier = identifier.map(Into::into);
+        self.update_layout();
+    }
+
+    /// Get header bytes following ptxas options and identifier, which this
+    /// crate does not interpret. Written back verbatim, trailing zero
+    /// padding is not included.
+    pub fn get_extra_header(&self) -> &[u8] {
+        &self.extra_header
+    }
+
+    /// Set header bytes following ptxas options and identifier, header size
+    /// is updated accordingly
+    pub fn set_extra_header<T: Into<Vec<u8>>>(&mut self, extra_header: T) {
+        self.extra_header = extra_header.into();
+        self.update_layout();
+    }
+
+    /// Set CUDA SM architecture
+    pub fn set_sm_arch(&mut self, sm_arch: u32) {
+        self.entry_header.arch = sm_arch;
+    }
+
+    /// Set major and minor version
+    pub fn set_version(&mut self, major: u16, minor: u16) {
+        self.entry_header.major = major;
+        self.entry_header.minor = minor;
+    }
+
+    /// Set whether compiled for 64 bit
+    pub fn set_64bit(&mut self, is_64bit: bool) {
+        self.set_flag(FATBINARY_FLAG_COMPILE_SIZE_64BIT, is_64bit);
+    }
+
+    /// Set whether debug info is contained
+    pub fn set_debug_info(&mut self, has_debug_info: bool) {
+        self.set_flag(FATBINARY_FLAG_DEBUG, has_debug_info);
+    }
+
+    /// Set compiled in/for which host
+    pub fn set_host(&mut self, host: Host) {
+        self.entry_header.flags &=
+            !(FATBINARY_FLAG_HOST_LINUX | FATBINARY_FLAG_HOST_MAC | FATBINARY_FLAG_HOST_WINDOWS);
+        match host {
+            Host::Linux => self.entry_header.flags |= FATBINARY_FLAG_HOST_LINUX,
+            Host::Mac => self.entry_header.flags |= FATBINARY_FLAG_HOST_MAC,
+            Host::Windows => self.entry_header.flags |= FATBINARY_FLAG_HOST_WINDOWS,
+            Host::Unknown => {}
+        }
+    }
+
+    /// Set the producer of this entry
+    pub fn set_producer(&mut self, producer: Producer) {
+        self.entry_header.flags &= !(FATBINARY_FLAG_PRODUCER_CUDA | FATBINARY_FLAG_PRODUCER_OPENCL);
+        match producer {
+            Producer::CUDA => self.entry_header.flags |= FATBINARY_FLAG_PRODUCER_CUDA,
+            Producer::OpenCL => self.entry_header.flags |= FATBINARY_FLAG_PRODUCER_OPENCL,
+            Producer::Unknown => {}
+        }
+    }
+
+    /// Set raw flags without interpreting them, e.g. setting the compressed
+    /// flag does not compress the payload
+    pub fn set_flags(&mut self, flags: u64) {
+        self.entry_header.flags = flags;
+    }
+
+    fn set_flag(&mut self, flag: u64, value: bool) {
+        if value {
+            self.entry_header.flags |= flag;
+        } else {
+            self.entry_header.flags &= !flag;
+        }
+    }
+
+    /// Recompute fields derived from ptxas options, identifier, payload and
+    /// the compressed flag: header size, string offsets and lengths, and
+    /// payload sizes. Returns whether the header changed.
+    pub fn normalize(&mut self) -> bool {
+        let old = self.entry_header;
+        (self.entry_header, self.ptxas_options_offset) = self.normalized_layout();
+        old != self.entry_header
+    }
+
+    /// Entry header and ptxas options offset as [FatBinaryEntry::normalize]
+    /// would leave them, without modifying the entry
+    fn normalized_layout(&self) -> (FatBinaryEntryHeader, u32) {
+        let (mut entry_header, ptxas_options_offset) = self.layout();
+        entry_header.size = self.payload.len() as u64;
+        if self.is_compressed() {
+            // the compressed payload may be padded, but not be shorter
+            if entry_header.compressed_size as u64 > entry_header.size {
+                entry_header.compressed_size = entry_header.size as u32;
+            }
+        } else {
+            entry_header.compressed_size = 0;
+            entry_header.decompressed_size = 0;
+        }
+        (entry_header, ptxas_options_offset)
+    }
+
+    /// Serialized entry header and the rest of the header
+    #[cfg(feature = "std")]
+    fn serialized_headers(&self) -> ([u8; 64], Vec<u8>) {
+        self.serialized_headers_with(&self.entry_header, self.ptxas_options_offset)
+    }
+
+    /// Serialized headers with the given entry header and ptxas options
+    /// offset in place of those of the entry, e.g. normalized ones
+    #[cfg(feature = "std")]
+    fn serialized_headers_with(
+        &self,
+        entry_header: &FatBinaryEntryHeader,
+        ptxas_options_offset: u32,
+    ) -> ([u8; 64], Vec<u8>) {
+        let extra_header =
+            if entry_header.header_size > core::mem::size_of::<FatBinaryEntryHeader>() as u32 {
+                self.serialize_extra_header_with(entry_header, ptxas_options_offset)
+            } else {
+                vec![]
+            };
+        (entry_header.to_bytes(), extra_header)
+    }
+
+    /// Recompute header size and string offsets: header, ptxas options
+    /// descriptor (if any), ptxas options, identifier, extra header
+    fn update_layout(&mut self) {
+        (self.entry_header, self.ptxas_options_offset) = self.layout();
+    }
+
+    /// Entry header and ptxas options offset with the layout
+    /// [FatBinaryEntry::update_layout] computes
+    fn layout(&self) -> (FatBinaryEntryHeader, u32) {
+        let mut entry_header = self.entry_header;
+        let mut offset = core::mem::size_of::<FatBinaryEntryHeader>() as u32;
+
+        if self.ptxas_options.is_some() {
+            entry_header.options_offset = 0x40;
+        }
+        if entry_header.options_offset == 0x40
+            && (self.ptxas_options.is_some()
+                || self.identifier.is_some()
+                || !self.extra_header.is_empty())
+        {
+            // ptxas_options_offset and ptxas_options_size
+            offset += 8;
+        }
+
+        let mut ptxas_options_offset = 0;
+        if let Some(ptxas_options) = &self.ptxas_options {
+            ptxas_options_offset = offset;
+            offset += ptxas_options.len() as u32;
+        }
+
+        entry_header.obj_name_offset = 0;
+        entry_header.obj_name_len = 0;
+        if let Some(identifier) = &self.identifier {
+            entry_header.obj_name_offset = offset;
+            entry_header.obj_name_len = identifier.len() as u32;
+            offset += identifier.len() as u32;
+        }
+        offset += self.extra_header.len() as u32;
+
+        // keep payload 8-byte aligned
+        entry_header.header_size = (offset + 7) & !7;
+        (entry_header, ptxas_options_offset)
+    }
+
+    /// Offset in the header following the ptxas options descriptor, ptxas
+    /// options and identifier, where the extra header starts
+    fn known_header_end(&self) -> usize {
+        self.known_header_end_with(&self.entry_header, self.ptxas_options_offset)
+    }
+
+    fn known_header_end_with(
+        &self,
+        entry_header: &FatBinaryEntryHeader,
+        ptxas_options_offset: u32,
+    ) -> usize {
+        let base = core::mem::size_of::<FatBinaryEntryHeader>();
+        let mut end = base;
+        if entry_header.options_offset == 0x40 && entry_header.header_size as usize >= base + 8 {
+            end += 8;
+        }
+        if let (Some(ptxas_options), true) = (&self.ptxas_options, ptxas_options_offset != 0) {
+            end = end.max((ptxas_options_offset as usize).saturating_add(ptxas_options.len()));
+        }
+        if let Some(identifier) = &self.identifier {
+            let offset = entry_header.obj_name_offset as usize;
+            end = end.max(offset.saturating_add(identifier.len()));
+        }
+        end
+    }
+
+    /// Serialize the part of header beyond the fixed 64 bytes
+    #[cfg(feature = "std")]
+    fn serialize_extra_header(&self) -> Vec<u8> {
+        self.serialize_extra_header_with(&self.entry_header, self.ptxas_options_offset)
+    }
+
+    #[cfg(feature = "std")]
+    fn serialize_extra_header_with(
+        &self,
+        entry_header: &FatBinaryEntryHeader,
+        ptxas_options_offset: u32,
+    ) -> Vec<u8> {
+        let base = core::mem::size_of::<FatBinaryEntryHeader>();
+        let mut res = vec![0u8; entry_header.header_size as usize - base];
+
+        if entry_header.options_offset == 0x40 && res.len() >= 8 {
+            let ptxas_options = self.ptxas_options.as_deref().unwrap_or_default();
+            res[0..4].copy_from_slice(&ptxas_options_offset.to_le_bytes());
+            res[4..8].copy_from_slice(&(ptxas_options.len() as u32).to_le_bytes());
+            if ptxas_options_offset != 0 {
+                let begin = ptxas_options_offset as usize - base;
+                res[begin..(begin + ptxas_options.len())].copy_from_slice(ptxas_options);
+            }
+        }
+
+        if let Some(identifier) = &self.identifier {
+            let begin = entry_header.obj_name_offset as usize - base;
+            res[begin..(begin + identifier.len())].copy_from_slice(identifier);
+        }
+
+        let begin = self.known_header_end_with(entry_header, ptxas_options_offset) - base;
+        if let Some(extra_header) = res.get_mut(begin..begin + self.extra_header.len()) {
+            extra_header.copy_from_slice(&self.extra_header);
+        }
+
+        res
+    }
+}
+
+/// How strictly [FatBinary::read_with] validates its input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ValidationLevel {
+    /// Reject any anomaly, e.g. unknown entry kinds or entries not adding up
+    /// to the size in fatbinary header, for loaders
+    Strict,
+    /// Reject malformed headers
+    #[default]
+    Normal,
+    /// Accept unknown versions, ignore malformed strings and tolerate slack
+    /// after the last entry, for forensics
+    Permissive,
+}
+
+/// Options of [FatBinary::read_with]
+#[derive(Clone, Default)]
+pub struct ParseOptions {
+    pub level: ValidationLevel,
+    /// Called with [Phase::Read] after each entry
+    pub progress: Option<ProgressCallback>,
+    /// Checked before each entry
+    pub cancel: Option<CancellationToken>,
+}
+
+/// Phase of a long operation reported to a [ProgressCallback]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    /// Reading entries, in bytes of input
+    Read,
+    /// Compressing entries, in bytes of uncompressed payloads
+    Compress,
+    /// Decompressing entries, in bytes of decompressed payloads
+    Decompress,
+    /// Writing entries, in bytes of output
+    Write,
+}
+
+/// Callback reporting progress as (bytes done, bytes total, phase) after
+/// each entry. Entries are compressed in parallel with the `rayon` feature,
+/// so it may be called from multiple threads.
+pub type ProgressCallback = alloc::sync::Arc<dyn Fn(u64, u64, Phase) + Send + Sync>;
+
+impl core::fmt::Debug for ParseOptions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("level", &self.level)
+            .field("progress", &self.progress.as_ref().map(|_| ".."))
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl ParseOptions {
+    /// Whether remaining bytes in fatbinary are too short for an entry and
+    /// can be ignored, strict mode rejects them
+    fn is_slack(&self, expected: u64, got: u64) -> Result<bool, FatBinaryError> {
+        if expected - got >= core::mem::size_of::<FatBinaryEntryHeader>() as u64 {
+            return Ok(false);
+        }
+        match self.level {
+            ValidationLevel::Strict => Err(FatBinaryError::SizeMismatch { expected, got }),
+            ValidationLevel::Normal => Ok(false),
+            ValidationLevel::Permissive => Ok(true),
+        }
+    }
+
+    /// Report progress of reading entries of `current_size` bytes
+    fn report(&self, header: &FatBinaryHeader, current_size: u64) {
+        if let Some(progress) = &self.progress {
+            let header_size = header.header_size as u64;
+            progress(
+                header_size.saturating_add(current_size.min(header.size)),
+                header_size.saturating_add(header.size),
+                Phase::Read,
+            );
+        }
+    }
+
+    /// Check total size of entries against fatbinary header
+    fn check_size(&self, expected: u64, got: u64) -> Result<(), FatBinaryError> {
+        if self.level == ValidationLevel::Strict && expected != got {
+            return Err(FatBinaryError::SizeMismatch { expected, got });
+        }
+        Ok(())
+    }
+}
+
+/// Non-fatal anomaly found by [FatBinary::read_with]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ParseWarning {
+    /// Entry size is not a multiple of 8, so the following entry is misaligned
+    UnalignedSize { entry_index: usize, size: u64 },
+    /// Entry flags have bits of unknown meaning
+    UnknownFlags { entry_index: usize, flags: u64 },
+    /// Entry other than PTX, or with an unexpected options offset, has
+    /// options offset set but no ptxas options
+    OptionsOffsetWithoutOptions {
+        entry_index: usize,
+        options_offset: u32,
+    },
+    /// Entry has the compressed flag but zero compressed size, the payload
+    /// is treated as uncompressed
+    CompressedWithoutSize { entry_index: usize },
+}
+
+impl core::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseWarning::UnalignedSize { entry_index, size } => {
+                write!(
+                    f,
+                    "entry {}: size {} is not aligned to 8",
+                    entry_index, size
+                )
+            }
+            ParseWarning::UnknownFlags { entry_index, flags } => {
+                write!(f, "entry {}: unknown flags {:#x}", entry_index, flags)
+            }
+            ParseWarning::OptionsOffsetWithoutOptions {
+                entry_index,
+                options_offset,
+            } => write!(
+                f,
+                "entry {}: options offset {:#x} without ptxas options",
+                entry_index, options_offset
+            ),
+            ParseWarning::CompressedWithoutSize { entry_index } => write!(
+                f,
+                "entry {}: compressed flag with zero compressed size, treated as uncompressed",
+                entry_index
+            ),
+        }
+    }
+}
+
+/// Warnings collected by [FatBinary::read_with]
+pub type Warnings = Vec<ParseWarning>;
+
+/// Options of [FatBinary::write_with_options]
+#[derive(Clone, Default)]
+pub struct WriteOptions {
+    /// Compress entries which are not compressed yet, if it makes them smaller
+    pub compress: bool,
+    /// Recompute derived header fields first, see [FatBinary::normalize]
+    pub normalize: bool,
+    /// Called with [Phase::Compress] and [Phase::Write] after each entry
+    pub progress: Option<ProgressCallback>,
+    /// Checked before compressing or writing each entry, a cancelled write
+    /// leaves incomplete output
+    pub cancel: Option<CancellationToken>,
+}
+
+impl core::fmt::Debug for WriteOptions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WriteOptions")
+            .field("compress", &self.compress)
+            .field("normalize", &self.normalize)
+            .field("progress", &self.progress.as_ref().map(|_| ".."))
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+/// A fatbinary file
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct FatBinary<'a> {
+    entries: Vec<FatBinaryEntry<'a>>,
+}
+
+const FAT_BINARY_MAGIC: u32 = 0xBA55ED50;
+
+/// Magic of `__fatBinC_Wrapper_t`, the struct passed to `__cudaRegisterFatBinary`
+pub const FATBINC_MAGIC: u32 = 0x466243b1;
+
+/// Get `len` bytes at `offset` of `data`, fail with `UnexpectedEof` like reading past the end
+fn slice_at(data: &[u8], offset: u64, len: u64) -> Result<&[u8], FatBinaryError> {
+    offset
+        .checked_add(len)
+        .filter(|end| *end <= data.len() as u64)
+        .map(|end| &data[offset as usize..end as usize])
+        .ok_or_else(|| {
+            binread::io::Error::new(binread::io::ErrorKind::UnexpectedEof, "unexpected eof").into()
+        })
+}
+
+/// Convert size in the input to `usize`, failing instead of truncating on
+/// 32-bit targets
+fn checked_size(size: u64) -> Result<usize, FatBinaryError> {
+    usize::try_from(size).map_err(|_| FatBinaryError::EntryTooLargeForPlatform { size })
+}
+
+/// Check that payload of an entry fits in memory, both as stored and
+/// decompressed
+fn check_in_memory(entry_header: &FatBinaryEntryHeader) -> Result<(), FatBinaryError> {
+    checked_size(entry_header.size)?;
+    checked_size(entry_header.decompressed_size)?;
+    Ok(())
+}
+
+/// Tracks offsets of entries in the input to detect truncation
+struct EntryBounds {
+    index: usize,
+    offset: u64,
+    end: u64,
+}
+
+impl EntryBounds {
+    /// Bounds of entries starting at current position of reader
+    fn of_reader<R: Seek>(reader: &mut R) -> Result<Self, FatBinaryError> {
+        use binread::io::SeekFrom;
+        let offset = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(Self {
+            index: 0,
+            offset,
+            end,
+        })
+    }
+
+    /// Check that `needed` bytes from the current entry header are available
+    fn check(&self, needed: u64) -> Result<(), FatBinaryError> {
+        let available = self.end.saturating_sub(self.offset);
+        if needed > available {
+            return Err(FatBinaryError::Truncated {
+                entry_index: self.index,
+                header_offset: self.offset,
+                needed,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that the header of the next entry is available
+    fn check_header(&self) -> Result<(), FatBinaryError> {
+        self.check(core::mem::size_of::<FatBinaryEntryHeader>() as u64)
+    }
+
+    /// Check that the whole entry is available, return its size in the input
+    fn check_entry(&self, entry_header: &FatBinaryEntryHeader) -> Result<u64, FatBinaryError> {
+        let size = (entry_header.header_size as u64)
+            .max(core::mem::size_of::<FatBinaryEntryHeader>() as u64)
+            .saturating_add(entry_header.size);
+        self.check(size)?;
+        Ok(size)
+    }
+
+    /// Attach index and offset of the current entry to error
+    fn context<T>(&self, res: Result<T, FatBinaryError>) -> Result<T, FatBinaryError> {
+        res.map_err(|err| err.at_entry(self.index, self.offset))
+    }
+
+    /// Move to the next entry
+    fn advance(&mut self, size: u64) {
+        self.index += 1;
+        self.offset += size;
+    }
+}
+
+/// Read fatbinary header and check it
+fn read_header<R: Read>(
+    reader: &mut R,
+    options: &ParseOptions,
+) -> Result<FatBinaryHeader, FatBinaryError> {
+    // read fixed-size headers in one shot and parse from memory,
+    // binread would issue one read per field on the reader
+    let mut header = [0u8; core::mem::size_of::<FatBinaryHeader>()];
+    reader.read_exact(&mut header)?;
+    let header: FatBinaryHeader = binread::io::Cursor::new(&header[..]).read_le()?;
+    header.check(options)?;
+
+    // skip the rest of a larger header
+    let mut rest = vec![0u8; header.header_size as usize - core::mem::size_of::<FatBinaryHeader>()];
+    reader.read_exact(&mut rest)?;
+    Ok(header)
+}
+
+/// Read fixed-size entry header
+fn read_entry_header<R: Read>(reader: &mut R) -> Result<FatBinaryEntryHeader, FatBinaryError> {
+    let mut entry_header = [0u8; core::mem::size_of::<FatBinaryEntryHeader>()];
+    reader.read_exact(&mut entry_header)?;
+    Ok(binread::io::Cursor::new(&entry_header[..]).read_le()?)
+}
+
+/// Read the rest of the header following entry header, its size must be
+/// checked against the fatbinary first. At most `capacity` bytes are
+/// allocated up front, see [read_payload].
+fn read_extra_header<R: Read>(
+    reader: &mut R,
+    entry_header: &FatBinaryEntryHeader,
+    capacity: usize,
+) -> Result<Vec<u8>, FatBinaryError> {
+    // handle case when header size > 64 e.g. PTX
+    let extra_header_size = (entry_header.header_size as u64)
+        .saturating_sub(core::mem::size_of::<FatBinaryEntryHeader>() as u64);
+    read_payload(reader, extra_header_size, capacity)
+}
+
+/// Seek over `size` bytes
+fn skip<R: Seek>(reader: &mut R, size: u64) -> Result<(), FatBinaryError> {
+    let offset = i64::try_from(size).map_err(|_| {
+        binread::io::Error::new(binread::io::ErrorKind::InvalidData, "payload too large")
+    })?;
+    reader.seek(binread::io::SeekFrom::Current(offset))?;
+    Ok(())
+}
+
+/// Seek over entries preceding the entry at `index`, read its header and
+/// leave reader at its payload. Returns `None` if there are not enough
+/// entries.
+fn seek_to_entry<R: Read + Seek>(
+    reader: &mut R,
+    index: usize,
+) -> Result<Option<(EntryBounds, FatBinaryEntryHeader, Vec<u8>)>, FatBinaryError> {
+    let header = read_header(reader, &ParseOptions::default())?;
+    let mut bounds = EntryBounds::of_reader(reader)?;
+
+    let mut current_size = 0;
+    let mut current_index = 0;
+
+    while current_size < header.size {
+        bounds.check_header()?;
+        let entry_header = bounds.context(read_entry_header(reader))?;
+        let entry_size = bounds.check_entry(&entry_header)?;
+        let extra_header = bounds.context(read_extra_header(reader, &entry_header, usize::MAX))?;
+        current_size += entry_header.header_size as u64;
+
+        if current_index == index {
+            return Ok(Some((bounds, entry_header, extra_header)));
+        }
+
+        bounds.context(skip(reader, entry_header.size))?;
+        current_size += entry_header.size;
+        current_index += 1;
+        bounds.advance(entry_size);
+    }
+
+    Ok(None)
+}
+
+/// Write all buffers, like the unstable `Write::write_all_vectored`
+#[cfg(feature = "std")]
+fn write_all_vectored<W: Write>(writer: &mut W, mut bufs: &mut [IoSlice]) -> std::io::Result<()> {
+    // skip leading empty buffers
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match writer.write_vectored(bufs) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(len) => IoSlice::advance_slices(&mut bufs, len),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Preallocation for sizes not checked against the size of input, e.g.
+/// when reading streams, so a crafted header cannot exhaust memory before
+/// the input runs out
+const STREAM_PREALLOCATION: usize = 1 << 20;
+
+/// Read exactly `size` bytes of payload without zero-filling. At most
+/// `capacity` bytes are allocated up front: sizes checked against the size
+/// of input are read into a single allocation, others grow while reading.
+#[cfg(feature = "std")]
+fn read_payload<R: Read>(
+    reader: &mut R,
+    size: u64,
+    capacity: usize,
+) -> Result<Vec<u8>, FatBinaryError> {
+    let mut payload = Vec::with_capacity(checked_size(size)?.min(capacity));
+    reader.take(size).read_to_end(&mut payload)?;
+    if payload.len() as u64 != size {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(payload)
+}
+
+/// Read exactly `size` bytes of payload, binread::io::Read lacks `take` without std
+#[cfg(not(feature = "std"))]
+fn read_payload<R: Read>(
+    reader: &mut R,
+    size: u64,
+    capacity: usize,
+) -> Result<Vec<u8>, FatBinaryError> {
+    let size = checked_size(size)?;
+    let mut payload = vec![];
+    while payload.len() < size {
+        // at least double, as reading into a single allocation would
+        let begin = payload.len();
+        payload.resize(begin + (size - begin).min(capacity.max(begin)), 0);
+        reader.read_exact(&mut payload[begin..])?;
+    }
+    Ok(payload)
+}
+
+impl<'a> FatBinary<'a> {
+    /// Get entries contained in the fatbinary
+    pub fn entries(&self) -> &Vec<FatBinaryEntry<'a>> {
         &self.entries
     }
 
     /// Get mutable entries contained in the fatbinary
-    pub fn entries_mut(&mut self) -> &mut Vec<FatBinaryEntry> {
+    pub fn entries_mut(&mut self) -> &mut Vec<FatBinaryEntry<'a>> {
         &mut self.entries
     }
 
@@ -356,149 +2149,526 @@ impl FatBinary {
     }
 
     /// Read fatbinary from reader
-    pub fn read<R: Read + Seek>(mut reader: R) -> Result<FatBinary, FatBinaryError> {
-        let header: FatBinaryHeader = reader.read_le()?;
+    pub fn read<R: Read + Seek>(reader: R) -> Result<FatBinary<'static>, FatBinaryError> {
+        Ok(Self::read_with(reader, ParseOptions::default())?.0)
+    }
 
-        if header.magic != FAT_BINARY_MAGIC {
-            return Err(FatBinaryError::InvalidMagic {
-                expected: FAT_BINARY_MAGIC,
-                got: header.magic,
-            });
+    /// Read fatbinary from reader, validating as strictly as `options` requires.
+    /// Anomalies which are accepted are returned as warnings.
+    pub fn read_with<R: Read + Seek>(
+        mut reader: R,
+        options: ParseOptions,
+    ) -> Result<(FatBinary<'static>, Warnings), FatBinaryError> {
+        let header = read_header(&mut reader, &options)?;
+        let mut bounds = EntryBounds::of_reader(&mut reader)?;
+
+        let mut entries = vec![];
+        let mut warnings = vec![];
+        let mut current_size = 0;
+
+        while current_size < header.size {
+            cancel::check(&options.cancel)?;
+            if options.is_slack(header.size, current_size)? {
+                break;
+            }
+            bounds.check_header()?;
+            let entry_header = bounds.context(read_entry_header(&mut reader))?;
+            let entry_size = bounds.check_entry(&entry_header)?;
+            bounds.context(check_in_memory(&entry_header))?;
+            let extra_header =
+                bounds.context(read_extra_header(&mut reader, &entry_header, usize::MAX))?;
+            current_size += entry_header.header_size as u64;
+
+            let payload =
+                bounds.context(read_payload(&mut reader, entry_header.size, usize::MAX))?;
+            current_size += entry_header.size;
+
+            let entry = bounds.context(FatBinaryEntry::from_parts(
+                entry_header,
+                &extra_header,
+                Payload::Owned(payload).into_aligned(),
+                &options,
+            ))?;
+            entry.collect_warnings(bounds.index, &mut warnings);
+            entries.push(entry);
+            bounds.advance(entry_size);
+            options.report(&header, current_size);
         }
+        options.check_size(header.size, current_size)?;
 
-        if header.version != 1 {
-            return Err(FatBinaryError::InvalidVersion {
-                expected: 1,
-                got: header.version,
-            });
+        Ok((FatBinary { entries }, warnings))
+    }
+
+    /// Read fatbinary from reader which cannot seek, e.g. a pipe or a
+    /// socket. Exactly the bytes of the fatbinary are consumed, so
+    /// concatenated fatbinaries can be read one after another.
+    pub fn read_stream<R: Read>(reader: R) -> Result<FatBinary<'static>, FatBinaryError> {
+        Ok(Self::read_stream_with(reader, ParseOptions::default())?.0)
+    }
+
+    /// Read fatbinary from reader which cannot seek like
+    /// [FatBinary::read_stream], validating as strictly as `options`
+    /// requires. The size of input is unknown, so entries are checked
+    /// against the size in fatbinary header: entries extending past it are
+    /// reported as [FatBinaryError::Truncated], and input ending early as
+    /// an unexpected end of file.
+    pub fn read_stream_with<R: Read>(
+        mut reader: R,
+        options: ParseOptions,
+    ) -> Result<(FatBinary<'static>, Warnings), FatBinaryError> {
+        let header = read_header(&mut reader, &options)?;
+        // size of input is unknown, bound entries by fatbinary header instead
+        let mut bounds = EntryBounds {
+            index: 0,
+            offset: header.header_size as u64,
+            end: (header.header_size as u64).saturating_add(header.size),
+        };
+
+        let mut entries = vec![];
+        let mut warnings = vec![];
+        let mut current_size = 0;
+
+        while current_size < header.size {
+            cancel::check(&options.cancel)?;
+            if options.is_slack(header.size, current_size)? {
+                break;
+            }
+            let entry_header = bounds.context(read_entry_header(&mut reader))?;
+            let entry_size = bounds.check_entry(&entry_header)?;
+            bounds.context(check_in_memory(&entry_header))?;
+            let extra_header = bounds.context(read_extra_header(
+                &mut reader,
+                &entry_header,
+                STREAM_PREALLOCATION,
+            ))?;
+            current_size += entry_header.header_size as u64;
+
+            let payload = bounds.context(read_payload(
+                &mut reader,
+                entry_header.size,
+                STREAM_PREALLOCATION,
+            ))?;
+            current_size += entry_header.size;
+
+            let entry = bounds.context(FatBinaryEntry::from_parts(
+                entry_header,
+                &extra_header,
+                Payload::Owned(payload).into_aligned(),
+                &options,
+            ))?;
+            entry.collect_warnings(bounds.index, &mut warnings);
+            entries.push(entry);
+            bounds.advance(entry_size);
+            options.report(&header, current_size);
         }
+        options.check_size(header.size, current_size)?;
 
-        if header.header_size != std::mem::size_of::<FatBinaryHeader>() as u16 {
-            return Err(FatBinaryError::InvalidHeaderSize {
-                expected: std::mem::size_of::<FatBinaryHeader>() as u16,
-                got: header.header_size,
-            });
+        Ok((FatBinary { entries }, warnings))
+    }
+
+    /// Read metadata of entries from reader, seeking over payloads without
+    /// reading them. Unlike reading whole fatbinaries, this supports entries
+    /// too large to be loaded on this platform.
+    pub fn read_metadata<R: Read + Seek>(mut reader: R) -> Result<Vec<EntryInfo>, FatBinaryError> {
+        let header = read_header(&mut reader, &ParseOptions::default())?;
+        let mut bounds = EntryBounds::of_reader(&mut reader)?;
+
+        let mut res = vec![];
+        let mut current_size = 0;
+
+        while current_size < header.size {
+            bounds.check_header()?;
+            let entry_header = bounds.context(read_entry_header(&mut reader))?;
+            let entry_size = bounds.check_entry(&entry_header)?;
+            let extra_header =
+                bounds.context(read_extra_header(&mut reader, &entry_header, usize::MAX))?;
+            current_size += entry_header.header_size as u64;
+
+            bounds.context(skip(&mut reader, entry_header.size))?;
+            current_size += entry_header.size;
+
+            let entry = bounds.context(FatBinaryEntry::from_parts(
+                entry_header,
+                &extra_header,
+                Payload::Borrowed(&[]),
+                &ParseOptions::default(),
+            ))?;
+            res.push(entry.info());
+            bounds.advance(entry_size);
         }
 
+        Ok(res)
+    }
+
+    /// Read only the entry at `index` from reader, seeking over payloads of
+    /// preceding entries. Returns `None` if there are not enough entries.
+    pub fn read_entry_at<R: Read + Seek>(
+        mut reader: R,
+        index: usize,
+    ) -> Result<Option<FatBinaryEntry<'static>>, FatBinaryError> {
+        let Some((bounds, entry_header, extra_header)) = seek_to_entry(&mut reader, index)? else {
+            return Ok(None);
+        };
+        bounds.context(check_in_memory(&entry_header))?;
+        let payload = bounds.context(read_payload(&mut reader, entry_header.size, usize::MAX))?;
+        Ok(Some(bounds.context(FatBinaryEntry::from_parts(
+            entry_header,
+            &extra_header,
+            Payload::Owned(payload).into_aligned(),
+            &ParseOptions::default(),
+        ))?))
+    }
+
+    /// Read fatbinary from memory, payloads are borrowed from `data`
+    pub fn parse(data: &'a [u8]) -> Result<FatBinary<'a>, FatBinaryError> {
+        Ok(Self::parse_with(data, ParseOptions::default())?.0)
+    }
+
+    /// Read fatbinary from memory like [FatBinary::parse], validating as
+    /// strictly as `options` requires. Anomalies which are accepted are
+    /// returned as warnings.
+    pub fn parse_with(
+        data: &'a [u8],
+        options: ParseOptions,
+    ) -> Result<(FatBinary<'a>, Warnings), FatBinaryError> {
+        let mut cursor = binread::io::Cursor::new(data);
+        let header: FatBinaryHeader = cursor.read_le()?;
+        header.check(&options)?;
+        cursor.set_position(header.header_size as u64);
+        let mut bounds = EntryBounds {
+            index: 0,
+            offset: cursor.position(),
+            end: data.len() as u64,
+        };
+
         let mut entries = vec![];
+        let mut warnings = vec![];
         let mut current_size = 0;
 
         while current_size < header.size {
-            let entry_header: FatBinaryEntryHeader = reader.read_le()?;
-
-            // handle case when header size > 64 e.g. PTX
-            let mut ptxas_options = None;
-            if entry_header.header_size > std::mem::size_of::<FatBinaryEntryHeader>() as u32 {
-                if entry_header.options_offset != 0x40 {
-                    return Err(FatBinaryError::InvalidOffset {
-                        expected: 0x40,
-                        got: entry_header.options_offset,
-                    });
-                }
-                let ptxas_options_offset: u32 = reader.read_le()?;
-                let ptxas_options_size: u32 = reader.read_le()?;
-
-                // locate ptxas options
-                if ptxas_options_offset != 0 {
-                    reader.seek(SeekFrom::Current(
-                        (
-                            ptxas_options_offset as usize
-                        - std::mem::size_of::<FatBinaryEntryHeader>()
-                        - std::mem::size_of::<u32>() // ptxas_options_offset
-                        - std::mem::size_of::<u32>()
-                            // ptxas_options_size
-                        ) as i64,
-                    ))?;
-                    let mut ptxas_options_bytes = vec![0u8; ptxas_options_size as usize];
-                    reader.read_exact(&mut ptxas_options_bytes)?;
-                    ptxas_options = Some(String::from_utf8(ptxas_options_bytes)?);
-                }
-
-                // seek to payload
-                reader.seek(SeekFrom::Current(
-                    (
-                        entry_header.header_size as usize
-                        - std::mem::size_of::<FatBinaryEntryHeader>()
-                        - std::mem::size_of::<u32>() // ptxas_options_offset
-                        - std::mem::size_of::<u32>() // ptxas_options_size
-                        - ptxas_options_size as usize
-                        // ptxas_options
-                    ) as i64,
-                ))?;
+            cancel::check(&options.cancel)?;
+            if options.is_slack(header.size, current_size)? {
+                break;
             }
+            bounds.check_header()?;
+            let entry_header: FatBinaryEntryHeader = bounds.context(Ok(cursor.read_le()?))?;
+            let entry_size = bounds.check_entry(&entry_header)?;
+            bounds.context(check_in_memory(&entry_header))?;
+            let extra_header_size = (entry_header.header_size as usize)
+                .saturating_sub(core::mem::size_of::<FatBinaryEntryHeader>());
+            let extra_header =
+                bounds.context(slice_at(data, cursor.position(), extra_header_size as u64))?;
             current_size += entry_header.header_size as u64;
 
-            let mut payload = vec![0; entry_header.size as usize];
-            reader.read_exact(&mut payload[..])?;
+            let payload = bounds.context(slice_at(
+                data,
+                cursor.position() + extra_header_size as u64,
+                entry_header.size,
+            ))?;
+            cursor.set_position(cursor.position() + extra_header_size as u64 + entry_header.size);
             current_size += entry_header.size;
 
-            entries.push(FatBinaryEntry {
+            let entry = bounds.context(FatBinaryEntry::from_parts(
                 entry_header,
-                ptxas_options,
-                payload,
-            })
+                extra_header,
+                Payload::Borrowed(payload),
+                &options,
+            ))?;
+            entry.collect_warnings(bounds.index, &mut warnings);
+            entries.push(entry);
+            bounds.advance(entry_size);
+            options.report(&header, current_size);
         }
+        options.check_size(header.size, current_size)?;
 
-        let res = FatBinary { entries };
-        Ok(res)
+        Ok((FatBinary { entries }, warnings))
+    }
+
+    /// Convert into fatbinary owning all payloads
+    pub fn into_owned(self) -> FatBinary<'static> {
+        FatBinary {
+            entries: self
+                .entries
+                .into_iter()
+                .map(FatBinaryEntry::into_owned)
+                .collect(),
+        }
+    }
+
+    /// Convert into fatbinary with reference-counted payloads, cloning it
+    /// afterwards does not copy payloads
+    pub fn into_shared(self) -> FatBinary<'static> {
+        FatBinary {
+            entries: self
+                .entries
+                .into_iter()
+                .map(FatBinaryEntry::into_shared)
+                .collect(),
+        }
+    }
+
+    /// Recompute derived header fields of all entries after mutating them
+    /// through [FatBinary::entries_mut], see [FatBinaryEntry::normalize].
+    /// Returns number of entries changed.
+    pub fn normalize(&mut self) -> usize {
+        self.entries
+            .iter_mut()
+            .map(FatBinaryEntry::normalize)
+            .filter(|&changed| changed)
+            .count()
+    }
+
+    /// Remove identifiers (object names) and ptxas options of all entries,
+    /// shrinking headers accordingly. Returns number of entries changed.
+    pub fn strip_identifiers(&mut self) -> usize {
+        let mut res = 0;
+        for entry in &mut self.entries {
+            if entry.identifier.is_some() || entry.ptxas_options.is_some() {
+                entry.identifier = None;
+                entry.ptxas_options = None;
+                entry.update_layout();
+                res += 1;
+            }
+        }
+        res
+    }
+
+    /// Append entries of `other`. With `dedupe`, entries with the same kind,
+    /// arch and decompressed payload as an entry already present are skipped,
+    /// regardless of compression, identifier and ptxas options. Returns number
+    /// of entries skipped.
+    pub fn merge(&mut self, other: FatBinary<'a>, dedupe: bool) -> usize {
+        if !dedupe {
+            self.entries.extend(other.entries);
+            return 0;
+        }
+
+        // decompress each payload once, comparing payloads only on hash match
+        let mut seen: BTreeMap<(EntryKind, u32, u64), Vec<usize>> = BTreeMap::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let hash = fnv1a(&entry.get_decompressed_payload());
+            seen.entry((entry.kind(), entry.get_sm_arch(), hash))
+                .or_default()
+                .push(index);
+        }
+        let mut res = 0;
+        for entry in other.entries {
+            let payload = entry.get_decompressed_payload();
+            let key = (entry.kind(), entry.get_sm_arch(), fnv1a(&payload));
+            let candidates = seen.entry(key).or_default();
+            if candidates
+                .iter()
+                .any(|&index| self.entries[index].get_decompressed_payload() == payload)
+            {
+                res += 1;
+                continue;
+            }
+            candidates.push(self.entries.len());
+            self.entries.push(entry);
+        }
+        res
+    }
+
+    /// Split entries into fatbinaries grouped by `key`, e.g. by arch,
+    /// keeping the order of entries within each group
+    pub fn split_by<K: Ord>(
+        &self,
+        mut key: impl FnMut(&FatBinaryEntry<'a>) -> K,
+    ) -> BTreeMap<K, FatBinary<'a>> {
+        let mut res: BTreeMap<K, FatBinary<'a>> = BTreeMap::new();
+        for entry in &self.entries {
+            res.entry(key(entry))
+                .or_default()
+                .entries
+                .push(entry.clone());
+        }
+        res
+    }
+
+    /// Group references to entries by `key`, keeping the order of entries
+    /// within each group. Unlike [FatBinary::split_by], entries are not
+    /// cloned.
+    pub fn group_by<K: Ord>(
+        &self,
+        mut key: impl FnMut(&FatBinaryEntry<'a>) -> K,
+    ) -> BTreeMap<K, Vec<&FatBinaryEntry<'a>>> {
+        let mut res: BTreeMap<K, Vec<&FatBinaryEntry<'a>>> = BTreeMap::new();
+        for entry in &self.entries {
+            res.entry(key(entry)).or_default().push(entry);
+        }
+        res
+    }
+
+    /// Group entries by SM arch, e.g. to check that every arch has both
+    /// ELF and PTX
+    pub fn group_by_arch(&self) -> BTreeMap<u32, Vec<&FatBinaryEntry<'a>>> {
+        self.group_by(FatBinaryEntry::get_sm_arch)
+    }
+
+    /// Group entries by kind
+    pub fn group_by_kind(&self) -> BTreeMap<EntryKind, Vec<&FatBinaryEntry<'a>>> {
+        self.group_by(FatBinaryEntry::kind)
     }
 
     /// Wriet fatbinary to writer
-    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), FatBinaryError> {
-        let payload_size = self
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), FatBinaryError> {
+        Self::write_entries(writer, self.entries.iter())
+    }
+
+    /// Write fatbinary to writer with options, e.g. compressing entries.
+    /// With the `rayon` feature, entries are compressed in parallel.
+    #[cfg(feature = "std")]
+    pub fn write_with_options<W: Write>(
+        &self,
+        writer: W,
+        options: &WriteOptions,
+    ) -> Result<(), FatBinaryError> {
+        if !options.compress {
+            return Self::write_entries_with(writer, self.entries.iter(), options);
+        }
+
+        let total = self
             .entries
             .iter()
-            .map(|entry| entry.entry_header.header_size as u64 + entry.entry_header.size)
+            .map(|entry| entry.payload.len() as u64)
             .sum();
-        let header = FatBinaryHeader {
-            magic: FAT_BINARY_MAGIC,
-            version: 1,
-            header_size: std::mem::size_of::<FatBinaryHeader>() as u16,
-            size: payload_size,
+        let done = core::sync::atomic::AtomicU64::new(0);
+        let compress = |entry| -> Result<Cow<'_, FatBinaryEntry<'a>>, FatBinaryError> {
+            cancel::check(&options.cancel)?;
+            let res = match FatBinaryEntry::compressed_payload(entry) {
+                Some((payload, compressed_size)) => {
+                    Cow::Owned(entry.with_compressed_payload(payload, compressed_size))
+                }
+                None => Cow::Borrowed(entry),
+            };
+            if let Some(progress) = &options.progress {
+                let size = entry.payload.len() as u64;
+                let done = done.fetch_add(size, core::sync::atomic::Ordering::Relaxed) + size;
+                progress(done, total, Phase::Compress);
+            }
+            Ok(res)
         };
+        #[cfg(feature = "rayon")]
+        let entries: Vec<Cow<'_, FatBinaryEntry<'a>>> = {
+            use rayon::prelude::*;
+            self.entries
+                .par_iter()
+                .map(compress)
+                .collect::<Result<_, _>>()?
+        };
+        #[cfg(not(feature = "rayon"))]
+        let entries: Vec<Cow<'_, FatBinaryEntry<'a>>> = self
+            .entries
+            .iter()
+            .map(compress)
+            .collect::<Result<_, _>>()?;
 
-        writer.write_all(&header.magic.to_le_bytes())?;
-        writer.write_all(&header.version.to_le_bytes())?;
-        writer.write_all(&header.header_size.to_le_bytes())?;
-        writer.write_all(&header.size.to_le_bytes())?;
+        Self::write_entries_with(writer, entries.iter().map(|entry| entry.as_ref()), options)
+    }
 
-        for entry in &self.entries {
-            writer.write_all(&entry.entry_header.kind.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.__unknown1.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.header_size.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.size.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.compressed_size.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.options_offset.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.minor.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.major.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.arch.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.obj_name_offset.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.obj_name_len.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.flags.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.zero.to_le_bytes())?;
-            writer.write_all(&entry.entry_header.decompressed_size.to_le_bytes())?;
-
-            if entry.entry_header.header_size > std::mem::size_of::<FatBinaryEntryHeader>() as u32 {
-                let zeros = vec![
-                    0u8;
-                    entry.entry_header.header_size as usize
-                        - std::mem::size_of::<FatBinaryEntryHeader>()
+    #[cfg(feature = "std")]
+    fn write_entries<'b, 'c: 'b, W: Write, I: Iterator<Item = &'b FatBinaryEntry<'c>> + Clone>(
+        writer: W,
+        entries: I,
+    ) -> Result<(), FatBinaryError> {
+        Self::write_entries_with(writer, entries, &WriteOptions::default())
+    }
+
+    /// Write entries, reporting progress and checking for cancellation
+    /// between entries as `options` require
+    #[cfg(feature = "std")]
+    fn write_entries_with<
+        'b,
+        'c: 'b,
+        W: Write,
+        I: Iterator<Item = &'b FatBinaryEntry<'c>> + Clone,
+    >(
+        mut writer: W,
+        entries: I,
+        options: &WriteOptions,
+    ) -> Result<(), FatBinaryError> {
+        // normalize headers on the fly rather than cloning the entries
+        let layouts: Vec<(FatBinaryEntryHeader, u32)> = entries
+            .clone()
+            .map(|entry| {
+                if options.normalize {
+                    entry.normalized_layout()
+                } else {
+                    (entry.entry_header, entry.ptxas_options_offset)
+                }
+            })
+            .collect();
+        let header = Self::header_of(layouts.iter().map(|(entry_header, _)| entry_header));
+        let total = header.header_size as u64 + header.size;
+        let header = header.to_bytes();
+
+        // assemble headers first, then write headers and payloads together
+        // with vectored writes to reduce syscalls
+        let headers: Vec<([u8; 64], Vec<u8>)> = entries
+            .clone()
+            .zip(&layouts)
+            .map(|(entry, (entry_header, ptxas_options_offset))| {
+                entry.serialized_headers_with(entry_header, *ptxas_options_offset)
+            })
+            .collect();
+
+        if options.progress.is_some() || options.cancel.is_some() {
+            // write entry by entry to report in between
+            writer.write_all(&header)?;
+            let mut done = header.len() as u64;
+            for (entry, (entry_header, extra_header)) in entries.zip(&headers) {
+                cancel::check(&options.cancel)?;
+                let mut bufs = [
+                    IoSlice::new(entry_header),
+                    IoSlice::new(extra_header),
+                    IoSlice::new(&entry.payload),
                 ];
-                writer.write_all(&zeros)?;
+                write_all_vectored(&mut writer, &mut bufs)?;
+                done += (entry_header.len() + extra_header.len() + entry.payload.len()) as u64;
+                if let Some(progress) = &options.progress {
+                    progress(done, total, Phase::Write);
+                }
             }
+            return Ok(());
+        }
 
-            writer.write_all(&entry.payload)?;
+        let mut bufs = vec![IoSlice::new(&header)];
+        for (entry, (entry_header, extra_header)) in entries.zip(&headers) {
+            bufs.push(IoSlice::new(entry_header));
+            bufs.push(IoSlice::new(extra_header));
+            bufs.push(IoSlice::new(&entry.payload));
         }
+        write_all_vectored(&mut writer, &mut bufs)?;
 
         Ok(())
     }
+
+    /// Compute fatbinary header from entry headers
+    #[cfg(feature = "std")]
+    fn header_of<'b, I: Iterator<Item = &'b FatBinaryEntryHeader>>(
+        entry_headers: I,
+    ) -> FatBinaryHeader {
+        let payload_size = entry_headers
+            .map(|entry_header| entry_header.header_size as u64 + entry_header.size)
+            .sum();
+        FatBinaryHeader {
+            magic: FAT_BINARY_MAGIC,
+            version: 1,
+            header_size: core::mem::size_of::<FatBinaryHeader>() as u16,
+            size: payload_size,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::fs::File;
 
-    use crate::FatBinary;
+    use crate::{
+        EntryKind, FatBinary, FatBinaryEntry, FatBinaryEntryHeader, FatBinaryError, ParseOptions,
+        ParseWarning, Payload, Phase, ValidationLevel, WriteOptions,
+    };
 
     #[test]
     fn read_axpy_default() {
@@ -548,4 +2718,1113 @@ mod tests {
         // second is ptx
         assert_eq!(entries[1].get_ptxas_options().unwrap().trim(), "-O3");
     }
+
+    #[test]
+    fn write_read_strings() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        let mut entry = FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec());
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let read = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(read, fatbin);
+
+        let entries = read.entries();
+        assert_eq!(entries[0].get_ptxas_options(), Some("-O3"));
+        assert_eq!(entries[0].get_identifier(), Some("axpy.cu"));
+        assert_eq!(entries[1].get_ptxas_options(), None);
+        assert_eq!(entries[1].get_identifier(), Some("axpy.cu"));
+    }
+
+    #[test]
+    fn strip_identifiers() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_identifier(Some("/home/user/src/axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        assert_eq!(fatbin.strip_identifiers(), 1);
+        assert_eq!(fatbin.strip_identifiers(), 0);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        assert!(!buffer.windows(7).any(|window| window == b"axpy.cu"));
+        let read = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(read, fatbin);
+        let header = read.entries()[0].get_header();
+        assert_eq!({ header.header_size }, 64);
+        assert_eq!(read.entries()[0].get_identifier(), None);
+        assert_eq!(read.entries()[0].get_ptxas_options(), None);
+    }
+
+    #[test]
+    fn checked_size() {
+        assert_eq!(crate::checked_size(0x40).unwrap(), 0x40);
+        let res = crate::checked_size(1 << 33);
+        if cfg!(target_pointer_width = "64") {
+            assert_eq!(res.unwrap() as u64, 1 << 33);
+        } else {
+            assert!(matches!(
+                res,
+                Err(FatBinaryError::EntryTooLargeForPlatform {
+                    size: 0x2_0000_0000
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn progress() {
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let record: crate::ProgressCallback = {
+            let reports = reports.clone();
+            std::sync::Arc::new(move |done, total, phase| {
+                reports.lock().unwrap().push((done, total, phase));
+            })
+        };
+        let take = || core::mem::take(&mut *reports.lock().unwrap());
+
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ptx.as_bytes()));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        let options = WriteOptions {
+            compress: true,
+            progress: Some(record.clone()),
+            ..Default::default()
+        };
+        let mut buffer = vec![];
+        fatbin.write_with_options(&mut buffer, &options).unwrap();
+        let total = buffer.len() as u64;
+        // entries may be compressed in any order
+        let payload_size = ptx.len() as u64 + 4;
+        let reports = take();
+        assert_eq!(reports.len(), 4);
+        assert_eq!(
+            reports[..2].iter().max(),
+            Some(&(payload_size, payload_size, Phase::Compress))
+        );
+        assert_eq!(
+            reports[2..],
+            [
+                (total - 68, total, Phase::Write),
+                (total, total, Phase::Write)
+            ]
+        );
+
+        let options = ParseOptions {
+            progress: Some(record.clone()),
+            ..Default::default()
+        };
+        let (mut read, _) =
+            FatBinary::read_with(std::io::Cursor::new(&buffer), options.clone()).unwrap();
+        FatBinary::parse_with(&buffer, options).unwrap();
+        assert_eq!(
+            take(),
+            [
+                (total - 68, total, Phase::Read),
+                (total, total, Phase::Read),
+                (total - 68, total, Phase::Read),
+                (total, total, Phase::Read),
+            ]
+        );
+
+        assert_eq!(read.decompress_with_progress(&*record), 1);
+        assert_eq!(
+            take(),
+            [(ptx.len() as u64, ptx.len() as u64, Phase::Decompress)]
+        );
+    }
+
+    #[test]
+    fn read_stream() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(4)));
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let len = buffer.len();
+        buffer.extend_from_within(..);
+
+        // slices can be read but not seeked
+        let mut reader = &buffer[..];
+        assert_eq!(FatBinary::read_stream(&mut reader).unwrap(), fatbin);
+        assert_eq!(reader.len(), len);
+        assert_eq!(FatBinary::read_stream(&mut reader).unwrap(), fatbin);
+        assert!(reader.is_empty());
+
+        assert!(matches!(
+            FatBinary::read_stream(&buffer[..len - 1])
+                .unwrap_err()
+                .inner(),
+            FatBinaryError::Io { .. }
+        ));
+        // entry size beyond size in fatbinary header
+        let mut data = buffer[..len].to_vec();
+        let second = len - 80;
+        data[second + 8] += 8;
+        assert!(matches!(
+            FatBinary::read_stream(&data[..]).unwrap_err(),
+            FatBinaryError::Truncated { entry_index: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn normalize() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        let mut entry = FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(16));
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        let mut expected = vec![];
+        fatbin.write(&mut expected).unwrap();
+        assert_eq!(fatbin.normalize(), 0);
+
+        let mut stale = fatbin.clone();
+        let header = &mut stale.entries_mut()[0].entry_header;
+        header.header_size = 64;
+        header.options_offset = 0;
+        header.obj_name_len = 0;
+        header.size += 8;
+        header.decompressed_size = 1;
+        stale.entries_mut()[1].entry_header.header_size = 100;
+        let mut buffer = vec![];
+        stale
+            .write_with_options(
+                &mut buffer,
+                &WriteOptions {
+                    normalize: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(buffer, expected);
+        assert_eq!(stale.normalize(), 2);
+        assert_eq!(stale, fatbin);
+    }
+
+    #[test]
+    fn merge() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut a = FatBinary::new();
+        a.entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ptx.as_bytes()));
+        let mut b = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.as_bytes());
+        assert!(entry.compress());
+        b.entries_mut().push(entry);
+        b.entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        let mut merged = a.clone();
+        assert_eq!(merged.merge(b.clone(), false), 0);
+        assert_eq!(merged.entries().len(), 3);
+        let mut merged = a.clone();
+        assert_eq!(merged.merge(b.clone(), true), 1);
+        assert_eq!(merged.entries().len(), 2);
+        assert_eq!(merged.entries()[1].get_sm_arch(), 80);
+    }
+
+    #[test]
+    fn split_by() {
+        let mut fatbin = FatBinary::new();
+        for (arch, payload) in [(70, ".target sm_70\n"), (80, "\x7fELF"), (70, "\x7fELF")] {
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, payload.as_bytes()));
+        }
+
+        let split = fatbin.split_by(|entry| entry.get_sm_arch());
+        assert_eq!(split.keys().copied().collect::<Vec<_>>(), vec![70, 80]);
+        let kinds: Vec<_> = split[&70].entries().iter().map(|e| e.kind()).collect();
+        assert_eq!(kinds, vec![EntryKind::Ptx, EntryKind::Elf]);
+        assert_eq!(split[&80].entries(), &fatbin.entries()[1..2]);
+    }
+
+    #[test]
+    fn group_by() {
+        let mut fatbin = FatBinary::new();
+        for (arch, payload) in [
+            (70, b".target sm_70\n".as_slice()),
+            (80, b"\x7fELF".as_slice()),
+            (70, b"\x7fELF".as_slice()),
+        ] {
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, payload));
+        }
+        let entries = fatbin.entries();
+
+        let by_arch = fatbin.group_by_arch();
+        assert_eq!(by_arch.keys().copied().collect::<Vec<_>>(), [70, 80]);
+        assert_eq!(by_arch[&70], [&entries[0], &entries[2]]);
+        assert_eq!(by_arch[&80], [&entries[1]]);
+        // every arch has both ELF and PTX
+        assert!(!by_arch.values().all(|group| {
+            group.iter().any(|entry| entry.kind() == EntryKind::Elf)
+                && group.iter().any(|entry| entry.kind() == EntryKind::Ptx)
+        }));
+
+        let by_kind = fatbin.group_by_kind();
+        assert_eq!(by_kind[&EntryKind::Ptx], [&entries[0]]);
+        assert_eq!(by_kind[&EntryKind::Elf], [&entries[1], &entries[2]]);
+        assert!(FatBinary::new().group_by_kind().is_empty());
+    }
+
+    #[test]
+    fn set_header_fields() {
+        let mut entry = FatBinaryEntry::new_auto(70, b"\x7fELF".to_vec());
+        entry.set_sm_arch(86);
+        entry.set_version(1, 8);
+        entry.set_flags(entry.get_flags() | 0x2);
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let read = FatBinary::parse(&buffer).unwrap();
+        let entry = &read.entries()[0];
+        assert_eq!(entry.get_sm_arch(), 86);
+        assert_eq!(
+            (entry.get_version_major(), entry.get_version_minor()),
+            (1, 8)
+        );
+        assert!(entry.has_debug_info());
+        assert_eq!(entry.get_flags(), fatbin.entries()[0].get_flags());
+    }
+
+    #[test]
+    fn module_image() {
+        let entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        assert_eq!(
+            entry.to_module_image(),
+            b".version 7.0\n.target sm_70\n\0".to_vec()
+        );
+        let entry = FatBinaryEntry::new_auto(70, b"\x7fELF".to_vec());
+        assert_eq!(entry.to_module_image(), b"\x7fELF".to_vec());
+    }
+
+    #[test]
+    fn read_call_count() {
+        /// Reader counting calls to read
+        struct CountingReader<'a> {
+            inner: std::io::Cursor<&'a [u8]>,
+            reads: usize,
+        }
+
+        impl std::io::Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.reads += 1;
+                self.inner.read(buf)
+            }
+        }
+
+        impl std::io::Seek for CountingReader<'_> {
+            fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 75, 80, 86] {
+            let mut entry =
+                FatBinaryEntry::new_auto(arch, ".version 7.0\n.target sm_70\n".as_bytes());
+            entry.set_ptxas_options(Some("-O3"));
+            entry.set_identifier(Some("axpy.cu"));
+            fatbin.entries_mut().push(entry);
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let mut reader = CountingReader {
+            inner: std::io::Cursor::new(&buffer[..]),
+            reads: 0,
+        };
+        assert_eq!(FatBinary::read(&mut reader).unwrap(), fatbin);
+        // header, then entry header, extra header and payload of each entry
+        assert!(reader.reads <= 1 + 3 * fatbin.entries().len());
+    }
+
+    #[test]
+    fn parse_borrowed() {
+        let ptx = ".version 7.0\n.target sm_70\n".as_bytes();
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(70, ptx));
+        assert_eq!(fatbin.entries()[0].get_payload().as_ptr(), ptx.as_ptr());
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        // payloads point into the input buffer
+        let parsed = FatBinary::parse(&buffer).unwrap();
+        let payload = parsed.entries()[0].get_payload();
+        assert!(buffer.as_ptr_range().contains(&payload.as_ptr()));
+        assert_eq!(parsed, fatbin);
+
+        let owned = parsed.into_owned();
+        drop(buffer);
+        assert_eq!(owned, fatbin);
+
+        // truncated input
+        assert!(FatBinary::parse(&[0x50, 0xed, 0x55, 0xba]).is_err());
+    }
+
+    #[test]
+    fn read_metadata() {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80] {
+            let mut entry = FatBinaryEntry::new_auto(arch, b".version 7.0\n.target sm_70\n");
+            entry.set_identifier(Some("axpy.cu"));
+            fatbin.entries_mut().push(entry);
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let infos = FatBinary::read_metadata(std::io::Cursor::new(&buffer)).unwrap();
+        let expected: Vec<_> = fatbin.entries().iter().map(|entry| entry.info()).collect();
+        assert_eq!(infos, expected);
+    }
+
+    #[test]
+    fn entry_info_sizes() {
+        let mut entry = FatBinaryEntry::new_auto(80, ".target sm_80\n".repeat(64).into_bytes());
+        let info = entry.info();
+        assert_eq!(info.payload_size(), 896);
+        assert_eq!(info.padding(), 0);
+        assert_eq!(info.compression_ratio(), Some(1.0));
+
+        assert!(entry.compress());
+        let info = entry.info();
+        assert_eq!(info.payload_size(), 896);
+        assert_eq!(info.padding(), info.size - info.compressed_size as u64);
+        assert_eq!(info.size % 8, 0);
+        assert_eq!(info.compression_ratio(), Some(896.0 / info.size as f64));
+        assert_eq!(
+            FatBinaryEntry::new_auto(80, vec![])
+                .info()
+                .compression_ratio(),
+            None
+        );
+    }
+
+    #[test]
+    fn read_entry_at() {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 75, 80] {
+            let ptx = format!(".version 7.0\n.target sm_{}\n", arch);
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, ptx.into_bytes()));
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        for (index, entry) in fatbin.entries().iter().enumerate() {
+            let read = FatBinary::read_entry_at(std::io::Cursor::new(&buffer), index).unwrap();
+            assert_eq!(read.as_ref(), Some(entry));
+        }
+        assert_eq!(
+            FatBinary::read_entry_at(std::io::Cursor::new(&buffer), 3).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn write_call_count() {
+        /// Writer counting calls to write, accepting at most 100 bytes at once
+        #[derive(Default)]
+        struct CountingWriter {
+            inner: Vec<u8>,
+            writes: usize,
+        }
+
+        impl std::io::Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.write_vectored(&[std::io::IoSlice::new(buf)])
+            }
+
+            fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+                self.writes += 1;
+                let mut len = 0;
+                for buf in bufs {
+                    let buf = &buf[..buf.len().min(100 - len)];
+                    self.inner.extend_from_slice(buf);
+                    len += buf.len();
+                }
+                Ok(len)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 75, 80, 86] {
+            let mut entry = FatBinaryEntry::new_auto(arch, b".version 7.0\n.target sm_70\n");
+            entry.set_ptxas_options(Some("-O3"));
+            entry.set_identifier(Some("axpy.cu"));
+            fatbin.entries_mut().push(entry);
+        }
+        let mut writer = CountingWriter::default();
+        fatbin.write(&mut writer).unwrap();
+        assert_eq!(FatBinary::parse(&writer.inner).unwrap(), fatbin);
+        // partial writes are resumed, one call per 100 bytes
+        assert_eq!(writer.writes, writer.inner.len().div_ceil(100));
+    }
+
+    #[test]
+    fn decompress_into() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut compressed = FatBinaryEntry::new_auto(70, ptx.as_bytes());
+        assert!(compressed.compress());
+        let plain = FatBinaryEntry::new_auto(70, b".version 7.0\n");
+
+        let mut buf = vec![];
+        assert_eq!(compressed.decompress_into(&mut buf), ptx.len());
+        assert_eq!(buf, ptx.as_bytes());
+        let capacity = buf.capacity();
+
+        // buffer is cleared and reused
+        assert_eq!(plain.decompress_into(&mut buf), 13);
+        assert_eq!(buf, b".version 7.0\n");
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    /// Debug cubin whose payload is compressed as concatenated LZ4 blocks
+    /// of `block_size` bytes each
+    fn multi_block_entry(len: usize, block_size: usize) -> (FatBinaryEntry<'static>, Vec<u8>) {
+        let mut payload = b"\x7fELF".to_vec();
+        let mut line = 0u32;
+        while payload.len() < len {
+            line = line.wrapping_mul(1103515245).wrapping_add(12345);
+            payload.extend(format!(".debug_line axpy.cu:{}\n", line >> 20).bytes());
+        }
+        payload.truncate(len);
+        let mut compressed = vec![];
+        for block in payload.chunks(block_size) {
+            compressed.extend(crate::compress::compress(block));
+        }
+        let header = FatBinaryEntryHeader {
+            kind: 2,
+            arch: 80,
+            flags: crate::FATBINARY_FLAG_COMPRESSED | crate::FATBINARY_FLAG_DEBUG,
+            compressed_size: compressed.len() as u32,
+            decompressed_size: payload.len() as u64,
+            ..Default::default()
+        };
+        compressed.resize(compressed.len().next_multiple_of(8), 0);
+        (FatBinaryEntry::from_raw_header(header, compressed), payload)
+    }
+
+    #[test]
+    fn multi_block_payload() {
+        use std::io::Read;
+
+        for (len, block_size) in [(200_000, 1 << 16), (600_000, 1 << 18), (1 << 17, 1 << 16)] {
+            let (entry, payload) = multi_block_entry(len, block_size);
+            assert_eq!(entry.try_get_decompressed_payload().unwrap(), payload);
+            assert_eq!(entry.get_decompressed_payload(), payload);
+            let mut output = vec![];
+            entry.payload_reader().read_to_end(&mut output).unwrap();
+            assert_eq!(output, payload);
+
+            let mut fatbin = FatBinary::new();
+            fatbin.entries_mut().push(entry);
+            let mut buffer = vec![];
+            fatbin.write(&mut buffer).unwrap();
+            let mut output = vec![];
+            FatBinary::copy_entry_payload(std::io::Cursor::new(&buffer), 0, &mut output).unwrap();
+            assert_eq!(output, payload);
+            fatbin.decompress();
+            assert_eq!(fatbin.entries()[0].get_payload(), payload);
+        }
+
+        // single blocks of the same size still decode as one block
+        let (mut entry, payload) = multi_block_entry(200_000, 1 << 20);
+        assert_eq!(entry.try_get_decompressed_payload().unwrap(), payload);
+        entry.entry_header.decompressed_size += 1;
+        assert!(entry.try_get_decompressed_payload().is_err());
+    }
+
+    #[test]
+    fn detect_block_size_reads_chunks() {
+        /// Cursor counting reads and seeks, like syscalls of a file
+        struct Counting<'a> {
+            cursor: std::io::Cursor<&'a [u8]>,
+            calls: usize,
+        }
+
+        impl std::io::Read for Counting<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.calls += 1;
+                self.cursor.read(buf)
+            }
+        }
+
+        impl std::io::Seek for Counting<'_> {
+            fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+                self.calls += 1;
+                self.cursor.seek(pos)
+            }
+        }
+
+        let (entry, _) = multi_block_entry(600_000, 1 << 16);
+        let header = entry.get_header();
+        let mut reader = Counting {
+            cursor: std::io::Cursor::new(entry.get_payload()),
+            calls: 0,
+        };
+        let block_size = crate::detect_block_size(
+            &mut reader,
+            header.compressed_size as u64,
+            header.decompressed_size,
+        )
+        .unwrap();
+        assert_eq!(block_size, Some(1 << 16));
+        assert_eq!(reader.cursor.position(), 0);
+        assert!(reader.calls < 100, "{} calls", reader.calls);
+    }
+
+    #[test]
+    fn parse_options() {
+        let strict = ParseOptions {
+            level: ValidationLevel::Strict,
+            ..Default::default()
+        };
+        let permissive = ParseOptions {
+            level: ValidationLevel::Permissive,
+            ..Default::default()
+        };
+
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, b".version 7.0\n.target sm_70\n");
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        assert_eq!(
+            FatBinary::parse_with(&buffer, strict.clone()).unwrap().0,
+            fatbin
+        );
+
+        // unknown version
+        let mut data = buffer.clone();
+        data[4] = 2;
+        assert!(FatBinary::parse(&data).is_err());
+        assert_eq!(
+            FatBinary::parse_with(&data, permissive.clone()).unwrap().0,
+            fatbin
+        );
+
+        // unknown kind
+        let mut data = buffer.clone();
+        data[16] = 3;
+        assert!(FatBinary::parse(&data).is_ok());
+        assert!(matches!(
+            FatBinary::parse_with(&data, strict.clone())
+                .unwrap_err()
+                .inner(),
+            FatBinaryError::InvalidKind { kind: 3 }
+        ));
+
+        // slack after the last entry
+        let mut data = buffer.clone();
+        data[8] += 8;
+        data.extend_from_slice(&[0; 8]);
+        assert!(FatBinary::parse(&data).is_err());
+        assert!(matches!(
+            FatBinary::parse_with(&data, strict.clone()),
+            Err(FatBinaryError::SizeMismatch { .. })
+        ));
+        assert_eq!(
+            FatBinary::parse_with(&data, permissive.clone()).unwrap().0,
+            fatbin
+        );
+        assert_eq!(
+            FatBinary::read_with(std::io::Cursor::new(&data), permissive.clone())
+                .unwrap()
+                .0,
+            fatbin
+        );
+
+        // identifier out of header bounds
+        let mut data = buffer.clone();
+        data[16 + 32] = 0xff;
+        assert!(FatBinary::parse(&data).is_err());
+        let (parsed, _) = FatBinary::parse_with(&data, permissive.clone()).unwrap();
+        assert_eq!(parsed.entries()[0].get_identifier(), None);
+    }
+
+    #[test]
+    fn parse_warnings() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, b"\x7fELF\0\0\0\0"));
+        let mut entry = FatBinaryEntry::new_auto(70, b".version 7.0\n.target sm_70\n\0\0\0\0\0");
+        entry.set_ptxas_options(Some("-O3"));
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let (parsed, warnings) = FatBinary::parse_with(&buffer, ParseOptions::default()).unwrap();
+        assert_eq!(parsed, fatbin);
+        assert_eq!(warnings, vec![]);
+
+        let mut data = buffer.clone();
+        // options offset in ELF header without room for options
+        data[16 + 20] = 0x40;
+        // unknown flag bit and unaligned size of PTX
+        let second = 16 + 64 + 8;
+        data[second + 40 + 2] |= 0x80;
+        data[second + 8] -= 1;
+        data[8] -= 1;
+        data.pop();
+        let (_, warnings) = FatBinary::parse_with(&data, ParseOptions::default()).unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                ParseWarning::OptionsOffsetWithoutOptions {
+                    entry_index: 0,
+                    options_offset: 0x40
+                },
+                ParseWarning::UnalignedSize {
+                    entry_index: 1,
+                    size: 31
+                },
+                ParseWarning::UnknownFlags {
+                    entry_index: 1,
+                    flags: 0x800000
+                },
+            ]
+        );
+        let (_, read_warnings) =
+            FatBinary::read_with(std::io::Cursor::new(&data), ParseOptions::default()).unwrap();
+        assert_eq!(read_warnings, warnings);
+        assert_eq!(warnings[2].to_string(), "entry 1: unknown flags 0x800000");
+    }
+
+    #[test]
+    fn compressed_without_size() {
+        let ptx = b".version 7.0\n.target sm_70\n\0\0\0\0\0";
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, &ptx[..]));
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        // set compressed flag only
+        buffer[16 + 40 + 1] |= 0x20;
+
+        let (parsed, warnings) = FatBinary::parse_with(&buffer, ParseOptions::default()).unwrap();
+        assert_eq!(
+            warnings,
+            vec![ParseWarning::CompressedWithoutSize { entry_index: 0 }]
+        );
+        let mut entry = parsed.entries()[0].clone();
+        assert!(!entry.is_compressed());
+        assert_eq!(entry.get_payload(), &ptx[..]);
+        assert_eq!(entry.get_decompressed_payload(), &ptx[..]);
+        let mut buf = vec![];
+        assert_eq!(entry.decompress_into(&mut buf), ptx.len());
+        entry.decompress();
+        assert_eq!(entry.get_payload(), &ptx[..]);
+    }
+
+    #[test]
+    fn non_utf8_strings() {
+        let mut entry = FatBinaryEntry::new_auto(70, b".version 7.0\n.target sm_70\n");
+        entry.set_identifier_bytes(Some(&b"axpy\xff.cu\0"[..]));
+        entry.set_ptxas_options_bytes(Some(&b"-O3\xfe"[..]));
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let parsed = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(parsed, fatbin);
+        let entry = &parsed.entries()[0];
+        assert_eq!(entry.get_identifier(), None);
+        assert_eq!(entry.get_identifier_bytes(), Some(&b"axpy\xff.cu\0"[..]));
+        assert_eq!(entry.get_identifier_lossy().unwrap(), "axpy\u{fffd}.cu\0");
+        assert_eq!(entry.get_ptxas_options(), None);
+        assert_eq!(entry.get_ptxas_options_lossy().unwrap(), "-O3\u{fffd}");
+    }
+
+    #[test]
+    fn normalized_strings() {
+        let mut entry = FatBinaryEntry::new_auto(70, b".version 7.0\n.target sm_70\n\0\0\0\0");
+        entry.set_identifier(Some("axpy.cu\0\0"));
+        entry.set_ptxas_options(Some(" -O3 \0"));
+        assert_eq!(entry.identifier(), Some("axpy.cu"));
+        assert_eq!(entry.get_identifier(), Some("axpy.cu\0\0"));
+        assert_eq!(entry.ptxas_options(), Some("-O3"));
+        assert_eq!(entry.ptx_source().unwrap(), ".version 7.0\n.target sm_70\n");
+
+        // compressed payload is decompressed first
+        let mut entry = FatBinaryEntry::new_auto(70, ".target sm_70\n".repeat(10).into_bytes());
+        assert!(entry.compress());
+        assert_eq!(entry.ptx_source().unwrap(), ".target sm_70\n".repeat(10));
+
+        let entry = FatBinaryEntry::new_auto(70, b"\x7fELF");
+        assert_eq!(entry.ptx_source(), None);
+        assert_eq!(entry.identifier(), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80] {
+            let ptx = format!(".version 7.0\n.target sm_{}\n", arch);
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, ptx.into_bytes()));
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        // PTX payloads are 27 bytes
+        let second = 16 + 64 + 27;
+
+        // ends in the payload of the second entry
+        let data = &buffer[..buffer.len() - 4];
+        let expected = (1, second as u64, 64 + 27, 64 + 23);
+        let check = |res: Result<_, FatBinaryError>| match res {
+            Err(FatBinaryError::Truncated {
+                entry_index,
+                header_offset,
+                needed,
+                available,
+            }) => assert_eq!((entry_index, header_offset, needed, available), expected),
+            _ => panic!("expected truncated error"),
+        };
+        check(FatBinary::parse(data).map(|_| ()));
+        check(FatBinary::read(std::io::Cursor::new(data)).map(|_| ()));
+        check(FatBinary::read_metadata(std::io::Cursor::new(data)).map(|_| ()));
+
+        // ends in the header of the second entry
+        let data = &buffer[..second + 10];
+        assert!(matches!(
+            FatBinary::parse(data),
+            Err(FatBinaryError::Truncated {
+                entry_index: 1,
+                needed: 64,
+                available: 10,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn error_context() {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80] {
+            let ptx = format!(".version 7.0\n.target sm_{}\n", arch);
+            let mut entry = FatBinaryEntry::new_auto(arch, ptx.into_bytes());
+            entry.set_identifier(Some("axpy.cu"));
+            fatbin.entries_mut().push(entry);
+        }
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        // move identifier of the second entry out of its header
+        let second = 16 + buffer[16 + 4] as usize + 27;
+        buffer[second + 32] = 0xff;
+        let check = |res: Result<_, FatBinaryError>| match res {
+            Err(FatBinaryError::AtEntry {
+                index,
+                offset,
+                source,
+            }) => {
+                assert_eq!((index, offset), (1, second as u64));
+                assert!(matches!(*source, FatBinaryError::OutOfHeaderBounds { .. }));
+            }
+            _ => panic!("expected error with entry context"),
+        };
+        check(FatBinary::parse(&buffer).map(|_| ()));
+        check(FatBinary::read(std::io::Cursor::new(&buffer)).map(|_| ()));
+        check(FatBinary::read_metadata(std::io::Cursor::new(&buffer)).map(|_| ()));
+        check(FatBinary::read_entry_at(std::io::Cursor::new(&buffer), 1).map(|_| ()));
+        assert!(FatBinary::read_entry_at(std::io::Cursor::new(&buffer), 0).is_ok());
+
+        let err = FatBinary::parse(&buffer).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("Entry 1 at offset {:#x}: ", second)));
+    }
+
+    #[test]
+    fn oversized_fields() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, b".version 7.0\n.target sm_70\n");
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        // header size beyond input is rejected before allocating it
+        let mut data = buffer.clone();
+        data[16 + 4..16 + 8].copy_from_slice(&0xfffffff0u32.to_le_bytes());
+        assert!(matches!(
+            FatBinary::read(std::io::Cursor::new(&data)),
+            Err(FatBinaryError::Truncated {
+                needed: 0xfffffff0..,
+                ..
+            })
+        ));
+        assert!(matches!(
+            FatBinary::parse(&data),
+            Err(FatBinaryError::Truncated { .. })
+        ));
+
+        // identifier beyond header
+        let mut data = buffer.clone();
+        data[16 + 36..16 + 40].copy_from_slice(&u32::MAX.to_le_bytes());
+        for res in [
+            FatBinary::read(std::io::Cursor::new(&data)),
+            FatBinary::parse(&data).map(FatBinary::into_owned),
+        ] {
+            assert!(matches!(
+                res.unwrap_err().inner(),
+                FatBinaryError::OutOfHeaderBounds { len: u32::MAX, .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn raw_header() {
+        let header = FatBinaryEntryHeader {
+            kind: 0x10,
+            version: 0x202,
+            arch: 123,
+            flags: 1 << 40,
+            reserved: 0xdead,
+            // layout fields are recomputed
+            header_size: 3,
+            size: 1000,
+            ..Default::default()
+        };
+        let mut entry = FatBinaryEntry::from_raw_header(header, b"payload".as_slice());
+        entry.set_identifier(Some("probe.cu"));
+        let written = *entry.get_header();
+        assert_eq!({ written.kind }, 0x10);
+        assert_eq!({ written.reserved }, 0xdead);
+        assert_eq!({ written.size }, 7);
+        assert_eq!({ written.header_size }, 72);
+        assert_eq!(entry.kind(), EntryKind::Unknown(0x10));
+
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let parsed = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(parsed, fatbin);
+        assert_eq!(parsed.entries()[0].get_header(), &written);
+
+        let mut entry = parsed.entries()[0].clone();
+        let mut header = *entry.get_header();
+        header.version = 0x101;
+        entry.set_raw_header(header);
+        assert_eq!({ entry.get_header().version }, 0x101);
+        assert_eq!(entry.get_identifier(), Some("probe.cu"));
+    }
+
+    #[test]
+    fn extra_header() {
+        let mut entry = FatBinaryEntry::new_auto(70, b".target sm_70\n".as_slice());
+        entry.set_identifier(Some("a.cu"));
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        // header, descriptor and identifier end at 0x4c, followed by padding
+        let header = &buffer[16..];
+        assert_eq!(u32::from_le_bytes(header[4..8].try_into().unwrap()), 0x50);
+        assert!(FatBinary::parse(&buffer).unwrap().entries()[0]
+            .get_extra_header()
+            .is_empty());
+
+        // unknown data in the padding is kept verbatim
+        buffer[16 + 0x4d] = 0x42;
+        let parsed = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(parsed.entries()[0].get_extra_header(), [0, 0x42]);
+        let mut written = vec![];
+        parsed.write(&mut written).unwrap();
+        assert_eq!(written, buffer);
+
+        // and moved after the strings when they change
+        let mut entry = parsed.entries()[0].clone();
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_extra_header(b"tlv".as_slice());
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(entry);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let parsed = FatBinary::parse(&buffer).unwrap();
+        let entry = &parsed.entries()[0];
+        assert_eq!(entry.get_ptxas_options(), Some("-O3"));
+        assert_eq!(entry.get_identifier(), Some("a.cu"));
+        assert_eq!(entry.get_extra_header(), b"tlv");
+    }
+
+    #[test]
+    fn malformed_compressed_payload() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(100);
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.as_bytes());
+        assert!(entry.compress());
+
+        // decompressed size in header is off
+        let mut mismatched = entry.clone();
+        mismatched.entry_header.decompressed_size += 1;
+        assert!(matches!(
+            mismatched.try_get_decompressed_payload(),
+            Err(FatBinaryError::DecompressedSizeMismatch { .. })
+        ));
+        assert_eq!(mismatched.get_decompressed_payload(), ptx.as_bytes());
+        assert!(mismatched.try_decompress().is_err());
+
+        // literal followed by a match reaching before the output
+        let mut malformed = entry.clone();
+        malformed.payload = Payload::Owned(b"\x10.\x05\x00\0\0\0\0".to_vec());
+        malformed.entry_header.compressed_size = 4;
+        assert!(matches!(
+            malformed.try_get_decompressed_payload(),
+            Err(FatBinaryError::InvalidCompressedPayload)
+        ));
+        assert_eq!(malformed.get_decompressed_payload(), &b"."[..]);
+        assert_eq!(malformed.decompress_into(&mut vec![]), 1);
+        assert!(malformed.rewrite_ptx(|ptx| ptx.to_string()).is_err());
+        malformed.decompress();
+        assert!(malformed.is_compressed());
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(malformed.clone());
+        fatbin.entries_mut().push(entry);
+        assert_eq!(fatbin.decompress(), 1);
+        assert!(fatbin.entries()[0].is_compressed());
+
+        // compressed size beyond payload is clamped
+        malformed.entry_header.compressed_size = u32::MAX;
+        assert_eq!(malformed.get_payload().len(), 8);
+        malformed.set_ptx(".version 7.0\n").unwrap();
+        assert!(!malformed.is_compressed());
+    }
+
+    #[test]
+    fn corrupted_input() {
+        let mut fatbin = FatBinary::new();
+        let mut entry =
+            FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".repeat(4).into_bytes());
+        entry.set_identifier(Some("axpy.cu"));
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(4)));
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let options = ParseOptions {
+            level: ValidationLevel::Permissive,
+            ..Default::default()
+        };
+        // overwrite every byte, and every field with extreme values
+        let mut inputs = vec![];
+        for offset in 0..buffer.len() {
+            for value in [0, 0x7f, 0xff] {
+                let mut data = buffer.clone();
+                data[offset] = value;
+                inputs.push(data);
+            }
+            let mut data = buffer.clone();
+            let len = (buffer.len() - offset).min(8);
+            data[offset..offset + len].copy_from_slice(&u64::MAX.to_le_bytes()[..len]);
+            inputs.push(data);
+        }
+        for data in inputs {
+            let _ = FatBinary::read_stream_with(&data[..], options.clone());
+            let Ok((mut read, _)) = FatBinary::parse_with(&data, options.clone()) else {
+                continue;
+            };
+            let _ = read.verify();
+            let _ = read.report();
+            for entry in read.entries() {
+                let _ = entry.info();
+                let _ = entry.get_decompressed_payload();
+            }
+            read.decompress();
+            read.write(&mut vec![]).unwrap();
+        }
+    }
+
+    #[test]
+    fn clone_shared() {
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            70,
+            b".version 7.0\n.target sm_70\n",
+        ));
+        let shared = fatbin.clone().into_shared();
+        assert!(shared.entries()[0].payload().is_shared());
+        assert_eq!(shared, fatbin);
+
+        // clones point to the same payload
+        let cloned = shared.clone();
+        assert_eq!(
+            cloned.entries()[0].get_payload().as_ptr(),
+            shared.entries()[0].get_payload().as_ptr()
+        );
+    }
+
+    #[test]
+    fn write_compressed() {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80] {
+            let ptx = format!(".version 7.0\n.target sm_{}\n", arch).repeat(100);
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, ptx.into_bytes()));
+        }
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            90,
+            ".version 7.0\n.target sm_90\n".as_bytes(),
+        ));
+
+        let mut buffer = vec![];
+        fatbin
+            .write_with_options(
+                &mut buffer,
+                &WriteOptions {
+                    compress: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let read = FatBinary::parse(&buffer).unwrap();
+        assert!(read.verify().is_empty());
+        // order is preserved and only compressible entries are compressed
+        for (entry, original) in read.entries().iter().zip(fatbin.entries()) {
+            assert_eq!(entry.get_sm_arch(), original.get_sm_arch());
+            assert_eq!(entry.is_compressed(), original.get_sm_arch() != 90);
+            assert_eq!(entry.get_decompressed_payload(), original.get_payload());
+        }
+    }
 }
@@ -0,0 +1,499 @@
+//! Decoding DWARF line tables of debug cubins
+//!
+//! Cubins compiled with `-G` or `-lineinfo` carry a `.debug_line` section
+//! mapping SASS addresses to source lines. Line programs of DWARF versions
+//! 2 to 5 are decoded; cubins are little endian.
+
+use crate::{EntryKind, FatBinaryEntry, FatBinaryError};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use object::{Object, ObjectSection};
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_SET_COLUMN: u8 = 5;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_BASIC_BLOCK: u8 = 7;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+
+/// Row of the line table of a cubin, returned by
+/// [FatBinaryEntry::line_info]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineRow {
+    /// SASS address, relative to the section of the function in
+    /// relocatable cubins
+    pub address: u64,
+    /// Source file, joined with its include directory if recorded
+    pub file: String,
+    pub line: u64,
+    /// Column, 0 if unknown
+    pub column: u64,
+}
+
+fn invalid(message: &str) -> FatBinaryError {
+    FatBinaryError::InvalidLineInfo {
+        message: message.to_string(),
+    }
+}
+
+/// Little endian reader of DWARF data
+struct Reader<'d> {
+    data: &'d [u8],
+    offset: usize,
+}
+
+impl<'d> Reader<'d> {
+    fn bytes(&mut self, len: usize) -> Result<&'d [u8], FatBinaryError> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.offset..end))
+            .ok_or_else(|| invalid("truncated .debug_line"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    /// Unsigned integer of `len` bytes, at most 8
+    fn uint(&mut self, len: usize) -> Result<u64, FatBinaryError> {
+        let mut bytes = [0u8; 8];
+        bytes[..len].copy_from_slice(self.bytes(len)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn u8(&mut self) -> Result<u8, FatBinaryError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn uleb(&mut self) -> Result<u64, FatBinaryError> {
+        let mut res = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                res |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(res);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64, FatBinaryError> {
+        let mut res = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                res |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    res |= -1 << shift;
+                }
+                return Ok(res);
+            }
+        }
+    }
+
+    /// NUL-terminated string
+    fn cstr(&mut self) -> Result<String, FatBinaryError> {
+        let rest = &self.data[self.offset.min(self.data.len())..];
+        let len = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let res = String::from_utf8_lossy(&rest[..len]).to_string();
+        self.offset += len + 1;
+        Ok(res)
+    }
+}
+
+/// NUL-terminated string at `offset` of a string section
+fn string_at(section: &[u8], offset: u64) -> Result<String, FatBinaryError> {
+    Reader {
+        data: section,
+        offset: usize::try_from(offset).map_err(|_| invalid("string out of section"))?,
+    }
+    .cstr()
+}
+
+/// Directory or file entry of a DWARF 5 header as (path, directory index)
+fn read_entry(
+    reader: &mut Reader,
+    formats: &[(u64, u64)],
+    offset_size: usize,
+    line_str: &[u8],
+    str: &[u8],
+) -> Result<(String, u64), FatBinaryError> {
+    let mut res = (String::new(), 0);
+    for &(content, form) in formats {
+        let mut text = None;
+        let value = match form {
+            DW_FORM_STRING => {
+                text = Some(reader.cstr()?);
+                0
+            }
+            DW_FORM_LINE_STRP => {
+                text = Some(string_at(line_str, reader.uint(offset_size)?)?);
+                0
+            }
+            DW_FORM_STRP => {
+                text = Some(string_at(str, reader.uint(offset_size)?)?);
+                0
+            }
+            DW_FORM_UDATA => reader.uleb()?,
+            DW_FORM_DATA1 => reader.uint(1)?,
+            DW_FORM_DATA2 => reader.uint(2)?,
+            DW_FORM_DATA4 => reader.uint(4)?,
+            DW_FORM_DATA8 => reader.uint(8)?,
+            DW_FORM_DATA16 => {
+                reader.bytes(16)?;
+                0
+            }
+            DW_FORM_BLOCK => {
+                let len = reader.uleb()? as usize;
+                reader.bytes(len)?;
+                0
+            }
+            form => return Err(invalid(&format!("unsupported form {:#x}", form))),
+        };
+        match content {
+            DW_LNCT_PATH => res.0 = text.unwrap_or_default(),
+            DW_LNCT_DIRECTORY_INDEX => res.1 = value,
+            _ => {}
+        }
+    }
+    Ok(res)
+}
+
+/// Entry formats of a DWARF 5 header
+fn read_formats(reader: &mut Reader) -> Result<Vec<(u64, u64)>, FatBinaryError> {
+    let count = reader.u8()?;
+    (0..count)
+        .map(|_| Ok((reader.uleb()?, reader.uleb()?)))
+        .collect()
+}
+
+/// Path of `name` in directory `dir`
+fn join(dir: &str, name: String) -> String {
+    if dir.is_empty() || name.starts_with('/') {
+        name
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+}
+
+/// Decode rows of all line programs in `.debug_line`, resolving strings in
+/// `.debug_line_str` and `.debug_str` of DWARF 5. End of sequence rows are
+/// omitted.
+fn parse_debug_line(
+    debug_line: &[u8],
+    line_str: &[u8],
+    str: &[u8],
+) -> Result<Vec<LineRow>, FatBinaryError> {
+    let mut res = Vec::new();
+    let mut reader = Reader {
+        data: debug_line,
+        offset: 0,
+    };
+    while reader.offset < debug_line.len() {
+        let (unit_length, offset_size) = match reader.uint(4)? {
+            0xffff_ffff => (reader.uint(8)?, 8),
+            len => (len, 4),
+        };
+        let unit_end = usize::try_from(unit_length)
+            .ok()
+            .and_then(|len| reader.offset.checked_add(len))
+            .filter(|&end| end <= debug_line.len())
+            .ok_or_else(|| invalid("line program out of section"))?;
+        let version = reader.uint(2)?;
+        if !(2..=5).contains(&version) {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+        if version >= 5 {
+            // address and segment selector sizes
+            reader.bytes(2)?;
+        }
+        let header_length = reader.uint(offset_size)? as usize;
+        let program_start = reader.offset.saturating_add(header_length);
+        let min_inst_length = reader.u8()? as u64;
+        if version >= 4 {
+            // maximum operations per instruction, only used by VLIW
+            reader.u8()?;
+        }
+        // default of is_stmt, which rows do not record
+        reader.u8()?;
+        let line_base = reader.u8()? as i8 as i64;
+        let line_range = reader.u8()?;
+        if line_range == 0 {
+            return Err(invalid("line range of 0"));
+        }
+        let opcode_base = reader.u8()?;
+        let opcode_lengths = reader.bytes(opcode_base.saturating_sub(1) as usize)?;
+
+        // directories and files are numbered from 1 before DWARF 5, where
+        // index 0 stands for the compilation directory
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        if version >= 5 {
+            let formats = read_formats(&mut reader)?;
+            for _ in 0..reader.uleb()? {
+                dirs.push(read_entry(&mut reader, &formats, offset_size, line_str, str)?.0);
+            }
+            let formats = read_formats(&mut reader)?;
+            for _ in 0..reader.uleb()? {
+                files.push(read_entry(
+                    &mut reader,
+                    &formats,
+                    offset_size,
+                    line_str,
+                    str,
+                )?);
+            }
+        } else {
+            dirs.push(String::new());
+            loop {
+                let dir = reader.cstr()?;
+                if dir.is_empty() {
+                    break;
+                }
+                dirs.push(dir);
+            }
+            files.push((String::new(), 0));
+            loop {
+                let name = reader.cstr()?;
+                if name.is_empty() {
+                    break;
+                }
+                let dir = reader.uleb()?;
+                // modification time and length
+                reader.uleb()?;
+                reader.uleb()?;
+                files.push((name, dir));
+            }
+        }
+        let file_name = |files: &[(String, u64)], file: u64| {
+            let Some((name, dir)) = files.get(file as usize) else {
+                return format!("<file {}>", file);
+            };
+            let dir = dirs.get(*dir as usize).map_or("", String::as_str);
+            join(dir, name.clone())
+        };
+
+        let initial = (0u64, 1u64, 1u64, 0u64);
+        let (mut address, mut file, mut line, mut column) = initial;
+        let mut program = Reader {
+            data: &debug_line[..unit_end],
+            offset: program_start,
+        };
+        while program.offset < unit_end {
+            let mut emit = false;
+            match program.u8()? {
+                0 => {
+                    let len = program.uleb()? as usize;
+                    let start = program.offset;
+                    match program.u8()? {
+                        DW_LNE_END_SEQUENCE => (address, file, line, column) = initial,
+                        DW_LNE_SET_ADDRESS => {
+                            address = program.uint(len.saturating_sub(1).min(8))?;
+                        }
+                        DW_LNE_DEFINE_FILE => {
+                            let name = program.cstr()?;
+                            let dir = program.uleb()?;
+                            files.push((name, dir));
+                        }
+                        _ => {}
+                    }
+                    program.offset = start
+                        .checked_add(len)
+                        .ok_or_else(|| invalid("extended opcode out of section"))?;
+                }
+                DW_LNS_COPY => emit = true,
+                DW_LNS_ADVANCE_PC => {
+                    address = address.wrapping_add(program.uleb()?.wrapping_mul(min_inst_length))
+                }
+                DW_LNS_ADVANCE_LINE => line = line.wrapping_add_signed(program.sleb()?),
+                DW_LNS_SET_FILE => file = program.uleb()?,
+                DW_LNS_SET_COLUMN => column = program.uleb()?,
+                DW_LNS_NEGATE_STMT | DW_LNS_BASIC_BLOCK => {}
+                DW_LNS_CONST_ADD_PC => {
+                    let advance = (255 - opcode_base) / line_range;
+                    address = address.wrapping_add(advance as u64 * min_inst_length);
+                }
+                DW_LNS_FIXED_ADVANCE_PC => address = address.wrapping_add(program.uint(2)?),
+                opcode if opcode < opcode_base => {
+                    // skip operands of opcodes without effect on rows
+                    for _ in 0..opcode_lengths[opcode as usize - 1] {
+                        program.uleb()?;
+                    }
+                }
+                opcode => {
+                    let adjusted = opcode - opcode_base;
+                    address =
+                        address.wrapping_add((adjusted / line_range) as u64 * min_inst_length);
+                    line = line.wrapping_add_signed(line_base + (adjusted % line_range) as i64);
+                    emit = true;
+                }
+            }
+            if emit {
+                res.push(LineRow {
+                    address,
+                    file: file_name(&files, file),
+                    line,
+                    column,
+                });
+            }
+        }
+        reader.offset = unit_end;
+    }
+    Ok(res)
+}
+
+impl FatBinaryEntry<'_> {
+    /// Decode `.debug_line` of the decompressed cubin into rows mapping SASS
+    /// addresses to source lines, in the order of the line programs. Empty
+    /// if the entry is not ELF or has no `.debug_line`, fails if the ELF or
+    /// its line table is malformed.
+    pub fn line_info(&self) -> Result<Vec<LineRow>, FatBinaryError> {
+        if self.kind() != EntryKind::Elf {
+            return Ok(Vec::new());
+        }
+        let payload = self.try_get_decompressed_payload()?;
+        let file = object::File::parse(&*payload).map_err(|err| invalid(&err.to_string()))?;
+        let section = |name: &str| -> Result<&[u8], FatBinaryError> {
+            match file.section_by_name(name) {
+                Some(section) => section.data().map_err(|err| invalid(&err.to_string())),
+                None => Ok(&[]),
+            }
+        };
+        parse_debug_line(
+            section(".debug_line")?,
+            section(".debug_line_str")?,
+            section(".debug_str")?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_debug_line;
+    use crate::{FatBinaryEntry, FatBinaryError, LineRow};
+
+    /// Line program unit with `header` after the version and `program`
+    fn unit(version: u16, header: &[u8], program: &[u8]) -> Vec<u8> {
+        let mut res = (2 + 4 + header.len() as u32 + program.len() as u32)
+            .to_le_bytes()
+            .to_vec();
+        res.extend_from_slice(&version.to_le_bytes());
+        let (prefix, header) = if version >= 5 {
+            header.split_at(2)
+        } else {
+            header.split_at(0)
+        };
+        res.extend_from_slice(prefix);
+        res.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        res.extend_from_slice(header);
+        res.extend_from_slice(program);
+        res
+    }
+
+    /// DWARF 2 line table of `/src/axpy.cu` and `b.h`
+    fn debug_line_v2() -> Vec<u8> {
+        let mut header = vec![1, 1, -5i8 as u8, 14, 10, 0, 1, 1, 1, 1, 0, 0, 0, 1];
+        header.extend_from_slice(b"/src\0\0axpy.cu\0\x01\0\0b.h\0\0\0\0\0");
+        let mut program = vec![0, 9, 2];
+        program.extend_from_slice(&0x10u64.to_le_bytes());
+        program.extend_from_slice(&[
+            3, 9, 5, 5, 1,   // line 10, column 5
+            240, // address +16, line +1
+            4, 2, 2, 8, 1, // file 2, address +8
+            8, 9, 7, 0, 3, 0x7d, 1, // address +17 +7, line -3
+            0, 1, 1,
+        ]);
+        unit(2, &header, &program)
+    }
+
+    #[test]
+    fn line_info() {
+        let row = |address, file: &str, line, column| LineRow {
+            address,
+            file: file.to_string(),
+            line,
+            column,
+        };
+        let v2 = vec![
+            row(0x10, "/src/axpy.cu", 10, 5),
+            row(0x20, "/src/axpy.cu", 11, 5),
+            row(0x28, "b.h", 11, 5),
+            row(0x40, "b.h", 8, 5),
+        ];
+        assert_eq!(parse_debug_line(&debug_line_v2(), &[], &[]).unwrap(), v2);
+
+        // path of directory in .debug_line_str, of file inline
+        let mut header = vec![8, 0, 1, 1, 1, -5i8 as u8, 14, 13];
+        header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend_from_slice(&[1, 1, 0x1f, 1, 0, 0, 0, 0]);
+        header.extend_from_slice(&[2, 1, 0x08, 2, 0x0b, 1]);
+        header.extend_from_slice(b"k.cu\0\0");
+        let mut program = vec![0, 9, 2];
+        program.extend_from_slice(&0x100u64.to_le_bytes());
+        // files are numbered from 0 in DWARF 5
+        program.extend_from_slice(&[4, 0, 3, 4, 1, 0, 1, 1]);
+        let mut data = debug_line_v2();
+        data.extend(unit(5, &header, &program));
+        let rows = parse_debug_line(&data, b"/work\0", &[]).unwrap();
+        assert_eq!(rows[..4], v2);
+        assert_eq!(rows[4..], [row(0x100, "/work/k.cu", 5, 0)]);
+
+        let mut truncated = debug_line_v2();
+        truncated.truncate(truncated.len() - 4);
+        assert!(matches!(
+            parse_debug_line(&truncated, &[], &[]),
+            Err(FatBinaryError::InvalidLineInfo { .. })
+        ));
+        assert!(parse_debug_line(&[], &[], &[]).unwrap().is_empty());
+        let ptx = FatBinaryEntry::new_auto(80, b".target sm_80\n".as_slice());
+        assert!(ptx.line_info().unwrap().is_empty());
+        let elf = FatBinaryEntry::new_auto(80, b"\x7fELF".as_slice());
+        assert!(elf.line_info().is_err());
+    }
+
+    #[cfg(feature = "object-write")]
+    #[test]
+    fn cubin_line_info() {
+        use object::write::Object;
+        use object::{Architecture, BinaryFormat, Endianness, SectionKind};
+
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let id = obj.add_section(vec![], b".debug_line".to_vec(), SectionKind::Debug);
+        obj.append_section_data(id, &debug_line_v2(), 1);
+        let mut entry = FatBinaryEntry::new_auto(80, obj.write().unwrap());
+        entry.compress();
+        let rows = entry.line_info().unwrap();
+        assert_eq!(rows, parse_debug_line(&debug_line_v2(), &[], &[]).unwrap());
+    }
+}
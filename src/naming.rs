@@ -0,0 +1,186 @@
+//! Output file names of extracted entries
+//!
+//! A [NameTemplate] like `{stem}.{index}.{arch}.{ext}` expands placeholders
+//! with the fields of an [EntryFileName]:
+//!
+//! - `{stem}`: file stem of the input, e.g. `axpy`
+//! - `{index}`: index of the entry
+//! - `{arch}`: architecture like `sm_80`, `{sm}` for the number alone
+//! - `{ext}`: `ptx`, `cubin` or `bin`
+//!
+//! `{{` and `}}` stand for literal braces.
+
+use crate::{EntryKind, FatBinaryError, SmArch};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Fields of the output file name of an extracted entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryFileName<'a> {
+    /// File stem of the input, e.g. `axpy` for `axpy.fatbin`
+    pub stem: &'a str,
+    pub index: usize,
+    pub arch: SmArch,
+    /// Extension without dot
+    pub extension: &'a str,
+}
+
+impl<'a> EntryFileName<'a> {
+    /// Fields of entry at `index`, with extension of the decompressed
+    /// payload of its kind
+    pub fn new(stem: &'a str, index: usize, kind: EntryKind, arch: SmArch) -> Self {
+        EntryFileName {
+            stem,
+            index,
+            arch,
+            extension: match kind {
+                EntryKind::Ptx => "ptx",
+                EntryKind::Elf => "cubin",
+                EntryKind::Unknown(_) => "bin",
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Stem,
+    Index,
+    Arch,
+    Sm,
+    Extension,
+}
+
+/// Template of output file names of extracted entries, parsed from strings
+/// like [NameTemplate::DEFAULT]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+impl NameTemplate {
+    /// Naming convention of NVIDIA's cuobjdump, e.g. `axpy.1.sm_80.ptx`
+    pub const DEFAULT: &'static str = "{stem}.{index}.{arch}.{ext}";
+
+    /// Expand placeholders with the fields of `name`
+    pub fn render(&self, name: &EntryFileName) -> String {
+        let mut res = String::new();
+        for part in &self.parts {
+            // writing to String never fails
+            let _ = match part {
+                Part::Literal(text) => write!(res, "{}", text),
+                Part::Stem => write!(res, "{}", name.stem),
+                Part::Index => write!(res, "{}", name.index),
+                Part::Arch => write!(res, "{}", name.arch),
+                Part::Sm => write!(res, "{}", name.arch.0),
+                Part::Extension => write!(res, "{}", name.extension),
+            };
+        }
+        res
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        let dot = || Part::Literal(".".to_string());
+        NameTemplate {
+            parts: vec![
+                Part::Stem,
+                dot(),
+                Part::Index,
+                dot(),
+                Part::Arch,
+                dot(),
+                Part::Extension,
+            ],
+        }
+    }
+}
+
+impl core::str::FromStr for NameTemplate {
+    type Err = FatBinaryError;
+
+    /// Parse template, failing on unknown placeholders and unmatched braces
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FatBinaryError::InvalidNameTemplate {
+            template: s.to_string(),
+        };
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            let placeholder = match c {
+                '{' if rest.starts_with('{') => None,
+                '}' if rest.starts_with('}') => None,
+                '}' => return Err(invalid()),
+                '{' => {
+                    let (name, after) = rest.split_once('}').ok_or_else(invalid)?;
+                    rest = after;
+                    Some(match name {
+                        "stem" => Part::Stem,
+                        "index" => Part::Index,
+                        "arch" => Part::Arch,
+                        "sm" => Part::Sm,
+                        "ext" => Part::Extension,
+                        _ => return Err(invalid()),
+                    })
+                }
+                _ => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+            match placeholder {
+                Some(part) => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(core::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                // escaped brace
+                None => {
+                    literal.push(c);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(NameTemplate { parts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EntryFileName, EntryKind, FatBinaryError, NameTemplate, SmArch};
+
+    #[test]
+    fn name_template() {
+        let name = EntryFileName::new("axpy", 1, EntryKind::Ptx, SmArch(80));
+        let default: NameTemplate = NameTemplate::DEFAULT.parse().unwrap();
+        assert_eq!(default, NameTemplate::default());
+        assert_eq!(default.render(&name), "axpy.1.sm_80.ptx");
+
+        let template: NameTemplate = "{sm}/{{{stem}}}-{index}.{ext}".parse().unwrap();
+        assert_eq!(
+            template.render(&EntryFileName::new("a", 0, EntryKind::Elf, SmArch(90))),
+            "90/{a}-0.cubin"
+        );
+        assert_eq!(
+            "plain".parse::<NameTemplate>().unwrap().render(&name),
+            "plain"
+        );
+
+        for template in ["{stem", "{name}", "stem}", "{}"] {
+            assert!(matches!(
+                template.parse::<NameTemplate>(),
+                Err(FatBinaryError::InvalidNameTemplate { .. })
+            ));
+        }
+    }
+}
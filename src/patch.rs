@@ -0,0 +1,412 @@
+//! Compact binary patches between fatbinaries
+//!
+//! Each entry of the new fatbinary is either kept from the old one, rebuilt
+//! from the stored payload of a similar old entry by copying ranges and
+//! inserting bytes, or stored in full. The encoding is little endian:
+//!
+//! ```text
+//! "FBPATCH1" entry_count:u32
+//! 0 index:u32                                   keep
+//! 1 base:u32 header_len:u32 header op_count:u32 delta
+//!   ops: 0 offset:u64 len:u64 (copy) | 1 len:u64 bytes (insert)
+//! 2 header_len:u32 header len:u64 payload       replace
+//! ```
+
+use crate::{
+    read_entry_header, FatBinary, FatBinaryEntry, FatBinaryError, ParseOptions, Payload,
+    ValidationLevel,
+};
+use std::collections::HashMap;
+
+const PATCH_MAGIC: &[u8; 8] = b"FBPATCH1";
+
+/// Shortest range worth copying from the base payload
+const BLOCK_SIZE: usize = 16;
+
+/// Operation rebuilding a payload from the payload of its base entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaOp {
+    /// Copy bytes of base payload
+    Copy { offset: u64, len: u64 },
+    /// Insert new bytes
+    Insert(Vec<u8>),
+}
+
+/// How an entry of the new fatbinary is rebuilt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryPatch {
+    /// Entry of old fatbinary at the index, unchanged
+    Keep(usize),
+    /// Raw entry header, with payload rebuilt from the payload of old entry
+    /// at `base`
+    Delta {
+        base: usize,
+        header: Vec<u8>,
+        ops: Vec<DeltaOp>,
+    },
+    /// Raw entry header and payload
+    Replace { header: Vec<u8>, payload: Vec<u8> },
+}
+
+/// Patch from one fatbinary to another, see [FatBinary::diff]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FatBinPatch {
+    pub entries: Vec<EntryPatch>,
+}
+
+/// Raw header of entry, as stored in fatbinary
+fn raw_header(entry: &FatBinaryEntry) -> Vec<u8> {
+    let mut res = entry.entry_header.to_bytes().to_vec();
+    res.extend(entry.serialize_extra_header());
+    res
+}
+
+/// Compute copy and insert operations turning `old` into `new`
+fn delta(old: &[u8], new: &[u8]) -> Vec<DeltaOp> {
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for offset in (0..(old.len() + 1).saturating_sub(BLOCK_SIZE)).rev() {
+        blocks.insert(&old[offset..offset + BLOCK_SIZE], offset);
+    }
+
+    let mut res = vec![];
+    let mut insert = vec![];
+    let mut i = 0;
+    while i < new.len() {
+        let found = new
+            .get(i..i + BLOCK_SIZE)
+            .and_then(|block| blocks.get(block));
+        let Some(&offset) = found else {
+            insert.push(new[i]);
+            i += 1;
+            continue;
+        };
+        let len = old[offset..]
+            .iter()
+            .zip(&new[i..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if !insert.is_empty() {
+            res.push(DeltaOp::Insert(std::mem::take(&mut insert)));
+        }
+        res.push(DeltaOp::Copy {
+            offset: offset as u64,
+            len: len as u64,
+        });
+        i += len;
+    }
+    if !insert.is_empty() {
+        res.push(DeltaOp::Insert(insert));
+    }
+    res
+}
+
+/// Encoded size of delta operations
+fn delta_size(ops: &[DeltaOp]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            DeltaOp::Copy { .. } => 17,
+            DeltaOp::Insert(bytes) => 9 + bytes.len(),
+        })
+        .sum()
+}
+
+/// Reader of encoded patch
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, len: u64) -> Result<&'a [u8], FatBinaryError> {
+        let invalid = || FatBinaryError::InvalidPatch {
+            message: "truncated patch".to_string(),
+        };
+        let len = usize::try_from(len).map_err(|_| invalid())?;
+        if len > self.data.len() {
+            return Err(invalid());
+        }
+        let (res, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Result<u8, FatBinaryError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, FatBinaryError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, FatBinaryError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Read `len` bytes into owned buffer
+    fn sized(&mut self, len: u64) -> Result<Vec<u8>, FatBinaryError> {
+        Ok(self.bytes(len)?.to_vec())
+    }
+}
+
+/// Build entry from raw header and payload
+fn entry_from_raw(
+    header: &[u8],
+    payload: Vec<u8>,
+) -> Result<FatBinaryEntry<'static>, FatBinaryError> {
+    let mut reader = header;
+    let entry_header = read_entry_header(&mut reader)?;
+    if entry_header.header_size as usize != header.len()
+        || entry_header.size != payload.len() as u64
+    {
+        return Err(FatBinaryError::InvalidPatch {
+            message: "entry header does not match patch".to_string(),
+        });
+    }
+    FatBinaryEntry::from_parts(
+        entry_header,
+        reader,
+        Payload::Owned(payload).into_aligned(),
+        &ParseOptions {
+            level: ValidationLevel::Permissive,
+            ..Default::default()
+        },
+    )
+}
+
+impl FatBinPatch {
+    /// Apply patch to the old fatbinary it was computed from
+    pub fn apply(&self, old: &FatBinary) -> Result<FatBinary<'static>, FatBinaryError> {
+        let base = |index: usize| {
+            old.entries
+                .get(index)
+                .ok_or_else(|| FatBinaryError::InvalidPatch {
+                    message: format!("entry {} does not exist", index),
+                })
+        };
+        let mut entries = vec![];
+        for entry in &self.entries {
+            entries.push(match entry {
+                EntryPatch::Keep(index) => base(*index)?.clone().into_owned(),
+                EntryPatch::Delta {
+                    base: index,
+                    header,
+                    ops,
+                } => {
+                    let old_payload = base(*index)?.get_payload();
+                    // copies may repeat, so stop at the size in the header
+                    // rather than growing without bound
+                    let size = read_entry_header(&mut &header[..])?.size;
+                    let mut payload = vec![];
+                    for op in ops {
+                        let bytes = match op {
+                            DeltaOp::Copy { offset, len } => usize::try_from(*offset)
+                                .ok()
+                                .zip(usize::try_from(offset.saturating_add(*len)).ok())
+                                .and_then(|(begin, end)| old_payload.get(begin..end))
+                                .ok_or_else(|| FatBinaryError::InvalidPatch {
+                                    message: format!("copy out of entry {} payload", index),
+                                })?,
+                            DeltaOp::Insert(bytes) => &bytes[..],
+                        };
+                        if (payload.len() + bytes.len()) as u64 > size {
+                            return Err(FatBinaryError::InvalidPatch {
+                                message: "entry header does not match patch".to_string(),
+                            });
+                        }
+                        payload.extend_from_slice(bytes);
+                    }
+                    entry_from_raw(header, payload)?
+                }
+                EntryPatch::Replace { header, payload } => entry_from_raw(header, payload.clone())?,
+            });
+        }
+        Ok(FatBinary { entries })
+    }
+
+    /// Encode patch into bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = PATCH_MAGIC.to_vec();
+        res.extend((self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            match entry {
+                EntryPatch::Keep(index) => {
+                    res.push(0);
+                    res.extend((*index as u32).to_le_bytes());
+                }
+                EntryPatch::Delta { base, header, ops } => {
+                    res.push(1);
+                    res.extend((*base as u32).to_le_bytes());
+                    res.extend((header.len() as u32).to_le_bytes());
+                    res.extend(header);
+                    res.extend((ops.len() as u32).to_le_bytes());
+                    for op in ops {
+                        match op {
+                            DeltaOp::Copy { offset, len } => {
+                                res.push(0);
+                                res.extend(offset.to_le_bytes());
+                                res.extend(len.to_le_bytes());
+                            }
+                            DeltaOp::Insert(bytes) => {
+                                res.push(1);
+                                res.extend((bytes.len() as u64).to_le_bytes());
+                                res.extend(bytes);
+                            }
+                        }
+                    }
+                }
+                EntryPatch::Replace { header, payload } => {
+                    res.push(2);
+                    res.extend((header.len() as u32).to_le_bytes());
+                    res.extend(header);
+                    res.extend((payload.len() as u64).to_le_bytes());
+                    res.extend(payload);
+                }
+            }
+        }
+        res
+    }
+
+    /// Decode patch encoded by [FatBinPatch::to_bytes]
+    pub fn from_bytes(data: &[u8]) -> Result<Self, FatBinaryError> {
+        let invalid = |message: &str| FatBinaryError::InvalidPatch {
+            message: message.to_string(),
+        };
+        let mut input = Input { data };
+        if input.bytes(PATCH_MAGIC.len() as u64)? != PATCH_MAGIC {
+            return Err(invalid("invalid magic"));
+        }
+
+        let count = input.u32()?;
+        let mut entries = vec![];
+        for _ in 0..count {
+            entries.push(match input.u8()? {
+                0 => EntryPatch::Keep(input.u32()? as usize),
+                1 => {
+                    let base = input.u32()? as usize;
+                    let len = input.u32()?;
+                    let header = input.sized(len as u64)?;
+                    let mut ops = vec![];
+                    for _ in 0..input.u32()? {
+                        ops.push(match input.u8()? {
+                            0 => DeltaOp::Copy {
+                                offset: input.u64()?,
+                                len: input.u64()?,
+                            },
+                            1 => {
+                                let len = input.u64()?;
+                                DeltaOp::Insert(input.sized(len)?)
+                            }
+                            _ => return Err(invalid("invalid delta operation")),
+                        });
+                    }
+                    EntryPatch::Delta { base, header, ops }
+                }
+                2 => {
+                    let len = input.u32()?;
+                    let header = input.sized(len as u64)?;
+                    let len = input.u64()?;
+                    let payload = input.sized(len)?;
+                    EntryPatch::Replace { header, payload }
+                }
+                _ => return Err(invalid("invalid entry patch")),
+            });
+        }
+        if !input.data.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(FatBinPatch { entries })
+    }
+}
+
+impl FatBinary<'_> {
+    /// Compute patch turning `old` into `new`. Changed entries are encoded
+    /// as delta against the old entry of the same kind and arch if smaller,
+    /// otherwise stored in full.
+    pub fn diff(old: &FatBinary, new: &FatBinary) -> FatBinPatch {
+        let mut entries = vec![];
+        for entry in &new.entries {
+            if let Some(index) = old.entries.iter().position(|old| old == entry) {
+                entries.push(EntryPatch::Keep(index));
+                continue;
+            }
+
+            let header = raw_header(entry);
+            let similar = old.entries.iter().position(|old| {
+                (old.kind(), old.get_sm_arch()) == (entry.kind(), entry.get_sm_arch())
+            });
+            if let Some(base) = similar {
+                let ops = delta(old.entries[base].get_payload(), entry.get_payload());
+                if delta_size(&ops) < entry.get_payload().len() {
+                    entries.push(EntryPatch::Delta { base, header, ops });
+                    continue;
+                }
+            }
+            entries.push(EntryPatch::Replace {
+                header,
+                payload: entry.get_payload().to_vec(),
+            });
+        }
+        FatBinPatch { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::patch::{delta, DeltaOp, EntryPatch};
+    use crate::{FatBinPatch, FatBinary, FatBinaryEntry, FatBinaryError};
+
+    #[test]
+    fn delta_ops() {
+        let old = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let new = b"XY0123456789abcdefghijkZ";
+        assert_eq!(
+            delta(old, new),
+            vec![
+                DeltaOp::Insert(b"XY".to_vec()),
+                DeltaOp::Copy { offset: 0, len: 21 },
+                DeltaOp::Insert(b"Z".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn patch_roundtrip() {
+        let ptx = ".version 7.0\n.target sm_70\n// kernel\n".repeat(32);
+        let mut old = FatBinary::new();
+        old.entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF cubin".to_vec()));
+        old.entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ptx.as_bytes().to_vec()));
+
+        let mut new = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.replace("kernel", "axpy").into_bytes());
+        entry.set_identifier(Some("axpy.cu"));
+        new.entries_mut().push(entry);
+        new.entries_mut().push(old.entries()[0].clone());
+        new.entries_mut()
+            .push(FatBinaryEntry::new_auto(90, b"\x7fELF sm_90".to_vec()));
+
+        let patch = FatBinary::diff(&old, &new);
+        assert!(matches!(
+            patch.entries[0],
+            EntryPatch::Delta { base: 1, .. }
+        ));
+        assert_eq!(patch.entries[1], EntryPatch::Keep(0));
+        assert!(matches!(patch.entries[2], EntryPatch::Replace { .. }));
+
+        let bytes = patch.to_bytes();
+        assert!(bytes.len() < ptx.len());
+        let decoded = FatBinPatch::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, patch);
+        assert_eq!(decoded.apply(&old).unwrap(), new);
+
+        assert!(matches!(
+            FatBinPatch::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(FatBinaryError::InvalidPatch { .. })
+        ));
+        // applying to a different fatbinary fails instead of panicking
+        assert!(matches!(
+            patch.apply(&FatBinary::new()),
+            Err(FatBinaryError::InvalidPatch { .. })
+        ));
+    }
+}
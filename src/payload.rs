@@ -0,0 +1,256 @@
+//! Payload storage of entries
+//!
+//! Buffers allocated by this crate are aligned to [PAYLOAD_ALIGNMENT] bytes,
+//! so cubins can be passed to `cuModuleLoadData` without copying. The
+//! global allocator returns aligned buffers in practice, others are copied
+//! into an [AlignedBuffer].
+
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+/// Alignment of payloads in buffers allocated by this crate, see
+/// [Payload::into_aligned]
+pub const PAYLOAD_ALIGNMENT: usize = 8;
+
+/// Owned buffer aligned to [PAYLOAD_ALIGNMENT] bytes
+#[derive(Clone, Default)]
+pub struct AlignedBuffer {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    /// Copy `data` into an aligned buffer
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut words = vec![0u64; data.len().div_ceil(8)];
+        for (word, chunk) in words.iter_mut().zip(data.chunks(8)) {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            *word = u64::from_ne_bytes(bytes);
+        }
+        Self {
+            words,
+            len: data.len(),
+        }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `words` holds at least `len` initialized bytes, which
+        // live as long as `self`
+        unsafe { core::slice::from_raw_parts(self.words.as_ptr().cast(), self.len) }
+    }
+}
+
+impl fmt::Debug for AlignedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AlignedBuffer").field(&&**self).finish()
+    }
+}
+
+/// Payload of an entry, either borrowed from the input, owned, or shared
+/// between clones
+///
+/// Cloning a [Payload::Shared] only bumps the reference count, use
+/// [crate::FatBinary::into_shared] before fanning a fatbinary out to
+/// multiple workers.
+#[derive(Debug, Clone)]
+pub enum Payload<'a> {
+    /// Borrowed from input
+    Borrowed(&'a [u8]),
+    /// Owned buffer
+    Owned(Vec<u8>),
+    /// Reference-counted buffer, cheap to clone
+    Shared(Arc<[u8]>),
+    /// Owned buffer aligned to [PAYLOAD_ALIGNMENT] bytes
+    Aligned(AlignedBuffer),
+}
+
+impl<'a> Payload<'a> {
+    /// Convert into payload without borrows, shared payloads stay shared.
+    /// Borrowed payloads are copied into an aligned buffer.
+    pub fn into_owned(self) -> Payload<'static> {
+        match self {
+            Payload::Borrowed(data) => Payload::Owned(data.to_vec()).into_aligned(),
+            Payload::Owned(data) => Payload::Owned(data),
+            Payload::Shared(data) => Payload::Shared(data),
+            Payload::Aligned(data) => Payload::Aligned(data),
+        }
+    }
+
+    /// Convert into reference-counted payload
+    pub fn into_shared(self) -> Payload<'static> {
+        match self {
+            Payload::Borrowed(data) => Payload::Shared(data.into()),
+            Payload::Owned(data) => Payload::Shared(data.into()),
+            Payload::Shared(data) => Payload::Shared(data),
+            Payload::Aligned(data) => Payload::Shared((*data).into()),
+        }
+    }
+
+    /// Convert into payload without borrows aligned to [PAYLOAD_ALIGNMENT]
+    /// bytes. Buffers which are already aligned are kept, others are
+    /// copied into an [AlignedBuffer].
+    pub fn into_aligned(self) -> Payload<'static> {
+        match self {
+            Payload::Borrowed(data) => Payload::Owned(data.to_vec()).into_aligned(),
+            Payload::Aligned(data) => Payload::Aligned(data),
+            payload if payload.is_aligned() => payload.into_owned(),
+            payload => Payload::Aligned(AlignedBuffer::from_slice(&payload)),
+        }
+    }
+
+    /// Whether the payload starts at a multiple of [PAYLOAD_ALIGNMENT]
+    pub fn is_aligned(&self) -> bool {
+        (self.as_ptr() as usize).is_multiple_of(PAYLOAD_ALIGNMENT)
+    }
+
+    /// Whether the payload is reference-counted
+    pub fn is_shared(&self) -> bool {
+        matches!(self, Payload::Shared(_))
+    }
+}
+
+impl Deref for Payload<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Payload::Borrowed(data) => data,
+            Payload::Owned(data) => data,
+            Payload::Shared(data) => data,
+            Payload::Aligned(data) => data,
+        }
+    }
+}
+
+impl AsRef<[u8]> for Payload<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PartialEq for Payload<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Payload<'_> {}
+
+impl PartialOrd for Payload<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Payload<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl Hash for Payload<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Payload<'a> {
+    fn from(data: &'a [u8]) -> Self {
+        Payload::Borrowed(data)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for Payload<'a> {
+    fn from(data: &'a [u8; N]) -> Self {
+        Payload::Borrowed(data)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for Payload<'a> {
+    fn from(data: &'a Vec<u8>) -> Self {
+        Payload::Borrowed(data)
+    }
+}
+
+impl From<Vec<u8>> for Payload<'_> {
+    fn from(data: Vec<u8>) -> Self {
+        Payload::Owned(data)
+    }
+}
+
+impl From<Arc<[u8]>> for Payload<'_> {
+    fn from(data: Arc<[u8]>) -> Self {
+        Payload::Shared(data)
+    }
+}
+
+impl From<AlignedBuffer> for Payload<'_> {
+    fn from(data: AlignedBuffer) -> Self {
+        Payload::Aligned(data)
+    }
+}
+
+impl<'a> From<Cow<'a, [u8]>> for Payload<'a> {
+    fn from(data: Cow<'a, [u8]>) -> Self {
+        match data {
+            Cow::Borrowed(data) => Payload::Borrowed(data),
+            Cow::Owned(data) => Payload::Owned(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AlignedBuffer, FatBinary, FatBinaryEntry, Payload, PAYLOAD_ALIGNMENT};
+
+    #[test]
+    fn alignment() {
+        let data: Vec<u8> = (0..29).collect();
+        let buffer = AlignedBuffer::from_slice(&data);
+        assert_eq!(*buffer, data[..]);
+        assert_eq!(buffer.as_ptr() as usize % PAYLOAD_ALIGNMENT, 0);
+        assert!(AlignedBuffer::from_slice(&[]).is_empty());
+
+        // a slice starting at an odd address is never aligned
+        let payload = Payload::Borrowed(&data[1..]);
+        assert!(!payload.is_aligned());
+        let aligned = payload.clone().into_aligned();
+        assert!(aligned.is_aligned());
+        assert_eq!(aligned, payload);
+        assert!(payload.into_owned().is_aligned());
+        assert_eq!(Payload::from(buffer).into_shared(), Payload::from(&data));
+
+        // parse from a misaligned buffer, so borrowed payloads are misaligned
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(8)));
+        let mut buffer = vec![0];
+        fatbin.write(&mut buffer).unwrap();
+        let mut parsed = FatBinary::parse(&buffer[1..]).unwrap();
+        let entry = &mut parsed.entries_mut()[0];
+        assert!(!entry.payload().is_aligned());
+        let expected = entry.payload().to_vec();
+        assert_eq!(entry.payload_aligned(), &expected[..]);
+        assert!(entry.payload().is_aligned());
+
+        let read = FatBinary::read(std::io::Cursor::new(&buffer[1..])).unwrap();
+        assert!(read.entries()[0].payload().is_aligned());
+        let mut entry = fatbin.entries()[0].clone();
+        assert!(entry.compress());
+        assert!(entry.payload().is_aligned());
+        entry.decompress();
+        assert!(entry.payload().is_aligned());
+    }
+}
@@ -0,0 +1,110 @@
+//! Reading fatbinaries from memory of a live process on Linux with
+//! `process_vm_readv`, enabled by the `process` feature
+//!
+//! Reading memory of another process needs the same permission as attaching
+//! to it with ptrace, see `ptrace(2)` and `/proc/sys/kernel/yama/ptrace_scope`.
+
+use crate::{FatBinary, FatBinaryError, FatBinaryWrapper, FATBINC_MAGIC, FAT_BINARY_MAGIC};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Largest fatbinary read from another process, guarding against garbage
+/// sizes in headers
+const MAX_FATBINARY_SIZE: u64 = 1 << 32;
+
+/// Fill `buf` from `address` in memory of process `pid`
+fn read_process_memory(pid: i32, address: u64, buf: &mut [u8]) -> Result<(), FatBinaryError> {
+    let mut done = 0;
+    while done < buf.len() {
+        let local = libc::iovec {
+            iov_base: buf[done..].as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len() - done,
+        };
+        let remote = libc::iovec {
+            iov_base: (address + done as u64) as *mut libc::c_void,
+            iov_len: buf.len() - done,
+        };
+        // SAFETY: local iovec covers the rest of `buf`, remote memory is
+        // only read by the kernel and checked against the mappings of `pid`
+        let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+        match read {
+            -1 => return Err(std::io::Error::last_os_error().into()),
+            // partial reads stop at the end of a mapping
+            0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            read => done += read as usize,
+        }
+    }
+    Ok(())
+}
+
+impl FatBinary<'static> {
+    /// Read fatbinary at `address` in memory of process `pid`. `address`
+    /// points either to a fatbinary or to a `__fatBinC_Wrapper_t`, e.g. the
+    /// argument of an intercepted `__cudaRegisterFatBinary` call. Only
+    /// available on Linux.
+    pub fn read_from_process(pid: i32, address: u64) -> Result<FatBinary<'static>, FatBinaryError> {
+        let mut magic = [0u8; 4];
+        read_process_memory(pid, address, &mut magic)?;
+        let address = if u32::from_le_bytes(magic) == FATBINC_MAGIC {
+            let mut wrapper = [0u8; FatBinaryWrapper::SIZE];
+            read_process_memory(pid, address, &mut wrapper)?;
+            FatBinaryWrapper::parse(&wrapper)?.data
+        } else {
+            address
+        };
+
+        // header_size at offset 6, size of entries at offset 8
+        let mut header = [0u8; 16];
+        read_process_memory(pid, address, &mut header)?;
+        let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        if magic != FAT_BINARY_MAGIC {
+            return Err(FatBinaryError::InvalidMagic {
+                expected: FAT_BINARY_MAGIC,
+                got: magic,
+            });
+        }
+        let header_size = u16::from_le_bytes(header[6..8].try_into().unwrap()) as u64;
+        let size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let len = header_size
+            .checked_add(size)
+            .filter(|&len| len <= MAX_FATBINARY_SIZE)
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or(FatBinaryError::WrapperOutOfBounds { address, len: size })?;
+
+        let mut data: Vec<u8> = vec![0; len];
+        read_process_memory(pid, address, &mut data)?;
+        Ok(FatBinary::parse(&data)?.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError, FatBinaryWrapper};
+
+    #[test]
+    fn read_from_process() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".repeat(4)));
+        let mut data = vec![];
+        fatbin.write(&mut data).unwrap();
+        let pid = std::process::id() as i32;
+
+        let read = FatBinary::read_from_process(pid, data.as_ptr() as u64).unwrap();
+        assert_eq!(read, fatbin);
+
+        let wrapper = FatBinaryWrapper::new(data.as_ptr() as u64).to_bytes();
+        let read = FatBinary::read_from_process(pid, wrapper.as_ptr() as u64).unwrap();
+        assert_eq!(read, fatbin);
+
+        assert!(matches!(
+            FatBinary::read_from_process(pid, data[4..].as_ptr() as u64),
+            Err(FatBinaryError::InvalidMagic { .. })
+        ));
+        assert!(matches!(
+            FatBinary::read_from_process(pid, 0),
+            Err(FatBinaryError::Io { .. })
+        ));
+    }
+}
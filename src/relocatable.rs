@@ -0,0 +1,137 @@
+//! Relocatable object files embedding fatbinary, enabled by the `object-write` feature
+//!
+//! The layout follows what nvcc emits for the host object:
+//! `.nv_fatbin` holds the fatbinary (symbol `fatbinData`), and
+//! `.nvFatBinSegment` holds the `__fatBinC_Wrapper_t` struct (symbol
+//! `__fatDeviceText`) passed to `__cudaRegisterFatBinary`.
+
+use crate::{FatBinary, FatBinaryError, FATBINC_MAGIC};
+use object::write::{Object, Relocation, Symbol, SymbolSection};
+use object::{
+    Architecture, BinaryFormat, Endianness, RelocationEncoding, RelocationFlags, RelocationKind,
+    SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+};
+
+/// Name of section containing fatbinary
+pub const NV_FATBIN_SECTION: &str = ".nv_fatbin";
+
+/// Name of section containing `__fatBinC_Wrapper_t`
+pub const NV_FATBIN_SEGMENT_SECTION: &str = ".nvFatBinSegment";
+
+impl FatBinary<'_> {
+    /// Create ELF relocatable object for host `architecture` embedding this
+    /// fatbinary, with global symbol `__fatDeviceText` pointing to the
+    /// `__fatBinC_Wrapper_t` struct for registration
+    pub fn to_relocatable_object(
+        &self,
+        architecture: Architecture,
+    ) -> Result<Vec<u8>, FatBinaryError> {
+        let pointer_size = match architecture.address_size() {
+            Some(address_size) => address_size.bytes() as usize,
+            None => {
+                return Err(FatBinaryError::UnsupportedArchitecture {
+                    architecture: format!("{:?}", architecture),
+                })
+            }
+        };
+        let endian = match architecture {
+            Architecture::PowerPc | Architecture::PowerPc64 | Architecture::Sparc64 => {
+                Endianness::Big
+            }
+            _ => Endianness::Little,
+        };
+        let mut obj = Object::new(BinaryFormat::Elf, architecture, endian);
+
+        let mut fatbin = vec![];
+        self.write(&mut fatbin)?;
+        let fatbin_section = obj.add_section(
+            vec![],
+            NV_FATBIN_SECTION.as_bytes().to_vec(),
+            SectionKind::ReadOnlyData,
+        );
+        obj.set_section_data(fatbin_section, fatbin.clone(), 8);
+        let fatbin_symbol = obj.add_symbol(Symbol {
+            name: b"fatbinData".to_vec(),
+            value: 0,
+            size: fatbin.len() as u64,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Compilation,
+            weak: false,
+            section: SymbolSection::Section(fatbin_section),
+            flags: SymbolFlags::None,
+        });
+
+        // struct __fatBinC_Wrapper_t { int magic; int version; const unsigned long long *data; void *filename_or_fatbins; }
+        let mut wrapper = vec![0u8; 8 + 2 * pointer_size];
+        let magic = FATBINC_MAGIC;
+        let version = 1u32;
+        match endian {
+            Endianness::Little => {
+                wrapper[0..4].copy_from_slice(&magic.to_le_bytes());
+                wrapper[4..8].copy_from_slice(&version.to_le_bytes());
+            }
+            Endianness::Big => {
+                wrapper[0..4].copy_from_slice(&magic.to_be_bytes());
+                wrapper[4..8].copy_from_slice(&version.to_be_bytes());
+            }
+        }
+        let segment_section = obj.add_section(
+            vec![],
+            NV_FATBIN_SEGMENT_SECTION.as_bytes().to_vec(),
+            SectionKind::Data,
+        );
+        obj.set_section_data(segment_section, wrapper.clone(), pointer_size as u64);
+        obj.add_symbol(Symbol {
+            name: b"__fatDeviceText".to_vec(),
+            value: 0,
+            size: wrapper.len() as u64,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Section(segment_section),
+            flags: SymbolFlags::None,
+        });
+        obj.add_relocation(
+            segment_section,
+            Relocation {
+                offset: 8,
+                symbol: fatbin_symbol,
+                addend: 0,
+                flags: RelocationFlags::Generic {
+                    kind: RelocationKind::Absolute,
+                    encoding: RelocationEncoding::Generic,
+                    size: pointer_size as u8 * 8,
+                },
+            },
+        )?;
+
+        Ok(obj.write()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+    use object::{Architecture, Object, ObjectSection, ObjectSymbol};
+
+    #[test]
+    fn relocatable_object() {
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            70,
+            ".version 7.0\n.target sm_70\n".as_bytes(),
+        ));
+        let data = fatbin.to_relocatable_object(Architecture::X86_64).unwrap();
+
+        let obj = object::File::parse(&data[..]).unwrap();
+        let section = obj.section_by_name(".nv_fatbin").unwrap();
+        assert_eq!(FatBinary::parse(section.data().unwrap()).unwrap(), fatbin);
+
+        let segment = obj.section_by_name(".nvFatBinSegment").unwrap();
+        assert_eq!(&segment.data().unwrap()[0..4], &0x466243b1u32.to_le_bytes());
+        assert_eq!(segment.relocations().count(), 1);
+        assert!(obj
+            .symbols()
+            .any(|symbol| symbol.name() == Ok("__fatDeviceText") && symbol.is_global()));
+    }
+}
@@ -0,0 +1,138 @@
+//! Human-readable summary of fatbinary
+
+use crate::{EntryKind, FatBinary};
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+/// Name of entry kind in reports
+fn kind_name(kind: EntryKind) -> String {
+    match kind {
+        EntryKind::Ptx => "PTX".to_string(),
+        EntryKind::Elf => "ELF".to_string(),
+        EntryKind::Unknown(kind) => format!("{:#x}", kind),
+    }
+}
+
+/// Ratio of decompressed size to stored size
+fn ratio(stored: u64, decompressed: u64) -> String {
+    if stored == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}", decompressed as f64 / stored as f64)
+    }
+}
+
+impl FatBinary<'_> {
+    /// Summarize the fatbinary: totals, arch coverage and one line per entry
+    /// with sizes, padding, compression ratio, debug info and identifier
+    pub fn report(&self) -> String {
+        let mut res = String::new();
+        let infos: alloc::vec::Vec<_> = self.entries.iter().map(|entry| entry.info()).collect();
+
+        let stored: u64 = infos.iter().map(|info| info.size).sum();
+        // decompressed sizes are not checked against the input
+        let decompressed = infos
+            .iter()
+            .map(|info| info.payload_size())
+            .fold(0u64, u64::saturating_add);
+        let padding: u64 = infos.iter().map(|info| info.padding()).sum();
+        let compressed = infos.iter().filter(|info| info.is_compressed).count();
+        let debug = infos.iter().filter(|info| info.has_debug_info).count();
+        // writing to String never fails
+        let _ = writeln!(res, "Summary:");
+        let _ = writeln!(res, "  entries: {}", infos.len());
+        let _ = writeln!(
+            res,
+            "  payload: {} bytes stored, {} bytes decompressed (ratio {})",
+            stored,
+            decompressed,
+            ratio(stored, decompressed)
+        );
+        let _ = writeln!(res, "  padding: {} bytes", padding);
+        let _ = writeln!(res, "  compressed entries: {}", compressed);
+        let _ = writeln!(res, "  entries with debug info: {}", debug);
+
+        let _ = writeln!(res);
+        let _ = writeln!(res, "Architectures:");
+        let _ = writeln!(res, "  {:<8} {:<4} {:<4} family", "arch", "ELF", "PTX");
+        let mark = |present: bool| if present { "yes" } else { "-" };
+        for (arch, coverage) in self.coverage().iter() {
+            let _ = writeln!(
+                res,
+                "  {:<8} {:<4} {:<4} {}",
+                arch.to_string(),
+                mark(coverage.has_sass()),
+                mark(coverage.has_ptx()),
+                arch.family_name().unwrap_or("-")
+            );
+        }
+
+        let _ = writeln!(res);
+        let _ = writeln!(res, "Entries:");
+        let _ = writeln!(
+            res,
+            "  {:>3} {:<5} {:<8} {:>10} {:>12} {:>7} {:>6} {:<5} identifier",
+            "#", "kind", "arch", "stored", "decompressed", "padding", "ratio", "debug"
+        );
+        for (index, (entry, info)) in self.entries.iter().zip(&infos).enumerate() {
+            let _ = writeln!(
+                res,
+                "  {:>3} {:<5} {:<8} {:>10} {:>12} {:>7} {:>6} {:<5} {}",
+                index,
+                kind_name(info.kind),
+                info.arch.to_string(),
+                info.size,
+                info.payload_size(),
+                info.padding(),
+                ratio(info.size, info.payload_size()),
+                if info.has_debug_info { "yes" } else { "no" },
+                entry
+                    .get_identifier_lossy()
+                    .as_deref()
+                    .map(|identifier| identifier.trim_end_matches('\0'))
+                    .unwrap_or("-")
+            );
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+
+    #[test]
+    fn report() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".target sm_70\n".repeat(64).into_bytes());
+        assert!(entry.compress());
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        let mut entry = FatBinaryEntry::new_auto(70, b"\x7fELF\x02\x01\x01\0".to_vec());
+        entry.set_debug_info(true);
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b".target sm_80\n".to_vec()));
+
+        let report = fatbin.report();
+        let stored = fatbin.entries()[0].get_header().size;
+        assert!(report.contains("  entries: 3\n"));
+        assert!(report.contains("  compressed entries: 1\n"));
+        assert!(report.contains("  entries with debug info: 1\n"));
+        assert!(report.contains("  sm_70    yes  yes  Volta\n"));
+        assert!(report.contains("  sm_80    -    yes  Ampere\n"));
+        let padding = fatbin.entries()[0].info().padding();
+        assert!(padding < 8);
+        assert!(report.contains(&format!("  padding: {} bytes\n", padding)));
+        assert!(report.contains(&format!(
+            "    0 PTX   sm_70    {:>10}          896 {:>7} {:>6.2} no    axpy.cu\n",
+            stored,
+            padding,
+            896.0 / stored as f64
+        )));
+        assert!(report
+            .contains("    1 ELF   sm_70             8            8       0   1.00 yes   -\n"));
+    }
+}
@@ -0,0 +1,262 @@
+//! Serializable representation of fatbinary files
+
+use crate::{
+    EntryFileName, EntryInfo, FatBinary, FatBinaryEntry, FatBinaryError, NameTemplate, SmArch,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+
+/// Payload of [EntryRepr]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadRepr {
+    /// Payload encoded in base64
+    Base64(String),
+    /// Payload stored in external file
+    File(PathBuf),
+}
+
+/// Serializable representation of [FatBinaryEntry]. Strings of `info` are
+/// lossy, so raw bytes which are not valid UTF-8 are kept in base64 too.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntryRepr {
+    #[serde(flatten)]
+    pub info: EntryInfo,
+    /// Raw identifier in base64, if it differs from that of `info`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier_base64: Option<String>,
+    /// Raw ptxas options in base64, if they differ from those of `info`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ptxas_options_base64: Option<String>,
+    /// Header bytes following the strings in base64, see
+    /// [FatBinaryEntry::get_extra_header]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_header: Option<String>,
+    /// Stored (possibly compressed) payload
+    pub payload: PayloadRepr,
+}
+
+/// Raw `bytes` in base64 if they differ from the lossy `text`
+fn raw_bytes(bytes: Option<&[u8]>, text: Option<&str>) -> Option<String> {
+    let bytes = bytes?;
+    (text.map(str::as_bytes) != Some(bytes))
+        .then(|| base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Format of manifest files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Yaml,
+}
+
+impl ManifestFormat {
+    /// Detect format from extension: JSON for `.json`, YAML otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => ManifestFormat::Json,
+            _ => ManifestFormat::Yaml,
+        }
+    }
+}
+
+/// Serializable representation of [FatBinary]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default)]
+pub struct FatBinaryRepr {
+    pub entries: Vec<EntryRepr>,
+}
+
+impl FatBinary<'_> {
+    /// Convert to serializable representation with payloads encoded in
+    /// base64. Header layout fields like offsets of strings are not kept,
+    /// but recomputed by [FatBinary::from_repr], like [FatBinary::normalize]
+    /// does.
+    pub fn to_repr(&self) -> FatBinaryRepr {
+        let res = self.to_repr_with(|_, entry| {
+            Ok::<_, Infallible>(PayloadRepr::Base64(
+                base64::engine::general_purpose::STANDARD.encode(entry.get_payload()),
+            ))
+        });
+        match res {
+            Ok(res) => res,
+            Err(err) => match err {},
+        }
+    }
+
+    /// Convert to serializable representation, payloads are converted by
+    /// callback e.g. to save them in external files
+    pub fn to_repr_with<F, E>(&self, mut f: F) -> Result<FatBinaryRepr, E>
+    where
+        F: FnMut(usize, &FatBinaryEntry) -> Result<PayloadRepr, E>,
+    {
+        let mut entries = vec![];
+        for (index, entry) in self.entries.iter().enumerate() {
+            let info = entry.info();
+            entries.push(EntryRepr {
+                identifier_base64: raw_bytes(
+                    entry.get_identifier_bytes(),
+                    info.identifier.as_deref(),
+                ),
+                ptxas_options_base64: raw_bytes(
+                    entry.get_ptxas_options_bytes(),
+                    info.ptxas_options.as_deref(),
+                ),
+                extra_header: (!entry.get_extra_header().is_empty()).then(|| {
+                    base64::engine::general_purpose::STANDARD.encode(entry.get_extra_header())
+                }),
+                info,
+                payload: f(index, entry)?,
+            });
+        }
+        Ok(FatBinaryRepr { entries })
+    }
+
+    /// Convert from serializable representation, external payload files are
+    /// relative to `base_dir`
+    pub fn from_repr(repr: &FatBinaryRepr, base_dir: &Path) -> Result<Self, FatBinaryError> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let mut entries = vec![];
+        for entry in &repr.entries {
+            let payload = match &entry.payload {
+                PayloadRepr::Base64(data) => base64.decode(data)?,
+                PayloadRepr::File(path) => std::fs::read(base_dir.join(path))?,
+            };
+            let mut res = FatBinaryEntry::from_info(&entry.info, payload);
+            if let Some(identifier) = &entry.identifier_base64 {
+                res.set_identifier_bytes(Some(base64.decode(identifier)?));
+            }
+            if let Some(ptxas_options) = &entry.ptxas_options_base64 {
+                res.set_ptxas_options_bytes(Some(base64.decode(ptxas_options)?));
+            }
+            if let Some(extra_header) = &entry.extra_header {
+                res.set_extra_header(base64.decode(extra_header)?);
+            }
+            entries.push(res);
+        }
+        Ok(FatBinary { entries })
+    }
+
+    /// Write manifest describing all entries to `path`, in JSON or YAML
+    /// according to its extension. Payloads are saved next to it in files
+    /// named by [NameTemplate::DEFAULT], with extension `bin` for compressed
+    /// payloads.
+    pub fn to_manifest(&self, path: &Path) -> Result<(), FatBinaryError> {
+        let template = NameTemplate::default();
+        self.to_manifest_with(path, |name| template.render(name))
+    }
+
+    /// Write manifest like [FatBinary::to_manifest], the callback names the
+    /// payload files, relative to the manifest
+    pub fn to_manifest_with<F>(&self, path: &Path, mut f: F) -> Result<(), FatBinaryError>
+    where
+        F: FnMut(&EntryFileName) -> String,
+    {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let repr = self.to_repr_with(|index, entry| {
+            let mut name =
+                EntryFileName::new(&stem, index, entry.kind(), SmArch(entry.get_sm_arch()));
+            if entry.is_compressed() {
+                name.extension = "bin";
+            }
+            let name = f(&name);
+            std::fs::write(dir.join(&name), entry.get_payload())?;
+            Ok::<_, FatBinaryError>(PayloadRepr::File(name.into()))
+        })?;
+
+        let file = std::fs::File::create(path)?;
+        match ManifestFormat::from_path(path) {
+            ManifestFormat::Json => serde_json::to_writer_pretty(file, &repr)?,
+            ManifestFormat::Yaml => serde_yaml::to_writer(file, &repr)?,
+        }
+        Ok(())
+    }
+
+    /// Read manifest written by [FatBinary::to_manifest], payload files are
+    /// relative to the manifest
+    pub fn from_manifest(path: &Path) -> Result<Self, FatBinaryError> {
+        let file = std::fs::File::open(path)?;
+        let repr: FatBinaryRepr = match ManifestFormat::from_path(path) {
+            ManifestFormat::Json => serde_json::from_reader(file)?,
+            ManifestFormat::Yaml => serde_yaml::from_reader(file)?,
+        };
+        Self::from_repr(&repr, path.parent().unwrap_or(Path::new(".")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryRepr, Host, NameTemplate};
+    use std::path::Path;
+
+    #[test]
+    fn repr_roundtrip() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_host(Host::Linux);
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+
+        let yaml = serde_yaml::to_string(&fatbin.to_repr()).unwrap();
+        assert!(yaml.contains("identifier: axpy.cu"));
+        assert!(yaml.contains("host: Linux"));
+        let repr: FatBinaryRepr = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(FatBinary::from_repr(&repr, Path::new(".")).unwrap(), fatbin);
+        assert!(!yaml.contains("_base64") && !yaml.contains("extra_header"));
+    }
+
+    #[test]
+    fn repr_raw_bytes() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_identifier_bytes(Some(b"axpy\xff.cu".to_vec()));
+        entry.set_ptxas_options_bytes(Some(b"-O3\xfe".to_vec()));
+        entry.set_extra_header(vec![1, 2, 3]);
+        fatbin.entries_mut().push(entry);
+
+        let json = serde_json::to_string(&fatbin.to_repr()).unwrap();
+        assert!(json.contains("\"identifier\":\"axpy\u{fffd}.cu\""));
+        assert!(json.contains("\"extra_header\":\"AQID\""));
+        let repr: FatBinaryRepr = serde_json::from_str(&json).unwrap();
+        let read = FatBinary::from_repr(&repr, Path::new(".")).unwrap();
+        assert_eq!(read, fatbin);
+        assert_eq!(
+            read.entries()[0].get_identifier_bytes(),
+            Some(b"axpy\xff.cu".as_slice())
+        );
+    }
+
+    #[test]
+    fn manifest_roundtrip() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".target sm_70\n".repeat(16).into_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        let dir = std::env::temp_dir().join(format!("fatbinary-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["axpy.json", "axpy.yaml"] {
+            fatbin.to_manifest(&dir.join(name)).unwrap();
+            assert_eq!(FatBinary::from_manifest(&dir.join(name)).unwrap(), fatbin);
+        }
+        let template: NameTemplate = "{stem}-{sm}-{index}.{ext}".parse().unwrap();
+        let custom = dir.join("custom.yaml");
+        fatbin
+            .to_manifest_with(&custom, |name| template.render(name))
+            .unwrap();
+        assert_eq!(FatBinary::from_manifest(&custom).unwrap(), fatbin);
+        assert!(dir.join("custom-80-1.cubin").exists());
+        let json = std::fs::read_to_string(dir.join("axpy.json")).unwrap();
+        let cubin = std::fs::read(dir.join("axpy.1.sm_80.cubin")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(json.contains("\"file\": \"axpy.0.sm_70.bin\""));
+        assert!(json.contains("\"ptxas_options\": \"-O3\""));
+        assert_eq!(cubin, b"\x7fELF");
+    }
+}
@@ -0,0 +1,119 @@
+//! Editing PTX of entries in place
+//!
+//! PTX is stored NUL-terminated and padded with NULs to 8 bytes, compressed
+//! entries are compressed again after rewriting.
+
+use crate::{
+    trim_nul, EntryKind, FatBinary, FatBinaryEntry, FatBinaryError, Payload, SmArch,
+    FATBINARY_FLAG_COMPRESSED,
+};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+impl FatBinaryEntry<'_> {
+    /// Replace PTX of this entry, fails if the entry is not PTX
+    pub fn set_ptx(&mut self, ptx: &str) -> Result<(), FatBinaryError> {
+        if self.kind() != EntryKind::Ptx {
+            return Err(FatBinaryError::NotPtx {
+                kind: self.entry_header.kind,
+            });
+        }
+
+        // the old payload is replaced, so it need not be decompressed
+        let was_compressed = self.is_compressed();
+        self.entry_header.flags &= !FATBINARY_FLAG_COMPRESSED;
+        self.entry_header.compressed_size = 0;
+        self.entry_header.decompressed_size = 0;
+        let mut payload = Vec::with_capacity((ptx.len() + 1).next_multiple_of(8));
+        payload.extend_from_slice(ptx.as_bytes());
+        payload.resize((ptx.len() + 1).next_multiple_of(8), 0);
+        self.entry_header.size = payload.len() as u64;
+        self.payload = Payload::Owned(payload).into_aligned();
+        if was_compressed {
+            self.compress();
+        }
+        Ok(())
+    }
+
+    /// Transform PTX of this entry with `f`, which gets PTX without padding
+    /// NULs. Fails if the entry is not PTX, its compressed payload is
+    /// malformed or it is not valid UTF-8.
+    pub fn rewrite_ptx<F: FnOnce(&str) -> String>(&mut self, f: F) -> Result<(), FatBinaryError> {
+        if self.kind() != EntryKind::Ptx {
+            return Err(FatBinaryError::NotPtx {
+                kind: self.entry_header.kind,
+            });
+        }
+        let ptx = String::from_utf8(trim_nul(&self.try_get_decompressed_payload()?).to_vec())?;
+        self.set_ptx(&f(&ptx))
+    }
+}
+
+impl FatBinary<'_> {
+    /// Transform PTX of all PTX entries with `f`, which gets arch of the
+    /// entry and PTX without padding NULs. Returns number of entries
+    /// rewritten.
+    pub fn rewrite_ptx<F: FnMut(SmArch, &str) -> String>(
+        &mut self,
+        mut f: F,
+    ) -> Result<usize, FatBinaryError> {
+        let mut res = 0;
+        for entry in self.entries.iter_mut() {
+            if entry.kind() == EntryKind::Ptx {
+                let arch = SmArch(entry.get_sm_arch());
+                entry.rewrite_ptx(|ptx| f(arch, ptx))?;
+                res += 1;
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+
+    #[test]
+    fn rewrite_ptx() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(8);
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, format!("{}\0\0", ptx).into_bytes());
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, ptx.as_bytes()));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        let count = fatbin
+            .rewrite_ptx(|arch, ptx| format!("// {}\n{}", arch, ptx))
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let read = FatBinary::parse(&buffer).unwrap();
+        let first = &read.entries()[0];
+        assert!(first.is_compressed());
+        let expected = format!("// sm_70\n{}", ptx);
+        assert_eq!(first.ptx_source().unwrap(), expected);
+        let decompressed = first.get_decompressed_payload();
+        assert_eq!(decompressed.len() % 8, 0);
+        assert_eq!(decompressed[expected.len()], 0);
+        assert_eq!(
+            read.entries()[1].ptx_source().unwrap(),
+            format!("// sm_80\n{}", ptx)
+        );
+        assert_eq!(read.entries()[2].get_payload(), b"\x7fELF");
+
+        let mut elf = read.entries()[2].clone();
+        assert!(matches!(
+            elf.set_ptx(".version 7.0\n"),
+            Err(FatBinaryError::NotPtx { kind: 2 })
+        ));
+        let mut invalid = FatBinaryEntry::new_auto(70, b".target \xff\n");
+        assert!(invalid.rewrite_ptx(|ptx| ptx.to_string()).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Reproducible listing of entries for SBOM and provenance documents,
+//! enabled by the `digest` feature
+//!
+//! The listing is JSON with a fixed field order and layout, containing only
+//! data derived from the fatbinary, so the same fatbinary always produces
+//! the same bytes:
+//!
+//! ```text
+//! {
+//!   "format": "fatbinary-listing",
+//!   "version": 1,
+//!   "entries": [
+//!     {
+//!       "index": 0,
+//!       "kind": "elf",
+//!       "arch": "sm_80",
+//!       ...
+//!       "sha256": "<hex digest of decompressed payload>"
+//!     }
+//!   ]
+//! }
+//! ```
+
+use crate::digest::{to_hex, PayloadDigest, Sha256Digest};
+use crate::{EntryKind, FatBinary, Host, Producer};
+use alloc::string::String;
+use core::fmt::Write;
+
+const LISTING_FORMAT: &str = "fatbinary-listing";
+const LISTING_VERSION: u32 = 1;
+
+/// JSON string literal of `text`, or `null`
+fn json_string(text: Option<&str>) -> String {
+    let Some(text) = text else {
+        return "null".into();
+    };
+    let mut res = String::with_capacity(text.len() + 2);
+    res.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                // writing to String never fails
+                let _ = write!(res, "\\u{:04x}", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+impl FatBinary<'_> {
+    /// Deterministic JSON listing of entries with metadata and SHA-256 of
+    /// decompressed payloads: an object with `format`
+    /// (`"fatbinary-listing"`), `version` and one object per entry in
+    /// `entries`. Fields are never reordered; new fields bump the version.
+    pub fn sbom_listing(&self) -> String {
+        let mut res = String::new();
+        res += "{\n";
+        let _ = writeln!(res, "  \"format\": {},", json_string(Some(LISTING_FORMAT)));
+        let _ = writeln!(res, "  \"version\": {},", LISTING_VERSION);
+        res += "  \"entries\": [";
+        for (index, entry) in self.entries.iter().enumerate() {
+            let kind = match entry.kind() {
+                EntryKind::Ptx => String::from("ptx"),
+                EntryKind::Elf => String::from("elf"),
+                EntryKind::Unknown(kind) => alloc::format!("{:#x}", kind),
+            };
+            let host = match entry.host() {
+                Host::Linux => "linux",
+                Host::Mac => "mac",
+                Host::Windows => "windows",
+                Host::Unknown => "unknown",
+            };
+            let producer = match entry.producer() {
+                Producer::CUDA => "cuda",
+                Producer::OpenCL => "opencl",
+                Producer::Unknown => "unknown",
+            };
+            let payload = entry.get_decompressed_payload();
+            let ptxas_options = entry.get_ptxas_options_lossy();
+            let identifier = entry.get_identifier_lossy();
+            let trim = |text: &str| -> String { text.trim_end_matches('\0').into() };
+
+            res += if index == 0 { "\n" } else { ",\n" };
+            res += "    {\n";
+            let _ = writeln!(res, "      \"index\": {},", index);
+            let _ = writeln!(res, "      \"kind\": {},", json_string(Some(&kind)));
+            let _ = writeln!(res, "      \"arch\": \"sm_{}\",", entry.get_sm_arch());
+            let _ = writeln!(
+                res,
+                "      \"code_version\": \"{}.{}\",",
+                entry.get_version_major(),
+                entry.get_version_minor()
+            );
+            let _ = writeln!(res, "      \"host\": \"{}\",", host);
+            let _ = writeln!(res, "      \"producer\": \"{}\",", producer);
+            let _ = writeln!(res, "      \"is_64bit\": {},", entry.is_64bit());
+            let _ = writeln!(res, "      \"has_debug_info\": {},", entry.has_debug_info());
+            let _ = writeln!(res, "      \"is_compressed\": {},", entry.is_compressed());
+            let _ = writeln!(res, "      \"flags\": {},", entry.get_flags());
+            let _ = writeln!(res, "      \"stored_size\": {},", {
+                entry.get_header().size
+            });
+            let _ = writeln!(res, "      \"size\": {},", payload.len());
+            let _ = writeln!(
+                res,
+                "      \"identifier\": {},",
+                json_string(identifier.as_deref().map(trim).as_deref())
+            );
+            let _ = writeln!(
+                res,
+                "      \"ptxas_options\": {},",
+                json_string(ptxas_options.as_deref().map(trim).as_deref())
+            );
+            let _ = writeln!(
+                res,
+                "      \"sha256\": \"{}\"",
+                to_hex(&Sha256Digest.digest(&payload))
+            );
+            res += "    }";
+        }
+        if !self.entries.is_empty() {
+            res += "\n  ";
+        }
+        res += "]\n}\n";
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sbom::json_string;
+    use crate::{FatBinary, FatBinaryEntry};
+
+    #[test]
+    fn escape() {
+        assert_eq!(json_string(None), "null");
+        assert_eq!(
+            json_string(Some("a\"b\\c\n\u{1}é")),
+            "\"a\\\"b\\\\c\\n\\u0001é\""
+        );
+    }
+
+    #[test]
+    fn sbom_listing() {
+        assert_eq!(
+            FatBinary::new().sbom_listing(),
+            "{\n  \"format\": \"fatbinary-listing\",\n  \"version\": 1,\n  \"entries\": []\n}\n"
+        );
+
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(80, ".target sm_80\n".repeat(16).into_bytes());
+        entry.set_identifier(Some("axpy.cu"));
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        let listing = fatbin.sbom_listing();
+        assert_eq!(listing, fatbin.clone().sbom_listing());
+        assert!(listing.contains(
+            "      \"kind\": \"ptx\",\n      \"arch\": \"sm_80\",\n      \"code_version\": \"0.0\",\n"
+        ));
+        assert!(listing.contains("      \"is_compressed\": true,\n"));
+        assert!(listing.contains(&format!("      \"size\": {},\n", 14 * 16)));
+        assert!(listing.contains("      \"identifier\": \"axpy.cu\",\n"));
+        // sha256 of "\x7fELF"
+        assert!(listing.ends_with(
+            "      \"identifier\": null,\n      \"ptxas_options\": null,\n      \"sha256\": \"3bdbb4fe8397cd2b842430b39ccff01a8663c751945ef5e9a09e267fb8b1d359\"\n    }\n  ]\n}\n"
+        ));
+    }
+}
@@ -0,0 +1,458 @@
+//! Locating and parsing many fatbinaries in one input: concatenated
+//! fatbinaries (e.g. dumped `.nv_fatbin` sections), host ELF binaries and
+//! `ar` archives of host objects
+//!
+//! Locating fatbinaries only reads their 16-byte headers, so parsing of the
+//! fatbinaries themselves is independent and runs in parallel with the
+//! `rayon` feature.
+
+use crate::{FatBinary, FatBinaryError, ParseOptions, FAT_BINARY_MAGIC};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+use object::{Object, ObjectSection};
+
+/// Magic at the start of `ar` archives like static libraries
+pub const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_SIZE: usize = 60;
+
+/// Ranges of fatbinaries in `data` starting at `offset`, which are either
+/// adjacent or separated by NUL padding
+fn scan_concatenated(
+    data: &[u8],
+    offset: usize,
+    res: &mut Vec<Range<usize>>,
+) -> Result<(), FatBinaryError> {
+    let mut offset = offset;
+    while offset < data.len() {
+        if data[offset] == 0 {
+            offset += 1;
+            continue;
+        }
+
+        let magic = data
+            .get(offset..offset + 4)
+            .map_or(0, |magic| u32::from_le_bytes(magic.try_into().unwrap()));
+        if magic != FAT_BINARY_MAGIC {
+            return Err(FatBinaryError::AtContainer {
+                offset: offset as u64,
+                source: alloc::boxed::Box::new(FatBinaryError::InvalidMagic {
+                    expected: FAT_BINARY_MAGIC,
+                    got: magic,
+                }),
+            });
+        }
+        let header = data
+            .get(offset..offset + 16)
+            .ok_or(FatBinaryError::Truncated {
+                entry_index: 0,
+                header_offset: offset as u64,
+                needed: 16,
+                available: (data.len() - offset) as u64,
+            })?;
+        let header_size = u16::from_le_bytes([header[6], header[7]]) as u64;
+        let size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let available = (data.len() - offset) as u64;
+        let len = size
+            .checked_add(header_size)
+            .filter(|&len| len <= available)
+            .ok_or(FatBinaryError::Truncated {
+                entry_index: 0,
+                header_offset: offset as u64,
+                needed: header_size.saturating_add(size),
+                available,
+            })? as usize;
+        // a zero-sized container would never advance
+        let end = offset + len.max(16);
+        res.push(offset..end);
+        offset = end;
+    }
+    Ok(())
+}
+
+/// Range of `.nv_fatbin` section of ELF `data`, `None` if absent
+fn nv_fatbin_section(data: &[u8]) -> Result<Option<Range<usize>>, FatBinaryError> {
+    let invalid = |message: String| FatBinaryError::InvalidHostElf { message };
+    let file = object::File::parse(data).map_err(|err| invalid(err.to_string()))?;
+    let Some(section) = file.section_by_name(".nv_fatbin") else {
+        return Ok(None);
+    };
+    let (offset, size) = section
+        .file_range()
+        .ok_or_else(|| invalid(".nv_fatbin section has no data in file".to_string()))?;
+    let range = offset as usize..offset.saturating_add(size) as usize;
+    if range.end > data.len() {
+        return Err(invalid(".nv_fatbin section out of file".to_string()));
+    }
+    Ok(Some(range))
+}
+
+/// Ranges of fatbinaries in host ELF `data`, shifted by `base`
+fn scan_elf(data: &[u8], base: usize, res: &mut Vec<Range<usize>>) -> Result<bool, FatBinaryError> {
+    let Some(section) = nv_fatbin_section(data)? else {
+        return Ok(false);
+    };
+    let mut ranges = Vec::new();
+    scan_concatenated(&data[section.clone()], 0, &mut ranges)
+        .map_err(|err| shift_error(err, base + section.start))?;
+    let start = base + section.start;
+    res.extend(
+        ranges
+            .into_iter()
+            .map(|range| start + range.start..start + range.end),
+    );
+    Ok(true)
+}
+
+/// Make offsets of errors relative to the whole input
+fn shift_error(err: FatBinaryError, base: usize) -> FatBinaryError {
+    match err {
+        FatBinaryError::AtContainer { offset, source } => FatBinaryError::AtContainer {
+            offset: offset + base as u64,
+            source,
+        },
+        FatBinaryError::Truncated {
+            entry_index,
+            header_offset,
+            needed,
+            available,
+        } => FatBinaryError::Truncated {
+            entry_index,
+            header_offset: header_offset + base as u64,
+            needed,
+            available,
+        },
+        err => err,
+    }
+}
+
+/// Member of an `ar` archive containing fatbinaries, see
+/// [FatBinary::scan_archive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMember {
+    /// Member name, e.g. `axpy.o`
+    pub name: String,
+    /// Ranges of fatbinaries of the member in the archive
+    pub fatbinaries: Vec<Range<usize>>,
+}
+
+/// Name of member with `header` and `data`, and the offset of its contents
+/// in `data`. GNU long names are looked up in the `//` table, BSD long
+/// names precede the contents.
+fn member_name(header: &[u8], data: &[u8], long_names: &[u8]) -> Option<(String, usize)> {
+    let name = core::str::from_utf8(&header[..16]).ok()?.trim_end();
+    if let Some(len) = name.strip_prefix("#1/") {
+        let len = len.parse::<usize>().ok()?;
+        let name = data.get(..len)?;
+        let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+        return Some((String::from_utf8_lossy(name).to_string(), len));
+    }
+    let name = match name.strip_prefix('/') {
+        Some(offset) if !offset.is_empty() && offset.bytes().all(|c| c.is_ascii_digit()) => {
+            let long_name = long_names.get(offset.parse::<usize>().ok()?..)?;
+            let end = long_names_end(long_name);
+            String::from_utf8_lossy(&long_name[..end]).to_string()
+        }
+        _ => name.strip_suffix('/').unwrap_or(name).to_string(),
+    };
+    Some((name, 0))
+}
+
+/// End of a name in the GNU long name table, terminated by `/\n`
+fn long_names_end(names: &[u8]) -> usize {
+    names
+        .windows(2)
+        .position(|end| end == b"/\n")
+        .or_else(|| names.iter().position(|&byte| byte == b'\n'))
+        .unwrap_or(names.len())
+}
+
+/// Members of `ar` archive `data` with fatbinaries. Members without
+/// fatbinaries, including symbol and name tables, are skipped.
+fn archive_members(data: &[u8]) -> Result<Vec<ArchiveMember>, FatBinaryError> {
+    let mut res = Vec::new();
+    let mut long_names: &[u8] = &[];
+    let mut offset = AR_MAGIC.len();
+    while offset < data.len() {
+        let invalid = |message: String| FatBinaryError::InvalidArchive { message };
+        let header = data
+            .get(offset..offset + AR_HEADER_SIZE)
+            .ok_or_else(|| invalid(format!("truncated member header at offset {:#x}", offset)))?;
+        if &header[58..60] != b"`\n" {
+            return Err(invalid(format!(
+                "invalid member header at offset {:#x}",
+                offset
+            )));
+        }
+        let size = core::str::from_utf8(&header[48..58])
+            .ok()
+            .and_then(|size| size.trim_end().parse::<usize>().ok())
+            .ok_or_else(|| invalid(format!("invalid member size at offset {:#x}", offset)))?;
+        let start = offset + AR_HEADER_SIZE;
+        let member = start
+            .checked_add(size)
+            .and_then(|end| data.get(start..end))
+            .ok_or_else(|| invalid(format!("truncated member at offset {:#x}", offset)))?;
+        if header.starts_with(b"// ") {
+            long_names = member;
+        }
+        let (name, name_len) = member_name(header, member, long_names)
+            .ok_or_else(|| invalid(format!("invalid member name at offset {:#x}", offset)))?;
+        let start = start + name_len;
+        let member = &member[name_len..];
+
+        let mut fatbinaries = Vec::new();
+        if member.starts_with(&FAT_BINARY_MAGIC.to_le_bytes()) {
+            let mut ranges = Vec::new();
+            scan_concatenated(member, 0, &mut ranges).map_err(|err| shift_error(err, start))?;
+            fatbinaries.extend(
+                ranges
+                    .into_iter()
+                    .map(|range| start + range.start..start + range.end),
+            );
+        } else if member.starts_with(b"\x7fELF") {
+            scan_elf(member, start, &mut fatbinaries)?;
+        }
+        if !fatbinaries.is_empty() {
+            res.push(ArchiveMember { name, fatbinaries });
+        }
+        // members are aligned to 2 bytes
+        offset = (start + member.len()).next_multiple_of(2);
+    }
+    Ok(res)
+}
+
+impl<'a> FatBinary<'a> {
+    /// Ranges of all fatbinaries in `data`, in order: fatbinaries
+    /// concatenated with optional NUL padding (e.g. a dumped `.nv_fatbin` section),
+    /// the `.nv_fatbin` section of a host ELF binary, or those of host
+    /// objects in an `ar` archive. Only headers of fatbinaries are read.
+    pub fn scan_containers(data: &[u8]) -> Result<Vec<Range<usize>>, FatBinaryError> {
+        let mut res = Vec::new();
+        if data.starts_with(AR_MAGIC) {
+            for member in archive_members(data)? {
+                res.extend(member.fatbinaries);
+            }
+        } else if data.starts_with(b"\x7fELF") {
+            if !scan_elf(data, 0, &mut res)? {
+                return Err(FatBinaryError::InvalidHostElf {
+                    message: "no .nv_fatbin section".to_string(),
+                });
+            }
+        } else {
+            scan_concatenated(data, 0, &mut res)?;
+        }
+        Ok(res)
+    }
+
+    /// Members of `ar` archive `data` containing fatbinaries, in order, e.g.
+    /// to attribute fatbinaries of a static library to its objects. GNU and
+    /// BSD long member names are supported.
+    pub fn scan_archive(data: &[u8]) -> Result<Vec<ArchiveMember>, FatBinaryError> {
+        if !data.starts_with(AR_MAGIC) {
+            return Err(FatBinaryError::InvalidArchive {
+                message: "missing archive magic".to_string(),
+            });
+        }
+        archive_members(data)
+    }
+
+    /// Parse all fatbinaries found by [FatBinary::scan_containers], in order
+    /// along with their offsets in `data`. Payloads are borrowed from
+    /// `data`. With the `rayon` feature, fatbinaries are parsed in
+    /// parallel; if several fail, any one of the errors is returned.
+    pub fn parse_all(data: &'a [u8]) -> Result<Vec<(u64, FatBinary<'a>)>, FatBinaryError> {
+        Self::parse_all_with(data, ParseOptions::default())
+    }
+
+    /// Parse all fatbinaries like [FatBinary::parse_all], parsing each with
+    /// `options` like [FatBinary::parse_with]. Warnings are dropped.
+    pub fn parse_all_with(
+        data: &'a [u8],
+        options: ParseOptions,
+    ) -> Result<Vec<(u64, FatBinary<'a>)>, FatBinaryError> {
+        let parse = |range: Range<usize>| -> Result<(u64, FatBinary<'a>), FatBinaryError> {
+            let offset = range.start as u64;
+            match FatBinary::parse_with(&data[range], options.clone()) {
+                Ok((fatbin, _)) => Ok((offset, fatbin)),
+                Err(FatBinaryError::Cancelled) => Err(FatBinaryError::Cancelled),
+                Err(err) => Err(FatBinaryError::AtContainer {
+                    offset,
+                    source: alloc::boxed::Box::new(err),
+                }),
+            }
+        };
+
+        let ranges = Self::scan_containers(data)?;
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            ranges.into_par_iter().map(parse).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        ranges.into_iter().map(parse).collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl FatBinary<'static> {
+    /// Read host ELF binary or object at `path`, e.g. an executable built
+    /// by nvcc, and parse all fatbinaries of its `.nv_fatbin` section in
+    /// order. Use [FatBinary::merge] for a single fatbinary of all entries.
+    pub fn from_host_elf<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Vec<FatBinary<'static>>, FatBinaryError> {
+        let data = std::fs::read(path)?;
+        if !data.starts_with(b"\x7fELF") {
+            return Err(FatBinaryError::InvalidHostElf {
+                message: "not an ELF file".to_string(),
+            });
+        }
+        Ok(FatBinary::parse_all(&data)?
+            .into_iter()
+            .map(|(_, fatbin)| fatbin.into_owned())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError};
+
+    fn fatbin(arch: u32, payload: &[u8]) -> Vec<u8> {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(arch, payload.to_vec()));
+        let mut res = Vec::new();
+        fatbin.write(&mut res).unwrap();
+        res
+    }
+
+    fn ar_member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut res = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name,
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        )
+        .into_bytes();
+        res.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            res.push(b'\n');
+        }
+        res
+    }
+
+    #[test]
+    fn parse_all() {
+        let mut data = Vec::new();
+        for arch in 0..100 {
+            data.extend(fatbin(arch, &b".target sm\n".repeat(arch as usize + 1)));
+            // padding between containers
+            data.resize(data.len().next_multiple_of(16), 0);
+        }
+        let ranges = FatBinary::scan_containers(&data).unwrap();
+        assert_eq!(ranges.len(), 100);
+        let fatbins = FatBinary::parse_all(&data).unwrap();
+        assert_eq!(fatbins.len(), 100);
+        for (arch, (offset, fatbin)) in fatbins.iter().enumerate() {
+            assert_eq!(*offset, ranges[arch].start as u64);
+            assert_eq!(fatbin.entries()[0].get_sm_arch(), arch as u32);
+        }
+        assert!(FatBinary::parse_all(&[]).unwrap().is_empty());
+
+        // garbage after the first container
+        let mut data = fatbin(70, b"ptx");
+        data.resize(data.len().next_multiple_of(8), 0);
+        let len = data.len();
+        data.extend_from_slice(b"garbage!");
+        assert!(matches!(
+            FatBinary::parse_all(&data),
+            Err(FatBinaryError::AtContainer { offset, .. }) if offset == len as u64
+        ));
+        let mut data = fatbin(70, b"ptx");
+        data.truncate(data.len() - 1);
+        assert!(matches!(
+            FatBinary::parse_all(&data),
+            Err(FatBinaryError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn scan_archive() {
+        let first = fatbin(70, b"ptx");
+        let second = fatbin(80, b"\x7fELF");
+        let mut data = b"!<arch>\n".to_vec();
+        data.extend(ar_member("/", b"symbols"));
+        data.extend(ar_member("a.fatbin/", &first));
+        data.extend(ar_member("host.txt/", b"not a fatbinary"));
+        data.extend(ar_member("b.fatbin/", &second));
+
+        let fatbins = FatBinary::parse_all(&data).unwrap();
+        assert_eq!(fatbins.len(), 2);
+        let offset = fatbins[0].0 as usize;
+        assert_eq!(&data[offset..offset + first.len()], first);
+        assert_eq!(fatbins[0].1.entries()[0].get_sm_arch(), 70);
+        assert_eq!(fatbins[1].1.entries()[0].get_sm_arch(), 80);
+        let members = FatBinary::scan_archive(&data).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, "a.fatbin");
+        assert_eq!(members[1].name, "b.fatbin");
+        assert_eq!(members[0].fatbinaries[0], offset..offset + first.len());
+        assert!(FatBinary::scan_archive(&first).is_err());
+
+        // GNU and BSD long names
+        let mut long = b"!<arch>\n".to_vec();
+        long.extend(ar_member("//", b"a_very_long_member_name.o/\nb.o/\n"));
+        long.extend(ar_member("/0", &first));
+        let mut bsd = b"long_bsd_name.o\0".to_vec();
+        bsd.extend_from_slice(&second);
+        long.extend(ar_member("#1/16", &bsd));
+        let members = FatBinary::scan_archive(&long).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, "a_very_long_member_name.o");
+        assert_eq!(members[1].name, "long_bsd_name.o");
+        let range = members[1].fatbinaries[0].clone();
+        assert_eq!(long[range], second);
+
+        data.truncate(data.len() - 1);
+        assert!(matches!(
+            FatBinary::scan_containers(&data),
+            Err(FatBinaryError::InvalidArchive { .. })
+        ));
+    }
+
+    #[cfg(feature = "object-write")]
+    #[test]
+    fn scan_host_elf() {
+        use object::Architecture;
+
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+        let image = fatbin.to_relocatable_object(Architecture::X86_64).unwrap();
+        let fatbins = FatBinary::parse_all(&image).unwrap();
+        assert_eq!(fatbins.len(), 1);
+        assert_eq!(fatbins[0].1, fatbin);
+
+        let mut data = b"!<arch>\n".to_vec();
+        data.extend(ar_member("kernels.o/", &image));
+        assert_eq!(FatBinary::parse_all(&data).unwrap().len(), 1);
+
+        let path = std::env::temp_dir().join(format!("fatbinary-host-{}.o", std::process::id()));
+        std::fs::write(&path, &image).unwrap();
+        assert_eq!(FatBinary::from_host_elf(&path).unwrap(), [fatbin]);
+        std::fs::write(&path, &data).unwrap();
+        assert!(matches!(
+            FatBinary::from_host_elf(&path),
+            Err(FatBinaryError::InvalidHostElf { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
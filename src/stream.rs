@@ -0,0 +1,510 @@
+//! Copying payloads to writers and reading them incrementally without
+//! holding whole payloads in memory
+//!
+//! Compressed payloads are decoded into a sliding window: matches reach at
+//! most 64 KiB back, so older output is flushed to the writer early, or
+//! dropped once read.
+
+use crate::{
+    block_ends, detect_block_size, seek_to_entry, FatBinary, FatBinaryEntry, FatBinaryError,
+    ParseOptions, Payload,
+};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, Write};
+
+/// Output kept for matches, larger than the maximum match offset
+const WINDOW: usize = 1 << 16;
+/// Output buffered before flushing all but the window
+const FLUSH_THRESHOLD: usize = 4 * WINDOW;
+
+fn invalid_data() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "invalid compressed payload",
+    )
+}
+
+fn invalid_payload() -> FatBinaryError {
+    invalid_data().into()
+}
+
+/// Writer keeping recent output for matches
+struct WindowWriter<W> {
+    writer: W,
+    buf: Vec<u8>,
+    written: u64,
+}
+
+impl<W: Write> WindowWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::with_capacity(2 * FLUSH_THRESHOLD),
+            written: 0,
+        }
+    }
+
+    /// Flush output older than the window if enough is buffered
+    fn flush_old(&mut self) -> std::io::Result<()> {
+        if self.buf.len() >= FLUSH_THRESHOLD {
+            let len = self.buf.len() - WINDOW;
+            self.writer.write_all(&self.buf[..len])?;
+            self.buf.drain(..len);
+            self.written += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Append `len` bytes starting `back_offset` bytes before the end
+    fn copy_match(&mut self, back_offset: usize, mut len: usize) -> Result<(), FatBinaryError> {
+        if back_offset == 0 || back_offset > self.buf.len() {
+            return Err(invalid_payload());
+        }
+        // the output repeats with period `back_offset`, so the copied range
+        // can double until flushed
+        let mut start = self.buf.len() - back_offset;
+        while len > 0 {
+            let chunk = len.min(self.buf.len() - start);
+            self.buf.extend_from_within(start..start + chunk);
+            len -= chunk;
+            if self.buf.len() >= FLUSH_THRESHOLD {
+                self.flush_old()?;
+                start = self.buf.len() - back_offset;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of bytes decoded so far
+    fn decoded(&self) -> u64 {
+        self.written + self.buf.len() as u64
+    }
+
+    /// Flush the rest, return number of bytes written
+    fn finish(mut self) -> std::io::Result<u64> {
+        self.writer.write_all(&self.buf)?;
+        self.writer.flush()?;
+        Ok(self.written + self.buf.len() as u64)
+    }
+}
+
+impl<W: Write> Write for WindowWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        self.flush_old()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, FatBinaryError> {
+    let mut byte = [0u8];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// Read length beyond the 4-bit token field and add it to `len`
+fn read_length<R: Read>(reader: &mut R, len: usize) -> Result<usize, FatBinaryError> {
+    let mut res = len;
+    loop {
+        let byte = read_u8(reader)?;
+        res = res.checked_add(byte as usize).ok_or_else(invalid_payload)?;
+        if byte != 0xff {
+            return Ok(res);
+        }
+    }
+}
+
+/// Decompress payload from `compressed` to `writer` like
+/// [crate::try_decompress], with blocks of `block_size` bytes, return number
+/// of bytes written
+fn decompress_to<R: BufRead, W: Write>(
+    mut compressed: R,
+    writer: W,
+    block_size: Option<usize>,
+) -> Result<u64, FatBinaryError> {
+    let mut output = WindowWriter::new(writer);
+    while !compressed.fill_buf()?.is_empty() {
+        let token = read_u8(&mut compressed)?;
+        let mut literal_len = (token >> 4) as usize;
+        if literal_len == 0xf {
+            literal_len = read_length(&mut compressed, literal_len)?;
+        }
+        let copied = std::io::copy(&mut (&mut compressed).take(literal_len as u64), &mut output)?;
+        if copied != literal_len as u64 {
+            return Err(invalid_payload());
+        }
+
+        if compressed.fill_buf()?.is_empty() {
+            break;
+        }
+        if block_ends(output.decoded(), block_size) {
+            continue;
+        }
+        let back_offset =
+            u16::from_le_bytes([read_u8(&mut compressed)?, read_u8(&mut compressed)?]);
+        let mut match_len = 4 + (token & 0xf) as usize;
+        if match_len == 0xf + 4 {
+            match_len = read_length(&mut compressed, match_len)?;
+        }
+        output.copy_match(back_offset as usize, match_len)?;
+    }
+    Ok(output.finish()?)
+}
+
+/// Step of decoding a compressed payload
+#[derive(Debug, Clone, Copy)]
+enum DecodeState {
+    /// Next token, or end of payload
+    Token,
+    /// Literals of the current sequence, followed by a match
+    Literal {
+        remaining: usize,
+        match_len: usize,
+    },
+    /// Match offset and length of the current sequence, or end of payload
+    MatchHeader {
+        match_len: usize,
+    },
+    Match {
+        back_offset: usize,
+        remaining: usize,
+    },
+    Done,
+}
+
+/// Payload of an entry as [Read], decompressed on the fly if it was
+/// compressed, created by [FatBinaryEntry::payload_reader]
+#[derive(Debug, Clone)]
+pub struct PayloadReader<'b> {
+    input: &'b [u8],
+    compressed: bool,
+    /// Decoded output, `buf[..pos]` was read and is kept for matches
+    buf: Vec<u8>,
+    pos: usize,
+    /// Bytes decoded so far, dropped ones included
+    decoded: u64,
+    /// Decompressed size of blocks of multi-block payloads
+    block_size: Option<usize>,
+    state: DecodeState,
+}
+
+impl PayloadReader<'_> {
+    /// Take one byte of input
+    fn next_byte(&mut self) -> std::io::Result<u8> {
+        let (&byte, rest) = self.input.split_first().ok_or_else(invalid_data)?;
+        self.input = rest;
+        Ok(byte)
+    }
+
+    /// Take length beyond the 4-bit token field and add it to `len`
+    fn next_length(&mut self, len: usize) -> std::io::Result<usize> {
+        let mut res = len;
+        loop {
+            let byte = self.next_byte()?;
+            res = res.checked_add(byte as usize).ok_or_else(invalid_data)?;
+            if byte != 0xff {
+                return Ok(res);
+            }
+        }
+    }
+
+    /// Decode until some output is produced or the payload ends
+    fn decode(&mut self) -> std::io::Result<()> {
+        // drop output which was read and is beyond reach of matches
+        if self.pos >= FLUSH_THRESHOLD {
+            self.buf.drain(..self.pos - WINDOW);
+            self.pos = WINDOW;
+        }
+        let len = self.buf.len();
+        while self.buf.len() == len {
+            self.state = match self.state {
+                DecodeState::Token if self.input.is_empty() => DecodeState::Done,
+                DecodeState::Token => {
+                    let token = self.next_byte()?;
+                    let mut literal_len = (token >> 4) as usize;
+                    if literal_len == 0xf {
+                        literal_len = self.next_length(literal_len)?;
+                    }
+                    DecodeState::Literal {
+                        remaining: literal_len,
+                        match_len: 4 + (token & 0xf) as usize,
+                    }
+                }
+                DecodeState::Literal {
+                    remaining: 0,
+                    match_len,
+                } => DecodeState::MatchHeader { match_len },
+                DecodeState::Literal {
+                    remaining,
+                    match_len,
+                } => {
+                    let chunk = remaining.min(WINDOW);
+                    if chunk > self.input.len() {
+                        return Err(invalid_data());
+                    }
+                    let (literals, rest) = self.input.split_at(chunk);
+                    self.buf.extend_from_slice(literals);
+                    self.input = rest;
+                    DecodeState::Literal {
+                        remaining: remaining - chunk,
+                        match_len,
+                    }
+                }
+                // the last sequence has no match
+                DecodeState::MatchHeader { .. } if self.input.is_empty() => DecodeState::Done,
+                // neither has the last sequence of each block
+                DecodeState::MatchHeader { .. }
+                    if block_ends(
+                        self.decoded + (self.buf.len() - len) as u64,
+                        self.block_size,
+                    ) =>
+                {
+                    DecodeState::Token
+                }
+                DecodeState::MatchHeader { mut match_len } => {
+                    let back_offset = u16::from_le_bytes([self.next_byte()?, self.next_byte()?]);
+                    if match_len == 0xf + 4 {
+                        match_len = self.next_length(match_len)?;
+                    }
+                    let decoded = self.decoded + (self.buf.len() - len) as u64;
+                    if back_offset == 0 || back_offset as u64 > decoded {
+                        return Err(invalid_data());
+                    }
+                    DecodeState::Match {
+                        back_offset: back_offset as usize,
+                        remaining: match_len,
+                    }
+                }
+                DecodeState::Match { remaining: 0, .. } => DecodeState::Token,
+                DecodeState::Match {
+                    back_offset,
+                    remaining,
+                } => {
+                    // the output repeats with period `back_offset`
+                    let chunk = remaining.min(WINDOW);
+                    let start = self.buf.len() - back_offset;
+                    let mut copied = 0;
+                    while copied < chunk {
+                        let len = (chunk - copied).min(self.buf.len() - start);
+                        self.buf.extend_from_within(start..start + len);
+                        copied += len;
+                    }
+                    DecodeState::Match {
+                        back_offset,
+                        remaining: remaining - chunk,
+                    }
+                }
+                DecodeState::Done => return Ok(()),
+            };
+        }
+        self.decoded += (self.buf.len() - len) as u64;
+        Ok(())
+    }
+}
+
+impl Read for PayloadReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if !self.compressed {
+            return self.input.read(out);
+        }
+        if self.pos == self.buf.len() {
+            self.decode()?;
+        }
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl FatBinaryEntry<'_> {
+    /// Get payload as [Read], decompressing it on the fly if it was
+    /// compressed. Unlike [FatBinaryEntry::get_decompressed_payload], the
+    /// decompressed payload is never held in memory as a whole.
+    pub fn payload_reader(&self) -> PayloadReader<'_> {
+        PayloadReader {
+            input: self.get_payload(),
+            compressed: self.is_compressed(),
+            buf: vec![],
+            pos: 0,
+            decoded: 0,
+            block_size: self.block_size(),
+            state: DecodeState::Token,
+        }
+    }
+
+    /// Decompressed size of blocks of a multi-block compressed payload
+    fn block_size(&self) -> Option<usize> {
+        if !self.is_compressed() {
+            return None;
+        }
+        let payload = self.get_payload();
+        detect_block_size(
+            &mut Cursor::new(payload),
+            payload.len() as u64,
+            self.entry_header.decompressed_size,
+        )
+        .ok()
+        .flatten()
+    }
+
+    /// Write payload to `writer`, decompressing it on the fly if it was
+    /// compressed. Unlike [FatBinaryEntry::get_decompressed_payload], the
+    /// decompressed payload is never held in memory as a whole. Returns
+    /// number of bytes written.
+    pub fn copy_payload_to<W: Write>(&self, mut writer: W) -> Result<u64, FatBinaryError> {
+        if self.is_compressed() {
+            decompress_to(self.get_payload(), writer, self.block_size())
+        } else {
+            writer.write_all(&self.payload)?;
+            writer.flush()?;
+            Ok(self.payload.len() as u64)
+        }
+    }
+}
+
+impl FatBinary<'_> {
+    /// Write payload of the entry at `index` in reader to `writer` like
+    /// [FatBinaryEntry::copy_payload_to], seeking over preceding entries.
+    /// Neither the stored nor the decompressed payload is held in memory as
+    /// a whole, so this supports entries too large to be loaded. Returns
+    /// number of bytes written, `None` if there are not enough entries.
+    pub fn copy_entry_payload<R: Read + Seek, W: Write>(
+        mut reader: R,
+        index: usize,
+        mut writer: W,
+    ) -> Result<Option<u64>, FatBinaryError> {
+        let Some((bounds, entry_header, extra_header)) = seek_to_entry(&mut reader, index)? else {
+            return Ok(None);
+        };
+        // validate header like reading the whole entry would
+        let entry = bounds.context(FatBinaryEntry::from_parts(
+            entry_header,
+            &extra_header,
+            Payload::Borrowed(&[]),
+            &ParseOptions::default(),
+        ))?;
+
+        let written = if entry.is_compressed() {
+            let len = (entry_header.compressed_size as u64).min(entry_header.size);
+            // only lengths are read to detect the layout, then the reader
+            // seeks back
+            let block_size = bounds.context(detect_block_size(
+                &mut reader,
+                len,
+                entry_header.decompressed_size,
+            ))?;
+            let compressed = reader.take(len);
+            bounds.context(decompress_to(
+                BufReader::new(compressed),
+                writer,
+                block_size,
+            ))?
+        } else {
+            let copied = std::io::copy(&mut reader.take(entry_header.size), &mut writer)?;
+            if copied != entry_header.size {
+                let err = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+                return bounds.context(Err(err.into()));
+            }
+            writer.flush()?;
+            copied
+        };
+        Ok(Some(written))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+    use std::io::{Cursor, Read};
+
+    /// Payload with short and long matches, reaching back up to 64 KiB
+    fn payload() -> Vec<u8> {
+        let mut res = vec![];
+        let mut state = 1u32;
+        for round in 0..40u32 {
+            let len = res.len();
+            for _ in 0..1024 {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                res.push((state >> 16) as u8);
+            }
+            if len > 0x8000 {
+                res.extend_from_within(len - 0x8000..len);
+            }
+            res.resize(res.len() + round as usize * 1000, 0);
+        }
+        res
+    }
+
+    #[test]
+    fn payload_reader() {
+        let payload = payload();
+        let mut entry = FatBinaryEntry::new_auto(80, payload.clone());
+        let mut output = vec![];
+        entry.payload_reader().read_to_end(&mut output).unwrap();
+        assert_eq!(output, payload);
+
+        assert!(entry.compress());
+        // read in small pieces
+        let mut reader = entry.payload_reader();
+        let mut output = vec![];
+        let mut buf = [0u8; 1000];
+        loop {
+            let len = reader.read(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(output, payload);
+
+        // truncated literals
+        let mut entry = FatBinaryEntry::new_auto(80, b"\x40ab\0\0\0\0\0".to_vec());
+        entry.entry_header.flags |= crate::FATBINARY_FLAG_COMPRESSED;
+        entry.entry_header.compressed_size = 3;
+        let err = entry.payload_reader().read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn copy_payload_to() {
+        let payload = payload();
+        let mut entry = FatBinaryEntry::new_auto(80, payload.clone());
+        assert!(entry.compress());
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, b".target sm_70\n".to_vec()));
+        fatbin.entries_mut().push(entry.clone());
+
+        let mut output = vec![];
+        assert_eq!(
+            entry.copy_payload_to(&mut output).unwrap(),
+            payload.len() as u64
+        );
+        assert_eq!(output, payload);
+
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        let mut output = vec![];
+        let written = FatBinary::copy_entry_payload(Cursor::new(&buffer), 1, &mut output).unwrap();
+        assert_eq!(written, Some(payload.len() as u64));
+        assert_eq!(output, payload);
+        let mut output = vec![];
+        FatBinary::copy_entry_payload(Cursor::new(&buffer), 0, &mut output).unwrap();
+        assert_eq!(output, b".target sm_70\n");
+        assert_eq!(
+            FatBinary::copy_entry_payload(Cursor::new(&buffer), 2, &mut output).unwrap(),
+            None
+        );
+
+        // match before any output
+        let mut entry = FatBinaryEntry::new_auto(80, b"\x0f\x01\x00\x00\x00\x00\x00".to_vec());
+        entry.entry_header.flags |= crate::FATBINARY_FLAG_COMPRESSED;
+        entry.entry_header.compressed_size = 7;
+        assert!(entry.copy_payload_to(&mut vec![]).is_err());
+    }
+}
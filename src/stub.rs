@@ -0,0 +1,107 @@
+//! Host-side registration stub generation
+
+use crate::{FatBinary, FATBINC_MAGIC};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+impl FatBinary<'_> {
+    /// Generate C source registering this fatbinary and its kernels with the
+    /// CUDA runtime when the program starts, so it can be linked into host
+    /// programs without nvcc.
+    ///
+    /// For each kernel `name`, a host handle `char name_handle` is defined,
+    /// pass `&name_handle` to `cudaLaunchKernel`.
+    pub fn generate_registration_stub(&self) -> String {
+        let mut fatbin = Vec::new();
+        self.write(&mut fatbin)
+            .expect("writing to memory should not fail");
+        // fatbin data is aligned to 8 bytes
+        fatbin.resize(fatbin.len().next_multiple_of(8), 0);
+
+        let kernels = self.kernels();
+
+        let mut res = String::new();
+        res += "/* Generated by fatbinary crate, do not edit */\n";
+        res += "#include <stdlib.h>\n\n";
+        res += "extern void **__cudaRegisterFatBinary(void *fatCubin);\n";
+        res += "extern void __cudaRegisterFatBinaryEnd(void **fatCubinHandle);\n";
+        res += "extern void __cudaUnregisterFatBinary(void **fatCubinHandle);\n";
+        res += "extern void __cudaRegisterFunction(void **fatCubinHandle, const char *hostFun,\n";
+        res += "    char *deviceFun, const char *deviceName, int thread_limit, void *tid,\n";
+        res += "    void *bid, void *bDim, void *gDim, int *wSize);\n\n";
+
+        res += "__attribute__((aligned(8), section(\".nv_fatbin\")))\n";
+        res += "static const unsigned long long fatbinData[] = {";
+        for (i, chunk) in fatbin.chunks(8).enumerate() {
+            if i % 4 == 0 {
+                res += "\n   ";
+            }
+            let value = u64::from_le_bytes(chunk.try_into().unwrap());
+            let _ = write!(res, " 0x{:016x}ULL,", value);
+        }
+        res += "\n};\n\n";
+
+        res += "__attribute__((aligned(8), section(\".nvFatBinSegment\")))\n";
+        res += "static const struct {\n";
+        res += "    int magic;\n";
+        res += "    int version;\n";
+        res += "    const unsigned long long *data;\n";
+        res += "    void *filename_or_fatbins;\n";
+        let _ = writeln!(
+            res,
+            "}} __fatDeviceText = {{ 0x{:x}, 1, fatbinData, NULL }};\n",
+            FATBINC_MAGIC
+        );
+
+        res += "/* host handles of kernels */\n";
+        for name in kernels.keys() {
+            let _ = writeln!(res, "char {}_handle;", name);
+        }
+        res += "\nstatic void **__fatbinary_handle;\n\n";
+
+        res += "static void __fatbinary_unregister(void) {\n";
+        res += "    __cudaUnregisterFatBinary(__fatbinary_handle);\n";
+        res += "}\n\n";
+
+        res += "__attribute__((constructor)) static void __fatbinary_register(void) {\n";
+        res += "    __fatbinary_handle = __cudaRegisterFatBinary((void *)&__fatDeviceText);\n";
+        for name in kernels.keys() {
+            let _ = writeln!(
+                res,
+                "    __cudaRegisterFunction(__fatbinary_handle, &{0}_handle, \"{0}\", \"{0}\", -1, NULL, NULL, NULL, NULL, NULL);",
+                name
+            );
+        }
+        res += "    __cudaRegisterFatBinaryEnd(__fatbinary_handle);\n";
+        res += "    atexit(__fatbinary_unregister);\n";
+        res += "}\n";
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+
+    #[test]
+    fn registration_stub() {
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            70,
+            ".version 7.0\n.target sm_70\n.visible .entry _Z4axpyfPfS_(\n.param .f32 a\n)\n{\n}\n.entry foo()\n{\n}\n".as_bytes(),
+        ));
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            80,
+            ".version 7.0\n.target sm_80\n// .entry commented()\n.entry foo()\n{\n}\n".as_bytes(),
+        ));
+
+        let stub = fatbin.generate_registration_stub();
+        assert!(stub.contains("char _Z4axpyfPfS__handle;"));
+        assert!(stub
+            .contains("__cudaRegisterFunction(__fatbinary_handle, &foo_handle, \"foo\", \"foo\""));
+        assert_eq!(stub.matches("char foo_handle;").count(), 1);
+        assert!(!stub.contains("commented"));
+        assert!(stub.contains("0x466243b1, 1, fatbinData, NULL"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Compiling PTX entries into cubins with `ptxas`, enabled by the
+//! `toolchain` feature
+//!
+//! `ptxas` is taken from the `PTXAS` environment variable, then `PATH`.
+
+use crate::{trim_nul, EntryKind, FatBinary, FatBinaryEntry, FatBinaryError};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Distinguish temporary files of concurrent invocations in this process
+static INVOCATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Path of `ptxas` to run
+fn ptxas_path() -> PathBuf {
+    std::env::var_os("PTXAS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("ptxas"))
+}
+
+/// Temporary files removed on drop
+struct TempFiles {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+impl TempFiles {
+    fn new() -> Self {
+        let prefix = format!(
+            "fatbinary-ptxas-{}-{}",
+            std::process::id(),
+            INVOCATION.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = std::env::temp_dir();
+        TempFiles {
+            input: dir.join(format!("{}.ptx", prefix)),
+            output: dir.join(format!("{}.cubin", prefix)),
+        }
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.input);
+        let _ = std::fs::remove_file(&self.output);
+    }
+}
+
+impl FatBinaryEntry<'_> {
+    /// Compile PTX of this entry into a cubin for `target_sm` with `ptxas`,
+    /// passing extra `options`. The new ELF entry keeps version, host,
+    /// producer, address size and identifier of this entry.
+    pub fn assemble_with_ptxas(
+        &self,
+        target_sm: u32,
+        options: &[&str],
+    ) -> Result<FatBinaryEntry<'static>, FatBinaryError> {
+        self.assemble_with(&ptxas_path(), target_sm, options)
+    }
+
+    /// Same as [FatBinaryEntry::assemble_with_ptxas] with `ptxas` at `ptxas`
+    pub fn assemble_with(
+        &self,
+        ptxas: &Path,
+        target_sm: u32,
+        options: &[&str],
+    ) -> Result<FatBinaryEntry<'static>, FatBinaryError> {
+        if self.kind() != EntryKind::Ptx {
+            return Err(FatBinaryError::NotPtx {
+                kind: self.entry_header.kind,
+            });
+        }
+
+        let files = TempFiles::new();
+        std::fs::write(&files.input, trim_nul(&self.get_decompressed_payload()))?;
+        let output = Command::new(ptxas)
+            .arg(format!("-arch=sm_{}", target_sm))
+            .args(options)
+            .arg(&files.input)
+            .arg("-o")
+            .arg(&files.output)
+            .output()?;
+        if !output.status.success() {
+            return Err(FatBinaryError::Ptxas {
+                status: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+
+        let mut res = FatBinaryEntry::new(
+            true,
+            target_sm,
+            self.get_version_major(),
+            self.get_version_minor(),
+            self.is_64bit(),
+            std::fs::read(&files.output)?,
+        );
+        res.set_host(self.host());
+        res.set_producer(self.producer());
+        res.set_identifier_bytes(self.identifier.clone());
+        Ok(res)
+    }
+}
+
+impl FatBinary<'_> {
+    /// Compile the PTX of the highest arch not above `target_sm` into a
+    /// cubin for `target_sm` and append it. Returns false without running
+    /// `ptxas` if there is no such PTX.
+    pub fn append_ptxas_cubin(
+        &mut self,
+        target_sm: u32,
+        options: &[&str],
+    ) -> Result<bool, FatBinaryError> {
+        let ptx = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind() == EntryKind::Ptx && entry.get_sm_arch() <= target_sm)
+            .max_by_key(|entry| entry.get_sm_arch());
+        let Some(ptx) = ptx else {
+            return Ok(false);
+        };
+        let cubin = ptx.assemble_with_ptxas(target_sm, options)?;
+        self.entries.push(cubin);
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::{EntryKind, FatBinaryEntry, FatBinaryError, Host};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    /// Fake ptxas writing its arguments and input as the cubin
+    fn fake_ptxas(script: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fatbinary-fake-ptxas-{}-{}",
+            std::process::id(),
+            script.len()
+        ));
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn assemble() {
+        let ptxas = fake_ptxas(
+            "#!/bin/sh\nprintf '\\177ELF %s %s ' \"$1\" \"$2\" > \"$5\"\ncat \"$3\" >> \"$5\"\n",
+        );
+        let mut ptx = FatBinaryEntry::new(false, 70, 1, 7, true, &b".target sm_70\0\0"[..]);
+        ptx.set_host(Host::Windows);
+        ptx.set_identifier(Some("axpy.cu"));
+        let cubin = ptx.assemble_with(&ptxas, 86, &["-O3"]).unwrap();
+        std::fs::remove_file(&ptxas).unwrap();
+
+        assert_eq!(cubin.kind(), EntryKind::Elf);
+        assert_eq!(cubin.get_sm_arch(), 86);
+        assert_eq!(cubin.get_version_minor(), 7);
+        assert_eq!(cubin.host(), Host::Windows);
+        assert_eq!(cubin.get_identifier(), Some("axpy.cu"));
+        assert_eq!(
+            cubin.get_payload(),
+            b"\x7fELF -arch=sm_86 -O3 .target sm_70"
+        );
+
+        let failing = fake_ptxas("#!/bin/sh\necho 'syntax error' >&2\nexit 2\n");
+        let err = ptx.assemble_with(&failing, 86, &[]).unwrap_err();
+        std::fs::remove_file(&failing).unwrap();
+        assert!(matches!(
+            err,
+            FatBinaryError::Ptxas { status: Some(2), stderr } if stderr == "syntax error\n"
+        ));
+        assert!(matches!(
+            cubin.assemble_with(&failing, 86, &[]),
+            Err(FatBinaryError::NotPtx { kind: 2 })
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+//! Hooks intercepting payloads of entries when reading and writing, e.g. to
+//! decrypt obfuscated payloads or to watermark PTX
+//!
+//! Hooks get decompressed payloads. A transformed payload is stored as
+//! given, without padding, and compressed again if the entry was
+//! compressed.
+
+use crate::{FatBinary, FatBinaryEntry, FatBinaryError, Payload, FATBINARY_FLAG_COMPRESSED};
+#[cfg(feature = "std")]
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use binread::io::{Read, Seek};
+#[cfg(feature = "std")]
+use std::io::Write;
+
+/// Hooks called per entry by [PayloadPipeline], the default hooks keep
+/// payloads unchanged
+pub trait PayloadTransform {
+    /// Transform decompressed payload of the entry at `index` after
+    /// reading, `None` keeps it
+    fn on_read(
+        &mut self,
+        index: usize,
+        entry: &FatBinaryEntry<'_>,
+        payload: &[u8],
+    ) -> Option<Vec<u8>> {
+        let _ = (index, entry, payload);
+        None
+    }
+
+    /// Transform decompressed payload of the entry at `index` before
+    /// writing, `None` keeps it
+    fn on_write(
+        &mut self,
+        index: usize,
+        entry: &FatBinaryEntry<'_>,
+        payload: &[u8],
+    ) -> Option<Vec<u8>> {
+        let _ = (index, entry, payload);
+        None
+    }
+}
+
+/// Reads and writes fatbinaries passing payloads through a
+/// [PayloadTransform], created by [FatBinary::with_payload_transform]
+#[derive(Debug, Clone)]
+pub struct PayloadPipeline<T> {
+    transform: T,
+}
+
+impl FatBinaryEntry<'_> {
+    /// Replace payload with decompressed `payload`, compressing it if the
+    /// entry was compressed
+    fn replace_decompressed_payload(&mut self, payload: Vec<u8>) {
+        let was_compressed = self.is_compressed();
+        self.entry_header.flags &= !FATBINARY_FLAG_COMPRESSED;
+        self.entry_header.compressed_size = 0;
+        self.entry_header.decompressed_size = 0;
+        self.entry_header.size = payload.len() as u64;
+        self.payload = Payload::Owned(payload).into_aligned();
+        if was_compressed {
+            self.compress();
+        }
+    }
+}
+
+impl FatBinary<'_> {
+    /// Create a pipeline reading and writing fatbinaries with payloads
+    /// intercepted by `transform`
+    pub fn with_payload_transform<T: PayloadTransform>(transform: T) -> PayloadPipeline<T> {
+        PayloadPipeline { transform }
+    }
+}
+
+impl<T: PayloadTransform> PayloadPipeline<T> {
+    fn apply_read(&mut self, fatbin: &mut FatBinary<'_>) {
+        for (index, entry) in fatbin.entries.iter_mut().enumerate() {
+            let payload = self
+                .transform
+                .on_read(index, entry, &entry.get_decompressed_payload());
+            if let Some(payload) = payload {
+                entry.replace_decompressed_payload(payload);
+            }
+        }
+    }
+
+    /// Read fatbinary from memory like [FatBinary::parse], transforming
+    /// payloads with [PayloadTransform::on_read]. Payloads which are kept
+    /// are borrowed from `data`.
+    pub fn parse<'a>(&mut self, data: &'a [u8]) -> Result<FatBinary<'a>, FatBinaryError> {
+        let mut res = FatBinary::parse(data)?;
+        self.apply_read(&mut res);
+        Ok(res)
+    }
+
+    /// Read fatbinary from reader like [FatBinary::read], transforming
+    /// payloads with [PayloadTransform::on_read]
+    pub fn read<R: Read + Seek>(
+        &mut self,
+        reader: R,
+    ) -> Result<FatBinary<'static>, FatBinaryError> {
+        let mut res = FatBinary::read(reader)?;
+        self.apply_read(&mut res);
+        Ok(res)
+    }
+
+    /// Write fatbinary like [FatBinary::write], transforming payloads with
+    /// [PayloadTransform::on_write]. `fatbin` is not modified.
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(
+        &mut self,
+        fatbin: &FatBinary<'_>,
+        writer: W,
+    ) -> Result<(), FatBinaryError> {
+        let entries: Vec<Cow<'_, FatBinaryEntry<'_>>> = fatbin
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                match self
+                    .transform
+                    .on_write(index, entry, &entry.get_decompressed_payload())
+                {
+                    Some(payload) => {
+                        let mut entry = entry.clone();
+                        entry.replace_decompressed_payload(payload);
+                        Cow::Owned(entry)
+                    }
+                    None => Cow::Borrowed(entry),
+                }
+            })
+            .collect();
+        FatBinary::write_entries(writer, entries.iter().map(|entry| entry.as_ref()))
+    }
+
+    /// Get the transform back, e.g. to inspect state collected by hooks
+    pub fn into_inner(self) -> T {
+        self.transform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EntryKind, FatBinary, FatBinaryEntry, PayloadTransform};
+
+    /// XOR payloads of cubins, counting entries seen
+    struct Xor {
+        key: u8,
+        seen: usize,
+    }
+
+    impl PayloadTransform for Xor {
+        fn on_read(
+            &mut self,
+            _: usize,
+            entry: &FatBinaryEntry<'_>,
+            payload: &[u8],
+        ) -> Option<Vec<u8>> {
+            self.seen += 1;
+            (entry.kind() == EntryKind::Elf)
+                .then(|| payload.iter().map(|byte| byte ^ self.key).collect())
+        }
+
+        fn on_write(
+            &mut self,
+            index: usize,
+            entry: &FatBinaryEntry<'_>,
+            payload: &[u8],
+        ) -> Option<Vec<u8>> {
+            self.on_read(index, entry, payload)
+        }
+    }
+
+    /// Append a comment to PTX
+    struct Watermark;
+
+    impl PayloadTransform for Watermark {
+        fn on_write(
+            &mut self,
+            _: usize,
+            entry: &FatBinaryEntry<'_>,
+            payload: &[u8],
+        ) -> Option<Vec<u8>> {
+            (entry.kind() == EntryKind::Ptx).then(|| {
+                let mut res = crate::trim_nul(payload).to_vec();
+                res.extend_from_slice(b"// watermark\n\0");
+                res
+            })
+        }
+    }
+
+    #[test]
+    fn payload_transform() {
+        let ptx = ".version 7.0\n.target sm_70\n".repeat(8);
+        let cubin = b"\x7fELF\x02\x01\x01\0".repeat(8);
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ptx.as_bytes());
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, cubin.clone()));
+
+        // obfuscate on write, deobfuscate on read
+        let mut pipeline = FatBinary::with_payload_transform(Xor { key: 0x5a, seen: 0 });
+        let mut buffer = vec![];
+        pipeline.write(&fatbin, &mut buffer).unwrap();
+        let stored = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(stored.entries()[0], fatbin.entries()[0]);
+        assert_ne!(stored.entries()[1].get_payload(), cubin);
+        assert_eq!(pipeline.parse(&buffer).unwrap(), fatbin);
+        let read = pipeline.read(std::io::Cursor::new(&buffer)).unwrap();
+        assert_eq!(read, fatbin);
+        assert_eq!(pipeline.into_inner().seen, 6);
+
+        // compressed PTX stays compressed
+        let mut buffer = vec![];
+        FatBinary::with_payload_transform(Watermark)
+            .write(&fatbin, &mut buffer)
+            .unwrap();
+        let read = FatBinary::parse(&buffer).unwrap();
+        assert!(read.entries()[0].is_compressed());
+        assert_eq!(
+            read.entries()[0].ptx_source().unwrap(),
+            format!("{}// watermark\n", ptx)
+        );
+        assert_eq!(read.entries()[1], fatbin.entries()[1]);
+    }
+}
@@ -0,0 +1,165 @@
+//! Removing entries not needed by a set of GPUs
+
+use crate::{EntryInfo, EntryKind, FatBinary, FatBinaryEntry, FatBinaryHeader, SmArch};
+use alloc::vec::Vec;
+
+/// Size of fatbinary before and after [FatBinary::trim_to_arches]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrimReport {
+    pub entries_before: usize,
+    pub entries_after: usize,
+    /// Size of written fatbinary before trimming
+    pub bytes_before: u64,
+    /// Size of written fatbinary after trimming
+    pub bytes_after: u64,
+    /// Metadata of removed entries
+    pub removed: Vec<EntryInfo>,
+}
+
+impl core::fmt::Display for TrimReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "entries: {} -> {} ({} removed)",
+            self.entries_before,
+            self.entries_after,
+            self.removed.len()
+        )?;
+        write!(
+            f,
+            "size: {} -> {} bytes ({} saved)",
+            self.bytes_before,
+            self.bytes_after,
+            self.bytes_before - self.bytes_after
+        )
+    }
+}
+
+/// Size of fatbinary with `entries` when written
+fn written_size<'b, 'c: 'b>(entries: impl Iterator<Item = &'b FatBinaryEntry<'c>>) -> u64 {
+    let headers = core::mem::size_of::<FatBinaryHeader>() as u64;
+    headers
+        + entries
+            .map(|entry| entry.entry_header.header_size as u64 + entry.entry_header.size)
+            .sum::<u64>()
+}
+
+impl FatBinary<'_> {
+    /// Keep only entries needed to run on GPUs of `archs`: for each GPU the
+    /// cubin of the highest compatible arch, or the PTX it would JIT if there
+    /// is no cubin. With `keep_ptx_fallback`, the PTX of the highest arch is
+    /// kept as well, for GPUs newer than `archs`.
+    pub fn trim_to_arches(&mut self, archs: &[SmArch], keep_ptx_fallback: bool) -> TrimReport {
+        let entries_before = self.entries.len();
+        let bytes_before = written_size(self.entries.iter());
+
+        let mut keep = alloc::vec![false; self.entries.len()];
+        let best = |kind: EntryKind, sm: u32| {
+            self.entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.kind() == kind && entry.runs_on(sm))
+                .max_by_key(|(_, entry)| entry.get_sm_arch())
+                .map(|(index, _)| index)
+        };
+        for arch in archs {
+            if let Some(index) =
+                best(EntryKind::Elf, arch.0).or_else(|| best(EntryKind::Ptx, arch.0))
+            {
+                keep[index] = true;
+            }
+        }
+        if keep_ptx_fallback {
+            let fallback = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.kind() == EntryKind::Ptx && !entry.is_arch_specific())
+                .max_by_key(|(_, entry)| entry.get_sm_arch());
+            if let Some((index, _)) = fallback {
+                keep[index] = true;
+            }
+        }
+
+        let mut removed = Vec::new();
+        let mut keep = keep.into_iter();
+        self.entries.retain(|entry| {
+            let res = keep.next().unwrap_or(true);
+            if !res {
+                removed.push(entry.info());
+            }
+            res
+        });
+
+        TrimReport {
+            entries_before,
+            entries_after: self.entries.len(),
+            bytes_before,
+            bytes_after: written_size(self.entries.iter()),
+            removed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EntryKind, FatBinary, FatBinaryEntry, SmArch};
+
+    fn sample() -> FatBinary<'static> {
+        let mut fatbin = FatBinary::new();
+        for arch in [70, 80, 86, 90] {
+            fatbin
+                .entries_mut()
+                .push(FatBinaryEntry::new_auto(arch, b"\x7fELF".to_vec()));
+        }
+        for arch in [70, 90] {
+            fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+                arch,
+                format!(".target sm_{}\n", arch).into_bytes(),
+            ));
+        }
+        fatbin
+    }
+
+    #[test]
+    fn trim_to_arches() {
+        let mut fatbin = sample();
+        let report =
+            fatbin.trim_to_arches(&[SmArch(86), SmArch(89), SmArch(75), SmArch(100)], false);
+        let kept: Vec<_> = fatbin
+            .entries()
+            .iter()
+            .map(|entry| (entry.kind(), entry.get_sm_arch()))
+            .collect();
+        // sm_86 and sm_89 run the sm_86 cubin, sm_75 runs the sm_70 cubin
+        // and sm_100 JITs PTX of sm_90
+        assert_eq!(
+            kept,
+            vec![
+                (EntryKind::Elf, 70),
+                (EntryKind::Elf, 86),
+                (EntryKind::Ptx, 90)
+            ]
+        );
+        assert_eq!(report.entries_before, 6);
+        assert_eq!(report.entries_after, 3);
+        assert_eq!(report.removed.len(), 3);
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+        assert_eq!(report.bytes_after, buffer.len() as u64);
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "entries: 6 -> 3 (3 removed)\nsize: {} -> {} bytes ({} saved)",
+                report.bytes_before,
+                report.bytes_after,
+                report.bytes_before - report.bytes_after
+            )
+        );
+
+        let mut fatbin = sample();
+        fatbin.trim_to_arches(&[SmArch(80)], true);
+        assert_eq!(fatbin.entries().len(), 2);
+        assert_eq!(fatbin.entries()[1].get_sm_arch(), 90);
+    }
+}
@@ -0,0 +1,340 @@
+//! Structural validation of fatbinary files
+
+use crate::{try_decompress, FatBinary, FatBinaryEntry, FatBinaryEntryHeader};
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Display;
+use object::read::elf::{ElfFile, FileHeader};
+use object::{elf, Object, ObjectSection};
+
+/// Issue found by [FatBinary::verify]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VerifyIssue {
+    /// Index of the entry with the issue
+    pub entry_index: usize,
+    /// Description of the issue
+    pub message: String,
+}
+
+impl Display for VerifyIssue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "entry {}: {}", self.entry_index, self.message)
+    }
+}
+
+/// Check ELF header and section bounds
+fn verify_elf<Elf: FileHeader<Endian = object::Endianness>>(
+    payload: &[u8],
+    messages: &mut Vec<String>,
+) {
+    let file = match ElfFile::<Elf>::parse(payload) {
+        Ok(file) => file,
+        Err(err) => {
+            messages.push(format!("malformed ELF: {}", err));
+            return;
+        }
+    };
+
+    let machine = file.elf_header().e_machine(file.endian());
+    if machine != elf::EM_CUDA {
+        messages.push(format!(
+            "ELF machine is {} instead of EM_CUDA ({})",
+            machine,
+            elf::EM_CUDA
+        ));
+    }
+
+    for section in file.sections() {
+        if let Err(err) = section.data() {
+            messages.push(format!(
+                "malformed ELF section {}: {}",
+                section.name().unwrap_or("<unknown>"),
+                err
+            ));
+        }
+    }
+}
+
+/// Check that PTX is text containing the mandatory directives
+fn verify_ptx(payload: &[u8], messages: &mut Vec<String>) {
+    // PTX is padded with NULs
+    let end = payload
+        .iter()
+        .rposition(|b| *b != 0)
+        .map(|pos| pos + 1)
+        .unwrap_or(0);
+    let ptx = match core::str::from_utf8(&payload[..end]) {
+        Ok(ptx) => ptx,
+        Err(err) => {
+            messages.push(format!("PTX is not valid UTF-8: {}", err));
+            return;
+        }
+    };
+
+    for directive in [".version", ".target"] {
+        if !ptx
+            .lines()
+            .any(|line| line.trim_start().starts_with(directive))
+        {
+            messages.push(format!("PTX is missing {} directive", directive));
+        }
+    }
+
+    let mut depth = 0i64;
+    for c in ptx.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            break;
+        }
+    }
+    if depth != 0 {
+        messages.push("PTX has unbalanced braces".to_string());
+    }
+}
+
+impl FatBinaryEntry<'_> {
+    fn verify(&self, messages: &mut Vec<String>) {
+        let header = &self.entry_header;
+        let kind = header.kind;
+        if kind != 1 && kind != 2 {
+            messages.push(format!("unknown kind {:#x}", kind));
+        }
+        let version = header.version;
+        if version != 0x101 {
+            messages.push(format!("unexpected version {:#x}", version));
+        }
+        let header_size = header.header_size;
+        if header_size < core::mem::size_of::<FatBinaryEntryHeader>() as u32 {
+            messages.push(format!("header size {} is too small", header_size));
+        }
+        let reserved = header.reserved;
+        if reserved != 0 {
+            messages.push(format!("reserved field is {:#x} instead of 0", reserved));
+        }
+
+        if self.has_compressed_flag() && !self.is_compressed() {
+            messages.push("compressed flag set with zero compressed size".to_string());
+        }
+
+        let payload = if self.is_compressed() {
+            let compressed_size = header.compressed_size as usize;
+            let decompressed_size = header.decompressed_size as usize;
+            if compressed_size > self.payload.len() {
+                messages.push(format!(
+                    "compressed size {} exceeds payload size {}",
+                    compressed_size,
+                    self.payload.len()
+                ));
+                return;
+            }
+            match try_decompress(&self.payload[..compressed_size], decompressed_size) {
+                Some(payload) => {
+                    if payload.len() != decompressed_size {
+                        messages.push(format!(
+                            "decompressed size {} differs from header {}",
+                            payload.len(),
+                            decompressed_size
+                        ));
+                    }
+                    Cow::Owned(payload)
+                }
+                None => {
+                    messages.push("decompression failed".to_string());
+                    return;
+                }
+            }
+        } else {
+            Cow::Borrowed(&self.payload[..])
+        };
+
+        if self.contains_elf() {
+            // e_ident[EI_CLASS]
+            match payload.get(4) {
+                Some(&elf::ELFCLASS64) => {
+                    verify_elf::<elf::FileHeader64<object::Endianness>>(&payload, messages)
+                }
+                Some(&elf::ELFCLASS32) => {
+                    verify_elf::<elf::FileHeader32<object::Endianness>>(&payload, messages)
+                }
+                _ => messages.push("malformed ELF: invalid class".to_string()),
+            }
+        } else {
+            verify_ptx(&payload, messages);
+        }
+    }
+}
+
+/// Compare entry with its roundtripped copy field by field, ignoring the
+/// layout of strings in the header which the writer may change
+#[cfg(feature = "std")]
+fn diff_roundtrip(old: &FatBinaryEntry, new: &FatBinaryEntry, messages: &mut Vec<String>) {
+    let (a, b) = (&old.entry_header, &new.entry_header);
+    let fields: [(&str, u64, u64); 10] = [
+        ("kind", a.kind as u64, b.kind as u64),
+        ("version", a.version as u64, b.version as u64),
+        ("size", a.size, b.size),
+        (
+            "compressed size",
+            a.compressed_size as u64,
+            b.compressed_size as u64,
+        ),
+        ("minor version", a.minor as u64, b.minor as u64),
+        ("major version", a.major as u64, b.major as u64),
+        ("arch", a.arch as u64, b.arch as u64),
+        ("flags", a.flags, b.flags),
+        ("reserved field", a.reserved, b.reserved),
+        (
+            "decompressed size",
+            a.decompressed_size,
+            b.decompressed_size,
+        ),
+    ];
+    for (name, old, new) in fields {
+        if old != new {
+            messages.push(format!("{} changed from {:#x} to {:#x}", name, old, new));
+        }
+    }
+
+    if old.identifier != new.identifier {
+        messages.push(format!(
+            "identifier changed from {:?} to {:?}",
+            old.get_identifier_lossy(),
+            new.get_identifier_lossy()
+        ));
+    }
+    if old.ptxas_options != new.ptxas_options {
+        messages.push(format!(
+            "ptxas options changed from {:?} to {:?}",
+            old.get_ptxas_options_lossy(),
+            new.get_ptxas_options_lossy()
+        ));
+    }
+    if old.extra_header != new.extra_header {
+        messages.push(format!(
+            "extra header changed from {:02x?} to {:02x?}",
+            old.extra_header, new.extra_header
+        ));
+    }
+    if old.payload != new.payload {
+        messages.push(format!(
+            "payload changed from {} to {} bytes",
+            old.payload.len(),
+            new.payload.len()
+        ));
+    }
+}
+
+impl FatBinary<'_> {
+    /// Write the fatbinary and parse it back, reporting every field of the
+    /// entries which does not survive the roundtrip
+    #[cfg(feature = "std")]
+    pub fn verify_roundtrip(&self) -> Result<Vec<VerifyIssue>, crate::FatBinaryError> {
+        let mut buffer = vec![];
+        self.write(&mut buffer)?;
+        let parsed = FatBinary::parse(&buffer)?;
+
+        let mut res = vec![];
+        let len = self.entries.len().max(parsed.entries.len());
+        for entry_index in 0..len {
+            let mut messages = vec![];
+            match (
+                self.entries.get(entry_index),
+                parsed.entries.get(entry_index),
+            ) {
+                (Some(old), Some(new)) => diff_roundtrip(old, new, &mut messages),
+                (Some(_), None) => messages.push("missing after roundtrip".to_string()),
+                (None, _) => messages.push("unexpected after roundtrip".to_string()),
+            }
+            res.extend(messages.into_iter().map(|message| VerifyIssue {
+                entry_index,
+                message,
+            }));
+        }
+        Ok(res)
+    }
+
+    /// Run structural validation on all entries: header invariants,
+    /// decompression, ELF well-formedness and PTX sanity
+    pub fn verify(&self) -> Vec<VerifyIssue> {
+        let mut res = vec![];
+        for (entry_index, entry) in self.entries.iter().enumerate() {
+            let mut messages = vec![];
+            entry.verify(&mut messages);
+            res.extend(messages.into_iter().map(|message| VerifyIssue {
+                entry_index,
+                message,
+            }));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+
+    #[test]
+    fn verify_entries() {
+        let mut fatbin = FatBinary::new();
+        fatbin.entries_mut().push(FatBinaryEntry::new_auto(
+            70,
+            ".version 7.0\n.target sm_70\n.entry foo() {\n}\n\0\0".as_bytes(),
+        ));
+        assert!(fatbin.verify().is_empty());
+
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, b"\x7fELF\x02".to_vec()));
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(70, ".target sm_70\n{".as_bytes()));
+        let issues = fatbin.verify();
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[0].entry_index, 1);
+        assert!(issues[0].message.starts_with("malformed ELF"));
+        assert_eq!(issues[1].entry_index, 2);
+        assert_eq!(issues[1].message, "PTX is missing .version directive");
+        assert_eq!(issues[2].message, "PTX has unbalanced braces");
+    }
+
+    #[test]
+    fn verify_roundtrip() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, b".version 7.0\n.target sm_70\n");
+        entry.set_identifier(Some("axpy.cu"));
+        entry.set_ptxas_options(Some("-O3"));
+        fatbin.entries_mut().push(entry);
+        let mut entry = FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec());
+        assert!(!entry.compress());
+        fatbin.entries_mut().push(entry);
+        assert_eq!(fatbin.verify_roundtrip().unwrap(), vec![]);
+
+        // options are only written with the options offset set
+        fatbin.entries_mut()[0].entry_header.options_offset = 0;
+        let issues = fatbin.verify_roundtrip().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].entry_index, 0);
+        assert_eq!(
+            issues[0].message,
+            "ptxas options changed from Some(\"-O3\") to None"
+        );
+
+        // trailing NULs of the extra header are padding
+        fatbin.entries_mut()[0].entry_header.options_offset = 0x40;
+        fatbin.entries_mut()[1].set_extra_header(vec![1, 0]);
+        let issues = fatbin.verify_roundtrip().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].entry_index, 1);
+        assert_eq!(
+            issues[0].message,
+            "extra header changed from [01, 00] to [01]"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Reading the serialized form of a fatbinary without building it
+//!
+//! Headers are serialized up front, payloads are borrowed from the entries,
+//! so the reader yields the same bytes as [FatBinary::write] while holding
+//! only headers in memory.
+
+use crate::FatBinary;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Serialized bytes of a fatbinary as [Read] and [Seek], created by
+/// [FatBinary::reader]
+#[derive(Debug, Clone)]
+pub struct FatBinaryReader<'r> {
+    /// Serialized headers and borrowed payloads, in order
+    segments: Vec<Segment<'r>>,
+    /// Offset of each segment
+    offsets: Vec<u64>,
+    len: u64,
+    position: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Segment<'r> {
+    Header(Vec<u8>),
+    Payload(&'r [u8]),
+}
+
+impl Segment<'_> {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Segment::Header(bytes) => bytes,
+            Segment::Payload(bytes) => bytes,
+        }
+    }
+}
+
+impl FatBinary<'_> {
+    /// Get the bytes [FatBinary::write] would write as [Read] and [Seek],
+    /// e.g. to upload or hash the fatbinary without a buffer for the whole
+    /// output. Payloads are borrowed from the entries.
+    pub fn reader(&self) -> FatBinaryReader<'_> {
+        let mut segments = vec![Segment::Header(
+            Self::header_of(self.entries.iter().map(|entry| &entry.entry_header))
+                .to_bytes()
+                .to_vec(),
+        )];
+        for entry in &self.entries {
+            let (entry_header, extra_header) = entry.serialized_headers();
+            let mut header = entry_header.to_vec();
+            header.extend_from_slice(&extra_header);
+            segments.push(Segment::Header(header));
+            segments.push(Segment::Payload(&entry.payload));
+        }
+
+        let mut offsets = Vec::with_capacity(segments.len());
+        let mut len = 0;
+        for segment in &segments {
+            offsets.push(len);
+            len += segment.bytes().len() as u64;
+        }
+        FatBinaryReader {
+            segments,
+            offsets,
+            len,
+            position: 0,
+        }
+    }
+}
+
+impl FatBinaryReader<'_> {
+    /// Size of the serialized fatbinary
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the serialized fatbinary is empty, which it never is
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for FatBinaryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.len {
+            return Ok(0);
+        }
+        // last segment starting at or before position, skipping empty ones
+        let index = self
+            .offsets
+            .partition_point(|&offset| offset <= self.position)
+            - 1;
+        let bytes = self.segments[index].bytes();
+        let begin = (self.position - self.offsets[index]) as usize;
+        let len = buf.len().min(bytes.len() - begin);
+        buf[..len].copy_from_slice(&bytes[begin..begin + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for FatBinaryReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry};
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn reader() {
+        let mut fatbin = FatBinary::new();
+        let mut entry = FatBinaryEntry::new_auto(70, ".version 7.0\n.target sm_70\n".as_bytes());
+        entry.set_ptxas_options(Some("-O3"));
+        entry.set_identifier(Some("axpy.cu"));
+        fatbin.entries_mut().push(entry);
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, Vec::new()));
+        let mut entry = FatBinaryEntry::new_auto(90, b"\x7fELF".repeat(16));
+        assert!(entry.compress());
+        fatbin.entries_mut().push(entry);
+        let mut expected = vec![];
+        fatbin.write(&mut expected).unwrap();
+
+        let mut reader = fatbin.reader();
+        assert_eq!(reader.len(), expected.len() as u64);
+        let mut output = vec![];
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+
+        // reading across segments after seeking
+        for offset in [0, 15, 16, 100, expected.len() as u64 - 3] {
+            reader.seek(SeekFrom::Start(offset)).unwrap();
+            let mut buf = [0u8; 20];
+            let len = reader.read(&mut buf).unwrap();
+            assert!(len > 0);
+            assert_eq!(buf[..len], expected[offset as usize..offset as usize + len]);
+        }
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        let mut output = vec![];
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected[expected.len() - 4..]);
+        assert_eq!(
+            reader.seek(SeekFrom::Current(10)).unwrap(),
+            reader.len() + 10
+        );
+        assert_eq!(reader.read(&mut [0u8; 4]).unwrap(), 0);
+        assert!(reader.seek(SeekFrom::Current(-1000)).is_err());
+
+        assert_eq!(FatBinary::read(fatbin.reader()).unwrap(), fatbin);
+    }
+}
@@ -0,0 +1,172 @@
+//! `__fatBinC_Wrapper_t`, the struct passed to `__cudaRegisterFatBinary`
+//!
+//! ```c
+//! struct __fatBinC_Wrapper_t {
+//!     int magic;   // 0x466243b1
+//!     int version; // 1
+//!     const unsigned long long *data;
+//!     void *filename_or_fatbins;
+//! };
+//! ```
+//!
+//! Only the 24-byte layout of little endian 64-bit hosts is supported.
+
+use crate::{FatBinary, FatBinaryError, FATBINC_MAGIC};
+
+/// Parsed `__fatBinC_Wrapper_t`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FatBinaryWrapper {
+    pub version: u32,
+    /// Address of the fatbinary
+    pub data: u64,
+    /// Address of file name or list of fatbinaries, usually null
+    pub filename_or_fatbins: u64,
+}
+
+impl FatBinaryWrapper {
+    /// Size of the wrapper in bytes
+    pub const SIZE: usize = 24;
+
+    /// Create wrapper pointing to fatbinary at `data`
+    pub fn new(data: u64) -> Self {
+        FatBinaryWrapper {
+            version: 1,
+            data,
+            filename_or_fatbins: 0,
+        }
+    }
+
+    /// Parse wrapper from the start of `bytes`
+    pub fn parse(bytes: &[u8]) -> Result<Self, FatBinaryError> {
+        let bytes: &[u8; Self::SIZE] = bytes
+            .get(..Self::SIZE)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(FatBinaryError::WrapperOutOfBounds {
+                address: 0,
+                len: bytes.len() as u64,
+            })?;
+        let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        if magic != FATBINC_MAGIC {
+            return Err(FatBinaryError::InvalidMagic {
+                expected: FATBINC_MAGIC,
+                got: magic,
+            });
+        }
+        Ok(FatBinaryWrapper {
+            version: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            data: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            filename_or_fatbins: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+        })
+    }
+
+    /// Serialize wrapper
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut res = [0u8; Self::SIZE];
+        res[0..4].copy_from_slice(&FATBINC_MAGIC.to_le_bytes());
+        res[4..8].copy_from_slice(&self.version.to_le_bytes());
+        res[8..16].copy_from_slice(&self.data.to_le_bytes());
+        res[16..24].copy_from_slice(&self.filename_or_fatbins.to_le_bytes());
+        res
+    }
+
+    /// Read the fatbinary the wrapper points to in the memory of this
+    /// process, e.g. in an interposed `__cudaRegisterFatBinary`
+    ///
+    /// # Safety
+    ///
+    /// `wrapper` must point to a valid `__fatBinC_Wrapper_t` whose `data`
+    /// points to a complete fatbinary, both alive for `'a`.
+    pub unsafe fn from_ptr<'a>(
+        wrapper: *const core::ffi::c_void,
+    ) -> Result<FatBinary<'a>, FatBinaryError> {
+        let bytes = core::slice::from_raw_parts(wrapper as *const u8, Self::SIZE);
+        let data = Self::parse(bytes)?.data as *const u8;
+        // header_size at offset 6, size of entries at offset 8
+        let header = core::slice::from_raw_parts(data, 16);
+        let header_size = u16::from_le_bytes(header[6..8].try_into().unwrap()) as u64;
+        let size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let len = usize::try_from(header_size.saturating_add(size)).map_err(|_| {
+            FatBinaryError::WrapperOutOfBounds {
+                address: data as u64,
+                len: size,
+            }
+        })?;
+        FatBinary::parse(core::slice::from_raw_parts(data, len))
+    }
+}
+
+impl<'a> FatBinary<'a> {
+    /// Parse fatbinary from memory, which is either a fatbinary or a
+    /// `__fatBinC_Wrapper_t` followed by the fatbinary it points to.
+    /// `base_address` is the address `data` was loaded at, used to locate
+    /// the fatbinary from the pointer in the wrapper.
+    pub fn parse_wrapped(
+        data: &'a [u8],
+        base_address: u64,
+    ) -> Result<(FatBinary<'a>, Option<FatBinaryWrapper>), FatBinaryError> {
+        if data.get(0..4) != Some(&FATBINC_MAGIC.to_le_bytes()[..]) {
+            return Ok((FatBinary::parse(data)?, None));
+        }
+        let wrapper = FatBinaryWrapper::parse(data)?;
+        let inner = wrapper
+            .data
+            .checked_sub(base_address)
+            .and_then(|offset| usize::try_from(offset).ok())
+            .and_then(|offset| data.get(offset..))
+            .ok_or(FatBinaryError::WrapperOutOfBounds {
+                address: wrapper.data,
+                len: data.len() as u64,
+            })?;
+        Ok((FatBinary::parse(inner)?, Some(wrapper)))
+    }
+
+    /// Write `__fatBinC_Wrapper_t` followed by the fatbinary, for loading
+    /// at `base_address`
+    #[cfg(feature = "std")]
+    pub fn write_wrapped<W: std::io::Write>(
+        &self,
+        mut writer: W,
+        base_address: u64,
+    ) -> Result<(), FatBinaryError> {
+        let wrapper = FatBinaryWrapper::new(base_address + FatBinaryWrapper::SIZE as u64);
+        writer.write_all(&wrapper.to_bytes())?;
+        self.write(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FatBinary, FatBinaryEntry, FatBinaryError, FatBinaryWrapper};
+
+    #[test]
+    fn wrapper() {
+        let mut fatbin = FatBinary::new();
+        fatbin
+            .entries_mut()
+            .push(FatBinaryEntry::new_auto(80, b"\x7fELF".to_vec()));
+
+        let mut buffer = vec![];
+        fatbin.write_wrapped(&mut buffer, 0x1000).unwrap();
+        let (read, wrapper) = FatBinary::parse_wrapped(&buffer, 0x1000).unwrap();
+        assert_eq!(read, fatbin);
+        assert_eq!(wrapper, Some(FatBinaryWrapper::new(0x1018)));
+        assert_eq!(wrapper.unwrap().to_bytes()[..], buffer[..24]);
+
+        // bare fatbinary
+        let (read, wrapper) = FatBinary::parse_wrapped(&buffer[24..], 0).unwrap();
+        assert_eq!((read, wrapper), (fatbin.clone(), None));
+
+        assert!(matches!(
+            FatBinary::parse_wrapped(&buffer, 0x2000),
+            Err(FatBinaryError::WrapperOutOfBounds {
+                address: 0x1018,
+                ..
+            })
+        ));
+
+        // wrapper pointing to fatbinary in memory
+        let wrapper = FatBinaryWrapper::new(buffer[24..].as_ptr() as u64).to_bytes();
+        let read = unsafe { FatBinaryWrapper::from_ptr(wrapper.as_ptr() as *const _) }.unwrap();
+        assert_eq!(read, fatbin);
+    }
+}
@@ -0,0 +1,99 @@
+//! Property tests: fatbinaries generated with `arbitrary` survive write and
+//! read unchanged
+//!
+//! Run with `cargo test --features arbitrary --test roundtrip`, set
+//! `FATBINARY_ROUNDTRIP_SEED` to reproduce a failing seed.
+
+#![cfg(feature = "arbitrary")]
+
+use arbitrary::{Arbitrary, Unstructured};
+use fatbinary::{FatBinary, ParseOptions, ValidationLevel};
+
+const ROUNDS: usize = 512;
+
+/// xorshift64, to avoid depending on rand
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Random bytes of random length, biased to small values so short
+    /// strings and payloads with repetition are common
+    fn bytes(&mut self) -> Vec<u8> {
+        let len = (self.next() % 4096) as usize;
+        (0..len).map(|_| (self.next() % 8) as u8 * 17).collect()
+    }
+}
+
+fn seed() -> u64 {
+    std::env::var("FATBINARY_ROUNDTRIP_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(0xfa7b_1a4e_5eed)
+}
+
+/// Run `check` on fatbinaries generated from random bytes
+fn for_each_fatbin(check: impl Fn(u64, &FatBinary)) {
+    let mut rng = Rng(seed());
+    for _ in 0..ROUNDS {
+        let round_seed = rng.next();
+        let data = Rng(round_seed).bytes();
+        let fatbin = FatBinary::arbitrary_take_rest(Unstructured::new(&data)).unwrap();
+        check(round_seed, &fatbin);
+    }
+}
+
+#[test]
+fn write_read() {
+    for_each_fatbin(|seed, fatbin| {
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let read = FatBinary::read(std::io::Cursor::new(&buffer)).unwrap();
+        assert_eq!(&read, fatbin, "seed {}", seed);
+        let parsed = FatBinary::parse(&buffer).unwrap();
+        assert_eq!(&parsed, fatbin, "seed {}", seed);
+
+        // output of the writer has no anomalies
+        let strict = ParseOptions {
+            level: ValidationLevel::Strict,
+            ..Default::default()
+        };
+        let (parsed, _) = FatBinary::parse_with(&buffer, strict).unwrap();
+        assert_eq!(&parsed, fatbin, "seed {}", seed);
+        assert_eq!(fatbin.verify_roundtrip().unwrap(), vec![], "seed {}", seed);
+    });
+}
+
+#[test]
+fn write_read_metadata() {
+    for_each_fatbin(|seed, fatbin| {
+        let mut buffer = vec![];
+        fatbin.write(&mut buffer).unwrap();
+
+        let infos = FatBinary::read_metadata(std::io::Cursor::new(&buffer)).unwrap();
+        let expected: Vec<_> = fatbin.entries().iter().map(|entry| entry.info()).collect();
+        assert_eq!(infos, expected, "seed {}", seed);
+    });
+}
+
+#[test]
+fn decompress() {
+    for_each_fatbin(|seed, fatbin| {
+        for entry in fatbin.entries() {
+            let mut decompressed = entry.clone();
+            decompressed.decompress();
+            assert_eq!(
+                decompressed.get_payload(),
+                &entry.get_decompressed_payload()[..],
+                "seed {}",
+                seed
+            );
+        }
+    });
+}